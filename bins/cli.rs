@@ -2,6 +2,7 @@ use std::{
     env,
     io::{stdin, stdout, Read, Write},
     path::PathBuf,
+    time::UNIX_EPOCH,
 };
 
 use multibufferedfile::BufferedFile;
@@ -13,7 +14,7 @@ pub fn main() {
 
     let verb = args
         .next()
-        .expect("The first argument should be either read or write");
+        .expect("The first argument should be either read, write, status or inspect");
     let file = PathBuf::from(
         args.next()
             .expect("The second argument should be a file path"),
@@ -27,11 +28,49 @@ pub fn main() {
             transfer(reader, stdout)
         }
         "write" => {
-            let writer = buffered.write().expect("Could not create Reader");
+            let mut writer = buffered.write().expect("Could not create Reader");
             let stdin = stdin().lock();
-            transfer(stdin, writer)
+            transfer(stdin, &mut writer);
+            writer.commit().expect("Could not commit the written file");
         }
-        _ => panic!("The first argument should be either `read` or `write`"),
+        "status" => {
+            let status = buffered.status().expect("Could not query the status");
+            for slot in status.slots {
+                let saved = slot
+                    .written
+                    .and_then(|written| written.duration_since(UNIX_EPOCH).ok())
+                    .map(|since| format!(", last saved at {} (unix time)", since.as_secs()))
+                    .unwrap_or_default();
+                match slot.generation {
+                    Some(generation) => {
+                        println!("{}: generation {generation}{saved}", slot.path.display())
+                    }
+                    None if slot.exists => println!("{}: invalid", slot.path.display()),
+                    None => println!("{}: missing", slot.path.display()),
+                }
+            }
+        }
+        "inspect" => {
+            let slots = buffered
+                .inspect()
+                .expect("Could not inspect the backing files");
+            for slot in slots {
+                match slot.header {
+                    Some(Ok(header)) => println!(
+                        "{}: {:?}, generation {}, written {:?}, payload {:?} bytes, {:?}",
+                        slot.path.display(),
+                        header.version(),
+                        header.generation(),
+                        header.written(),
+                        slot.payload_len,
+                        slot.trailer,
+                    ),
+                    Some(Err(err)) => println!("{}: {err:?}", slot.path.display()),
+                    None => println!("{}: missing", slot.path.display()),
+                }
+            }
+        }
+        _ => panic!("The first argument should be either `read`, `write`, `status` or `inspect`"),
     }
 }
 
@@ -7,10 +7,9 @@ fuzz_target!(|data: &[u8]| {
     let temp_dir = utils::TempDir::new();
 
     if let Ok(file) = BufferedFile::new(temp_dir.path().join("fuzz_target_1.txt")) {
-        file.write()
-            .expect("should be writeable")
-            .write_all(data)
-            .expect("Error writing data");
+        let mut writer = file.write().expect("should be writeable");
+        writer.write_all(data).expect("Error writing data");
+        writer.commit().expect("Error committing data");
     }
 });
 
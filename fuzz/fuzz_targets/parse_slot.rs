@@ -0,0 +1,19 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use multibufferedfile::{SlotHeader, SlotTrailer};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(header) = SlotHeader::parse(data) {
+        // a parsed header encodes to the bytes it has been parsed from
+        let encoded = header
+            .version()
+            .header_written_at(header.generation(), header.written().unwrap_or_default());
+        assert_eq!(encoded.as_ref(), &data[..encoded.as_ref().len()]);
+
+        if let Some(trailer) = SlotTrailer::parse(header.version(), data) {
+            let mut encoded = [0u8; 12];
+            let len = trailer.encode(&mut encoded);
+            assert_eq!(&encoded[..len], &data[data.len() - len..]);
+        }
+    }
+});
@@ -0,0 +1,126 @@
+use std::{
+    future::Future,
+    io::{Read, Seek},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    task::JoinHandle,
+};
+
+use crate::BufferedFileReader;
+
+/// The maximum number of bytes read by one blocking task
+const MAX_CHUNK_LEN: usize = 64 * 1024;
+
+/// The reader handed back by a blocking task with the bytes it has read
+type Chunk<T> = (BufferedFileReader<T>, Vec<u8>, std::io::Result<usize>);
+
+///
+/// Reads the contents of a `BufferedFileReader` with `tokio::io::AsyncRead`, see `BufferedFileReader::into_async`.
+///
+/// Every read is performed by a blocking task of the tokio runtime, so the executor threads are never blocked.
+/// The contents are limited to the length of the payload like with the synchronous reader. Bytes read beyond
+/// the buffer passed to `poll_read` are kept for the next read, so no data is lost with small buffers.
+#[derive(Debug)]
+pub struct AsyncBufferedFileReader<T: Read> {
+    /// The reader, while no blocking task is running
+    reader: Option<BufferedFileReader<T>>,
+    pending: Option<JoinHandle<Chunk<T>>>,
+    buffer: Vec<u8>,
+    start: usize,
+}
+
+impl<T: Read + Seek + Send + 'static> BufferedFileReader<T> {
+    ///
+    /// Wraps the reader for asynchronous reads within a tokio runtime.
+    ///
+    /// The contents are read from the current position. Reading fails outside of a tokio runtime.
+    pub fn into_async(self) -> AsyncBufferedFileReader<T> {
+        AsyncBufferedFileReader {
+            reader: Some(self),
+            pending: None,
+            buffer: Vec::new(),
+            start: 0,
+        }
+    }
+}
+
+// the reader is never pinned, it is moved into the blocking tasks anyway
+impl<T: Read> Unpin for AsyncBufferedFileReader<T> {}
+
+impl<T: Read + Seek + Send + 'static> AsyncRead for AsyncBufferedFileReader<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        loop {
+            if this.start < this.buffer.len() {
+                let count = buf.remaining().min(this.buffer.len() - this.start);
+                buf.put_slice(&this.buffer[this.start..this.start + count]);
+                this.start += count;
+                return Poll::Ready(Ok(()));
+            }
+            if let Some(pending) = &mut this.pending {
+                let joined = ready!(Pin::new(pending).poll(cx));
+                this.pending = None;
+                let (reader, buffer, result) = joined.map_err(std::io::Error::other)?;
+                this.reader = Some(reader);
+                this.buffer = buffer;
+                this.start = 0;
+                let count = result.inspect_err(|_| this.buffer.clear())?;
+                this.buffer.truncate(count);
+                if count == 0 {
+                    return Poll::Ready(Ok(()));
+                }
+                continue;
+            }
+            let mut reader = this.reader.take().ok_or_else(|| {
+                std::io::Error::other("The reader has been lost by a failed blocking task")
+            })?;
+            let mut buffer = std::mem::take(&mut this.buffer);
+            buffer.resize(buf.remaining().min(MAX_CHUNK_LEN), 0);
+            this.pending = Some(tokio::task::spawn_blocking(move || {
+                let result = reader.read(&mut buffer);
+                (reader, buffer, result)
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use crate::{tests::utils::TempDir, BufferedFileOptions};
+
+    #[test]
+    fn reads_the_contents_asynchronously() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .create_with(&file, b"Hello World")
+            .unwrap();
+        let mut reader = managed_file.read().unwrap().into_async();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let contents = runtime.block_on(async {
+            let mut first = [0u8; 4];
+            reader.read_exact(&mut first).await.unwrap();
+            assert_eq!(&first, b"Hell");
+            let mut rest = Vec::new();
+            reader.read_to_end(&mut rest).await.unwrap();
+            rest
+        });
+        assert_eq!(contents, b"o World");
+    }
+}
@@ -0,0 +1,285 @@
+//! Packs the backing files of a managed file into a single bundle, e.g. to attach them to a support ticket
+//! or to move them to another device.
+//!
+//! A bundle starts with the magic bytes `MBB` and the version 1, followed by the number of backing files
+//! in one byte. Every backing file is stored as its number in one byte, its length in eight bytes in little endian
+//! and its unchanged contents, so the headers with the generations and the user metadata are kept.
+//! The bundle ends with the checksum (CRC-32/BZIP2) of all preceding bytes.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    checksum::ChecksumDigest, verify_file, BufferedFile, BufferedFileErrors, Durability,
+    FileCheckResult, Storage, StorageFile, DEFAULT_CHECKSUM,
+};
+
+/// Identifies a bundle and the version of its layout
+const BUNDLE_MAGIC: [u8; 4] = *b"MBB\x01";
+
+/// Writes `data` to `out`, feeding it into `digest`
+fn put(out: &mut impl Write, digest: &mut ChecksumDigest, data: &[u8]) -> std::io::Result<()> {
+    digest.update(data);
+    out.write_all(data)
+}
+
+/// Fills `buf` from `input`, feeding it into `digest`
+fn take(input: &mut impl Read, digest: &mut ChecksumDigest, buf: &mut [u8]) -> std::io::Result<()> {
+    input.read_exact(buf).map_err(|err| match err.kind() {
+        ErrorKind::UnexpectedEof => {
+            std::io::Error::new(ErrorKind::InvalidData, "The bundle is incomplete")
+        }
+        _ => err,
+    })?;
+    digest.update(buf);
+    Ok(())
+}
+
+impl<S: Storage> BufferedFile<S> {
+    ///
+    /// Packs all existing backing files into a single bundle at `path`, see `import_bundle`.
+    ///
+    /// The backing files are copied unchanged, including damaged ones, so the bundle reflects the state
+    /// on disk. Writers are blocked while the bundle is written.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multibufferedfile::BufferedFile;
+    /// # let dir = std::env::temp_dir().join("multibufferedfile-bundle-doc");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    ///
+    /// let file = BufferedFile::create_with(dir.join("config.bin"), b"Hello World").unwrap();
+    /// file.export_bundle(dir.join("config.bundle")).unwrap();
+    ///
+    /// let restored = BufferedFile::new(dir.join("restored.bin")).unwrap();
+    /// restored.import_bundle(dir.join("config.bundle")).unwrap();
+    /// assert_eq!(restored.read_or_default().unwrap(), b"Hello World");
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn export_bundle(&self, path: impl AsRef<Path>) -> Result<(), BufferedFileErrors> {
+        let _lock = self.lock()?;
+        let mut slots = Vec::new();
+        for (index, slot) in self.slot_paths().into_iter().enumerate() {
+            match self.storage.open(&slot) {
+                Ok(file) => slots.push((index + 1, self.storage.metadata(&slot)?.len, file)),
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let mut out = BufWriter::new(File::create(path)?);
+        let mut digest = ChecksumDigest::new(&DEFAULT_CHECKSUM);
+        put(&mut out, &mut digest, &BUNDLE_MAGIC)?;
+        put(&mut out, &mut digest, &[slots.len() as u8])?;
+        for (index, len, file) in slots {
+            put(&mut out, &mut digest, &[index as u8])?;
+            put(&mut out, &mut digest, &len.to_le_bytes())?;
+            let mut remaining = len;
+            let mut file = file.take(len);
+            let mut buf = [0u8; 8192];
+            while remaining > 0 {
+                let count = file.read(&mut buf)?;
+                if count == 0 {
+                    return Err(std::io::Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "The backing file has been truncated while it was bundled",
+                    )
+                    .into());
+                }
+                put(&mut out, &mut digest, &buf[..count])?;
+                remaining -= count as u64;
+            }
+        }
+        out.write_all(&digest.finalize().to_le_bytes())?;
+        out.into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+        Ok(())
+    }
+
+    ///
+    /// Replaces the backing files by the ones packed into the bundle at `path` by `export_bundle`.
+    ///
+    /// The bundle is only restored, if its checksum matches and it holds at least one valid backing file,
+    /// otherwise it is rejected with `ErrorKind::InvalidData` and the backing files stay untouched.
+    /// Backing files failing the validation are left out, like the backing files missing in the bundle.
+    /// The bundle has to be opened with the same options it has been exported with.
+    /// Returns the paths of the restored backing files.
+    pub fn import_bundle(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<Vec<PathBuf>, BufferedFileErrors> {
+        let _lock = self.lock()?;
+        let slots = self.slot_paths();
+        let mut staged = Vec::<(PathBuf, PathBuf)>::new();
+        let result = self.stage_bundle(path.as_ref(), &slots, &mut staged);
+        let valid = match result {
+            Ok(()) => staged
+                .iter()
+                .filter(|(_, staged)| {
+                    matches!(
+                        verify_file(&*self.storage, staged, &self.options),
+                        Ok(FileCheckResult::Good { .. })
+                    )
+                })
+                .cloned()
+                .collect::<Vec<_>>(),
+            Err(_) => Vec::new(),
+        };
+        if result.is_err() || valid.is_empty() {
+            for (_, staged) in &staged {
+                let _ = self.storage.remove(staged);
+            }
+            result?;
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "The bundle does not hold a valid backing file",
+            )
+            .into());
+        }
+
+        let mut restored = Vec::new();
+        for slot in &slots {
+            match valid.iter().find(|(path, _)| path == slot) {
+                Some((path, staged)) => {
+                    self.storage.rename(staged, path)?;
+                    restored.push(path.clone());
+                }
+                None => match self.storage.remove(slot) {
+                    Ok(()) => {}
+                    Err(err) if err.kind() == ErrorKind::NotFound => {}
+                    Err(err) => return Err(err.into()),
+                },
+            }
+            if let Some(cache) = &self.options.validation_cache {
+                cache.invalidate(slot);
+            }
+        }
+        for (_, staged) in staged.iter().filter(|staged| !valid.contains(staged)) {
+            let _ = self.storage.remove(staged);
+        }
+        if self.options.durability >= Durability::FsyncAndDir {
+            if let Some(parent) = slots.first().and_then(|slot| slot.parent()) {
+                self.storage.sync_dir(parent)?;
+            }
+        }
+        self.rescan();
+        Ok(restored)
+    }
+
+    /// Writes the backing files of the bundle next to the `slots` they replace, recording them in `staged`
+    fn stage_bundle(
+        &self,
+        path: &Path,
+        slots: &[PathBuf],
+        staged: &mut Vec<(PathBuf, PathBuf)>,
+    ) -> Result<(), BufferedFileErrors> {
+        let mut input = BufReader::new(File::open(path)?);
+        let mut digest = ChecksumDigest::new(&DEFAULT_CHECKSUM);
+        let mut magic = [0u8; 5];
+        take(&mut input, &mut digest, &mut magic)?;
+        if magic[..4] != BUNDLE_MAGIC {
+            return Err(
+                std::io::Error::new(ErrorKind::InvalidData, "The file is not a bundle").into(),
+            );
+        }
+
+        for _ in 0..magic[4] {
+            let mut entry = [0u8; 9];
+            take(&mut input, &mut digest, &mut entry)?;
+            let slot = usize::from(entry[0])
+                .checked_sub(1)
+                .and_then(|index| slots.get(index))
+                .ok_or_else(|| {
+                    std::io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("The bundle holds the unknown backing file {}", entry[0]),
+                    )
+                })?;
+            let mut name = slot.clone().into_os_string();
+            name.push(".tmp");
+            let target = PathBuf::from(name);
+            if self.options.slot_dir.is_some() {
+                if let Some(parent) = slot.parent() {
+                    self.storage.create_dir_all(parent)?;
+                }
+            }
+            let mut file = self.storage.create(&target, self.options.mode)?;
+            staged.push((slot.clone(), target));
+
+            let mut remaining =
+                u64::from_le_bytes(entry[1..].try_into().expect("the length has 8 bytes"));
+            let mut buf = [0u8; 8192];
+            while remaining > 0 {
+                let chunk = &mut buf[..usize::try_from(remaining).unwrap_or(usize::MAX).min(8192)];
+                take(&mut input, &mut digest, chunk)?;
+                file.write_all(chunk)?;
+                remaining -= chunk.len() as u64;
+            }
+            if self.options.durability >= Durability::Flush {
+                file.flush()?;
+            }
+            if self.options.durability >= Durability::Fsync {
+                file.sync_all()?;
+            }
+        }
+
+        let mut stored = [0u8; 4];
+        input.read_exact(&mut stored)?;
+        if digest.finalize() != u32::from_le_bytes(stored) {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "The checksum of the bundle does not match",
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use crate::{tests::utils::TempDir, BufferedFile, BufferedFileErrors};
+
+    #[test]
+    fn bundles_restore_the_backing_files() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file =
+            BufferedFile::create_with(&file, b"Hello World").expect("Can not create the file");
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        let bundle = dir.path().join("data-file.bundle");
+        managed_file.export_bundle(&bundle).unwrap();
+
+        let other = BufferedFile::new(dir.path().join("other.txt")).unwrap();
+        other.update(|_| b"unrelated".to_vec()).unwrap();
+        other.update(|_| b"unrelated".to_vec()).unwrap();
+        other.update(|_| b"unrelated".to_vec()).unwrap();
+        assert_eq!(
+            other.import_bundle(&bundle).unwrap(),
+            [
+                dir.path().join("other.txt.1"),
+                dir.path().join("other.txt.2")
+            ]
+        );
+        assert_eq!(other.read_or_default().unwrap(), b"Hello again");
+        assert_eq!(other.history().len(), 2);
+
+        // a damaged bundle is rejected without touching the backing files
+        let mut damaged = std::fs::read(&bundle).unwrap();
+        damaged[10] ^= 0xff;
+        std::fs::write(&bundle, damaged).unwrap();
+        match managed_file.import_bundle(&bundle) {
+            Err(BufferedFileErrors::IoError(err)) => assert_eq!(err.kind(), ErrorKind::InvalidData),
+            other => panic!("Unexpected result {other:?}"),
+        }
+        assert_eq!(managed_file.read_or_default().unwrap(), b"Hello again");
+        assert!(!dir.path().join("data-file.txt.1.tmp").exists());
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::SystemTime,
+};
+
+use crate::{ChecksumAlgorithm, Generation, StorageMetadata};
+
+/// Identifies the state of a backing file without reading its content
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Fingerprint {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl From<&StorageMetadata> for Fingerprint {
+    fn from(metadata: &StorageMetadata) -> Self {
+        Fingerprint {
+            len: metadata.len,
+            modified: metadata.modified,
+        }
+    }
+}
+
+/// The remembered generation of a backing file verified with a checksum algorithm
+type Entries = HashMap<(PathBuf, ChecksumAlgorithm), (Fingerprint, Generation)>;
+
+///
+/// Remembers the validation results of backing files, so unchanged files are not verified again.
+///
+/// A backing file is considered unchanged while its size and modification time stay the same.
+/// The cache can be shared by many managed files and is cheap to clone.
+/// Modifications, which keep size and modification time, are not detected.
+///
+/// # Example
+///
+/// ```
+/// use multibufferedfile::{BufferedFileOptions, ValidationCache};
+///
+/// let cache = ValidationCache::new();
+/// let mut options = BufferedFileOptions::new();
+/// options.validation_cache(&cache);
+///
+/// let first = options.open("file.txt");
+/// // the backing files are only verified again if they have been modified
+/// let second = options.open("file.txt");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ValidationCache {
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl ValidationCache {
+    /// Creates an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes all remembered validation results
+    pub fn clear(&self) {
+        self.entries().clear();
+    }
+
+    fn entries(&self) -> MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Provides the remembered generation, if the backing file did not change since its validation
+    pub(crate) fn get(
+        &self,
+        file: &Path,
+        algorithm: ChecksumAlgorithm,
+        metadata: &StorageMetadata,
+    ) -> Option<Generation> {
+        let key = (file.to_path_buf(), algorithm);
+        match self.entries().get(&key) {
+            Some((fingerprint, generation)) if *fingerprint == Fingerprint::from(metadata) => {
+                Some(*generation)
+            }
+            _ => None,
+        }
+    }
+
+    /// Remembers the validation result of the backing file
+    pub(crate) fn insert(
+        &self,
+        file: &Path,
+        algorithm: ChecksumAlgorithm,
+        metadata: &StorageMetadata,
+        generation: Generation,
+    ) {
+        self.entries().insert(
+            (file.to_path_buf(), algorithm),
+            (Fingerprint::from(metadata), generation),
+        );
+    }
+
+    /// Forgets the validation results of the backing file
+    pub(crate) fn invalidate(&self, file: &Path) {
+        self.entries().retain(|(path, _), _| path != file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use crate::{tests::utils::TempDir, BufferedFile, BufferedFileOptions, ValidationCache};
+
+    #[test]
+    fn skips_unchanged_files() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let cache = ValidationCache::new();
+        let mut options = BufferedFileOptions::new();
+        options.validation_cache(&cache);
+
+        options
+            .create_with(&file, b"Hello World")
+            .expect("Can not create the file");
+        assert_eq!(options.open(&file).unwrap().latest_generation(), Some(1));
+        assert_eq!(cache.entries().len(), 1);
+
+        // a cached result is trusted, as long as size and modification time do not change
+        let slot = dir.path().join("data-file.txt.1");
+        let metadata = std::fs::metadata(&slot).unwrap();
+        let mut contents = std::fs::read(&slot).unwrap();
+        contents[1] = b'J';
+        std::fs::write(&slot, contents).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&slot)
+            .unwrap()
+            .set_modified(metadata.modified().unwrap())
+            .unwrap();
+        assert_eq!(options.open(&file).unwrap().latest_generation(), Some(1));
+
+        // but verified again once they do
+        std::fs::write(&slot, b"\x01corrupted").unwrap();
+        assert_eq!(options.open(&file).unwrap().latest_generation(), None);
+
+        let managed_file = options.open(&file).unwrap();
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        let mut contents = Vec::new();
+        options
+            .open(&file)
+            .unwrap()
+            .read()
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert_eq!(contents, b"Hello again");
+        assert!(BufferedFile::new(&file).unwrap().exists());
+    }
+}
@@ -0,0 +1,101 @@
+//! Computes the checksums of the backing files.
+//!
+//! With the `hardware-crc` feature the checksums of the supported algorithms are computed with the CRC and
+//! carry-less multiplication instructions of the CPU (SSE4.2/PCLMULQDQ, ARMv8 CRC/PMULL), which are detected at
+//! runtime. The results are identical to the table driven implementation of the `crc` crate.
+
+use crc::{Crc, Digest};
+
+/// The digest of a checksum algorithm, computed by the fastest available implementation
+#[derive(Clone)]
+pub(crate) enum ChecksumDigest {
+    Table(Digest<'static, u32>),
+    #[cfg(feature = "hardware-crc")]
+    Hardware(Box<crc_fast::Digest>),
+}
+
+impl ChecksumDigest {
+    pub(crate) fn new(crc: &'static Crc<u32>) -> Self {
+        #[cfg(feature = "hardware-crc")]
+        if let Some(algorithm) = hardware_algorithm(crc) {
+            return ChecksumDigest::Hardware(Box::new(crc_fast::Digest::new(algorithm)));
+        }
+        ChecksumDigest::Table(crc.digest())
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            ChecksumDigest::Table(digest) => digest.update(data),
+            #[cfg(feature = "hardware-crc")]
+            ChecksumDigest::Hardware(digest) => digest.update(data),
+        }
+    }
+
+    pub(crate) fn finalize(self) -> u32 {
+        match self {
+            ChecksumDigest::Table(digest) => digest.finalize(),
+            #[cfg(feature = "hardware-crc")]
+            ChecksumDigest::Hardware(digest) => digest.finalize() as u32,
+        }
+    }
+}
+
+/// Computes the checksum of `data` at once
+#[cfg(feature = "std")]
+pub(crate) fn checksum(crc: &'static Crc<u32>, data: &[u8]) -> u32 {
+    let mut digest = ChecksumDigest::new(crc);
+    digest.update(data);
+    digest.finalize()
+}
+
+/// Finds the accelerated implementation with the same parameters as `crc`
+#[cfg(feature = "hardware-crc")]
+fn hardware_algorithm(crc: &Crc<u32>) -> Option<crc_fast::CrcAlgorithm> {
+    use crc::{Algorithm, CRC_32_BZIP2, CRC_32_ISCSI, CRC_32_ISO_HDLC};
+    use crc_fast::CrcAlgorithm;
+
+    let parameters = |algorithm: &Algorithm<u32>| {
+        (
+            algorithm.poly,
+            algorithm.init,
+            algorithm.refin,
+            algorithm.refout,
+            algorithm.xorout,
+        )
+    };
+    [
+        (CRC_32_BZIP2, CrcAlgorithm::Crc32Bzip2),
+        (CRC_32_ISCSI, CrcAlgorithm::Crc32Iscsi),
+        (CRC_32_ISO_HDLC, CrcAlgorithm::Crc32IsoHdlc),
+    ]
+    .into_iter()
+    .find(|(algorithm, _)| parameters(algorithm) == parameters(crc.algorithm))
+    .map(|(_, hardware)| hardware)
+}
+
+#[cfg(all(test, feature = "hardware-crc"))]
+mod tests {
+    use crate::ChecksumAlgorithm;
+
+    use super::ChecksumDigest;
+
+    #[test]
+    fn hardware_checksums_match_tables() {
+        let data = (0..100_000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .collect::<Vec<_>>();
+        for algorithm in [
+            ChecksumAlgorithm::Crc32Bzip2,
+            ChecksumAlgorithm::Crc32IsoHdlc,
+            ChecksumAlgorithm::Crc32Iscsi,
+        ] {
+            let crc = algorithm.crc();
+            let mut digest = ChecksumDigest::new(crc);
+            assert!(matches!(digest, ChecksumDigest::Hardware(_)));
+            for piece in data.chunks(1000 + algorithm as usize) {
+                digest.update(piece);
+            }
+            assert_eq!(digest.finalize(), crc.checksum(&data), "{algorithm:?}");
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! Reports damaged backing files to the application, e.g. to count them in telemetry.
+
+use std::{fmt::Debug, path::Path, sync::Arc};
+
+use crate::FileCheckResult;
+
+///
+/// Describes a backing file, which failed its verification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CorruptionEvent<'a> {
+    /// The path of the damaged backing file
+    pub path: &'a Path,
+    /// The generation claimed by the header of the backing file, if it could be read at all.
+    /// It is not trustworthy, as the backing file is damaged.
+    pub generation: Option<u64>,
+    /// The kind of the failure, never `FileCheckResult::Good`
+    pub result: FileCheckResult,
+}
+
+/// The callback registered with `BufferedFileOptions::on_corruption`
+#[derive(Clone)]
+pub(crate) struct CorruptionCallback(pub(crate) Arc<dyn Fn(&CorruptionEvent) + Send + Sync>);
+
+impl Debug for CorruptionCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CorruptionCallback")
+    }
+}
+
+/// Emits the structured tracing event for a damaged backing file and invokes the registered callback
+pub(crate) fn report_corruption(callback: Option<&CorruptionCallback>, event: &CorruptionEvent) {
+    tracing::warn!(
+        path = %event.path.display(),
+        generation = event.generation,
+        kind = ?event.result,
+        "Detected a damaged backing file"
+    );
+    if let Some(callback) = callback {
+        (callback.0)(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{tests::utils::TempDir, BufferedFile, BufferedFileOptions, FileCheckResult};
+
+    #[test]
+    fn damaged_backing_files_are_reported() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFile::create_with(&file, b"Hello World").unwrap();
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        let slot = dir.path().join("data-file.txt.2");
+        let mut contents = std::fs::read(&slot).unwrap();
+        contents[3] ^= 0xff;
+        std::fs::write(&slot, contents).unwrap();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        let reopened = BufferedFileOptions::new()
+            .on_corruption(move |event| {
+                recorded.lock().unwrap().push((
+                    event.path.to_path_buf(),
+                    event.generation,
+                    event.result,
+                ))
+            })
+            .open(&file)
+            .unwrap();
+        assert_eq!(reopened.read_or_default().unwrap(), b"Hello World");
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].0, slot);
+        assert_eq!(events[0].1, Some(2));
+        assert!(matches!(
+            events[0].2,
+            FileCheckResult::ChecksumFailure { .. }
+        ));
+    }
+}
@@ -0,0 +1,360 @@
+//! Stores generations as the difference to the previous generation, see `BufferedFileOptions::delta_writes`.
+//!
+//! Generations written in delta mode are marked by `DELTA_KEY` in their user metadata. Their contents start with
+//! a tag: `0` is followed by the whole contents, `1` by the generation and the checksum (CRC-32/BZIP2) of the
+//! contents of the base generation, the length of the rebuilt contents and the operations rebuilding them.
+//! An operation either copies bytes of the base (`0`, the offset and the length) or inserts bytes (`1`, the length
+//! and the bytes). All numbers are stored in eight bytes in little endian.
+
+use std::{
+    io::{ErrorKind, Read, Seek},
+    path::Path,
+};
+
+use crate::{
+    checksum::checksum, BufferedFile, BufferedFileErrors, BufferedFileReader, Storage,
+    DEFAULT_CHECKSUM, DELTA_KEY,
+};
+
+/// The tag of contents stored as they are
+const FULL: u8 = 0;
+/// The tag of contents stored as the difference to a base generation
+const DIFFERENCE: u8 = 1;
+/// The length of the tag, the generation and checksum of the base and the length of the contents
+const DIFFERENCE_HEADER_LEN: usize = 21;
+/// The operation copying bytes of the base
+const COPY: u8 = 0;
+/// The operation inserting bytes
+const INSERT: u8 = 1;
+/// The number of bytes compared at once between the base and the changed contents
+const BLOCK_LEN: usize = 64;
+
+/// The contents of a generation written as they are, against which the next generation is compared
+#[derive(Debug)]
+pub(crate) struct DeltaBase {
+    pub(crate) generation: u64,
+    pub(crate) contents: Vec<u8>,
+}
+
+/// A step rebuilding the contents, referring to ranges of the base or of the contents respectively
+#[derive(Debug)]
+enum Operation {
+    Copy { offset: usize, len: usize },
+    Insert { start: usize, len: usize },
+}
+
+/// Appends `operation`, merging it with the last operation if they are contiguous
+fn push(operations: &mut Vec<Operation>, operation: Operation) {
+    match (operations.last_mut(), operation) {
+        (_, Operation::Copy { len: 0, .. } | Operation::Insert { len: 0, .. }) => {}
+        (
+            Some(Operation::Copy { offset, len }),
+            Operation::Copy {
+                offset: next,
+                len: more,
+            },
+        ) if *offset + *len == next => *len += more,
+        (
+            Some(Operation::Insert { start, len }),
+            Operation::Insert {
+                start: next,
+                len: more,
+            },
+        ) if *start + *len == next => *len += more,
+        (_, operation) => operations.push(operation),
+    }
+}
+
+/// Finds the operations rebuilding `contents` from `base`.
+/// The common prefix and suffix are copied, the rest is compared block by block at the same offset or at the offset
+/// relative to the end, which matches unchanged blocks in front of and behind an insertion or removal.
+fn diff(base: &[u8], contents: &[u8]) -> Vec<Operation> {
+    let prefix = base
+        .iter()
+        .zip(contents)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = base[prefix..]
+        .iter()
+        .rev()
+        .zip(contents[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let end = contents.len() - suffix;
+
+    let mut operations = Vec::new();
+    push(
+        &mut operations,
+        Operation::Copy {
+            offset: 0,
+            len: prefix,
+        },
+    );
+    let mut pos = prefix;
+    while pos < end {
+        let len = BLOCK_LEN.min(end - pos);
+        let block = &contents[pos..pos + len];
+        let operation = [Some(pos), (pos + base.len()).checked_sub(contents.len())]
+            .into_iter()
+            .flatten()
+            .find(|offset| base.get(*offset..*offset + len) == Some(block))
+            .map_or(Operation::Insert { start: pos, len }, |offset| {
+                Operation::Copy { offset, len }
+            });
+        push(&mut operations, operation);
+        pos += len;
+    }
+    push(
+        &mut operations,
+        Operation::Copy {
+            offset: base.len() - suffix,
+            len: suffix,
+        },
+    );
+    operations
+}
+
+/// Encodes `contents` as the difference to `base`, or as they are if that is not shorter
+pub(crate) fn encode(base: Option<&DeltaBase>, contents: &[u8]) -> Vec<u8> {
+    if let Some(base) = base {
+        let mut encoded = vec![DIFFERENCE];
+        encoded.extend_from_slice(&base.generation.to_le_bytes());
+        encoded.extend_from_slice(&checksum(&DEFAULT_CHECKSUM, &base.contents).to_le_bytes());
+        encoded.extend_from_slice(&(contents.len() as u64).to_le_bytes());
+        for operation in diff(&base.contents, contents) {
+            match operation {
+                Operation::Copy { offset, len } => {
+                    encoded.push(COPY);
+                    encoded.extend_from_slice(&(offset as u64).to_le_bytes());
+                    encoded.extend_from_slice(&(len as u64).to_le_bytes());
+                }
+                Operation::Insert { start, len } => {
+                    encoded.push(INSERT);
+                    encoded.extend_from_slice(&(len as u64).to_le_bytes());
+                    encoded.extend_from_slice(&contents[start..start + len]);
+                }
+            }
+            if encoded.len() > contents.len() {
+                break;
+            }
+        }
+        if encoded.len() <= contents.len() {
+            return encoded;
+        }
+    }
+    let mut encoded = Vec::with_capacity(contents.len() + 1);
+    encoded.push(FULL);
+    encoded.extend_from_slice(contents);
+    encoded
+}
+
+/// The error reported for encoded contents, which can not be decoded
+fn damaged() -> std::io::Error {
+    std::io::Error::new(
+        ErrorKind::InvalidData,
+        "The difference to the base generation is damaged",
+    )
+}
+
+/// Splits the number stored in the first eight bytes off `data`
+fn take_u64(data: &[u8]) -> std::io::Result<(u64, &[u8])> {
+    if data.len() < 8 {
+        return Err(damaged());
+    }
+    let (number, rest) = data.split_at(8);
+    Ok((
+        u64::from_le_bytes(number.try_into().expect("the number has 8 bytes")),
+        rest,
+    ))
+}
+
+/// Rebuilds contents of `len` bytes from `base` with the encoded operations
+fn apply(base: &[u8], mut operations: &[u8], len: u64) -> std::io::Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    while let Some((operation, rest)) = operations.split_first() {
+        let (first, rest) = take_u64(rest)?;
+        operations = match *operation {
+            COPY => {
+                let (count, rest) = take_u64(rest)?;
+                let copied = usize::try_from(first)
+                    .ok()
+                    .zip(usize::try_from(count).ok())
+                    .and_then(|(offset, count)| base.get(offset..offset.checked_add(count)?))
+                    .ok_or_else(damaged)?;
+                contents.extend_from_slice(copied);
+                rest
+            }
+            INSERT => {
+                let count = usize::try_from(first).map_err(|_| damaged())?;
+                if rest.len() < count {
+                    return Err(damaged());
+                }
+                let (inserted, rest) = rest.split_at(count);
+                contents.extend_from_slice(inserted);
+                rest
+            }
+            _ => return Err(damaged()),
+        };
+    }
+    if contents.len() as u64 != len {
+        return Err(damaged());
+    }
+    Ok(contents)
+}
+
+impl<S: Storage> BufferedFile<S> {
+    ///
+    /// The newest valid generation, if it has been written as it is and can serve as the base of the next one.
+    ///
+    /// Generations stored as a difference are never used as a base, so rebuilding contents needs one base only.
+    pub(crate) fn delta_base(&self) -> Option<DeltaBase> {
+        let (path, generation) = self.select_newest_valid().ok()?;
+        match self.full_contents(&path) {
+            Ok(Some(contents)) => Some(DeltaBase {
+                generation,
+                contents,
+            }),
+            Ok(None) => None,
+            Err(err) => {
+                tracing::warn!("Could not read the base of the next generation: {err}");
+                None
+            }
+        }
+    }
+
+    /// The contents of the backing file at `path`, if they are not stored as a difference
+    fn full_contents(&self, path: &Path) -> Result<Option<Vec<u8>>, BufferedFileErrors> {
+        let mut reader = self.open_encoded(path)?;
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents)?;
+        if reader.user_metadata().get(DELTA_KEY).is_none() {
+            return Ok(Some(contents));
+        }
+        match contents.first() {
+            Some(&FULL) => {
+                contents.remove(0);
+                Ok(Some(contents))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    ///
+    /// Replaces the encoded contents of a generation written in delta mode by the rebuilt ones.
+    ///
+    /// Fails with `ErrorKind::InvalidData`, if the base generation is not retained in a valid backing file anymore.
+    pub(crate) fn rebuild<T: Read + Seek>(
+        &self,
+        mut reader: BufferedFileReader<T>,
+    ) -> std::io::Result<BufferedFileReader<T>> {
+        let mut encoded = Vec::new();
+        reader.read_to_end(&mut encoded)?;
+        let contents = match encoded.split_first() {
+            Some((&FULL, contents)) => contents.to_vec(),
+            Some((&DIFFERENCE, _)) if encoded.len() >= DIFFERENCE_HEADER_LEN => {
+                let (generation, rest) = take_u64(&encoded[1..])?;
+                let (expected, rest) = rest.split_at(4);
+                let expected =
+                    u32::from_le_bytes(expected.try_into().expect("the checksum has 4 bytes"));
+                let (len, operations) = take_u64(rest)?;
+                let candidates = self
+                    .files()
+                    .iter()
+                    .filter(|(_, gen)| gen.number() == Some(generation))
+                    .map(|(path, _)| path.clone())
+                    .collect::<Vec<_>>();
+                let base = candidates
+                    .iter()
+                    .filter_map(|path| self.full_contents(path).ok().flatten())
+                    .find(|base| checksum(&DEFAULT_CHECKSUM, base) == expected)
+                    .ok_or_else(|| {
+                        std::io::Error::new(
+                            ErrorKind::InvalidData,
+                            format!("The base generation {generation} is not available anymore"),
+                        )
+                    })?;
+                apply(&base, operations, len)?
+            }
+            _ => return Err(damaged()),
+        };
+        Ok(reader.in_memory(contents))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use crate::{tests::utils::TempDir, BufferedFileErrors, BufferedFileOptions, FormatVersion};
+
+    use super::{apply, encode, take_u64, DeltaBase, DIFFERENCE, DIFFERENCE_HEADER_LEN, FULL};
+
+    #[test]
+    fn differences_rebuild_the_contents() {
+        let base: Vec<u8> = (0..1000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let base = DeltaBase {
+            generation: 3,
+            contents: base,
+        };
+        let mut changed = base.contents.clone();
+        changed[500] ^= 0xff;
+        changed.splice(100..110, *b"inserted");
+        let mut appended = base.contents.clone();
+        appended.extend_from_slice(b"tail");
+
+        for contents in [
+            changed,
+            appended,
+            base.contents.clone(),
+            base.contents[..10].to_vec(),
+        ] {
+            let encoded = encode(Some(&base), &contents);
+            assert!(encoded.len() <= contents.len() + 1);
+            let rebuilt = match encoded.split_first() {
+                Some((&FULL, rest)) => rest.to_vec(),
+                Some((&DIFFERENCE, rest)) => {
+                    assert!(encoded.len() < contents.len() / 3);
+                    let (len, operations) = take_u64(&rest[12..]).unwrap();
+                    apply(&base.contents, operations, len).unwrap()
+                }
+                _ => panic!("Unexpected tag"),
+            };
+            assert_eq!(rebuilt, contents);
+        }
+        assert_eq!(encode(None, b"Hello"), b"\x00Hello");
+        let encoded = encode(Some(&base), &base.contents);
+        assert!(apply(b"short", &encoded[DIFFERENCE_HEADER_LEN..], 1000).is_err());
+    }
+
+    #[test]
+    fn delta_writes_are_read_transparently() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let options = BufferedFileOptions::new()
+            .format_version(FormatVersion::V4)
+            .delta_writes(true)
+            .clone();
+        let managed_file = options.open(&file).expect("Can not find files");
+        let first = vec![7u8; 4096];
+        managed_file.update(|_| first.clone()).unwrap();
+        let mut second = first.clone();
+        second[2000] = 8;
+        managed_file.update(|_| second.clone()).unwrap();
+
+        let slot = |index| std::fs::metadata(dir.path().join(format!("data-file.txt.{index}")));
+        assert!(slot(2).unwrap().len() < 200);
+        assert_eq!(managed_file.read_or_default().unwrap(), second);
+        let reopened = options.open(&file).expect("Can not find files");
+        assert_eq!(reopened.read_or_default().unwrap(), second);
+
+        // the newest generation is a difference, so the next one is written as it is
+        managed_file.update(|_| b"third".to_vec()).unwrap();
+        assert!(slot(1).unwrap().len() < 200);
+        assert_eq!(managed_file.read_or_default().unwrap(), b"third");
+        // the base of the second generation has been overwritten
+        match managed_file.read_generation(2) {
+            Err(BufferedFileErrors::IoError(err)) => assert_eq!(err.kind(), ErrorKind::InvalidData),
+            other => panic!("Unexpected result {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{Advice, FsStorage, Storage, StorageFile, StorageMetadata};
+
+/// The alignment of buffers, offsets and lengths required for direct I/O
+const BLOCK_SIZE: usize = 4096;
+
+/// A single block of a file, aligned for direct I/O
+#[repr(C, align(4096))]
+struct Block([u8; BLOCK_SIZE]);
+
+///
+/// Stores the backing files on the local file system, bypassing the page cache of the operating system.
+///
+/// The backing files are opened with `O_DIRECT` on linux. The required alignment is handled internally,
+/// by reading and writing whole blocks through an aligned buffer, so readers and writers can be used as usual.
+/// On other platforms, or if the file system does not support direct I/O, the files are accessed through
+/// the page cache instead.
+///
+/// # Example
+///
+/// ```
+/// use multibufferedfile::{BufferedFileOptions, DirectStorage};
+///
+/// let file = BufferedFileOptions::new().open_in(DirectStorage, "file.txt");
+/// assert!(file.is_ok());
+/// ```
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct DirectStorage;
+
+impl DirectStorage {
+    /// Opens the file with direct I/O, falling back to buffered I/O if the file system rejects it
+    fn open_direct(path: &Path, options: &mut OpenOptions) -> std::io::Result<DirectFile> {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            let mut direct = options.clone();
+            direct.custom_flags(libc::O_DIRECT);
+            match direct.open(path) {
+                Ok(file) => return DirectFile::new(file),
+                Err(err) if err.raw_os_error() == Some(libc::EINVAL) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        DirectFile::new(options.open(path)?)
+    }
+}
+
+impl Storage for DirectStorage {
+    type File = DirectFile;
+    type Lock = <FsStorage as Storage>::Lock;
+
+    fn open(&self, path: &Path) -> std::io::Result<Self::File> {
+        Self::open_direct(path, OpenOptions::new().read(true))
+    }
+
+    fn open_write(&self, path: &Path) -> std::io::Result<Self::File> {
+        // partially written blocks are read back before they are written
+        Self::open_direct(path, OpenOptions::new().read(true).write(true))
+    }
+
+    /// The mode is only applied on unix, other platforms use their default permissions
+    fn create(&self, path: &Path, mode: Option<u32>) -> std::io::Result<Self::File> {
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        if let Some(mode) = mode {
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, mode);
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+        Self::open_direct(path, &mut options)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        FsStorage.rename(from, to)
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        FsStorage.remove(path)
+    }
+
+    fn metadata(&self, path: &Path) -> std::io::Result<StorageMetadata> {
+        FsStorage.metadata(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        FsStorage.create_dir_all(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> std::io::Result<()> {
+        FsStorage.remove_dir(path)
+    }
+
+    fn lock(&self, path: &Path) -> std::io::Result<Self::Lock> {
+        FsStorage.lock(path)
+    }
+
+    fn sync_dir(&self, path: &Path) -> std::io::Result<()> {
+        FsStorage.sync_dir(path)
+    }
+
+    fn set_mode(&self, path: &Path, mode: u32) -> std::io::Result<()> {
+        FsStorage.set_mode(path, mode)
+    }
+
+    fn set_owner(&self, path: &Path, owner: (u32, u32)) -> std::io::Result<()> {
+        FsStorage.set_owner(path, owner)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        FsStorage.copy(from, to)
+    }
+}
+
+///
+/// An opened file of a `DirectStorage`.
+///
+/// Caches the block at the current position. Modifications are written back as whole blocks,
+/// when another block is accessed, on `flush` or when the file is dropped.
+pub struct DirectFile {
+    file: File,
+    block: Box<Block>,
+    block_index: Option<u64>,
+    dirty: bool,
+    len: u64,
+    pos: u64,
+}
+
+impl std::fmt::Debug for DirectFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DirectFile")
+            .field("file", &self.file)
+            .field("block_index", &self.block_index)
+            .field("dirty", &self.dirty)
+            .field("len", &self.len)
+            .field("pos", &self.pos)
+            .finish()
+    }
+}
+
+impl DirectFile {
+    fn new(file: File) -> std::io::Result<Self> {
+        let len = file.metadata()?.len();
+        Ok(DirectFile {
+            file,
+            block: Box::new(Block([0; BLOCK_SIZE])),
+            block_index: None,
+            dirty: false,
+            len,
+            pos: 0,
+        })
+    }
+
+    /// Makes the block containing the current position available in the buffer
+    fn load(&mut self) -> std::io::Result<usize> {
+        let index = self.pos / BLOCK_SIZE as u64;
+        if self.block_index != Some(index) {
+            self.write_back()?;
+            let start = index * BLOCK_SIZE as u64;
+            let mut read = 0;
+            // blocks beyond the end of the file need not be read
+            while start < self.len && read < BLOCK_SIZE {
+                self.file.seek(SeekFrom::Start(start + read as u64))?;
+                match self.file.read(&mut self.block.0[read..]) {
+                    Ok(0) => break,
+                    Ok(count) => read += count,
+                    Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            self.block.0[read..].fill(0);
+            self.block_index = Some(index);
+        }
+        Ok((self.pos % BLOCK_SIZE as u64) as usize)
+    }
+
+    /// Writes the modified block as a whole and cuts off the padding behind the end of the file
+    fn write_back(&mut self) -> std::io::Result<()> {
+        if let (true, Some(index)) = (self.dirty, self.block_index) {
+            let start = index * BLOCK_SIZE as u64;
+            self.file.seek(SeekFrom::Start(start))?;
+            self.file.write_all(&self.block.0)?;
+            if start + BLOCK_SIZE as u64 > self.len {
+                self.file.set_len(self.len)?;
+            }
+            self.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+impl Read for DirectFile {
+    /// Fills the buffer across block boundaries, like a read of a regular file
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut read = 0;
+        while read < buf.len() && self.pos < self.len {
+            let offset = self.load()?;
+            let remaining = usize::try_from(self.len - self.pos).unwrap_or(usize::MAX);
+            let count = (buf.len() - read).min(BLOCK_SIZE - offset).min(remaining);
+            buf[read..read + count].copy_from_slice(&self.block.0[offset..offset + count]);
+            self.pos += count as u64;
+            read += count;
+        }
+        Ok(read)
+    }
+}
+
+impl Write for DirectFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let offset = self.load()?;
+        let count = buf.len().min(BLOCK_SIZE - offset);
+        self.block.0[offset..offset + count].copy_from_slice(&buf[..count]);
+        self.dirty = true;
+        self.pos += count as u64;
+        self.len = self.len.max(self.pos);
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write_back()
+    }
+}
+
+impl Seek for DirectFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(start) => Some(start),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+        };
+        self.pos = new_pos.ok_or_else(|| {
+            std::io::Error::new(ErrorKind::InvalidInput, "seek before the start of the file")
+        })?;
+        Ok(self.pos)
+    }
+}
+
+impl StorageFile for DirectFile {
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        self.write_back()?;
+        self.file.sync_all()
+    }
+
+    fn allocate(&mut self, len: u64) -> std::io::Result<()> {
+        self.file.allocate(len)
+    }
+
+    fn advise(&mut self, advice: Advice) -> std::io::Result<()> {
+        self.file.advise(advice)
+    }
+}
+
+impl Drop for DirectFile {
+    fn drop(&mut self) {
+        if let Err(err) = self.write_back() {
+            tracing::error!("Could not write back the last block: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tests::utils::TempDir, BufferedFileOptions, DirectStorage};
+
+    #[test]
+    fn round_trip_with_unaligned_contents() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .open_in(DirectStorage, &file)
+            .expect("Can not find files");
+        let first = (0..6_000u32).map(|i| i as u8).collect::<Vec<_>>();
+        managed_file.update(|_| first.clone()).unwrap();
+        managed_file.update(|_| b"Hello World".to_vec()).unwrap();
+        assert_eq!(
+            std::fs::metadata(dir.path().join("data-file.txt.1"))
+                .unwrap()
+                .len(),
+            1 + 6_000 + 4
+        );
+
+        let reopened = BufferedFileOptions::new()
+            .open_in(DirectStorage, &file)
+            .unwrap();
+        assert_eq!(reopened.read_or_default().unwrap(), b"Hello World");
+        assert_eq!(reopened.rollback().unwrap(), 3);
+        assert_eq!(reopened.read_or_default().unwrap(), first);
+    }
+}
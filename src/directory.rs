@@ -0,0 +1,240 @@
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::{BufferedFile, BufferedFileErrors, BufferedFileOptions};
+
+///
+/// Describes backing files, which do not fit into a complete managed file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlotDiagnostic {
+    /// A backing file with a number outside of the configured buffer count
+    Orphan {
+        /// The path of the backing file
+        path: PathBuf,
+        /// The number of the backing file
+        slot: u8,
+    },
+    /// A managed file, where some of the backing files do not exist
+    Partial {
+        /// The path of the managed file
+        path: PathBuf,
+        /// The paths of the missing backing files
+        missing: Vec<PathBuf>,
+    },
+}
+
+///
+/// Groups the backing files in a directory back into the managed files they belong to.
+///
+/// Only backing files named by a suffix pattern can be grouped, custom naming strategies are not supported.
+///
+/// # Example
+///
+/// ```
+/// use multibufferedfile::{BufferedDirectory, BufferedFile};
+/// # let dir = std::env::temp_dir().join("multibufferedfile-directory-doc");
+/// # std::fs::create_dir_all(&dir).unwrap();
+///
+/// BufferedFile::create_with(dir.join("first.txt"), b"first").unwrap();
+/// BufferedFile::create_with(dir.join("second.txt"), b"second").unwrap();
+///
+/// let directory = BufferedDirectory::scan(&dir).unwrap();
+/// assert_eq!(directory.paths(), [dir.join("first.txt"), dir.join("second.txt")]);
+/// for file in directory.files() {
+///     assert!(file.unwrap().exists());
+/// }
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct BufferedDirectory {
+    options: BufferedFileOptions,
+    paths: Vec<PathBuf>,
+    diagnostics: Vec<SlotDiagnostic>,
+}
+
+impl BufferedDirectory {
+    ///
+    /// Scans the directory for backing files named with the default options.
+    pub fn scan(dir: impl AsRef<Path>) -> Result<Self, BufferedFileErrors> {
+        Self::scan_with(dir, &BufferedFileOptions::new())
+    }
+
+    ///
+    /// Scans the directory for backing files named according to `options`.
+    ///
+    /// If the options contain a slot directory, the backing files are searched there,
+    /// while the managed files are reported relative to `dir`.
+    pub fn scan_with(
+        dir: impl AsRef<Path>,
+        options: &BufferedFileOptions,
+    ) -> Result<Self, BufferedFileErrors> {
+        if options.naming.is_some() {
+            return Err(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "backing files of a custom naming strategy can not be grouped",
+            )
+            .into());
+        }
+        let (prefix, suffix) = options.suffix_pattern.split_once("{}").ok_or_else(|| {
+            BufferedFileErrors::InvalidSuffixPattern(options.suffix_pattern.clone())
+        })?;
+
+        let dir = dir.as_ref();
+        let slot_dir = match &options.slot_dir {
+            Some(slot_dir) => dir.join(slot_dir),
+            None => dir.to_path_buf(),
+        };
+        let entries = match std::fs::read_dir(&slot_dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound && options.slot_dir.is_some() => {
+                return Ok(BufferedDirectory {
+                    options: options.clone(),
+                    paths: Vec::new(),
+                    diagnostics: Vec::new(),
+                })
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut groups = BTreeMap::<String, Vec<u8>>::new();
+        let mut diagnostics = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let name = entry.file_name();
+            let (stem, slot) = match name
+                .to_str()
+                .and_then(|name| parse_slot(name, prefix, suffix))
+            {
+                Some(parsed) => parsed,
+                None => continue,
+            };
+
+            if (1..=options.buffer_count).contains(&slot) {
+                groups.entry(stem.to_string()).or_default().push(slot);
+            } else {
+                diagnostics.push(SlotDiagnostic::Orphan {
+                    path: entry.path(),
+                    slot,
+                });
+            }
+        }
+
+        let mut paths = Vec::with_capacity(groups.len());
+        for (stem, slots) in groups {
+            let path = dir.join(stem);
+            if slots.len() < options.buffer_count.into() {
+                let missing = (1..=options.buffer_count)
+                    .filter(|slot| !slots.contains(slot))
+                    .map(|slot| options.slot_path(&path, slot))
+                    .collect();
+                diagnostics.push(SlotDiagnostic::Partial {
+                    path: path.clone(),
+                    missing,
+                });
+            }
+            paths.push(path);
+        }
+
+        Ok(BufferedDirectory {
+            options: options.clone(),
+            paths,
+            diagnostics,
+        })
+    }
+
+    /// The paths of the managed files found in the directory, sorted by name
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Opens every managed file found in the directory
+    pub fn files(&self) -> impl Iterator<Item = Result<BufferedFile, BufferedFileErrors>> + '_ {
+        self.paths.iter().map(|path| self.options.open(path))
+    }
+
+    /// The backing files, which do not fit into a complete managed file
+    pub fn diagnostics(&self) -> &[SlotDiagnostic] {
+        &self.diagnostics
+    }
+}
+
+/// Splits the name of a backing file into the name of the managed file and the number of the backing file
+fn parse_slot<'a>(name: &'a str, prefix: &str, suffix: &str) -> Option<(&'a str, u8)> {
+    let name = name.strip_suffix(suffix)?;
+    let digits_start = name.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let (rest, digits) = name.split_at(digits_start);
+    let stem = rest.strip_suffix(prefix)?;
+    if stem.is_empty() || digits.is_empty() || digits.starts_with('0') {
+        return None;
+    }
+    Some((stem, digits.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tests::utils::TempDir, BufferedDirectory, BufferedFileOptions, SlotDiagnostic};
+
+    use super::parse_slot;
+
+    #[test]
+    fn parses_slot_names() {
+        assert_eq!(parse_slot("file.txt.1", ".", ""), Some(("file.txt", 1)));
+        assert_eq!(parse_slot("file.txt.12", ".", ""), Some(("file.txt", 12)));
+        assert_eq!(parse_slot("file~3.bak", "~", ".bak"), Some(("file", 3)));
+        assert_eq!(parse_slot("file.txt", ".", ""), None);
+        assert_eq!(parse_slot("file.txt.lock", ".", ""), None);
+        assert_eq!(parse_slot(".1", ".", ""), None);
+        assert_eq!(parse_slot("file.01", ".", ""), None);
+        assert_eq!(parse_slot("file.300", ".", ""), None);
+    }
+
+    #[test]
+    fn groups_backing_files() {
+        let dir = TempDir::new();
+        let mut options = BufferedFileOptions::new();
+        options.slot_dir("slots");
+        let complete = options
+            .create_with(dir.path().join("complete.txt"), b"first")
+            .unwrap();
+        complete.update(|_| b"second".to_vec()).unwrap();
+        options
+            .create_with(dir.path().join("partial.txt"), b"first")
+            .unwrap();
+        std::fs::write(dir.path().join("slots").join("orphan.txt.3"), b"").unwrap();
+        std::fs::write(dir.path().join("unrelated.txt"), b"").unwrap();
+
+        let directory = BufferedDirectory::scan_with(dir.path(), &options).unwrap();
+        assert_eq!(
+            directory.paths(),
+            [
+                dir.path().join("complete.txt"),
+                dir.path().join("partial.txt")
+            ]
+        );
+        assert_eq!(
+            directory.diagnostics(),
+            [
+                SlotDiagnostic::Orphan {
+                    path: dir.path().join("slots").join("orphan.txt.3"),
+                    slot: 3
+                },
+                SlotDiagnostic::Partial {
+                    path: dir.path().join("partial.txt"),
+                    missing: vec![dir.path().join("slots").join("partial.txt.2")]
+                }
+            ]
+        );
+
+        let contents = directory
+            .files()
+            .map(|file| file.unwrap().read_or_default().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(contents, [b"second".to_vec(), b"first".to_vec()]);
+    }
+}
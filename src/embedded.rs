@@ -0,0 +1,144 @@
+//! Implements the protocol of the backing files on top of the `embedded-io` traits.
+//!
+//! Only `core` is used in here, so the backing files can be verified and written on targets without `std`,
+//! e.g. with a flash file system like littlefs, once the default `std` feature is disabled. Selecting the backing
+//! file to read or to overwrite is left to `select_newest` and `select_target`. The backing files are read and
+//! written in `FormatVersion::V0`.
+
+use crc::Crc;
+use embedded_io::{ErrorType, Read, ReadExactError, Write};
+
+use crate::{checksum::ChecksumDigest, FileCheckResult, SlotVerifier, HEADER_LEN, TRAILER_LEN};
+
+///
+/// Verifies a whole backing file read from `reader`.
+pub fn verify_slot<R: Read>(
+    reader: &mut R,
+    crc: &'static Crc<u32>,
+) -> Result<FileCheckResult, R::Error> {
+    let mut verifier = SlotVerifier::new(crc);
+    let mut buf = [0u8; 256];
+    loop {
+        match reader.read(&mut buf)? {
+            0 => return Ok(verifier.finish()),
+            read => verifier.update(&buf[..read]),
+        }
+    }
+}
+
+///
+/// Reads the content of a backing file, which has been verified with `verify_slot`.
+pub struct EmbeddedReader<R: Read> {
+    inner: R,
+    remaining: u64,
+    generation: u8,
+}
+
+impl<R: Read> EmbeddedReader<R> {
+    /// Reads the generation from the start of a backing file of `len` bytes
+    pub fn new(mut inner: R, len: u64) -> Result<Self, ReadExactError<R::Error>> {
+        let mut generation = [0u8; 1];
+        inner.read_exact(&mut generation)?;
+        Ok(EmbeddedReader {
+            inner,
+            remaining: len.saturating_sub(HEADER_LEN + TRAILER_LEN),
+            generation: generation[0],
+        })
+    }
+
+    /// The generation of the backing file
+    pub fn generation(&self) -> u8 {
+        self.generation
+    }
+}
+
+impl<R: Read> ErrorType for EmbeddedReader<R> {
+    type Error = R::Error;
+}
+
+impl<R: Read> Read for EmbeddedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let limit = usize::try_from(self.remaining).unwrap_or(usize::MAX);
+        let limit = buf.len().min(limit);
+        let read = self.inner.read(&mut buf[..limit])?;
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+///
+/// Writes a backing file, generating the checksum while writing the contents.
+///
+/// In contrast to `BufferedFileWriter` the checksum is only written by `finish`,
+/// because errors can not be reported when dropping the writer.
+pub struct EmbeddedWriter<W: Write> {
+    inner: W,
+    digest: ChecksumDigest,
+}
+
+impl<W: Write> EmbeddedWriter<W> {
+    /// Starts a backing file holding `generation` at the current position of `inner`
+    pub fn new(mut inner: W, generation: u8, crc: &'static Crc<u32>) -> Result<Self, W::Error> {
+        inner.write_all(&[generation])?;
+        Ok(EmbeddedWriter {
+            inner,
+            digest: ChecksumDigest::new(crc),
+        })
+    }
+
+    /// Writes the checksum and flushes the backing file
+    pub fn finish(mut self) -> Result<W, W::Error> {
+        let checksum = self.digest.finalize();
+        self.inner.write_all(&checksum.to_le_bytes())?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> ErrorType for EmbeddedWriter<W> {
+    type Error = W::Error;
+}
+
+impl<W: Write> Write for EmbeddedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let count = self.inner.write(buf)?;
+        self.digest.update(&buf[..count]);
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_io::{Read, Write};
+
+    use crate::{
+        select_target, verify_slot, EmbeddedReader, EmbeddedWriter, FileCheckResult,
+        DEFAULT_CHECKSUM,
+    };
+
+    #[test]
+    fn round_trip_without_std_io() {
+        let mut flash = [0u8; 32];
+        let (_, generation) = select_target(&[Some(6), None]).unwrap();
+
+        let mut writer =
+            EmbeddedWriter::new(&mut flash[..], generation, &DEFAULT_CHECKSUM).unwrap();
+        writer.write_all(b"Hello World").unwrap();
+        let remaining = writer.finish().unwrap().len();
+        let len = flash.len() - remaining;
+
+        let result = verify_slot(&mut &flash[..len], &DEFAULT_CHECKSUM).unwrap();
+        assert_eq!(result, FileCheckResult::Good { generation: 7 });
+
+        let mut reader = EmbeddedReader::new(&flash[..len], len as u64).unwrap();
+        let mut contents = [0u8; 32];
+        let read = reader.read(&mut contents).unwrap();
+        assert_eq!(reader.generation(), 7);
+        assert_eq!(&contents[..read], b"Hello World");
+        assert_eq!(reader.read(&mut contents).unwrap(), 0);
+    }
+}
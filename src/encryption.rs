@@ -0,0 +1,314 @@
+//! Encrypts the contents of the backing files with XChaCha20-Poly1305.
+//!
+//! The header with the generation stays in the clear, so the backing files can be selected and rolled back
+//! without the key. It is followed by a random nonce prefix and the contents encrypted in chunks of
+//! `CHUNK_LEN` bytes, each followed by its authentication tag. The chunks are sealed with the STREAM
+//! construction: the nonce of a chunk consists of the prefix, the index of the chunk in four bytes in
+//! big endian and a flag marking the last chunk, so chunks can neither be reordered nor dropped from the end.
+//! Readers decrypt and authenticate one chunk at a time, writers hold at most one chunk in memory.
+//! The checksums cover the encrypted form, so damaged backing files are still detected without the key,
+//! while the tags detect any modification made without knowledge of the key.
+
+use std::{
+    io::{ErrorKind, Read, Seek},
+    sync::Arc,
+};
+
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+
+use crate::BufferedFileReader;
+
+/// The length of the random prefix of the nonces stored in front of the encrypted contents
+const PREFIX_LEN: usize = 19;
+
+/// The length of the authentication tag following every chunk
+const TAG_LEN: u64 = 16;
+
+/// The number of bytes of contents encrypted into one chunk
+pub(crate) const CHUNK_LEN: u64 = 64 * 1024;
+
+/// The number of bytes added to `len` bytes of contents by the encryption: the nonce prefix and a tag per chunk
+pub(crate) fn overhead(len: u64) -> u64 {
+    PREFIX_LEN as u64 + len.div_ceil(CHUNK_LEN).max(1) * TAG_LEN
+}
+
+/// The secret key of the encryption, which is not revealed by `Debug`
+#[derive(Clone)]
+pub(crate) struct EncryptionKey(Arc<XChaCha20Poly1305>);
+
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+impl EncryptionKey {
+    pub(crate) fn new(key: &[u8; 32]) -> Self {
+        EncryptionKey(Arc::new(XChaCha20Poly1305::new(key.into())))
+    }
+
+    /// Starts encrypting new contents with a random nonce prefix
+    pub(crate) fn encryptor(&self) -> Encryptor {
+        let mut prefix = [0u8; PREFIX_LEN];
+        OsRng.fill_bytes(&mut prefix);
+        Encryptor {
+            key: self.clone(),
+            prefix,
+            index: 0,
+            started: false,
+            chunk: Vec::new(),
+        }
+    }
+
+    /// Reads the nonce prefix at the start of the `reader` and decrypts the following contents while they are read
+    pub(crate) fn decrypt_reader<T: Read + Seek>(
+        &self,
+        mut reader: BufferedFileReader<T>,
+    ) -> std::io::Result<BufferedFileReader<T>> {
+        let mut prefix = [0u8; PREFIX_LEN];
+        reader
+            .read_exact(&mut prefix)
+            .map_err(|err| match err.kind() {
+                ErrorKind::UnexpectedEof => undecryptable(),
+                _ => err,
+            })?;
+        let decryptor = Decryptor::new(self.clone(), prefix, reader.len())?;
+        reader.decrypted(decryptor)
+    }
+
+    /// Builds the nonce of the chunk at `index` following the STREAM construction
+    fn nonce(prefix: &[u8; PREFIX_LEN], index: u32, last: bool) -> XNonce {
+        let mut nonce = XNonce::default();
+        nonce[..PREFIX_LEN].copy_from_slice(prefix);
+        nonce[PREFIX_LEN..PREFIX_LEN + 4].copy_from_slice(&index.to_be_bytes());
+        nonce[PREFIX_LEN + 4] = u8::from(last);
+        nonce
+    }
+}
+
+/// The error for contents, which have not been encrypted with the key or have been modified
+fn undecryptable() -> std::io::Error {
+    std::io::Error::new(
+        ErrorKind::InvalidData,
+        "The contents could not be decrypted",
+    )
+}
+
+/// Encrypts the contents chunk by chunk while they are written
+pub(crate) struct Encryptor {
+    key: EncryptionKey,
+    prefix: [u8; PREFIX_LEN],
+    index: u32,
+    /// Whether the nonce prefix has been handed out already
+    started: bool,
+    /// The contents of the current chunk, which is sealed once it is known whether it is the last one
+    chunk: Vec<u8>,
+}
+
+impl std::fmt::Debug for Encryptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Encryptor")
+            .field("index", &self.index)
+            .finish()
+    }
+}
+
+impl Encryptor {
+    /// Takes the contents in `buf`, returning the encrypted bytes, which are ready to be stored
+    pub(crate) fn update(&mut self, mut buf: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut sealed = self.take_prefix();
+        while !buf.is_empty() {
+            // a full chunk is only sealed, once more contents follow it
+            if self.chunk.len() as u64 == CHUNK_LEN {
+                sealed.extend_from_slice(&self.seal(false)?);
+            }
+            let count = buf.len().min(CHUNK_LEN as usize - self.chunk.len());
+            self.chunk.extend_from_slice(&buf[..count]);
+            buf = &buf[count..];
+        }
+        Ok(sealed)
+    }
+
+    /// Seals the last chunk, returning the remaining encrypted bytes
+    pub(crate) fn finish(mut self) -> std::io::Result<Vec<u8>> {
+        let mut sealed = self.take_prefix();
+        sealed.extend_from_slice(&self.seal(true)?);
+        Ok(sealed)
+    }
+
+    /// The nonce prefix, if it has not been handed out yet
+    fn take_prefix(&mut self) -> Vec<u8> {
+        if std::mem::replace(&mut self.started, true) {
+            return Vec::new();
+        }
+        self.prefix.to_vec()
+    }
+
+    /// Encrypts the current chunk and starts the next one
+    fn seal(&mut self, last: bool) -> std::io::Result<Vec<u8>> {
+        let nonce = EncryptionKey::nonce(&self.prefix, self.index, last);
+        let sealed = self
+            .key
+            .0
+            .encrypt(&nonce, self.chunk.as_slice())
+            .map_err(|_| std::io::Error::other("The contents could not be encrypted"))?;
+        self.index = self
+            .index
+            .checked_add(1)
+            .ok_or_else(|| std::io::Error::other("The contents are too large to be encrypted"))?;
+        self.chunk.clear();
+        Ok(sealed)
+    }
+}
+
+/// Decrypts the chunks of stored contents independently of each other
+#[derive(Clone)]
+pub(crate) struct Decryptor {
+    key: EncryptionKey,
+    prefix: [u8; PREFIX_LEN],
+    /// The number of bytes of the chunks following the nonce prefix
+    sealed_len: u64,
+    chunks: u64,
+}
+
+impl std::fmt::Debug for Decryptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Decryptor")
+            .field("sealed_len", &self.sealed_len)
+            .field("chunks", &self.chunks)
+            .finish()
+    }
+}
+
+impl Decryptor {
+    ///
+    /// Takes the layout of the chunks from the length of the stored contents including the nonce prefix.
+    ///
+    /// Fails with `ErrorKind::InvalidData`, if the last chunk is too short to hold its tag.
+    fn new(key: EncryptionKey, prefix: [u8; PREFIX_LEN], stored_len: u64) -> std::io::Result<Self> {
+        let sealed_len = stored_len.saturating_sub(PREFIX_LEN as u64);
+        let chunks = sealed_len.div_ceil(CHUNK_LEN + TAG_LEN).max(1);
+        let last = sealed_len.saturating_sub((chunks - 1) * (CHUNK_LEN + TAG_LEN));
+        if last < TAG_LEN || chunks > u64::from(u32::MAX) + 1 {
+            return Err(undecryptable());
+        }
+        Ok(Decryptor {
+            key,
+            prefix,
+            sealed_len,
+            chunks,
+        })
+    }
+
+    /// The length of the decrypted contents
+    pub(crate) fn len(&self) -> u64 {
+        self.sealed_len - self.chunks * TAG_LEN
+    }
+
+    /// The position of the chunk at `index` in the stored contents and its length including the tag
+    pub(crate) fn chunk_range(&self, index: u64) -> (u64, usize) {
+        let start = index * (CHUNK_LEN + TAG_LEN);
+        let len = (CHUNK_LEN + TAG_LEN).min(self.sealed_len - start);
+        (PREFIX_LEN as u64 + start, len as usize)
+    }
+
+    /// Decrypts the chunk at `index`, failing with `ErrorKind::InvalidData` if it can not be authenticated
+    pub(crate) fn decrypt_chunk(&self, index: u64, sealed: &[u8]) -> std::io::Result<Vec<u8>> {
+        let position = u32::try_from(index).map_err(|_| undecryptable())?;
+        let nonce = EncryptionKey::nonce(&self.prefix, position, index + 1 == self.chunks);
+        self.key
+            .0
+            .decrypt(&nonce, sealed)
+            .map_err(|_| undecryptable())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use super::{Decryptor, EncryptionKey, CHUNK_LEN, PREFIX_LEN};
+
+    /// Encrypts `contents` handing them to the encryptor in pieces of `piece` bytes
+    fn seal(key: &EncryptionKey, contents: &[u8], piece: usize) -> Vec<u8> {
+        let mut encryptor = key.encryptor();
+        let mut sealed = Vec::new();
+        for part in contents.chunks(piece) {
+            sealed.extend_from_slice(&encryptor.update(part).unwrap());
+        }
+        sealed.extend_from_slice(&encryptor.finish().unwrap());
+        sealed
+    }
+
+    /// Decrypts all chunks of `sealed`
+    fn open(key: &EncryptionKey, sealed: &[u8]) -> std::io::Result<Vec<u8>> {
+        let prefix = sealed[..PREFIX_LEN].try_into().unwrap();
+        let decryptor = Decryptor::new(key.clone(), prefix, sealed.len() as u64)?;
+        let mut contents = Vec::new();
+        for index in 0..decryptor.chunks {
+            let (start, len) = decryptor.chunk_range(index);
+            let start = start as usize;
+            contents.extend(decryptor.decrypt_chunk(index, &sealed[start..start + len])?);
+        }
+        assert_eq!(contents.len() as u64, decryptor.len());
+        Ok(contents)
+    }
+
+    #[test]
+    fn contents_survive_a_round_trip() {
+        let key = EncryptionKey::new(&[7; 32]);
+        let chunk = CHUNK_LEN as usize;
+        for len in [0, 1, chunk - 1, chunk, chunk + 1, 3 * chunk] {
+            let contents = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+            for piece in [1000, chunk, 2 * chunk + 3] {
+                let sealed = seal(&key, &contents, piece);
+                assert_eq!(
+                    sealed.len() as u64,
+                    len as u64 + super::overhead(len as u64)
+                );
+                assert_eq!(open(&key, &sealed).unwrap(), contents, "{len} in {piece}");
+            }
+        }
+    }
+
+    #[test]
+    fn other_keys_can_not_decrypt() {
+        let sealed = seal(&EncryptionKey::new(&[7; 32]), b"Hello World", 4);
+        let err = open(&EncryptionKey::new(&[8; 32]), &sealed).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn tampered_chunks_are_detected() {
+        let key = EncryptionKey::new(&[7; 32]);
+        let contents = vec![5u8; 2 * CHUNK_LEN as usize + 10];
+        let sealed = seal(&key, &contents, 4096);
+
+        let mut tag = sealed.clone();
+        *tag.last_mut().unwrap() ^= 1;
+        assert_eq!(open(&key, &tag).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        // dropping the last chunk leaves a chunk, which has not been sealed as the last one
+        let truncated = &sealed[..PREFIX_LEN + 2 * (CHUNK_LEN as usize + 16)];
+        assert_eq!(
+            open(&key, truncated).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+
+        let mut swapped = sealed.clone();
+        let len = CHUNK_LEN as usize + 16;
+        let (first, second) = swapped[PREFIX_LEN..].split_at_mut(len);
+        first.swap_with_slice(&mut second[..len]);
+        assert_eq!(
+            open(&key, &swapped).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+
+        // the last chunk can not even hold its tag
+        let prefix = sealed[..PREFIX_LEN].try_into().unwrap();
+        assert!(Decryptor::new(key, prefix, PREFIX_LEN as u64 + 15).is_err());
+    }
+}
@@ -6,13 +6,14 @@ use std::ptr;
 use std::{ffi::CStr, os::raw::c_char, path::PathBuf};
 use tracing::warn;
 
-use crate::{BufferedFile, BufferedFileErrors, BufferedFileReader, BufferedFileWriter};
+use crate::{BufferedFile, BufferedFileErrors, BufferedFileReader, BufferedFileWriter, Durability};
 
 #[derive(Debug)]
 pub enum Error {
     NonUtf8Path,
     InvalidPointer,
     BufferTooLong,
+    InvalidDurability(u32),
     BufferedFileErrors(BufferedFileErrors),
 }
 
@@ -27,7 +28,7 @@ pub enum ErrorCode {
 }
 
 thread_local! {
-    static LAST_ERROR: RefCell<Option<Error>>  = RefCell::new(None);
+    static LAST_ERROR: RefCell<Option<Error>>  = const { RefCell::new(None) };
 }
 
 pub type FileReader = *mut BufferedFileReader<std::fs::File>;
@@ -49,6 +50,20 @@ impl From<&std::io::Error> for ErrorCode {
     }
 }
 
+/// Converts a path handed over from C.
+/// On unix every byte sequence is accepted, elsewhere the path has to be valid UTF-8.
+fn path_from_c(path: &CStr) -> Option<PathBuf> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Some(PathBuf::from(std::ffi::OsStr::from_bytes(path.to_bytes())))
+    }
+    #[cfg(not(unix))]
+    {
+        path.to_str().ok().map(PathBuf::from)
+    }
+}
+
 ///
 /// Opens the latest valid version of the specified file for readonly access.
 ///
@@ -67,16 +82,14 @@ impl From<&std::io::Error> for ErrorCode {
 ///
 #[no_mangle]
 pub extern "C" fn bufferedfile_open_read(path: *const c_char) -> FileReader {
-    let path = unsafe { CStr::from_ptr(path) };
-    let path = match path.to_str() {
-        Ok(path) => path,
-        Err(_err) => {
+    let path = match path_from_c(unsafe { CStr::from_ptr(path) }) {
+        Some(path) => path,
+        None => {
             // TODO Error handling in ffi
             LAST_ERROR.with(|x| *x.borrow_mut() = Some(Error::NonUtf8Path));
             return ptr::null_mut();
         }
     };
-    let path = PathBuf::from(path);
 
     let file = match BufferedFile::new(&path) {
         Ok(file) => file,
@@ -109,6 +122,7 @@ pub extern "C" fn bufferedfile_open_read(path: *const c_char) -> FileReader {
 ///
 /// # remarks
 /// The file will be buffered on disk, so the opened file will either be the invalid file or the oldest valid file.
+/// The contents are not explicitly persisted on close, use `bufferedfile_open_write_durable` to select a `Durability`.
 ///
 /// # Returnvalue
 /// this function returns a pointer to a FileWriter struct in memory. This pointer must be used to write data to the file and is no file descriptor.
@@ -118,18 +132,46 @@ pub extern "C" fn bufferedfile_open_read(path: *const c_char) -> FileReader {
 ///
 #[no_mangle]
 pub extern "C" fn bufferedfile_open_write(path: *const c_char) -> FileWriter {
-    let path = unsafe { CStr::from_ptr(path) };
-    let path = match path.to_str() {
-        Ok(path) => path,
-        Err(_err) => {
+    bufferedfile_open_write_durable(path, Durability::None as u32)
+}
+
+///
+/// Opens the specified file for write access, persisting the contents with the given effort on close.
+///
+/// # params
+/// `path` - The specified file path. this path is suffixed by .1 or .2 before actually querying the file system.
+///          So if you obtain the path by file system enumeration you should strip the suffix before calling this function.
+/// `durability` - The effort spent by `bufferedfile_close_write` to persist the contents: 0 hands the contents to
+///                the operating system, 1 flushes them, 2 synchronizes the backing file to disk and 3 synchronizes
+///                its directory as well. Higher levels survive more kinds of crashes, but make closing the writer slower.
+///
+/// # Returnvalue
+/// this function returns a pointer to a FileWriter struct in memory, see `bufferedfile_open_write`.
+/// In case of an error this function returns a null pointer.
+/// You should use `last_error_length` and `last_error_message` to obtain the detailed error description.
+///
+#[no_mangle]
+pub extern "C" fn bufferedfile_open_write_durable(
+    path: *const c_char,
+    durability: u32,
+) -> FileWriter {
+    let durability = match Durability::try_from(durability) {
+        Ok(durability) => durability,
+        Err(level) => {
+            LAST_ERROR.with(|x| *x.borrow_mut() = Some(Error::InvalidDurability(level)));
+            return ptr::null_mut();
+        }
+    };
+    let path = match path_from_c(unsafe { CStr::from_ptr(path) }) {
+        Some(path) => path,
+        None => {
             // TODO Error handling in ffi
             LAST_ERROR.with(|x| *x.borrow_mut() = Some(Error::NonUtf8Path));
             return ptr::null_mut();
         }
     };
-    let path = PathBuf::from(path);
 
-    let file = match BufferedFile::new(&path) {
+    let mut file = match BufferedFile::new(&path) {
         Ok(file) => file,
         Err(inner) => {
             // TODO Error handling in ffi
@@ -137,6 +179,7 @@ pub extern "C" fn bufferedfile_open_write(path: *const c_char) -> FileWriter {
             return ptr::null_mut();
         }
     };
+    file.set_durability(durability);
 
     match file.write() {
         Ok(reader) => {
@@ -196,6 +239,94 @@ pub extern "C" fn bufferedfile_read(reader: FileReader, buffer: *mut u8, buffer_
     }
 }
 
+///
+/// Reports the length of the contents of the file in bytes.
+///
+/// # Params
+/// `reader` - the pointer to a `FileReader` obtained from `bufferedfile_open_read`.
+///
+/// # Returnvalue
+///
+/// In the success case the return value is the length of the whole contents, regardless of the data read so far.
+/// In case an error occures the return value is a negative number and you should use `last_error_length` and `last_error_message` to obtain the detailed error description.
+///
+#[no_mangle]
+pub extern "C" fn bufferedfile_len(reader: FileReader) -> i64 {
+    if reader.is_null() {
+        LAST_ERROR.with(|x| *x.borrow_mut() = Some(Error::InvalidPointer));
+        return ErrorCode::InvalidPointer.into();
+    }
+
+    let reader = unsafe { &*reader };
+    i64::try_from(reader.len()).unwrap_or(i64::MAX)
+}
+
+///
+/// Reports whether the contents of the file are empty.
+///
+/// # Params
+/// `reader` - the pointer to a `FileReader` obtained from `bufferedfile_open_read`.
+///
+/// # Returnvalue
+///
+/// In the success case the return value is 1 if the contents are empty and 0 otherwise.
+/// In case an error occures the return value is a negative number and you should use `last_error_length` and `last_error_message` to obtain the detailed error description.
+///
+#[no_mangle]
+pub extern "C" fn bufferedfile_is_empty(reader: FileReader) -> i64 {
+    if reader.is_null() {
+        LAST_ERROR.with(|x| *x.borrow_mut() = Some(Error::InvalidPointer));
+        return ErrorCode::InvalidPointer.into();
+    }
+
+    let reader = unsafe { &*reader };
+    i64::from(reader.is_empty())
+}
+
+///
+/// Reports the number of bytes of the contents, which have not been read yet.
+///
+/// # Params
+/// `reader` - the pointer to a `FileReader` obtained from `bufferedfile_open_read`.
+///
+/// # Returnvalue
+///
+/// In the success case the return value is the number of bytes behind the current position of the reader.
+/// In case an error occures the return value is a negative number and you should use `last_error_length` and `last_error_message` to obtain the detailed error description.
+///
+#[no_mangle]
+pub extern "C" fn bufferedfile_remaining(reader: FileReader) -> i64 {
+    if reader.is_null() {
+        LAST_ERROR.with(|x| *x.borrow_mut() = Some(Error::InvalidPointer));
+        return ErrorCode::InvalidPointer.into();
+    }
+
+    let reader = unsafe { &*reader };
+    i64::try_from(reader.remaining()).unwrap_or(i64::MAX)
+}
+
+///
+/// Reports the number of bytes read from the contents so far, e.g. to display the progress together with `bufferedfile_len`.
+///
+/// # Params
+/// `reader` - the pointer to a `FileReader` obtained from `bufferedfile_open_read`.
+///
+/// # Returnvalue
+///
+/// In the success case the return value is the number of bytes handed out by `bufferedfile_read`.
+/// In case an error occures the return value is a negative number and you should use `last_error_length` and `last_error_message` to obtain the detailed error description.
+///
+#[no_mangle]
+pub extern "C" fn bufferedfile_bytes_read(reader: FileReader) -> i64 {
+    if reader.is_null() {
+        LAST_ERROR.with(|x| *x.borrow_mut() = Some(Error::InvalidPointer));
+        return ErrorCode::InvalidPointer.into();
+    }
+
+    let reader = unsafe { &*reader };
+    i64::try_from(reader.bytes_read()).unwrap_or(i64::MAX)
+}
+
 ///
 /// Writes the buffer into the file.
 ///
@@ -263,17 +394,50 @@ pub extern "C" fn bufferedfile_close_read(reader: FileReader) {
 }
 
 ///
-/// Close the file opened for writing.
+/// Close the file opened for writing, committing the written data as the new generation.
 ///
 /// # Params
-/// `writer` - the pointer to a `FileReader` obtained from `bufferedfile_open_read`.
+/// `writer` - the pointer to a `FileWriter` obtained from `bufferedfile_open_write`.
+///
+/// # Return value
+/// In the success case the return value is 0.
+/// In case an error occures the return value is a negative Number and you should use `last_error_length` and `last_error_message` to obtain the detailed error description.
+/// The written data is discarded then.
 ///
 /// # Remarks
 /// The writer must not be used after calling this method.
 /// The pointer is invalidated here and a use after calling this method is a use after free bug.
 ///
 #[no_mangle]
-pub extern "C" fn bufferedfile_close_write(writer: FileWriter) {
+pub extern "C" fn bufferedfile_close_write(writer: FileWriter) -> i64 {
+    if writer.is_null() {
+        return 0;
+    }
+    let boxed = unsafe { Box::from_raw(writer) };
+    match boxed.commit() {
+        Ok(_) => 0,
+        Err(err) => {
+            let error = ErrorCode::from(&err);
+            LAST_ERROR.with(|x| {
+                *x.borrow_mut() = Some(Error::BufferedFileErrors(BufferedFileErrors::IoError(err)))
+            });
+            error.into()
+        }
+    }
+}
+
+///
+/// Close the file opened for writing, discarding the written data.
+///
+/// # Params
+/// `writer` - the pointer to a `FileWriter` obtained from `bufferedfile_open_write`.
+///
+/// # Remarks
+/// The writer must not be used after calling this method.
+/// The pointer is invalidated here and a use after calling this method is a use after free bug.
+///
+#[no_mangle]
+pub extern "C" fn bufferedfile_abort_write(writer: FileWriter) {
     if !writer.is_null() {
         let boxed = unsafe { Box::from_raw(writer) };
         drop(boxed)
@@ -351,12 +515,152 @@ impl std::fmt::Display for Error {
             Error::BufferTooLong => write!(f, "Provided buffer is too long"),
             Error::InvalidPointer => write!(f, "Provided pointer is invalid"),
             Error::NonUtf8Path => write!(f, "Provided path is no valid UTF-8"),
+            Error::InvalidDurability(level) => write!(f, "Unsupported durability {}", level),
             Error::BufferedFileErrors(BufferedFileErrors::AllFilesInvalidError) => {
                 write!(f, "No valid file exists.")
             }
             Error::BufferedFileErrors(BufferedFileErrors::IoError(err)) => {
                 write!(f, "Underlying IO Error: {}", err)
             }
+            Error::BufferedFileErrors(BufferedFileErrors::InvalidBufferCount(count)) => {
+                write!(f, "Unsupported buffer count {}", count)
+            }
+            Error::BufferedFileErrors(BufferedFileErrors::InvalidSuffixPattern(pattern)) => {
+                write!(f, "Invalid suffix pattern '{}'", pattern)
+            }
+            Error::BufferedFileErrors(BufferedFileErrors::DuplicateSlotPath(path)) => {
+                write!(f, "Duplicate backing file '{}'", path.display())
+            }
+            Error::BufferedFileErrors(BufferedFileErrors::AlreadyExists) => {
+                write!(f, "A valid file exists already.")
+            }
+            Error::BufferedFileErrors(BufferedFileErrors::GenerationNotFound(generation)) => {
+                write!(f, "Generation {} is not available.", generation)
+            }
+            Error::BufferedFileErrors(BufferedFileErrors::NoPreviousGeneration) => {
+                write!(f, "No valid previous generation exists.")
+            }
+            Error::BufferedFileErrors(BufferedFileErrors::InvalidPath(path)) => {
+                write!(f, "Invalid file path '{}'", path.display())
+            }
+            Error::BufferedFileErrors(BufferedFileErrors::NotAFile(path)) => {
+                write!(f, "'{}' is a directory", path.display())
+            }
+            Error::BufferedFileErrors(BufferedFileErrors::MetadataTooLarge(len)) => {
+                write!(f, "The metadata of {} bytes is too large.", len)
+            }
+            Error::BufferedFileErrors(BufferedFileErrors::MetadataNotSupported(version)) => {
+                write!(f, "Format version {:?} can not store metadata.", version)
+            }
+            Error::BufferedFileErrors(
+                err @ (BufferedFileErrors::ContentTypeMismatch { .. }
+                | BufferedFileErrors::ContentsTooLarge { .. }
+                | BufferedFileErrors::AllSlotsLeased
+                | BufferedFileErrors::AuthenticationFailed(_)),
+            ) => {
+                write!(f, "{}", err)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::CString, path::Path, ptr};
+
+    use crate::{tests::utils::TempDir, BufferedFile};
+
+    use super::{
+        bufferedfile_abort_write, bufferedfile_close_read, bufferedfile_close_write,
+        bufferedfile_is_empty, bufferedfile_len, bufferedfile_open_read,
+        bufferedfile_open_write_durable, bufferedfile_read, bufferedfile_remaining,
+        bufferedfile_write, take_last_error, Error, ErrorCode,
+    };
+
+    fn c_path(path: &Path) -> CString {
+        CString::new(path.to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn closing_commits_and_aborting_discards() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let path = c_path(&file);
+
+        let writer = bufferedfile_open_write_durable(path.as_ptr(), 3);
+        assert!(!writer.is_null());
+        let mut data = *b"Hello World";
+        assert_eq!(
+            bufferedfile_write(writer, data.as_mut_ptr(), data.len()),
+            11
+        );
+        assert_eq!(bufferedfile_close_write(writer), 0);
+        let managed_file = BufferedFile::new(&file).unwrap();
+        assert_eq!(managed_file.read_to_vec().unwrap(), b"Hello World");
+
+        let writer = bufferedfile_open_write_durable(path.as_ptr(), 1);
+        assert!(!writer.is_null());
+        let mut data = *b"discarded";
+        assert_eq!(bufferedfile_write(writer, data.as_mut_ptr(), data.len()), 9);
+        bufferedfile_abort_write(writer);
+        let managed_file = BufferedFile::new(&file).unwrap();
+        assert_eq!(managed_file.read_to_vec().unwrap(), b"Hello World");
+        assert_eq!(managed_file.latest_generation(), Some(1));
+
+        assert_eq!(bufferedfile_close_write(ptr::null_mut()), 0);
+        bufferedfile_abort_write(ptr::null_mut());
+    }
+
+    #[test]
+    fn unknown_durability_levels_are_rejected() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+
+        let writer = bufferedfile_open_write_durable(c_path(&file).as_ptr(), 4);
+        assert!(writer.is_null());
+        assert!(matches!(
+            take_last_error(),
+            Some(Error::InvalidDurability(4))
+        ));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn reports_the_length_of_the_contents() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        BufferedFile::create_with(&file, b"Hello World").unwrap();
+
+        let reader = bufferedfile_open_read(c_path(&file).as_ptr());
+        assert!(!reader.is_null());
+        assert_eq!(bufferedfile_len(reader), 11);
+        assert_eq!(bufferedfile_is_empty(reader), 0);
+        assert_eq!(bufferedfile_remaining(reader), 11);
+        let mut buf = [0u8; 5];
+        assert_eq!(bufferedfile_read(reader, buf.as_mut_ptr(), buf.len()), 5);
+        assert_eq!(bufferedfile_len(reader), 11);
+        assert_eq!(bufferedfile_remaining(reader), 6);
+        bufferedfile_close_read(reader);
+
+        let empty = dir.path().join("empty-file.txt");
+        BufferedFile::create_with(&empty, b"").unwrap();
+        let reader = bufferedfile_open_read(c_path(&empty).as_ptr());
+        assert_eq!(bufferedfile_len(reader), 0);
+        assert_eq!(bufferedfile_is_empty(reader), 1);
+        assert_eq!(bufferedfile_remaining(reader), 0);
+        bufferedfile_close_read(reader);
+    }
+
+    #[test]
+    fn length_queries_reject_null_pointers() {
+        let queries: [extern "C" fn(_) -> i64; 3] = [
+            bufferedfile_len,
+            bufferedfile_is_empty,
+            bufferedfile_remaining,
+        ];
+        for query in queries {
+            assert_eq!(query(ptr::null_mut()), ErrorCode::InvalidPointer as i64);
+            assert!(matches!(take_last_error(), Some(Error::InvalidPointer)));
         }
     }
 }
@@ -0,0 +1,185 @@
+//! Stores several messages in one managed file.
+//!
+//! Every frame is prefixed by the length of the message in four bytes in little endian and followed by the
+//! checksum of the length and the message (CRC-32/BZIP2 regardless of the configured checksum algorithm).
+
+use std::io::{ErrorKind, Read, Seek, Write};
+
+use crate::{checksum::ChecksumDigest, BufferedFileReader, BufferedFileWriter, DEFAULT_CHECKSUM};
+
+/// Computes the checksum of the length prefix and the message of a frame
+fn frame_checksum(len: [u8; 4], message: &[u8]) -> u32 {
+    let mut digest = ChecksumDigest::new(&DEFAULT_CHECKSUM);
+    digest.update(&len);
+    digest.update(message);
+    digest.finalize()
+}
+
+impl<T: Write> BufferedFileWriter<T> {
+    ///
+    /// Writes a message as a frame, which is read back by `BufferedFileReader::read_frame`.
+    ///
+    /// Fails with `ErrorKind::InvalidInput` if the message is 4 GiB or larger.
+    pub fn write_frame(&mut self, message: &[u8]) -> std::io::Result<()> {
+        let len = u32::try_from(message.len())
+            .map_err(|_| {
+                std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "A frame must be shorter than 4 GiB",
+                )
+            })?
+            .to_le_bytes();
+        self.write_all(&len)?;
+        self.write_all(message)?;
+        self.write_all(&frame_checksum(len, message).to_le_bytes())
+    }
+}
+
+impl<T: Read + Seek> BufferedFileReader<T> {
+    ///
+    /// Reads the next frame written by `BufferedFileWriter::write_frame`.
+    ///
+    /// Returns `None` at the end of the contents. Fails with `ErrorKind::InvalidData` if the frame is incomplete
+    /// or its checksum does not match.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multibufferedfile::BufferedFile;
+    /// # let dir = std::env::temp_dir().join("multibufferedfile-frame-doc");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    ///
+    /// let file = BufferedFile::new(dir.join("messages.bin")).unwrap();
+    /// let mut writer = file.write().unwrap();
+    /// writer.write_frame(b"Hello").unwrap();
+    /// writer.write_frame(b"World").unwrap();
+    /// writer.commit().unwrap();
+    ///
+    /// let mut reader = file.read().unwrap();
+    /// assert_eq!(reader.read_frame().unwrap().as_deref(), Some(&b"Hello"[..]));
+    /// assert_eq!(reader.read_frame().unwrap().as_deref(), Some(&b"World"[..]));
+    /// assert_eq!(reader.read_frame().unwrap(), None);
+    ///
+    /// let messages = file.read().unwrap().records().collect::<Result<Vec<_>, _>>().unwrap();
+    /// assert_eq!(messages, [b"Hello", b"World"]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn read_frame(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        let incomplete = || std::io::Error::new(ErrorKind::InvalidData, "The frame is incomplete");
+        let mut len = [0u8; 4];
+        let read = self.read(&mut len)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        self.read_exact(&mut len[read..])
+            .map_err(|_| incomplete())?;
+        let message_len = u64::from(u32::from_le_bytes(len));
+        if self.len().saturating_sub(self.stream_position()?) < message_len + 4 {
+            return Err(incomplete());
+        }
+
+        let mut message = vec![0u8; message_len as usize];
+        self.read_exact(&mut message)?;
+        let mut stored = [0u8; 4];
+        self.read_exact(&mut stored)?;
+        if frame_checksum(len, &message) != u32::from_le_bytes(stored) {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "The checksum of the frame does not match",
+            ));
+        }
+        Ok(Some(message))
+    }
+
+    ///
+    /// Iterates over the frames from the current position to the end of the contents, see `read_frame`.
+    ///
+    /// The iteration ends cleanly at the end of the contents and after the first error.
+    pub fn records(&mut self) -> FrameRecords<'_, T> {
+        FrameRecords {
+            reader: self,
+            failed: false,
+        }
+    }
+}
+
+///
+/// The frames of a reader, see `BufferedFileReader::records`.
+#[derive(Debug)]
+pub struct FrameRecords<'a, T: Read> {
+    reader: &'a mut BufferedFileReader<T>,
+    failed: bool,
+}
+
+impl<T: Read + Seek> Iterator for FrameRecords<'_, T> {
+    type Item = std::io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let frame = self.reader.read_frame().transpose();
+        self.failed = matches!(frame, Some(Err(_)));
+        frame
+    }
+}
+
+impl<T: Read + Seek> std::iter::FusedIterator for FrameRecords<'_, T> {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Write};
+
+    use crate::{tests::utils::TempDir, BufferedFile};
+
+    #[test]
+    fn frames_detect_damage() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFile::new(&file).unwrap();
+        let mut writer = managed_file.write().unwrap();
+        writer.write_frame(b"").unwrap();
+        writer.write_frame(b"Hello World").unwrap();
+        writer.write_all(&[3, 0, 0, 0, b'a']).unwrap();
+        writer.commit().unwrap();
+
+        let mut reader = managed_file.read().unwrap();
+        assert_eq!(reader.read_frame().unwrap(), Some(Vec::new()));
+        assert_eq!(reader.read_frame().unwrap(), Some(b"Hello World".to_vec()));
+        let err = reader.read_frame().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let mut reader = managed_file.read().unwrap();
+        let records: Vec<_> = reader.records().collect();
+        assert_eq!(records.len(), 3);
+        assert!(records[2].is_err());
+        assert_eq!(records[1].as_ref().unwrap(), b"Hello World");
+    }
+
+    #[test]
+    fn records_end_cleanly_or_after_the_first_error() {
+        let dir = TempDir::new();
+        let managed_file = BufferedFile::new(dir.path().join("data-file.txt")).unwrap();
+        managed_file.write().unwrap().commit().unwrap();
+        let mut reader = managed_file.read().unwrap();
+        assert_eq!(reader.records().count(), 0);
+
+        let mut writer = managed_file.write().unwrap();
+        writer.write_frame(b"").unwrap();
+        writer.commit().unwrap();
+        let mut reader = managed_file.read().unwrap();
+        let records = reader.records().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(records, [Vec::<u8>::new()]);
+
+        let mut writer = managed_file.write().unwrap();
+        writer.write_frame(b"Hello").unwrap();
+        writer.write_all(&[5, 0]).unwrap();
+        writer.commit().unwrap();
+        let mut reader = managed_file.read().unwrap();
+        let mut records = reader.records();
+        assert_eq!(records.next().unwrap().unwrap(), b"Hello");
+        let err = records.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(records.next().is_none());
+    }
+}
@@ -0,0 +1,281 @@
+//! Keeps frequent small updates of a managed file in a journal, which is compacted into the backing files.
+//!
+//! The journal is an append-only log (see `BufferedLog`) next to the managed file with the suffix `.journal`.
+//! Every record holds the generation of the managed file it applies to, the length of the contents after
+//! the update and the offset of the written bytes, each in eight bytes in little endian, followed by the bytes.
+//! Records of other generations are ignored, so a compaction interrupted after the managed file has been
+//! written does not apply the compacted updates twice.
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    path::Path,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use crate::{
+    BufferedFile, BufferedFileErrors, BufferedFileOptions, BufferedLog, FsStorage, Storage,
+};
+
+/// The number of updates kept in the journal, before it is compacted into the managed file by default
+pub const DEFAULT_COMPACT_AFTER: usize = 64;
+
+/// The number of bytes preceding the written bytes of a record
+const UPDATE_HEADER_LEN: usize = 24;
+
+/// The generation recorded for updates of a managed file without a valid generation
+const NO_GENERATION: u64 = u64::MAX;
+
+/// The contents built from the newest generation and the updates in the journal
+#[derive(Debug)]
+struct JournalState {
+    contents: Vec<u8>,
+    /// The generation of the managed file the updates in the journal apply to
+    base: u64,
+    /// The number of updates in the journal
+    pending: usize,
+}
+
+///
+/// A managed file, whose updates are appended to a journal instead of writing a new generation every time.
+///
+/// The current contents are kept in memory. Every update is appended to the journal as a checksummed record,
+/// which is much cheaper than rewriting the whole contents for small changes. Once `compact_after` updates have
+/// been collected, or when `compact` is called, the contents are written as a new generation of the managed file
+/// and the journal is started anew. Only one instance should modify a journaled file at a time.
+///
+/// # Example
+///
+/// ```
+/// use multibufferedfile::JournaledFile;
+/// # let dir = std::env::temp_dir().join("multibufferedfile-journal-doc");
+/// # std::fs::create_dir_all(&dir).unwrap();
+///
+/// let file = JournaledFile::open(dir.join("state.bin")).unwrap();
+/// file.replace(b"Hello World").unwrap();
+/// file.write_at(6, b"there").unwrap();
+///
+/// let reopened = JournaledFile::open(dir.join("state.bin")).unwrap();
+/// assert_eq!(reopened.contents(), b"Hello there");
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct JournaledFile<S: Storage = FsStorage> {
+    file: BufferedFile<S>,
+    journal: BufferedLog<S>,
+    compact_after: usize,
+    state: Mutex<JournalState>,
+}
+
+impl JournaledFile {
+    ///
+    /// Opens the journaled file at `path` with the default options.
+    /// The journal is kept next to the backing files with the suffix `.journal`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, BufferedFileErrors> {
+        BufferedFileOptions::new().open_journaled(path)
+    }
+}
+
+impl<S: Storage + Clone> JournaledFile<S> {
+    /// Reads the newest valid generation and applies the updates recorded for it in the journal
+    pub(crate) fn scan(
+        storage: S,
+        path: impl AsRef<Path>,
+        options: BufferedFileOptions,
+    ) -> Result<Self, BufferedFileErrors> {
+        let mut journal_path = path.as_ref().to_path_buf().into_os_string();
+        journal_path.push(".journal");
+        let file = BufferedFile::scan(storage.clone(), path, options.clone())?;
+        let journal = BufferedLog::scan(storage, journal_path, options)?;
+
+        let mut state = match file.read() {
+            Ok(mut reader) => {
+                let mut contents = Vec::new();
+                reader.read_to_end(&mut contents)?;
+                JournalState {
+                    contents,
+                    base: reader.generation(),
+                    pending: 0,
+                }
+            }
+            Err(BufferedFileErrors::AllFilesInvalidError) => JournalState {
+                contents: Vec::new(),
+                base: NO_GENERATION,
+                pending: 0,
+            },
+            Err(err) => return Err(err),
+        };
+        for record in journal.records()? {
+            let record = record?;
+            if let Some((generation, len, offset, data)) = decode_update(&record) {
+                if generation == state.base {
+                    apply_update(&mut state.contents, len, offset, data);
+                    state.pending += 1;
+                }
+            }
+        }
+
+        Ok(JournaledFile {
+            file,
+            journal,
+            compact_after: DEFAULT_COMPACT_AFTER,
+            state: Mutex::new(state),
+        })
+    }
+}
+
+impl<S: Storage> JournaledFile<S> {
+    /// A copy of the current contents, including the updates not compacted yet
+    pub fn contents(&self) -> Vec<u8> {
+        self.state().contents.clone()
+    }
+
+    /// The number of updates in the journal, which have not been compacted into the managed file yet
+    pub fn pending(&self) -> usize {
+        self.state().pending
+    }
+
+    /// Sets the number of updates collected in the journal before it is compacted automatically
+    pub fn set_compact_after(&mut self, updates: usize) {
+        self.compact_after = updates;
+    }
+
+    ///
+    /// Writes `data` at `offset`, extending the contents if necessary.
+    ///
+    /// A gap between the end of the contents and `offset` is filled with zeros.
+    /// The update is appended to the journal before it becomes visible through `contents`.
+    pub fn write_at(&self, offset: u64, data: &[u8]) -> Result<(), BufferedFileErrors> {
+        let mut state = self.state();
+        let end = offset.checked_add(data.len() as u64).ok_or_else(|| {
+            std::io::Error::new(ErrorKind::InvalidInput, "The update ends beyond 2^64 bytes")
+        })?;
+        let len = end.max(state.contents.len() as u64);
+        self.record(&mut state, len, offset, data)
+    }
+
+    /// Replaces the whole contents, see `write_at`
+    pub fn replace(&self, contents: &[u8]) -> Result<(), BufferedFileErrors> {
+        let mut state = self.state();
+        self.record(&mut state, contents.len() as u64, 0, contents)
+    }
+
+    ///
+    /// Writes the current contents as a new generation of the managed file and starts the journal anew.
+    ///
+    /// The previous generation and the journal are retained until the new generation has been committed.
+    pub fn compact(&self) -> Result<(), BufferedFileErrors> {
+        let mut state = self.state();
+        self.compact_locked(&mut state)
+    }
+
+    /// The managed file the updates are compacted into
+    pub fn file(&self) -> &BufferedFile<S> {
+        &self.file
+    }
+
+    /// Appends an update to the journal and applies it, compacting the journal once it is long enough
+    fn record(
+        &self,
+        state: &mut JournalState,
+        len: u64,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<(), BufferedFileErrors> {
+        let mut record = Vec::with_capacity(UPDATE_HEADER_LEN + data.len());
+        record.extend_from_slice(&state.base.to_le_bytes());
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(&offset.to_le_bytes());
+        record.extend_from_slice(data);
+        self.journal.append(&record)?;
+        apply_update(&mut state.contents, len, offset, data);
+        state.pending += 1;
+        if state.pending >= self.compact_after {
+            self.compact_locked(state)?;
+        }
+        Ok(())
+    }
+
+    /// Compacts the journal into the managed file, see `compact`
+    fn compact_locked(&self, state: &mut JournalState) -> Result<(), BufferedFileErrors> {
+        let mut writer = self.file.write()?;
+        writer.write_all(&state.contents)?;
+        writer.flush()?;
+        let generation = writer
+            .commit()?
+            .generation
+            .expect("writers of a managed file know their generation");
+        // the updates recorded for the previous generation are ignored from now on
+        state.base = generation;
+        state.pending = 0;
+        self.journal.compact(std::iter::empty::<&[u8]>())?;
+        Ok(())
+    }
+
+    /// provides access to the current contents
+    fn state(&self) -> MutexGuard<'_, JournalState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Splits a record into the generation it applies to, the length of the contents, the offset and the bytes
+fn decode_update(record: &[u8]) -> Option<(u64, u64, u64, &[u8])> {
+    if record.len() < UPDATE_HEADER_LEN {
+        return None;
+    }
+    let (header, data) = record.split_at(UPDATE_HEADER_LEN);
+    let field = |index: usize| {
+        u64::from_le_bytes(
+            header[index * 8..(index + 1) * 8]
+                .try_into()
+                .expect("the fields have 8 bytes"),
+        )
+    };
+    Some((field(0), field(1), field(2), data))
+}
+
+/// Resizes the contents to `len` and writes `data` at `offset`, both are bounded by `len`
+fn apply_update(contents: &mut Vec<u8>, len: u64, offset: u64, data: &[u8]) {
+    let len = usize::try_from(len).unwrap_or(usize::MAX);
+    contents.resize(len, 0);
+    let start = usize::try_from(offset).unwrap_or(usize::MAX).min(len);
+    let end = start.saturating_add(data.len()).min(len);
+    contents[start..end].copy_from_slice(&data[..end - start]);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tests::utils::TempDir, BufferedFileOptions, JournaledFile};
+
+    #[test]
+    fn updates_survive_compaction_and_reopening() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let mut journaled = JournaledFile::open(&file).unwrap();
+        journaled.set_compact_after(3);
+        journaled.replace(b"Hello World").unwrap();
+        journaled.write_at(6, b"there").unwrap();
+        assert_eq!(journaled.pending(), 2);
+        assert!(!journaled.file().exists());
+
+        journaled.write_at(13, b"!").unwrap();
+        assert_eq!(journaled.pending(), 0);
+        assert_eq!(
+            journaled.file().read_or_default().unwrap(),
+            b"Hello there\0\0!"
+        );
+        journaled.replace(b"Hi").unwrap();
+
+        let reopened = BufferedFileOptions::new().open_journaled(&file).unwrap();
+        assert_eq!(reopened.contents(), b"Hi");
+        assert_eq!(reopened.pending(), 1);
+
+        // a compaction interrupted before the journal has been started anew
+        reopened.file().update(|_| b"Hi!".to_vec()).unwrap();
+        drop(reopened);
+        let reopened = JournaledFile::open(&file).unwrap();
+        assert_eq!(reopened.contents(), b"Hi!");
+        assert_eq!(reopened.pending(), 0);
+        reopened.compact().unwrap();
+        assert_eq!(reopened.file().history().len(), 2);
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+};
+
+use crate::{
+    select_target_excluding, BufferedFile, BufferedFileErrors, Generation, ReaderLeases, Storage,
+};
+
+/// The backing files held by readers of a managed file and its clones
+#[derive(Debug, Default)]
+pub(crate) struct Leases {
+    held: Mutex<HashMap<PathBuf, usize>>,
+    /// Signals waiting writers, once a lease has been released
+    released: Condvar,
+}
+
+impl Leases {
+    fn held(&self) -> MutexGuard<'_, HashMap<PathBuf, usize>> {
+        self.held.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Keeps writers from overwriting a backing file, until the reader holding it has been dropped
+#[derive(Debug)]
+pub(crate) struct Lease {
+    leases: Arc<Leases>,
+    path: PathBuf,
+}
+
+impl Clone for Lease {
+    fn clone(&self) -> Self {
+        *self.leases.held().entry(self.path.clone()).or_default() += 1;
+        Lease {
+            leases: Arc::clone(&self.leases),
+            path: self.path.clone(),
+        }
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let mut held = self.leases.held();
+        if let Some(count) = held.get_mut(&self.path) {
+            *count -= 1;
+            if *count == 0 {
+                held.remove(&self.path);
+            }
+        }
+        self.leases.released.notify_all();
+    }
+}
+
+impl<S: Storage> BufferedFile<S> {
+    /// Leases the backing file at `path` for a reader, if readers are protected by the options
+    pub(crate) fn lease(&self, path: &Path) -> Option<Lease> {
+        if self.options.reader_leases == ReaderLeases::Ignore {
+            return None;
+        }
+        *self.leases.held().entry(path.to_path_buf()).or_default() += 1;
+        Some(Lease {
+            leases: Arc::clone(&self.leases),
+            path: path.to_path_buf(),
+        })
+    }
+
+    ///
+    /// Selects the backing file to write the next generation to, skipping the pinned and the leased backing files.
+    ///
+    /// Backing files skipped by lazy validation are verified first, so a corrupted newer backing file
+    /// is replaced rather than the last valid one.
+    /// Fails with `BufferedFileErrors::AllSlotsLeased` or waits for a lease to be released according to
+    /// `BufferedFileOptions::reader_leases`, if no other backing file is left.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn select_writable(
+        &self,
+        wide: bool,
+    ) -> Result<(MutexGuard<'_, Vec<(PathBuf, Generation)>>, usize, u64), BufferedFileErrors> {
+        self.verify_pending();
+        let pinned = self.pinned_index();
+        loop {
+            let files = self.files();
+            let generations = files
+                .iter()
+                .map(|(_, gen)| gen.number())
+                .collect::<Vec<_>>();
+            let held = self.leases.held();
+            let excluded = files
+                .iter()
+                .enumerate()
+                .filter(|(_, (path, _))| held.contains_key(path))
+                .map(|(index, _)| index)
+                .chain(pinned)
+                .collect::<Vec<_>>();
+            if let Some((index, generation)) =
+                select_target_excluding(&generations, wide, &excluded)
+            {
+                drop(held);
+                return Ok((files, index, generation));
+            }
+            // readers must be able to open new readers while this writer waits
+            drop(files);
+            match self.options.reader_leases {
+                ReaderLeases::Wait => drop(
+                    self.leases
+                        .released
+                        .wait(held)
+                        .unwrap_or_else(PoisonError::into_inner),
+                ),
+                _ => return Err(BufferedFileErrors::AllSlotsLeased),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use crate::{tests::utils::TempDir, BufferedFileErrors, BufferedFileOptions, ReaderLeases};
+
+    #[test]
+    fn readers_keep_their_backing_file() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let mut options = BufferedFileOptions::new();
+        options.reader_leases(ReaderLeases::Refuse);
+        let managed_file = options.create_with(&file, b"Hello World").unwrap();
+        let mut reader = managed_file.read().unwrap();
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        // the backing file held by the reader is skipped
+        managed_file.update(|_| b"Hello there".to_vec()).unwrap();
+        let newest = managed_file.read().unwrap();
+        assert!(matches!(
+            managed_file.update(|_| b"Hello".to_vec()),
+            Err(BufferedFileErrors::AllSlotsLeased)
+        ));
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"Hello World");
+        drop(reader);
+        managed_file.update(|_| b"Hello".to_vec()).unwrap();
+        drop(newest);
+
+        options.reader_leases(ReaderLeases::Wait);
+        let managed_file = options.open(&file).unwrap();
+        let reader = managed_file.read().unwrap();
+        managed_file.update(|_| b"Hello World".to_vec()).unwrap();
+        let newest = managed_file.read().unwrap();
+        std::thread::scope(|scope| {
+            let writer = scope.spawn(|| managed_file.update(|_| b"Hello again".to_vec()));
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            assert!(!writer.is_finished());
+            drop(reader);
+            writer.join().unwrap().unwrap();
+        });
+        drop(newest);
+        assert_eq!(managed_file.read_or_default().unwrap(), b"Hello again");
+    }
+}
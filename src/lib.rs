@@ -1,40 +1,101 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(feature = "std")]
 use std::{
-    cmp::Ordering,
-    fs::OpenOptions,
     io::{ErrorKind, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    thread::ThreadId,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use crc::{Crc, CRC_32_BZIP2};
+#[cfg(feature = "std")]
 use thiserror::Error;
 
-/// The number of parallel buffers, that exist at one point in time.
-const BUFFER_COUNT: u8 = 2;
+#[cfg(feature = "std")]
+use crate::{checksum::ChecksumDigest, lease::Leases};
+
+/// The number of parallel buffers, that exist at one point in time, if nothing else is configured.
+#[cfg(feature = "std")]
+const DEFAULT_BUFFER_COUNT: u8 = 2;
+
+/// The largest number of parallel buffers supported with generations stored in one byte.
+/// These wrap around after 255 and are ordered by their distance (see `compare_generations`),
+/// so more than 128 of them could no longer be ordered. Generations stored in eight bytes never wrap around,
+/// so these formats support up to 255 buffers, the largest buffer count stored by the pin.
+#[cfg(feature = "std")]
+const MAX_BUFFER_COUNT: u8 = 128;
 
 /// Describes the Generation of a stored file
 ///
+#[cfg(feature = "std")]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 enum Generation {
     /// The generation of a valid file with the value of the generation
-    Valid(u8),
+    Valid(u64),
+    /// The generation of a file, whose checksum has not been verified yet
+    Unchecked(u64),
     /// Marker for files which are either invalid or do not yet exist
     None,
 }
 
+#[cfg(feature = "std")]
 impl Generation {
     /// Checks if the generation is valid
     pub fn is_valid(&self) -> bool {
         matches!(self, Generation::Valid(_))
     }
+
+    /// The value of the generation, if the file is valid or not yet verified
+    fn number(&self) -> Option<u64> {
+        match self {
+            Generation::Valid(val) | Generation::Unchecked(val) => Some(*val),
+            Generation::None => None,
+        }
+    }
+}
+
+/// A generation retained in one of the backing files
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// The generation stored in the backing file
+    pub generation: u64,
+    /// The path of the backing file
+    pub path: PathBuf,
 }
 
 /// A double buffered File is represented here. It can be opened for either read or write access.
-#[derive(Debug, PartialEq)]
-pub struct BufferedFile {
-    files: Vec<(std::path::PathBuf, Generation)>,
+///
+/// The state of the backing files is scanned once on creation and kept up to date by the writers,
+/// so readers and writers can be opened repeatedly.
+/// The backing files are accessed through the `Storage`, which defaults to the local file system.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct BufferedFile<S: Storage = FsStorage> {
+    path: PathBuf,
+    files: Arc<Mutex<Vec<(std::path::PathBuf, Generation)>>>,
+    options: BufferedFileOptions,
+    storage: Arc<S>,
+    leases: Arc<Leases>,
+}
+
+/// Shares the state of the backing files, so clones see the generations committed by each other
+#[cfg(feature = "std")]
+impl<S: Storage> Clone for BufferedFile<S> {
+    fn clone(&self) -> Self {
+        BufferedFile {
+            path: self.path.clone(),
+            files: Arc::clone(&self.files),
+            options: self.options.clone(),
+            storage: Arc::clone(&self.storage),
+            leases: Arc::clone(&self.leases),
+        }
+    }
 }
 
 /// The definition of Errors of this library
+#[cfg(feature = "std")]
 #[derive(Error, Debug)]
 pub enum BufferedFileErrors {
     /// The underlying filesystem reported an error
@@ -43,79 +104,704 @@ pub enum BufferedFileErrors {
     /// Either no files exist, or all existing files are invalid
     #[error("No valid file available")]
     AllFilesInvalidError,
+    /// The requested number of buffers is not supported
+    #[error(
+        "Unsupported buffer count {0}. Expected a value between 2 and 128, or 255 with eight byte generations"
+    )]
+    InvalidBufferCount(u8),
+    /// The suffix pattern for the backing files does not contain exactly one `{}`
+    #[error("Invalid suffix pattern '{0}'. Expected exactly one '{{}}'")]
+    InvalidSuffixPattern(String),
+    /// The naming strategy generated the same path for more than one backing file
+    #[error("The path '{0}' is used for more than one backing file")]
+    DuplicateSlotPath(PathBuf),
+    /// A valid generation of the file exists already
+    #[error("A valid version of the file exists already")]
+    AlreadyExists,
+    /// No valid backing file holds the requested generation
+    #[error("Generation {0} is not available")]
+    GenerationNotFound(u64),
+    /// There is no valid previous generation to return to
+    #[error("No valid previous generation available")]
+    NoPreviousGeneration,
+    /// The path does not name a file, e.g. the root directory or a path ending in `..`
+    #[error("Invalid file path '{}'", .0.display())]
+    InvalidPath(PathBuf),
+    /// The managed file or one of its backing files is a directory
+    #[error("'{}' is a directory, not a file", .0.display())]
+    NotAFile(PathBuf),
+    /// The encoded user metadata exceeds `MAX_METADATA_LEN`
+    #[error("The metadata of {0} bytes exceeds the limit of {MAX_METADATA_LEN} bytes")]
+    MetadataTooLarge(usize),
+    /// The configured format version can not store user metadata
+    #[error("Format version {0:?} can not store metadata")]
+    MetadataNotSupported(FormatVersion),
+    /// The newest generation holds another kind of contents than expected
+    #[error("Expected contents of type '{expected}', found {}", .found.as_deref().unwrap_or("no type"))]
+    ContentTypeMismatch {
+        /// The expected content type
+        expected: String,
+        /// The content type of the newest generation, if any
+        found: Option<String>,
+    },
+    /// Every backing file, which could be written, is held by a reader, see `BufferedFileOptions::reader_leases`
+    #[error("All backing files, which could be written, are held by readers")]
+    AllSlotsLeased,
+    /// The contents exceed the limit configured with `BufferedFileOptions::max_read_len`
+    /// or passed to `BufferedFileReader::read_to_vec_limited`
+    #[error("The contents of {len} bytes exceed the limit of {limit} bytes")]
+    ContentsTooLarge {
+        /// The length of the contents
+        len: u64,
+        /// The configured limit
+        limit: u64,
+    },
+    /// The encrypted contents of the backing file do not match their authentication tag,
+    /// see `BufferedFileOptions::encryption_key`
+    #[error("The contents of '{}' could not be authenticated", .0.display())]
+    AuthenticationFailed(PathBuf),
 }
 
-enum FileCheckResult {
-    Good { generation: Generation },
-    ChecksumFailure,
-}
+#[cfg(feature = "std")]
+pub use cache::*;
+
+#[cfg(feature = "std")]
+mod cache;
+
+#[cfg(feature = "tokio")]
+pub use async_reader::*;
+
+#[cfg(feature = "tokio")]
+mod async_reader;
+
+#[cfg(feature = "std")]
+mod bundle;
+
+mod checksum;
+
+#[cfg(feature = "std")]
+pub use corruption::*;
+
+#[cfg(feature = "std")]
+mod corruption;
+
+#[cfg(feature = "std")]
+mod delta;
+
+#[cfg(feature = "std")]
+pub use direct::*;
+
+#[cfg(feature = "std")]
+mod direct;
+
+#[cfg(feature = "std")]
+pub use directory::*;
+
+#[cfg(feature = "std")]
+mod directory;
+
+#[cfg(feature = "encryption")]
+mod encryption;
+
+#[cfg(feature = "embedded-io")]
+pub use embedded::*;
+
+#[cfg(feature = "embedded-io")]
+mod embedded;
+
+#[cfg(feature = "std")]
+pub use frame::*;
+
+#[cfg(feature = "std")]
+mod frame;
+
+#[cfg(feature = "hmac")]
+mod mac;
 
-/// Stores and defines the used CRC algorithm for the checksums of the files
-const CRC: crc::Crc<u32> = Crc::<u32>::new(&CRC_32_BZIP2);
+#[cfg(feature = "std")]
+pub use journal::*;
 
+#[cfg(feature = "std")]
+mod journal;
+
+#[cfg(feature = "std")]
+mod lease;
+
+#[cfg(feature = "std")]
+pub use log::*;
+
+#[cfg(feature = "std")]
+mod log;
+
+#[cfg(feature = "std")]
+pub use memory::*;
+
+#[cfg(feature = "std")]
+mod memory;
+
+#[cfg(feature = "std")]
+pub use metadata::*;
+
+#[cfg(feature = "std")]
+mod metadata;
+
+#[cfg(feature = "mmap")]
+pub use mmap::*;
+
+#[cfg(feature = "mmap")]
+mod mmap;
+
+#[cfg(feature = "std")]
+pub use naming::*;
+
+#[cfg(feature = "std")]
+mod naming;
+
+#[cfg(feature = "object_store")]
+pub use object::*;
+
+#[cfg(feature = "object_store")]
+mod object;
+
+#[cfg(feature = "std")]
+pub use options::*;
+
+#[cfg(feature = "std")]
+mod options;
+
+pub use protocol::*;
+
+mod protocol;
+
+#[cfg(feature = "std")]
 pub use reader::*;
 
+#[cfg(feature = "std")]
 mod reader;
 
+#[cfg(feature = "std")]
+pub use sidecar::*;
+
+#[cfg(feature = "std")]
+mod sidecar;
+
+#[cfg(feature = "std")]
+pub use status::*;
+
+#[cfg(feature = "std")]
+mod pin;
+
+#[cfg(feature = "std")]
+mod plain;
+
+#[cfg(feature = "std")]
+mod status;
+
+#[cfg(feature = "std")]
+pub use storage::*;
+
+#[cfg(feature = "std")]
+mod storage;
+
+#[cfg(feature = "stream")]
+pub use stream::*;
+
+#[cfg(feature = "stream")]
+mod stream;
+
+#[cfg(feature = "std")]
+pub use temp::*;
+
+#[cfg(feature = "std")]
+mod temp;
+
+#[cfg(feature = "serde")]
+pub use typed::*;
+
+#[cfg(feature = "serde")]
+mod typed;
+
+#[cfg(feature = "serde")]
+pub use value::*;
+
+#[cfg(feature = "serde")]
+mod value;
+
+#[cfg(feature = "std")]
+pub use timeout::*;
+
+#[cfg(feature = "std")]
+mod timeout;
+
+#[cfg(feature = "std")]
 pub use writer::*;
 
+#[cfg(feature = "std")]
 mod writer;
 
+#[cfg(feature = "bytes")]
+mod zero_copy;
+
+#[cfg(feature = "std")]
 mod ffi;
 
-fn check_file(file: &Path) -> std::io::Result<FileCheckResult> {
-    let mut file = std::fs::File::open(file)?;
-    let mut digest = CRC.digest();
+/// Reads the first bytes of a backing file, which may hold the header of any version
+#[cfg(feature = "std")]
+fn read_prefix(file: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    let mut prefix = Vec::with_capacity(MAX_HEADER_LEN as usize);
+    file.take(MAX_HEADER_LEN).read_to_end(&mut prefix)?;
+    Ok(prefix)
+}
+
+/// The fields stored in the header of a backing file
+#[cfg(feature = "std")]
+#[derive(Debug, Copy, Clone)]
+struct SlotHeaderFields {
+    version: FormatVersion,
+    generation: u64,
+    written: Option<SystemTime>,
+}
+
+/// Opens a backing file and reads its header, leaving the file positioned at the start of the content
+#[cfg(feature = "std")]
+fn open_slot<S: Storage>(
+    storage: &S,
+    file: &Path,
+    options: &BufferedFileOptions,
+) -> std::io::Result<(S::File, SlotHeaderFields)> {
+    let mut opened = storage.open(file)?;
+    let prefix = read_prefix(&mut opened)?;
+    let header = match SlotHeader::parse(&prefix) {
+        // a file with an unknown version is only valid as a version 0 file
+        Err(HeaderError::UnsupportedVersion(_)) => FormatVersion::V0.parse_header(&prefix),
+        Ok(header)
+            if header.version() == FormatVersion::V0
+                || versioned_layout_holds(storage, file, &mut opened, &header, options)? =>
+        {
+            Ok(header)
+        }
+        // a version 0 file, whose generation and content happen to start like the magic bytes
+        header => {
+            opened.seek(SeekFrom::Start(0))?;
+            match verify_content(&mut opened, options.checksum.crc(), options.block_size())? {
+                FileCheckResult::Good { .. } => FormatVersion::V0.parse_header(&prefix),
+                _ => header,
+            }
+        }
+    };
+    let header = match header {
+        Ok(header) => header,
+        Err(HeaderError::ChecksumMismatch) => {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "The checksum of the header does not match",
+            ))
+        }
+        Err(_) => return Err(ErrorKind::UnexpectedEof.into()),
+    };
+    let written = header
+        .written()
+        .and_then(|millis| UNIX_EPOCH.checked_add(Duration::from_millis(millis)));
+    opened.seek(SeekFrom::Start(header.version().header_len()))?;
+    Ok((
+        opened,
+        SlotHeaderFields {
+            version: header.version(),
+            generation: header.generation(),
+            written,
+        },
+    ))
+}
+
+/// Checks the parts of a backing file, which tell a file of the detected `header` version apart from a version 0
+/// file starting like the magic bytes: the checksum of the header or the stored length. Version 1 stores neither,
+/// so its whole content is verified.
+#[cfg(feature = "std")]
+fn versioned_layout_holds<S: Storage>(
+    storage: &S,
+    path: &Path,
+    file: &mut S::File,
+    header: &SlotHeader,
+    options: &BufferedFileOptions,
+) -> std::io::Result<bool> {
+    let version = header.version();
+    if version.has_header_checksum() {
+        return Ok(true);
+    }
+    if version.footer_len() == 0 {
+        file.seek(SeekFrom::Start(version.header_len() - 1))?;
+        let result = verify_content(file, options.checksum.crc(), options.block_size())?;
+        return Ok(matches!(result, FileCheckResult::Good { .. }));
+    }
+    let body = storage
+        .metadata(path)?
+        .len
+        .saturating_sub(version.header_len());
+    match content_reader(
+        file,
+        version,
+        header.generation(),
+        body,
+        options.checksum.crc(),
+        options.block_size(),
+        options.aligned_sector_size(),
+    ) {
+        Ok(_) => Ok(true),
+        Err(err) if err.kind() == ErrorKind::InvalidData => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Converts a time to milliseconds since the unix epoch as stored in the header, earlier times become 0
+#[cfg(feature = "std")]
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |since| {
+        u64::try_from(since.as_millis()).unwrap_or(u64::MAX)
+    })
+}
+
+/// Reads the time a backing file has been written from its header, if it has been recorded
+#[cfg(feature = "std")]
+fn written_at(
+    storage: &impl Storage,
+    file: &Path,
+    options: &BufferedFileOptions,
+) -> Option<SystemTime> {
+    open_slot(storage, file, options).ok()?.1.written
+}
+
+/// Orders backing files holding the same generation by the time they have been written, then by their
+/// modification time
+#[cfg(feature = "std")]
+fn tie_breaker(
+    storage: &impl Storage,
+    file: &Path,
+    options: &BufferedFileOptions,
+) -> (Option<SystemTime>, Option<SystemTime>) {
+    let modified = storage
+        .metadata(file)
+        .ok()
+        .and_then(|metadata| metadata.modified);
+    (written_at(storage, file, options), modified)
+}
+
+/// Finds the length of the longest prefix of `body`, which is followed by its checksum
+#[cfg(feature = "std")]
+fn longest_checksummed_prefix(crc: &'static crc::Crc<u32>, body: &[u8]) -> Option<usize> {
+    let mut digest = ChecksumDigest::new(crc);
+    let mut longest = None;
+    for end in 0..(body.len() + 1).saturating_sub(TRAILER_LEN as usize) {
+        if body[end..end + TRAILER_LEN as usize] == digest.clone().finalize().to_le_bytes() {
+            longest = Some(end);
+        }
+        digest.update(&body[end..=end]);
+    }
+    longest
+}
+
+/// Reads the generation of a backing file without verifying its checksum
+#[cfg(feature = "std")]
+fn peek_generation(
+    storage: &impl Storage,
+    file: &Path,
+    options: &BufferedFileOptions,
+) -> Generation {
+    let read = open_slot(storage, file, options)
+        .and_then(|(_, header)| Ok((header, storage.metadata(file)?)));
+    match read {
+        Ok((header, metadata))
+            if metadata.len
+                >= header.version.header_len() + header.version.footer_len() + TRAILER_LEN =>
+        {
+            Generation::Unchecked(header.generation)
+        }
+        Ok(_) => Generation::None,
+        Err(err) if matches!(err.kind(), ErrorKind::NotFound | ErrorKind::UnexpectedEof) => {
+            Generation::None
+        }
+        Err(err) => {
+            tracing::warn!("Could not check {}: {err}", file.display());
+            Generation::None
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn check_file(
+    storage: &impl Storage,
+    path: &Path,
+    crc: &'static crc::Crc<u32>,
+    block_size: Option<u64>,
+    sector_size: Option<u64>,
+) -> std::io::Result<FileCheckResult> {
+    let mut file = storage.open(path)?;
+    // the whole file is read once, hints are only an optimization
+    let _ = file.advise(Advice::Sequential);
+    let prefix = read_prefix(&mut file)?;
+    // the generation is only trusted with an intact header, before the content is read at all
+    let result = match SlotHeader::parse(&prefix) {
+        Ok(header) => check_slot(
+            storage,
+            path,
+            &mut file,
+            &header,
+            crc,
+            block_size,
+            sector_size,
+        )?,
+        Err(HeaderError::UnsupportedVersion(version)) => {
+            FileCheckResult::UnsupportedVersion { version }
+        }
+        Err(HeaderError::ChecksumMismatch) => FileCheckResult::HeaderChecksumFailure,
+        Err(HeaderError::Truncated) => FileCheckResult::Truncated,
+    };
+    if matches!(result, FileCheckResult::Good { .. })
+        || FormatVersion::detect(&prefix) == Ok(FormatVersion::V0)
+    {
+        return Ok(result);
+    }
+    // a version 0 file, whose generation and content happen to start like the magic bytes
+    file.seek(SeekFrom::Start(0))?;
+    Ok(match verify_content(&mut file, crc, block_size)? {
+        good @ FileCheckResult::Good { .. } => good,
+        _ => result,
+    })
+}
+
+/// Verifies the content and the stored length of a backing file following its parsed `header`
+#[cfg(feature = "std")]
+fn check_slot<F: Read + Seek>(
+    storage: &impl Storage,
+    path: &Path,
+    mut file: F,
+    header: &SlotHeader,
+    crc: &'static crc::Crc<u32>,
+    block_size: Option<u64>,
+    sector_size: Option<u64>,
+) -> std::io::Result<FileCheckResult> {
+    let version = header.version();
+    file.seek(SeekFrom::Start(version.header_len() - 1))?;
+    // the verifier takes the first byte for the generation of a version 0 file, which the checksum does not cover,
+    // so it starts at the last byte of the header. The generation is taken from the whole header instead.
+    let result = match verify_content(&mut file, crc, block_size)? {
+        FileCheckResult::Good { .. } => FileCheckResult::Good {
+            generation: header.generation(),
+        },
+        result => result,
+    };
+    match result {
+        // the stored length can only be trusted, once the checksums have been verified
+        FileCheckResult::Good { generation } if version.footer_len() > 0 => {
+            let body = storage
+                .metadata(path)?
+                .len
+                .saturating_sub(version.header_len());
+            match content_reader(
+                file,
+                version,
+                generation,
+                body,
+                crc,
+                block_size,
+                sector_size,
+            ) {
+                Ok(_) => Ok(result),
+                Err(err) if err.kind() == ErrorKind::InvalidData => {
+                    Ok(FileCheckResult::LengthMismatch)
+                }
+                Err(err) => Err(err),
+            }
+        }
+        _ => Ok(result),
+    }
+}
+
+/// Reads the `body` of a backing file following its header, stripping the checksums and the stored length
+#[cfg(feature = "std")]
+fn content_reader<F: Read + Seek>(
+    file: F,
+    version: FormatVersion,
+    generation: u64,
+    body: u64,
+    crc: &'static crc::Crc<u32>,
+    block_size: Option<u64>,
+    sector_size: Option<u64>,
+) -> std::io::Result<BufferedFileReader<F>> {
+    let reader = match block_size {
+        Some(block_size) => BufferedFileReader::new(
+            file,
+            blocked_content_len(body, block_size).unwrap_or_default(),
+            generation,
+        )
+        .blocks(crc, block_size),
+        None => BufferedFileReader::new(file, body.saturating_sub(TRAILER_LEN), generation),
+    }
+    .header_len(version.header_len());
+    if version.footer_len() > 0 {
+        return reader.length_footer(sector_size);
+    }
+    Ok(reader)
+}
+
+/// Opens the contents of a backing file including the authentication code, which follows the actual contents
+#[cfg(feature = "std")]
+fn open_contents<S: Storage>(
+    storage: &S,
+    path: &Path,
+    options: &BufferedFileOptions,
+) -> std::io::Result<BufferedFileReader<S::File>> {
+    let (file, header) = open_slot(storage, path, options)?;
+    let body = storage
+        .metadata(path)?
+        .len
+        .saturating_sub(header.version.header_len());
+    let reader = content_reader(
+        file,
+        header.version,
+        header.generation,
+        body,
+        options.checksum.crc(),
+        options.block_size(),
+        options.aligned_sector_size(),
+    )?
+    .written_at(header.written)
+    .backing_file(path, Some(options.checksum));
+    // block checksums are verified while reading anyway
+    let reader = if options.streams_checksum() && options.block_size().is_none() {
+        reader.verify_while_reading(
+            options.checksum.crc(),
+            header.version.header_len() + body.saturating_sub(TRAILER_LEN),
+        )
+    } else {
+        reader
+    };
+    if header.version.has_user_metadata() {
+        return reader.user_metadata_section();
+    }
+    Ok(reader)
+}
+
+/// Verifies the checksums and, if a key has been configured, the authentication code or tag of a backing file.
+/// Damaged backing files are reported, see `BufferedFileOptions::on_corruption`.
+#[cfg(feature = "std")]
+fn verify_file(
+    storage: &impl Storage,
+    file: &Path,
+    options: &BufferedFileOptions,
+) -> std::io::Result<FileCheckResult> {
+    let result = verify_backing_file(storage, file, options)?;
+    if !matches!(result, FileCheckResult::Good { .. }) {
+        let generation = open_slot(storage, file, options)
+            .ok()
+            .map(|(_, header)| header.generation);
+        report_corruption(
+            options.on_corruption.as_ref(),
+            &CorruptionEvent {
+                path: file,
+                generation,
+                result,
+            },
+        );
+    }
+    Ok(result)
+}
+
+/// Verifies a backing file like `verify_file` without reporting damaged backing files
+#[cfg(feature = "std")]
+fn verify_backing_file(
+    storage: &impl Storage,
+    file: &Path,
+    options: &BufferedFileOptions,
+) -> std::io::Result<FileCheckResult> {
+    let result = check_file(
+        storage,
+        file,
+        options.checksum.crc(),
+        options.block_size(),
+        options.aligned_sector_size(),
+    )?;
+    #[cfg(feature = "hmac")]
+    if let (FileCheckResult::Good { .. }, Some(key)) = (&result, &options.mac_key) {
+        let mut contents = open_contents(storage, file, options)?;
+        let len = contents.len();
+        if !key.verify(&mut contents, len)? {
+            return Ok(FileCheckResult::MacMismatch);
+        }
+    }
+    #[cfg(feature = "encryption")]
+    if let (FileCheckResult::Good { .. }, Some(key)) = (&result, &options.encryption_key) {
+        let contents = open_contents(storage, file, options)?.exclude_trailer(options.mac_len());
+        // every chunk is authenticated, while it is decrypted
+        match key
+            .decrypt_reader(contents)
+            .and_then(|mut reader| std::io::copy(&mut reader, &mut std::io::sink()))
+        {
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::InvalidData => {
+                return Ok(FileCheckResult::MacMismatch)
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(result)
+}
+
+/// Verifies the content of a backing file starting at its generation.
+///
+/// The reads may return any number of bytes, the last four bytes seen are held back as the potential checksum.
+#[cfg(feature = "std")]
+fn verify_content(
+    file: &mut impl Read,
+    crc: &'static crc::Crc<u32>,
+    block_size: Option<u64>,
+) -> std::io::Result<FileCheckResult> {
+    let mut verifier = match block_size {
+        Some(block_size) => SlotVerifier::with_block_size(crc, block_size),
+        None => SlotVerifier::new(crc),
+    };
     let mut buf = [0u8; 8192];
-    let mut valid = file.read(&mut buf)?;
-    if valid < 5 {
-        return Ok(FileCheckResult::ChecksumFailure);
-    }
-    let read = &buf[..valid];
-    let generation = read[0];
-    digest.update(&read[1..read.len().saturating_sub(4)]);
-    let mut potential_expected_crc32: u32 = u32::from_le_bytes(
-        read[read.len() - 4..]
-            .try_into()
-            .expect("I should have valid bytes available"),
-    );
     loop {
-        valid = file.read(&mut buf)?;
-        match valid {
-            0 => {
-                // File is finished
-                return Ok(if digest.finalize() == potential_expected_crc32 {
-                    FileCheckResult::Good {
-                        generation: Generation::Valid(generation),
-                    }
-                } else {
-                    FileCheckResult::ChecksumFailure
-                });
-            }
-            x if x < 4 => {
-                todo!("not enough data available for a potential crc32 checksum")
-            }
-            _ => {
-                let read = &buf[..valid];
-                let (data, pot_checksum) = read.split_at(read.len() - 4);
-                potential_expected_crc32 = u32::from_le_bytes(
-                    pot_checksum
-                        .try_into()
-                        .expect("there should be 4 u8 available"),
-                );
-                digest.update(&potential_expected_crc32.to_le_bytes());
-                digest.update(data);
-            }
+        match file.read(&mut buf) {
+            Ok(0) => return Ok(verifier.finish()),
+            Ok(read) => verifier.update(&buf[..read]),
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// The lock files held within this process and the threads which have acquired them
+#[cfg(feature = "std")]
+static HELD_LOCKS: Mutex<Vec<(PathBuf, ThreadId)>> = Mutex::new(Vec::new());
+
+/// The lock of a managed file, which is released when it is dropped, see `BufferedFile::lock`
+#[cfg(feature = "std")]
+struct HeldLock<L> {
+    _lock: L,
+    path: PathBuf,
+    thread: ThreadId,
+}
+
+#[cfg(feature = "std")]
+impl<L> Drop for HeldLock<L> {
+    fn drop(&mut self) {
+        let mut held = HELD_LOCKS.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(index) = held
+            .iter()
+            .position(|(path, thread)| *path == self.path && *thread == self.thread)
+        {
+            held.swap_remove(index);
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl BufferedFile {
     /// Creates a representation of the managed file and scans all underlying files for their validity and generation.
     ///
     /// # Arguments
     /// * `path` - the path representing the desired file (this file does not exist on the filesystem)
-    ///            The backing files are stored with a suffix of .1 and .2 respectively.
+    ///   The backing files are stored with a suffix of .1 and .2 respectively.
     ///
     /// # Example
     ///
@@ -126,255 +812,2919 @@ impl BufferedFile {
     /// assert!(file.is_ok());
     /// ```
     pub fn new(path: impl AsRef<Path>) -> Result<Self, BufferedFileErrors> {
-        let files = Self::find_files(path);
-        let files = files
-            .into_iter()
-            .flat_map(|f| match check_file(&f) {
-                Ok(FileCheckResult::Good { generation }) => Ok((f, generation)),
-                Ok(FileCheckResult::ChecksumFailure) => Ok((f, Generation::None)),
-                Err(err) if err.kind() == ErrorKind::NotFound => Ok((f, Generation::None)),
-                Err(err) => Err(err),
-            })
-            .collect::<Vec<_>>();
+        BufferedFileOptions::new().open(path)
+    }
 
-        Ok(BufferedFile { files })
+    /// Creates a representation of the managed file with a custom number of rotating backing files.
+    ///
+    /// # Arguments
+    /// * `path` - the path representing the desired file (this file does not exist on the filesystem)
+    ///   The backing files are stored with a suffix of .1 up to .`count`.
+    /// * `count` - the number of backing files. Must be between 2 and 128.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multibufferedfile::BufferedFile;
+    ///
+    /// let file = BufferedFile::with_buffer_count("file.txt", 4);
+    /// assert!(file.is_ok());
+    /// ```
+    pub fn with_buffer_count(
+        path: impl AsRef<Path>,
+        count: u8,
+    ) -> Result<Self, BufferedFileErrors> {
+        BufferedFileOptions::new().buffer_count(count).open(path)
     }
 
-    /// selects the newest valid backing file
-    fn select_newest_valid(&self) -> Result<&Path, BufferedFileErrors> {
-        let file = self
-            .files
-            .iter()
-            .filter(|(_, gen)| gen.is_valid())
-            .max_by_key(|(_, gen)| match gen {
-                Generation::Valid(val) => *val,
-                _ => 0,
-            });
+    /// Creates the managed file with initial contents.
+    ///
+    /// The contents are written as the first generation while holding the update lock,
+    /// so concurrent creations can not overwrite each other.
+    /// Fails with `BufferedFileErrors::AlreadyExists` if a valid backing file exists already.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multibufferedfile::{BufferedFile, BufferedFileErrors};
+    /// # let dir = std::env::temp_dir()
+    /// #     .join(format!("multibufferedfile-create-with-doc-{}", std::process::id()));
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    ///
+    /// let file = BufferedFile::create_with(dir.join("config.txt"), b"defaults").unwrap();
+    /// assert_eq!(file.read_or_default().unwrap(), b"defaults");
+    ///
+    /// let again = BufferedFile::create_with(dir.join("config.txt"), b"other defaults");
+    /// assert!(matches!(again, Err(BufferedFileErrors::AlreadyExists)));
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn create_with(
+        path: impl AsRef<Path>,
+        contents: &[u8],
+    ) -> Result<Self, BufferedFileErrors> {
+        BufferedFileOptions::new().create_with(path, contents)
+    }
+}
 
-        match file {
-            Some((file, _)) => Ok(file),
-            None => Err(BufferedFileErrors::AllFilesInvalidError),
-        }
+#[cfg(feature = "std")]
+impl<S: Storage> BufferedFile<S> {
+    /// The storage holding the backing files
+    pub fn storage(&self) -> &S {
+        &self.storage
     }
 
-    ///
-    /// Opens the managed file for read-only access
-    pub fn read(self) -> Result<BufferedFileReader<std::fs::File>, BufferedFileErrors> {
-        let file = self.select_newest_valid()?;
-        let mut file = OpenOptions::new().read(true).open(file)?;
-        file.seek(SeekFrom::Start(1))?;
-        let usable_file_size = file.metadata()?.len().saturating_sub(5);
-        Ok(BufferedFileReader::new(file, usable_file_size))
+    /// The path representing the managed file
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
-    ///
-    /// Opens the managed file for write access
-    ///
-    pub fn write(self) -> Result<BufferedFileWriter<std::fs::File>, BufferedFileErrors> {
-        let file = self
-            .files
-            .iter()
-            .min_by(|(_, a), (_, b)| match (a, b) {
-                (Generation::Valid(a), Generation::Valid(b)) => wrapping_cmp(*a, *b),
-                (Generation::None, Generation::None) => Ordering::Equal,
-                (Generation::None, _) => Ordering::Less,
-                (_, Generation::None) => Ordering::Greater,
-            })
-            .expect("Files should contain at least one value");
+    /// The effort spent to persist the contents when a writer is finished
+    pub fn durability(&self) -> Durability {
+        self.options.durability
+    }
 
-        let current_generation = self
-            .files
-            .iter()
-            .map(|(_, gen)| match gen {
-                Generation::Valid(val) => *val,
-                _ => 0u8,
-            })
-            .max_by(|&a, &b| wrapping_cmp(a, b))
-            .expect("Files should contain at least one value");
+    /// Changes the effort spent to persist the contents for all writers opened afterwards
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.options.durability = durability;
+    }
 
-        let mut target_file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&file.0)?;
-        target_file.write_all(&[current_generation.wrapping_add(1)])?;
+    /// Writes the initial contents, if no valid backing file exists
+    pub(crate) fn initialize(&self, mut contents: impl Read) -> Result<(), BufferedFileErrors> {
+        let _lock = self.lock()?;
+        self.rescan();
+        self.verify_pending();
+        if self.files().iter().any(|(_, gen)| gen.is_valid()) {
+            return Err(BufferedFileErrors::AlreadyExists);
+        }
 
-        Ok(BufferedFileWriter::new(target_file))
+        let mut writer = self.write_locked(&UserMetadata::new())?;
+        std::io::copy(&mut contents, &mut writer)?;
+        writer.flush()?;
+        writer.commit()?;
+        Ok(())
     }
 
-    fn find_files(path: impl AsRef<Path>) -> Vec<PathBuf> {
-        let stem = path
-            .as_ref()
-            .file_name()
-            .expect("provided path should be a valid file path");
-        let ancestor = path
-            .as_ref()
-            .parent()
-            .expect("provided path should be a valid file path");
+    /// Scans the backing files with already validated options
+    pub(crate) fn scan(
+        storage: S,
+        path: impl AsRef<Path>,
+        options: BufferedFileOptions,
+    ) -> Result<Self, BufferedFileErrors> {
+        let files = Self::find_files(&path, &options);
+        for (i, file) in files.iter().enumerate() {
+            if files[..i].contains(file) {
+                return Err(BufferedFileErrors::DuplicateSlotPath(file.clone()));
+            }
+        }
+        for file in std::iter::once(path.as_ref()).chain(files.iter().map(PathBuf::as_path)) {
+            if matches!(storage.metadata(file), Ok(metadata) if metadata.is_dir) {
+                return Err(BufferedFileErrors::NotAFile(file.to_path_buf()));
+            }
+        }
+        let files = Self::check_files(&storage, files, &options);
+        let storage = Arc::new(storage);
+
+        Ok(BufferedFile {
+            path: path.as_ref().to_path_buf(),
+            files: Arc::new(Mutex::new(files)),
+            options,
+            storage,
+            leases: Arc::default(),
+        })
+    }
 
-        let mut result = Vec::with_capacity(BUFFER_COUNT.into());
-        for i in 1..=BUFFER_COUNT {
-            let mut file = ancestor.to_path_buf();
-            let mut file_name = stem.to_os_string();
-            file_name.push(format!(".{i}"));
-            file.push(file_name);
+    /// Determines the generation of every backing file.
+    /// With lazy validation only the generation is read and the checksum is verified later.
+    fn check_files(
+        storage: &S,
+        files: Vec<PathBuf>,
+        options: &BufferedFileOptions,
+    ) -> Vec<(PathBuf, Generation)> {
+        files
+            .into_iter()
+            .map(|f| {
+                let generation = if options.lazy_validation || options.verifies_while_reading() {
+                    peek_generation(storage, &f, options)
+                } else {
+                    Self::check_slot(storage, &f, options)
+                };
+                (f, generation)
+            })
+            .collect::<Vec<_>>()
+    }
 
-            result.push(file);
+    /// Verifies the checksum of a single backing file
+    fn check_slot(storage: &S, file: &Path, options: &BufferedFileOptions) -> Generation {
+        if let Some(cache) = &options.validation_cache {
+            if let Ok(metadata) = storage.metadata(file) {
+                if let Some(generation) = cache.get(file, options.checksum, &metadata) {
+                    return generation;
+                }
+                let generation = Self::verify_slot(storage, file, options);
+                cache.insert(file, options.checksum, &metadata, generation);
+                return generation;
+            }
         }
-        result
+        Self::verify_slot(storage, file, options)
     }
-}
 
-///
-/// helps comparing the generations with wrapping behaviour (assumes increments of 1)
-fn wrapping_cmp(a: u8, b: u8) -> Ordering {
-    match a.wrapping_sub(b) {
-        0 => Ordering::Equal,
-        x if x < 128 => Ordering::Greater,
-        _ => Ordering::Less,
+    /// Verifies the checksum of a single backing file bypassing the validation cache
+    fn verify_slot(storage: &S, file: &Path, options: &BufferedFileOptions) -> Generation {
+        match verify_file(storage, file, options) {
+            Ok(FileCheckResult::Good { generation }) => Generation::Valid(generation),
+            Ok(_) => Generation::None,
+            Err(err) if err.kind() == ErrorKind::NotFound => Generation::None,
+            Err(err) => {
+                tracing::warn!("Could not check {}: {err}", file.display());
+                Generation::None
+            }
+        }
     }
-}
 
-/// Provides tests for the helper function `wrapping_cmp`
-#[test]
-fn wrapping_cmp_test() {
-    assert_eq!(wrapping_cmp(0, 0), Ordering::Equal);
-    assert_eq!(wrapping_cmp(1, 1), Ordering::Equal);
-    assert_eq!(wrapping_cmp(0, 1), Ordering::Less);
-    assert_eq!(wrapping_cmp(1, 0), Ordering::Greater);
-    assert_eq!(wrapping_cmp(255, 0), Ordering::Less);
-    assert_eq!(wrapping_cmp(0, 255), Ordering::Greater);
-}
+    /// Verifies the checksums of all backing files, which have been skipped by lazy validation
+    fn verify_pending(&self) {
+        let mut files = self.files();
+        for (file, generation) in files.iter_mut() {
+            if let Generation::Unchecked(_) = generation {
+                *generation = Self::check_slot(&self.storage, file, &self.options);
+            }
+        }
+    }
 
-#[cfg(test)]
-mod tests {
-    use std::{
-        io::{Read, Write},
-        ops::BitAnd,
-    };
+    /// Scans the backing files again to pick up changes made by other instances
+    fn rescan(&self) {
+        let files = Self::find_files(&self.path, &self.options);
+        *self.files() = Self::check_files(&self.storage, files, &self.options);
+    }
 
-    use crate::{tests::utils::TempDir, BufferedFile, BufferedFileErrors};
+    /// Acquires the exclusive lock guarding updates of the managed file.
+    /// The lock is released when the returned guard is dropped.
+    ///
+    /// Fails with `ErrorKind::WouldBlock`, if the current thread holds the lock already,
+    /// as waiting for it would never end.
+    fn lock(&self) -> Result<HeldLock<S::Lock>, BufferedFileErrors> {
+        let path = self.options.lock_path(&self.path);
+        let thread = std::thread::current().id();
+        if HELD_LOCKS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains(&(path.clone(), thread))
+        {
+            return Err(std::io::Error::new(
+                ErrorKind::WouldBlock,
+                format!("{} is held by this thread already", path.display()),
+            )
+            .into());
+        }
+        if self.options.slot_dir.is_some() {
+            if let Some(parent) = path.parent() {
+                self.storage.create_dir_all(parent)?;
+            }
+        }
 
-    #[test]
-    fn new_file_gives_error_on_read() {
-        let dir = TempDir::new();
-        let file = dir.path().join("data-file.txt");
-        let managed_file = BufferedFile::new(&file)
-            .expect("It should be possible to create for not yet existing files.");
+        let lock = self.storage.lock(&path)?;
+        HELD_LOCKS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((path.clone(), thread));
+        Ok(HeldLock {
+            _lock: lock,
+            path,
+            thread,
+        })
+    }
 
-        let reader = managed_file.read();
-        assert!(
-            matches!(reader, Err(BufferedFileErrors::AllFilesInvalidError)),
-            "Reader is a {reader:?}. Expected an Err(BufferedFileErrors::AllFilesInvalidError)"
-        );
+    /// provides access to the known state of the backing files
+    fn files(&self) -> MutexGuard<'_, Vec<(PathBuf, Generation)>> {
+        self.files.lock().unwrap_or_else(PoisonError::into_inner)
     }
 
-    #[test]
-    fn can_read_a_written_file() {
-        let dir = TempDir::new();
-        let file = dir.path().join("data-file.txt");
+    /// selects the newest valid backing file.
+    /// Backing files skipped by lazy validation are verified, until a valid one is found,
+    /// unless the readers verify the checksum while reading.
+    fn select_newest_valid(&self) -> Result<(PathBuf, u64), BufferedFileErrors> {
+        let mut files = self.files();
+        loop {
+            let newest = files
+                .iter()
+                .filter_map(|(_, gen)| gen.number())
+                .max_by(|a, b| compare_wide_generations(*a, *b));
+            let mut candidates = files
+                .iter_mut()
+                .filter(|(_, gen)| newest.is_some() && gen.number() == newest)
+                .collect::<Vec<_>>();
+            // backing files holding the same generation, e.g. after concurrent writes or restored backups,
+            // are ordered by the time they have been written, the last backing file wins a complete tie
+            if candidates.len() > 1 {
+                candidates
+                    .sort_by_key(|(path, _)| tie_breaker(&*self.storage, path, &self.options));
+            }
 
-        let managed_file = BufferedFile::new(&file)
-            .expect("It should be possible to create for not yet existing files.");
-        let mut writer = managed_file.write().expect("Can not write the file");
-        writer
-            .write_all(b"Hello World")
-            .expect("Should be able to write");
-        drop(writer);
+            match candidates.pop() {
+                Some((file, Generation::Valid(generation))) => {
+                    return Ok((file.clone(), *generation))
+                }
+                Some((file, Generation::Unchecked(generation)))
+                    if self.options.verifies_while_reading() =>
+                {
+                    return Ok((file.clone(), *generation))
+                }
+                Some((file, generation)) => {
+                    *generation = Self::check_slot(&self.storage, file, &self.options)
+                }
+                None => return Err(BufferedFileErrors::AllFilesInvalidError),
+            }
+        }
+    }
 
-        let mut reader = BufferedFile::new(&file)
+    ///
+    /// Opens the managed file for read-only access
+    ///
+    /// Every call opens the newest valid backing file known to this instance,
+    /// including generations committed by writers obtained from this instance.
+    /// Each reader owns its own handle of the backing file, so several threads can read concurrently
+    /// without scanning the backing files again. Clones of the instance share its state.
+    pub fn read(&self) -> Result<BufferedFileReader<S::File>, BufferedFileErrors> {
+        match self.select_newest_valid() {
+            Ok((file, _)) => self.open_reader(&file),
+            Err(BufferedFileErrors::AllFilesInvalidError) if self.options.plain_fallback => {
+                self.open_plain()
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    ///
+    /// Opens the managed file for read-only access, if the newest generation holds contents of `content_type`.
+    ///
+    /// Fails with `BufferedFileErrors::ContentTypeMismatch` before any contents are read otherwise,
+    /// see `BufferedFileOptions::content_type`.
+    pub fn read_expecting(
+        &self,
+        content_type: &str,
+    ) -> Result<BufferedFileReader<S::File>, BufferedFileErrors> {
+        let reader = self.read()?;
+        if reader.content_type() != Some(content_type) {
+            return Err(BufferedFileErrors::ContentTypeMismatch {
+                expected: content_type.to_string(),
+                found: reader.content_type().map(String::from),
+            });
+        }
+        Ok(reader)
+    }
+
+    /// Opens a reader on the given backing file, rebuilding the contents of generations written in delta mode
+    fn open_reader(&self, path: &Path) -> Result<BufferedFileReader<S::File>, BufferedFileErrors> {
+        let reader = self.open_encoded(path)?.leased(self.lease(path));
+        if reader.user_metadata().get(DELTA_KEY).is_some() {
+            return Ok(self.rebuild(reader)?);
+        }
+        Ok(reader)
+    }
+
+    /// Opens a reader on the contents of the given backing file as they are stored
+    fn open_encoded(&self, path: &Path) -> Result<BufferedFileReader<S::File>, BufferedFileErrors> {
+        let mut reader = open_contents(&*self.storage, path, &self.options)?
+            .exclude_trailer(self.options.mac_len());
+        if self.options.streams_checksum() {
+            // later readers skip the backing file, once it turned out to be corrupted
+            let files = Arc::clone(&self.files);
+            let path = path.to_path_buf();
+            let corrupted = reader.generation();
+            reader = reader.on_corrupt(Box::new(move || {
+                let mut files = files.lock().unwrap_or_else(PoisonError::into_inner);
+                for (file, generation) in files.iter_mut() {
+                    if *file == path && generation.number() == Some(corrupted) {
+                        *generation = Generation::None;
+                    }
+                }
+            }));
+        }
+        #[cfg(feature = "encryption")]
+        if let Some(key) = &self.options.encryption_key {
+            return match key.decrypt_reader(reader) {
+                Ok(reader) => Ok(reader),
+                // modified since it has been verified, later readers skip the backing file
+                Err(err) if err.kind() == ErrorKind::InvalidData => {
+                    let mut files = self.files();
+                    for (file, generation) in files.iter_mut() {
+                        if file == path {
+                            *generation = Generation::None;
+                        }
+                    }
+                    Err(BufferedFileErrors::AuthenticationFailed(path.to_path_buf()))
+                }
+                Err(err) => Err(err.into()),
+            };
+        }
+        Ok(reader)
+    }
+
+    ///
+    /// The generation of the newest valid backing file, which would be opened by `read`.
+    ///
+    /// The generation is taken from the last scan, so writes of other instances or processes
+    /// are only visible after calling `refresh`.
+    pub fn latest_generation(&self) -> Option<u64> {
+        self.select_newest_valid()
+            .ok()
+            .map(|(_, generation)| generation)
+    }
+
+    ///
+    /// Lists all valid generations retained in the backing files, starting with the newest one.
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        self.verify_pending();
+        let mut history = self
+            .files()
+            .iter()
+            .filter_map(|(path, gen)| match gen {
+                Generation::Valid(generation) => Some(HistoryEntry {
+                    generation: *generation,
+                    path: path.clone(),
+                }),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        history.sort_by(|a, b| compare_wide_generations(b.generation, a.generation));
+        history
+    }
+
+    ///
+    /// Opens a retained older generation for read-only access.
+    ///
+    /// Fails with `BufferedFileErrors::GenerationNotFound` if no valid backing file holds this generation.
+    pub fn read_generation(
+        &self,
+        generation: u64,
+    ) -> Result<BufferedFileReader<S::File>, BufferedFileErrors> {
+        self.verify_pending();
+        let file = self
+            .files()
+            .iter()
+            .find(|(_, gen)| *gen == Generation::Valid(generation))
+            .map(|(file, _)| file.clone())
+            .ok_or(BufferedFileErrors::GenerationNotFound(generation))?;
+        self.open_reader(&file)
+    }
+
+    ///
+    /// Recovers the longest verifiable prefix of the contents of the newest backing file, even if it is damaged.
+    ///
+    /// Contents protected by `BufferedFileOptions::block_checksums` are recovered up to the first damaged block.
+    /// Otherwise the newest valid contents are returned, if there are any. If all backing files are damaged,
+    /// the newest one is searched for the longest prefix followed by its checksum, which recovers backing files
+    /// with garbage appended to them. Encrypted contents are never recovered partially.
+    pub fn salvage(&self) -> Result<Salvaged, BufferedFileErrors> {
+        if self.options.is_encrypted() {
+            return self.salvage_valid();
+        }
+        let block_size = self.options.block_size();
+        if block_size.is_none() && self.exists() {
+            return self.salvage_valid();
+        }
+        let paths = self
+            .files()
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        let generations = paths
+            .iter()
+            .map(|path| peek_generation(&*self.storage, path, &self.options).number())
+            .collect::<Vec<_>>();
+        let newest = match select_newest(&generations) {
+            Some(newest) => newest,
+            None => return Ok(Salvaged::default()),
+        };
+        let path = &paths[newest];
+
+        let (
+            mut file,
+            SlotHeaderFields {
+                version,
+                generation,
+                ..
+            },
+        ) = open_slot(&*self.storage, path, &self.options)?;
+        let trailer_len = (version.footer_len() + self.options.mac_len()) as usize;
+        let body = self
+            .storage
+            .metadata(path)?
+            .len
+            .saturating_sub(version.header_len());
+        let mut contents = Vec::new();
+        let complete = match block_size {
+            Some(block_size) => {
+                // a truncated backing file ends with an incomplete block, which is not part of the contents
+                let chunk = block_size + TRAILER_LEN;
+                let len = body / chunk * block_size + (body % chunk).saturating_sub(TRAILER_LEN);
+                let reader = BufferedFileReader::new(file, len, generation)
+                    .blocks(self.options.checksum.crc(), block_size)
+                    .header_len(version.header_len());
+                let reader = if version.has_user_metadata() {
+                    reader.user_metadata_section()
+                } else {
+                    Ok(reader)
+                };
+                match reader.and_then(|mut reader| reader.read_to_end(&mut contents)) {
+                    // the stored length and the authentication code can only be told apart from the contents
+                    // in a complete backing file
+                    Ok(_) => {
+                        contents.truncate(contents.len().saturating_sub(trailer_len));
+                        matches!(
+                            verify_file(&*self.storage, path, &self.options),
+                            Ok(FileCheckResult::Good { .. })
+                        )
+                    }
+                    Err(err) => {
+                        tracing::warn!(
+                            "Salvaged {} bytes of {}: {err}",
+                            contents.len(),
+                            path.display()
+                        );
+                        false
+                    }
+                }
+            }
+            None => {
+                file.read_to_end(&mut contents)?;
+                let prefix = longest_checksummed_prefix(self.options.checksum.crc(), &contents);
+                contents.truncate(prefix.unwrap_or(0));
+                if version.has_user_metadata() {
+                    let section = match contents.first_chunk::<2>() {
+                        Some(len) => usize::from(u16::from_le_bytes(*len)) + len.len(),
+                        None => 0,
+                    };
+                    contents.drain(..section.min(contents.len()));
+                }
+                contents.truncate(contents.len().saturating_sub(trailer_len));
+                tracing::warn!(
+                    "Salvaged {} bytes of {} by searching for a checksum",
+                    contents.len(),
+                    path.display()
+                );
+                false
+            }
+        };
+        Ok(Salvaged {
+            contents,
+            path: Some(path.clone()),
+            generation: Some(generation),
+            complete,
+        })
+    }
+
+    /// Recovers the newest valid contents completely
+    fn salvage_valid(&self) -> Result<Salvaged, BufferedFileErrors> {
+        let (path, generation) = match self.select_newest_valid() {
+            Ok(newest) => newest,
+            Err(BufferedFileErrors::AllFilesInvalidError) => return Ok(Salvaged::default()),
+            Err(err) => return Err(err),
+        };
+        let mut contents = Vec::new();
+        self.open_reader(&path)?.read_to_end(&mut contents)?;
+        Ok(Salvaged {
+            contents,
+            path: Some(path),
+            generation: Some(generation),
+            complete: true,
+        })
+    }
+
+    ///
+    /// Makes the previous valid generation the newest one again.
+    ///
+    /// The header of the previous backing file is rewritten with a generation following the newest generation,
+    /// while its content and checksum stay untouched. The undone generation is overwritten by the next write.
+    /// Returns the new generation of the restored content.
+    ///
+    /// A previous generation stored in a single byte can not follow a generation above 255
+    /// (see `FormatVersion::V5`), so it is not available for a rollback.
+    pub fn rollback(&self) -> Result<u64, BufferedFileErrors> {
+        let _lock = self.lock()?;
+        self.rescan();
+
+        let history = self.history();
+        let (newest, previous) = match history.as_slice() {
+            [newest, previous, ..] => (newest, previous),
+            _ => return Err(BufferedFileErrors::NoPreviousGeneration),
+        };
+        let (_, header) = open_slot(&*self.storage, &previous.path, &self.options)?;
+        let generation_len = header.version.generation_len();
+        let generation = next_generation(Some(newest.generation), generation_len > 1);
+        if generation_len == 1 && generation > u64::from(u8::MAX) {
+            return Err(BufferedFileErrors::NoPreviousGeneration);
+        }
+
+        let written = header.written.map_or(0, unix_millis);
+        self.rewrite_header(
+            &previous.path,
+            header.version.header_written_at(generation, written),
+        )?;
+
+        let mut files = self.files();
+        if let Some(slot) = files.iter_mut().find(|(path, _)| *path == previous.path) {
+            slot.1 = Generation::Valid(generation);
+        }
+        Ok(generation)
+    }
+
+    /// Replaces the header of the backing file at `path`, leaving its contents untouched
+    fn rewrite_header(&self, path: &Path, header: SlotHeader) -> std::io::Result<()> {
+        let mut file = self.storage.open_write(path)?;
+        file.write_all(header.as_ref())?;
+        if self.options.durability >= Durability::Flush {
+            file.flush()?;
+        }
+        if self.options.durability >= Durability::Fsync {
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Copies the backing file `source` as the next generation, only its header is written anew.
+    ///
+    /// The copy is staged and replaces the selected backing file, once its header has been written. The storage
+    /// may share the contents of both files, see `Storage::copy`. Returns `None` without copying anything, if
+    /// `source` has not been written with the configured format version or can not hold the next generation.
+    fn clone_slot(&self, source: &Path) -> Result<Option<u64>, BufferedFileErrors> {
+        let (_, header) = open_slot(&*self.storage, source, &self.options)?;
+        // a difference refers to its base by the generation, which does not exist in other managed files
+        if open_contents(&*self.storage, source, &self.options)?
+            .user_metadata()
+            .get(DELTA_KEY)
+            .is_some()
+        {
+            return Ok(None);
+        }
+        let version = self.options.format_version;
+        let (files, index, generation) = self.select_writable(version.generation_len() > 1)?;
+        drop(files);
+        if header.version != version
+            || (generation > u64::from(u8::MAX) && version.generation_len() == 1)
+        {
+            return Ok(None);
+        }
+
+        let file = self.files()[index].0.clone();
+        let mut name = file.clone().into_os_string();
+        name.push(".tmp");
+        let staged = PathBuf::from(name);
+        if self.options.slot_dir.is_some() {
+            if let Some(parent) = file.parent() {
+                self.storage.create_dir_all(parent)?;
+            }
+        }
+        self.storage.copy(source, &staged)?;
+        let written = unix_millis(SystemTime::now());
+        if let Err(err) =
+            self.rewrite_header(&staged, version.header_written_at(generation, written))
+        {
+            // the backing file is still untouched, the incomplete copy would only occupy space
+            let _ = self.storage.remove(&staged);
+            return Err(err.into());
+        }
+        self.storage.rename(&staged, &file)?;
+        if let Some(cache) = &self.options.validation_cache {
+            cache.invalidate(&file);
+        }
+        if self.options.durability >= Durability::FsyncAndDir {
+            if let Some(parent) = file.parent() {
+                self.storage.sync_dir(parent)?;
+            }
+        }
+        self.files()[index].1 = Generation::Valid(generation);
+        Ok(Some(generation))
+    }
+
+    ///
+    /// Restores the redundancy by copying the newest valid content over every invalid or missing backing file.
+    ///
+    /// Every copy is written as a new generation. If the newest backing file has been written with the configured
+    /// format version, it is copied by the storage and only the header is written anew, which shares the contents
+    /// on file systems supporting reflinks (see `Storage::copy`). Otherwise the contents are streamed into the
+    /// copy with a freshly computed checksum. Returns the paths of the repaired backing files.
+    pub fn repair(&self) -> Result<Vec<PathBuf>, BufferedFileErrors> {
+        let _lock = self.lock()?;
+        self.rescan();
+        self.verify_pending();
+
+        let mut repaired = Vec::new();
+        loop {
+            let target = self
+                .files()
+                .iter()
+                .find(|(_, gen)| !gen.is_valid())
+                .map(|(path, _)| path.clone());
+            let target = match target {
+                Some(target) => target,
+                None => return Ok(repaired),
+            };
+
+            let (newest, _) = self.select_newest_valid()?;
+            if self.clone_slot(&newest)?.is_none() {
+                let mut reader = self.open_reader(&newest)?;
+                let mut writer = self.write_locked(&UserMetadata::new())?;
+                std::io::copy(&mut reader, &mut writer)?;
+                writer.flush()?;
+                writer.commit()?;
+            }
+
+            let committed = self
+                .files()
+                .iter()
+                .any(|(path, gen)| *path == target && gen.is_valid());
+            if !committed {
+                return Err(std::io::Error::other(format!(
+                    "Could not finish the copy to {}",
+                    target.display()
+                ))
+                .into());
+            }
+            repaired.push(target);
+        }
+    }
+
+    ///
+    /// Rewrites the valid backing files written with an older format version in the configured one.
+    ///
+    /// Every backing file keeps its generation, its contents, its user metadata and its write time if it has
+    /// been recorded, otherwise its modification time is taken. The rewritten backing file is staged and
+    /// replaces the old one, once it is complete. Backing files of the configured or a later version are left
+    /// untouched. Returns the paths of the rewritten backing files.
+    pub fn upgrade_format(&self) -> Result<Vec<PathBuf>, BufferedFileErrors> {
+        let _lock = self.lock()?;
+        self.rescan();
+        self.verify_pending();
+
+        let version = self.options.format_version;
+        let slots = self
+            .files()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (path, gen))| match gen {
+                Generation::Valid(generation) => Some((index, path.clone(), *generation)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let mut upgraded = Vec::new();
+        for (index, path, generation) in slots {
+            let (_, header) = open_slot(&*self.storage, &path, &self.options)?;
+            if header.version as u8 >= version as u8 {
+                continue;
+            }
+            let written = match header.written {
+                Some(written) => Some(written),
+                None => self.storage.metadata(&path)?.modified,
+            }
+            .map_or(0, unix_millis);
+
+            let mut reader = self.open_reader(&path)?;
+            let section = match version.has_user_metadata() {
+                true => Some(reader.user_metadata().encode()?),
+                false => None,
+            };
+            let mut writer = self.start_generation(
+                self.files(),
+                index,
+                generation,
+                version.header_written_at(generation, written),
+                true,
+                section,
+            )?;
+            std::io::copy(&mut reader, &mut writer)?;
+            writer.flush()?;
+            writer.commit()?;
+
+            let committed = self
+                .files()
+                .get(index)
+                .is_some_and(|(_, gen)| *gen == Generation::Valid(generation));
+            if !committed {
+                return Err(std::io::Error::other(format!(
+                    "Could not finish the upgrade of {}",
+                    path.display()
+                ))
+                .into());
+            }
+            upgraded.push(path);
+        }
+        Ok(upgraded)
+    }
+
+    ///
+    /// Removes backing files numbered above the configured buffer count.
+    ///
+    /// Such files are left behind, when the number of retained generations is reduced.
+    /// Returns the paths of the removed files.
+    pub fn prune(&self) -> Result<Vec<PathBuf>, BufferedFileErrors> {
+        let mut removed = Vec::new();
+        for slot in self.options.buffer_count.saturating_add(1)..=u8::MAX {
+            let file = self.options.slot_path(&self.path, slot);
+            match self.storage.remove(&file) {
+                Ok(()) => removed.push(file),
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(removed)
+    }
+
+    ///
+    /// Scans the backing files again to pick up changes made by other instances or processes.
+    pub fn refresh(&self) {
+        self.rescan()
+    }
+
+    ///
+    /// Reads the whole content of the managed file.
+    /// Returns empty content, if no valid backing file exists.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multibufferedfile::BufferedFile;
+    ///
+    /// let file = BufferedFile::new("not-yet-existing.txt").unwrap();
+    /// assert!(file.read_or_default().unwrap().is_empty());
+    /// ```
+    pub fn read_or_default(&self) -> Result<Vec<u8>, BufferedFileErrors> {
+        self.read_or_init(Vec::new)
+    }
+
+    ///
+    /// Reads the whole content of the managed file.
+    /// Returns the content provided by `init`, if no valid backing file exists.
+    ///
+    /// The initial content is not written to the managed file.
+    pub fn read_or_init(
+        &self,
+        init: impl FnOnce() -> Vec<u8>,
+    ) -> Result<Vec<u8>, BufferedFileErrors> {
+        match self.read_to_vec() {
+            Err(BufferedFileErrors::AllFilesInvalidError) => Ok(init()),
+            result => result,
+        }
+    }
+
+    ///
+    /// Reads the whole content of the newest valid generation.
+    ///
+    /// Fails with `BufferedFileErrors::AllFilesInvalidError` if no valid backing file exists
+    /// and with `BufferedFileErrors::ContentsTooLarge` if the contents exceed `BufferedFileOptions::max_read_len`.
+    /// The checksum is verified before the contents are read,
+    /// or while they are read with `BufferedFileOptions::verify_while_reading`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multibufferedfile::BufferedFileOptions;
+    /// # let dir = std::env::temp_dir().join("multibufferedfile-read-to-vec-doc");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    ///
+    /// let file = BufferedFileOptions::new()
+    ///     .max_read_len(1024)
+    ///     .create_with(dir.join("greeting.txt"), b"Hello World")
+    ///     .unwrap();
+    /// assert_eq!(file.read_to_vec().unwrap(), b"Hello World");
+    /// assert_eq!(file.read_to_string().unwrap(), "Hello World");
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn read_to_vec(&self) -> Result<Vec<u8>, BufferedFileErrors> {
+        self.read()?
+            .read_to_vec_limited(self.options.max_read_len.unwrap_or(u64::MAX))
+    }
+
+    ///
+    /// Reads the whole content of the newest valid generation as UTF-8, see `read_to_vec`.
+    ///
+    /// Fails with `ErrorKind::InvalidData` if the contents are not valid UTF-8.
+    pub fn read_to_string(&self) -> Result<String, BufferedFileErrors> {
+        String::from_utf8(self.read_to_vec()?)
+            .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err).into())
+    }
+
+    ///
+    /// Atomically replaces the content of the managed file.
+    ///
+    /// The newest content is read and handed to `f`. The returned content is written as a new generation.
+    /// If no valid backing file exists, `f` receives empty content.
+    ///
+    /// An exclusive lock file next to the backing files is held during the whole operation,
+    /// so concurrent calls to `update` and writers opened with `write` from other instances or processes
+    /// are serialized. Opening a writer or updating the managed file from within `f` fails with
+    /// `ErrorKind::WouldBlock`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multibufferedfile::BufferedFile;
+    /// # let dir = std::env::temp_dir().join("multibufferedfile-update-doc");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    ///
+    /// let file = BufferedFile::new(dir.join("counter.txt")).unwrap();
+    /// file.update(|old| {
+    ///     let count = old.first().copied().unwrap_or(0);
+    ///     vec![count.wrapping_add(1)]
+    /// }).unwrap();
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn update(&self, f: impl FnOnce(&[u8]) -> Vec<u8>) -> Result<(), BufferedFileErrors> {
+        let _lock = self.lock()?;
+        self.rescan();
+
+        // the old contents are replaced anyway, so `max_read_len` does not keep them from being updated
+        let old = match self.read() {
+            Ok(mut reader) => reader.read_to_vec_limited(u64::MAX)?,
+            Err(BufferedFileErrors::AllFilesInvalidError) => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        let contents = f(&old);
+        let mut writer = self.write_locked(&UserMetadata::new())?;
+        writer.write_all(&contents)?;
+        writer.flush()?;
+        writer.commit()?;
+        Ok(())
+    }
+
+    ///
+    /// Removes all backing files of the managed file.
+    ///
+    /// The lock file used by `update` is removed as well, as is the slot directory, if one is configured and
+    /// it is empty afterwards. Returns the paths of the files, which have actually been removed.
+    pub fn delete(self) -> Result<Vec<PathBuf>, BufferedFileErrors> {
+        let mut removed = Vec::new();
+        let files = self
+            .files()
+            .iter()
+            .map(|(file, _)| file.clone())
+            .chain([
+                self.options.pin_path(&self.path),
+                self.options.lock_path(&self.path),
+            ])
+            .collect::<Vec<_>>();
+        for file in files {
+            match self.storage.remove(&file) {
+                Ok(()) => removed.push(file),
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        if self.options.slot_dir.is_some() {
+            if let Some(dir) = self.options.lock_path(&self.path).parent() {
+                match self.storage.remove_dir(dir) {
+                    Ok(()) => {}
+                    Err(err)
+                        if matches!(
+                            err.kind(),
+                            ErrorKind::NotFound | ErrorKind::DirectoryNotEmpty
+                        ) => {}
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+
+        Ok(removed)
+    }
+
+    ///
+    /// Moves all backing files to the managed file at `new_path`.
+    ///
+    /// The backing files are renamed one by one, followed by the record of the pinned backing file, if any. If a rename fails, the already moved backing files
+    /// are moved back, so the managed file stays complete at its original location.
+    /// Fails with `ErrorKind::AlreadyExists` if a backing file exists at the new location already.
+    pub fn rename(self, new_path: impl AsRef<Path>) -> Result<BufferedFile<S>, BufferedFileErrors> {
+        BufferedFileOptions::check_path(new_path.as_ref())?;
+        let lock = self.lock()?;
+        self.rescan();
+
+        let targets = Self::find_files(&new_path, &self.options);
+        for (i, target) in targets.iter().enumerate() {
+            if targets[..i].contains(target) {
+                return Err(BufferedFileErrors::DuplicateSlotPath(target.clone()));
+            }
+        }
+        let pin_target = self.options.pin_path(new_path.as_ref());
+        for target in targets.iter().chain([&pin_target]) {
+            if self.storage.metadata(target).is_ok() {
+                return Err(std::io::Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{} exists already", target.display()),
+                )
+                .into());
+            }
+        }
+        if self.options.slot_dir.is_some() {
+            if let Some(parent) = self.options.lock_path(new_path.as_ref()).parent() {
+                self.storage.create_dir_all(parent)?;
+            }
+        }
+
+        let sources = self
+            .files()
+            .iter()
+            .map(|(file, _)| file.clone())
+            .chain([self.options.pin_path(&self.path)])
+            .collect::<Vec<_>>();
+        let mut moved = Vec::with_capacity(sources.len());
+        for (source, target) in sources.iter().zip(targets.iter().chain([&pin_target])) {
+            match self.storage.rename(source, target) {
+                Ok(()) => moved.push((source, target)),
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => {
+                    for (source, target) in moved.into_iter().rev() {
+                        if let Err(rollback) = self.storage.rename(target, source) {
+                            tracing::error!(
+                                "Could not move {} back to {}: {rollback}",
+                                target.display(),
+                                source.display()
+                            );
+                        }
+                    }
+                    return Err(err.into());
+                }
+            }
+        }
+
+        drop(lock);
+        let _ = self.storage.remove(&self.options.lock_path(&self.path));
+
+        let files = targets
+            .into_iter()
+            .zip(self.files().iter().map(|(_, gen)| *gen))
+            .collect::<Vec<_>>();
+        Ok(BufferedFile {
+            path: new_path.as_ref().to_path_buf(),
+            files: Arc::new(Mutex::new(files)),
+            options: self.options,
+            storage: self.storage,
+            leases: self.leases,
+        })
+    }
+
+    ///
+    /// Copies the newest valid content into the managed file at `other_path`.
+    ///
+    /// The destination uses the same options as this file and receives the content as its next generation.
+    /// Like with `repair` the newest backing file is copied by the storage, if it has been written with the
+    /// configured format version, otherwise the checksum of the destination is computed while the content is streamed.
+    /// The lock of the destination is held during the whole copy, so it waits for writers of the destination.
+    pub fn copy_to(
+        &self,
+        other_path: impl AsRef<Path>,
+    ) -> Result<BufferedFile<S>, BufferedFileErrors>
+    where
+        S: Clone,
+    {
+        let (newest, _) = self.select_newest_valid()?;
+        let destination = self.options.open_in(S::clone(&self.storage), other_path)?;
+        let lock = destination.lock()?;
+        destination.rescan();
+        if destination.clone_slot(&newest)?.is_none() {
+            let mut reader = self.open_reader(&newest)?;
+            let mut writer = destination.write_locked(&UserMetadata::new())?;
+            std::io::copy(&mut reader, &mut writer)?;
+            writer.flush()?;
+            writer.commit()?;
+        }
+        drop(lock);
+        Ok(destination)
+    }
+
+    ///
+    /// Writes the contents of the newest valid generation as a new generation of `target`,
+    /// returning the number of bytes copied.
+    ///
+    /// Unlike `copy_to`, `target` may use other options or another storage. Only the contents are copied,
+    /// the user metadata of `target` is taken from its own options. See `BufferedFileReader::copy_into`.
+    pub fn replicate_to<T: Storage>(
+        &self,
+        target: &BufferedFile<T>,
+    ) -> Result<u64, BufferedFileErrors> {
+        let mut reader = self.read()?;
+        let mut writer = target.write()?;
+        let copied = reader.copy_into(&mut writer)?;
+        writer.flush()?;
+        writer.commit()?;
+        Ok(copied)
+    }
+
+    ///
+    /// Opens the managed file for write access
+    ///
+    /// The new generation becomes visible to `read` of this instance, once it has been committed with
+    /// `BufferedFileWriter::commit`. A writer dropped without being committed discards the new generation.
+    ///
+    /// The writer holds the lock file used by `update` until it is committed or dropped, so opening a writer
+    /// waits for other writers and updates of the managed file. Opening a second writer or calling `update`,
+    /// while the same thread holds a writer of the managed file, fails with `ErrorKind::WouldBlock` instead.
+    ///
+    pub fn write(&self) -> Result<BufferedFileWriter<S::File>, BufferedFileErrors> {
+        self.write_with_metadata(&UserMetadata::new())
+    }
+
+    ///
+    /// Opens the managed file for write access, attaching the user metadata to the new generation.
+    ///
+    /// Metadata can only be stored with `FormatVersion::V4` or later, other versions only accept empty metadata.
+    /// The configured content type is added to the metadata.
+    ///
+    /// Once a generation above 255 has been written, every following generation is written with
+    /// `FormatVersion::V5`, if the configured version stores the generation in a single byte.
+    pub fn write_with_metadata(
+        &self,
+        metadata: &UserMetadata,
+    ) -> Result<BufferedFileWriter<S::File>, BufferedFileErrors> {
+        let lock = self.lock()?;
+        Ok(self.write_locked(metadata)?.holding(Box::new(lock)))
+    }
+
+    /// Opens a writer like `write_with_metadata` for callers holding the lock already
+    fn write_locked(
+        &self,
+        metadata: &UserMetadata,
+    ) -> Result<BufferedFileWriter<S::File>, BufferedFileErrors> {
+        let delta = self.options.delta_writes && self.options.format_version.has_user_metadata();
+        let base = if delta { self.delta_base() } else { None };
+        let mut version = self.options.format_version;
+        let (files, index, generation) = self.select_writable(version.generation_len() > 1)?;
+        let generations = files
+            .iter()
+            .map(|(_, gen)| gen.number())
+            .collect::<Vec<_>>();
+        if generation > u64::from(u8::MAX) && version.generation_len() == 1 {
+            version = FormatVersion::V5;
+        }
+        let mut metadata = metadata.clone();
+        if let Some(content_type) = &self.options.content_type {
+            metadata.insert(CONTENT_TYPE_KEY, content_type.as_bytes());
+        }
+        if delta {
+            metadata.insert(DELTA_KEY, Vec::new());
+        }
+        let section = match (version.has_user_metadata(), metadata.is_empty()) {
+            (true, _) => Some(metadata.encode()?),
+            (false, true) => None,
+            (false, false) => return Err(BufferedFileErrors::MetadataNotSupported(version)),
+        };
+        let stage = match self.options.commit_strategy {
+            // the backing file being replaced may hold the base of the newest generation
+            _ if delta => true,
+            CommitStrategy::InPlace => false,
+            CommitStrategy::Rename => true,
+            CommitStrategy::PreserveValid => generations[index].is_some(),
+        };
+        let written = unix_millis(SystemTime::now());
+        let writer = self.start_generation(
+            files,
+            index,
+            generation,
+            version.header_written_at(generation, written),
+            stage,
+            section,
+        )?;
+        if delta {
+            return Ok(writer.delta(base));
+        }
+        Ok(writer)
+    }
+
+    /// Opens a writer on the backing file at `index`, which holds `generation` once the writer is finished.
+    /// A `stage`d generation is written to a temporary file, which replaces the backing file on commit.
+    fn start_generation(
+        &self,
+        mut files: MutexGuard<'_, Vec<(PathBuf, Generation)>>,
+        index: usize,
+        generation: u64,
+        header: SlotHeader,
+        stage: bool,
+        section: Option<Vec<u8>>,
+    ) -> Result<BufferedFileWriter<S::File>, BufferedFileErrors> {
+        // the header determines the layout of the following contents
+        let version = FormatVersion::detect(header.as_ref()).unwrap_or_default();
+        let generations = files
+            .iter()
+            .map(|(_, gen)| gen.number())
+            .collect::<Vec<_>>();
+        let file = files[index].0.clone();
+        let target = if stage {
+            let mut name = file.clone().into_os_string();
+            name.push(".tmp");
+            PathBuf::from(name)
+        } else {
+            file.clone()
+        };
+        let previous = match select_newest(&generations) {
+            Some(newest) => self.storage.metadata(&files[newest].0).ok(),
+            None => None,
+        };
+
+        if self.options.slot_dir.is_some() {
+            if let Some(parent) = file.parent() {
+                self.storage.create_dir_all(parent)?;
+            }
+        }
+
+        let mut target_file = self.storage.create(&target, self.options.mode)?;
+        if let Some(previous) = previous {
+            if let (true, Some(mode)) = (self.options.preserve_permissions, previous.mode) {
+                self.storage.set_mode(&target, mode)?;
+            }
+            if let (true, Some(owner)) = (self.options.preserve_owner, previous.owner) {
+                self.storage.set_owner(&target, owner)?;
+            }
+        }
+        if target == file {
+            files[index].1 = Generation::None;
+        }
+        if let Some(cache) = &self.options.validation_cache {
+            cache.invalidate(&file);
+        }
+        // with aligned sectors, the generation is only claimed once the rest has been written
+        let sector_size = self
+            .options
+            .aligned_sector_size()
+            .filter(|_| version.footer_len() > 0);
+        match sector_size {
+            Some(_) => {
+                target_file.write_all(&[0; MAX_HEADER_LEN as usize][..header.as_ref().len()])?
+            }
+            None => target_file.write_all(header.as_ref())?,
+        }
+
+        let state = Arc::clone(&self.files);
+        let storage = Arc::clone(&self.storage);
+        let cache = self.options.validation_cache.clone();
+        let durability = self.options.durability;
+        let mut writer =
+            BufferedFileWriter::new(target_file, self.options.checksum.crc(), durability)
+                .generation(generation);
+        if self.options.sparse {
+            writer = writer.sparse();
+        }
+        if let Some(block_size) = self.options.block_size() {
+            writer = writer.blocks(block_size);
+        }
+        if version.footer_len() > 0 {
+            writer = writer.length_footer();
+        }
+        if let Some(sector_size) = sector_size {
+            writer = writer
+                .align_to(sector_size, version.header_len())
+                .deferred_header(header);
+        }
+        #[cfg(feature = "encryption")]
+        if let Some(key) = &self.options.encryption_key {
+            writer = writer.encrypt(key);
+        }
+        #[cfg(feature = "hmac")]
+        if let Some(key) = &self.options.mac_key {
+            writer = writer.authenticate(key);
+        }
+        if target != file {
+            // an unfinished staged file would only occupy space, the backing file is still intact
+            let storage = Arc::clone(&self.storage);
+            let target = target.clone();
+            writer = writer.on_abort(Box::new(move || storage.remove(&target)));
+        }
+        let mut writer = writer
+            .on_sync(S::File::sync_all)
+            .on_commit(Box::new(move || {
+                if target != file {
+                    storage.rename(&target, &file)?;
+                    if let Some(cache) = cache {
+                        cache.invalidate(&file);
+                    }
+                }
+                if durability >= Durability::FsyncAndDir {
+                    if let Some(parent) = file.parent() {
+                        storage.sync_dir(parent)?;
+                    }
+                }
+                let mut files = state.lock().unwrap_or_else(PoisonError::into_inner);
+                files[index].1 = Generation::Valid(generation);
+                Ok(())
+            }));
+        if let Some(section) = section {
+            writer.user_metadata(&section)?;
+        }
+        Ok(writer)
+    }
+
+    fn find_files(path: impl AsRef<Path>, options: &BufferedFileOptions) -> Vec<PathBuf> {
+        (1..=options.buffer_count)
+            .map(|i| options.slot_path(path.as_ref(), i))
+            .collect()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        num::NonZeroU32,
+        ops::BitAnd,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use crate::{
+        tests::utils::TempDir, Advice, BufferedFile, BufferedFileErrors, BufferedFileOptions,
+        CommitStrategy, Durability, FormatVersion, SlotHeader, SlotOutcome, UserMetadata,
+    };
+
+    /// Hands out at most `limit` bytes per read
+    struct ShortReads<'a> {
+        data: &'a [u8],
+        limit: usize,
+    }
+
+    impl Read for ShortReads<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let count = buf.len().min(self.limit).min(self.data.len());
+            buf[..count].copy_from_slice(&self.data[..count]);
+            self.data = &self.data[count..];
+            Ok(count)
+        }
+    }
+
+    #[test]
+    fn verification_handles_any_read_sizes() {
+        let crc = crate::ChecksumAlgorithm::default().crc();
+        let verify = |file: &[u8], limit| {
+            let mut reader = ShortReads { data: file, limit };
+            crate::verify_content(&mut reader, crc, None).unwrap()
+        };
+        let sizes = (0..12).chain(8180..8200).chain(16370..16400);
+        for size in sizes {
+            let content = (0..size).map(|i| (i * 7 % 251) as u8).collect::<Vec<_>>();
+            let mut file = vec![3u8];
+            file.extend_from_slice(&content);
+            file.extend_from_slice(&crc.checksum(&content).to_le_bytes());
+            for limit in [1, 3, 4, 5, 4095, 8191, 8192, 8193, usize::MAX] {
+                assert_eq!(
+                    verify(&file, limit),
+                    crate::FileCheckResult::Good { generation: 3 },
+                    "size {size}, limit {limit}"
+                );
+            }
+
+            let mut damaged = file.clone();
+            damaged[1 + size / 2] ^= 1;
+            assert!(matches!(
+                verify(&damaged, 4093),
+                crate::FileCheckResult::ChecksumFailure { .. }
+            ));
+        }
+        for len in 0..5 {
+            assert_eq!(
+                verify(&[3, 0, 0, 0, 0][..len], 1),
+                crate::FileCheckResult::Truncated
+            );
+        }
+    }
+
+    #[test]
+    fn new_file_gives_error_on_read() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFile::new(&file)
+            .expect("It should be possible to create for not yet existing files.");
+
+        let reader = managed_file.read();
+        assert!(
+            matches!(reader, Err(BufferedFileErrors::AllFilesInvalidError)),
+            "Reader is a {reader:?}. Expected an Err(BufferedFileErrors::AllFilesInvalidError)"
+        );
+    }
+
+    #[test]
+    fn can_read_a_written_file() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+
+        let managed_file = BufferedFile::new(&file)
+            .expect("It should be possible to create for not yet existing files.");
+        let mut writer = managed_file.write().expect("Can not write the file");
+        writer
+            .write_all(b"Hello World")
+            .expect("Should be able to write");
+        writer.commit().unwrap();
+
+        let mut reader = BufferedFile::new(&file)
+            .expect("Can not find files")
+            .read()
+            .expect("Can not read the file");
+
+        let mut contents = Vec::new();
+        reader
+            .read_to_end(&mut contents)
+            .expect("Error reading from file");
+
+        assert_eq!(contents.as_slice(), b"Hello World")
+    }
+
+    #[test]
+    fn can_write_new_file() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+
+        let mut expected_generation: u8 = 0;
+        for i in 1..300 {
+            let managed_file = BufferedFile::new(&file)
+                .expect("It should be possible to create for not yet existing files.");
+
+            let mut writer = managed_file
+                .write()
+                .expect("A new file should be writeable");
+
+            writer
+                .write_all(b"Hello World")
+                .expect("Can not write into the file");
+
+            writer.commit().unwrap();
+
+            expected_generation = expected_generation.wrapping_add(1u8);
+            let file_number = if i.bitand(1) > 0 { 1 } else { 2 };
+            let expected_file = dir.path().join(format!("data-file.txt.{file_number}"));
+            assert!(
+                expected_file.exists(),
+                "The file {expected_file:?} does not exist"
+            );
+
+            let mut contents = Vec::new();
+            let mut file = std::fs::File::open(expected_file).expect("Could not open File");
+            file.read_to_end(&mut contents)
+                .expect("Could not verify written file");
+
+            assert_eq!(
+                contents.as_slice()[0],
+                expected_generation,
+                "Expected generation {expected_generation} in run {i}"
+            );
+            assert_eq!(&contents.as_slice()[1..], b"Hello World\xDA\x89\x5C\x06")
+        }
+    }
+
+    #[test]
+    fn can_write_empty_file() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+
+        let managed_file = BufferedFile::new(&file)
+            .expect("It should be possible to create for not yet existing files.");
+
+        let mut writer = managed_file
+            .write()
+            .expect("A new file should be writeable");
+
+        writer.write_all(b"").expect("Can not write into the file");
+
+        writer.commit().unwrap();
+
+        let expected_generation = 1;
+        let file_number = 1;
+        let expected_file = dir.path().join(format!("data-file.txt.{file_number}"));
+        assert!(
+            expected_file.exists(),
+            "The file {expected_file:?} does not exist"
+        );
+
+        let mut contents = Vec::new();
+        let mut file = std::fs::File::open(expected_file).expect("Could not open File");
+        file.read_to_end(&mut contents)
+            .expect("Could not verify written file");
+
+        assert_eq!(
+            contents.as_slice()[0],
+            expected_generation,
+            "Expected generation {expected_generation}"
+        );
+        assert_eq!(&contents.as_slice()[1..], b"\x00\x00\x00\x00")
+    }
+
+    #[test]
+    fn reuses_instance_for_multiple_operations() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFile::new(&file)
+            .expect("It should be possible to create for not yet existing files.");
+
+        for content in [&b"first"[..], b"second", b"third"] {
+            let mut writer = managed_file
+                .write()
+                .expect("A new file should be writeable");
+            writer
+                .write_all(content)
+                .expect("Can not write into the file");
+            writer.commit().unwrap();
+
+            for _ in 0..2 {
+                let mut contents = Vec::new();
+                managed_file
+                    .read()
+                    .expect("Can not read the file")
+                    .read_to_end(&mut contents)
+                    .expect("Error reading from file");
+                assert_eq!(contents.as_slice(), content);
+            }
+        }
+    }
+
+    #[test]
+    fn read_or_init_falls_back_for_missing_files() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFile::new(&file)
+            .expect("It should be possible to create for not yet existing files.");
+
+        assert_eq!(managed_file.read_or_default().unwrap(), b"");
+        assert_eq!(
+            managed_file.read_or_init(|| b"initial".to_vec()).unwrap(),
+            b"initial"
+        );
+
+        let mut writer = managed_file
+            .write()
+            .expect("A new file should be writeable");
+        writer
+            .write_all(b"stored")
+            .expect("Can not write into the file");
+        writer.commit().unwrap();
+
+        assert_eq!(managed_file.read_or_default().unwrap(), b"stored");
+        assert_eq!(
+            managed_file.read_or_init(|| b"initial".to_vec()).unwrap(),
+            b"stored"
+        );
+    }
+
+    #[test]
+    fn one_shot_reads_respect_the_limit() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .max_read_len(6)
+            .create_with(&file, b"stored")
+            .expect("Can not create the file");
+        assert!(matches!(
+            BufferedFile::new(dir.path().join("other.txt"))
+                .unwrap()
+                .read_to_vec(),
+            Err(BufferedFileErrors::AllFilesInvalidError)
+        ));
+        assert_eq!(managed_file.read_to_string().unwrap(), "stored");
+
+        managed_file.update(|_| b"stored!".to_vec()).unwrap();
+        assert!(matches!(
+            managed_file.read_to_vec(),
+            Err(BufferedFileErrors::ContentsTooLarge { len: 7, limit: 6 })
+        ));
+        let mut reader = managed_file.read().unwrap();
+        assert!(matches!(
+            reader.read_to_vec_limited(4),
+            Err(BufferedFileErrors::ContentsTooLarge { len: 7, limit: 4 })
+        ));
+        reader.read_exact(&mut [0u8; 3]).unwrap();
+        assert_eq!(reader.read_to_vec_limited(4).unwrap(), b"red!");
+        // `update` is not limited, so the contents can always be shortened again
+        managed_file
+            .update(|old| {
+                assert_eq!(old, b"stored!");
+                b"short".to_vec()
+            })
+            .unwrap();
+        assert_eq!(managed_file.read_to_string().unwrap(), "short");
+        let mut writer = managed_file.write().unwrap();
+        writer.write_all(&[0xff]).unwrap();
+        writer.commit().unwrap();
+        assert!(matches!(
+            managed_file.read_to_string(),
+            Err(BufferedFileErrors::IoError(err)) if err.kind() == std::io::ErrorKind::InvalidData
+        ));
+    }
+
+    #[test]
+    fn create_with_writes_the_first_generation() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFile::create_with(&file, b"initial").unwrap();
+        assert_eq!(managed_file.latest_generation(), Some(1));
+
+        let mut expected = vec![1u8];
+        expected.extend_from_slice(b"initial");
+        expected.extend_from_slice(&crate::DEFAULT_CHECKSUM.checksum(b"initial").to_le_bytes());
+        assert_eq!(
+            std::fs::read(dir.path().join("data-file.txt.1")).unwrap(),
+            expected
+        );
+        let reopened = BufferedFile::new(&file).unwrap();
+        assert_eq!(reopened.latest_generation(), Some(1));
+        assert_eq!(reopened.read_to_vec().unwrap(), b"initial");
+
+        assert!(matches!(
+            BufferedFile::create_with(&file, b"other"),
+            Err(BufferedFileErrors::AlreadyExists)
+        ));
+        assert_eq!(reopened.read_to_vec().unwrap(), b"initial");
+
+        // damaged backing files do not count as existing contents
+        std::fs::write(dir.path().join("data-file.txt.1"), b"\x01damaged").unwrap();
+        let recreated = BufferedFile::create_with(&file, b"recreated").unwrap();
+        assert_eq!(recreated.read_to_vec().unwrap(), b"recreated");
+    }
+
+    #[test]
+    fn dropped_writers_discard_the_generation() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .commit_strategy(CommitStrategy::Rename)
+            .create_with(&file, b"Hello World")
+            .expect("Can not create the file");
+
+        let mut writer = managed_file.write().unwrap();
+        writer.write_all(b"Hello again").unwrap();
+        drop(writer);
+        assert!(!dir.path().join("data-file.txt.2.tmp").exists());
+        assert_eq!(managed_file.latest_generation(), Some(1));
+
+        let mut writer = managed_file.write().unwrap();
+        writer.write_all(b"Hello again").unwrap();
+        let info = writer.commit().unwrap();
+        assert_eq!(info.generation, Some(2));
+        assert_eq!(info.bytes_written, 11);
+        let stored = std::fs::read(dir.path().join("data-file.txt.2")).unwrap();
+        assert!(stored.ends_with(&info.checksum.to_le_bytes()));
+        assert_eq!(managed_file.read_to_vec().unwrap(), b"Hello again");
+    }
+
+    #[test]
+    fn cloned_readers_move_independently() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file =
+            BufferedFile::create_with(&file, b"Hello World").expect("Can not create the file");
+        let mut reader = managed_file.read().unwrap();
+        let mut hello = [0u8; 6];
+        reader.read_exact(&mut hello).unwrap();
+
+        let mut clone = reader.try_clone().unwrap();
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        let mut world = [0u8; 2];
+        clone.read_exact(&mut world).unwrap();
+        assert_eq!(&world, b"Wo");
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"World");
+        rest.clear();
+        clone.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"rld");
+    }
+
+    #[test]
+    fn concurrent_readers_share_the_instance() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file =
+            BufferedFile::create_with(&file, b"Hello World").expect("Can not create the file");
+
+        let threads = (0..4)
+            .map(|_| {
+                let managed_file = managed_file.clone();
+                std::thread::spawn(move || {
+                    let mut readers = [managed_file.read().unwrap(), managed_file.read().unwrap()];
+                    let mut contents = [Vec::new(), Vec::new()];
+                    // the readers do not share a position
+                    for _ in 0..11 {
+                        for (reader, contents) in readers.iter_mut().zip(&mut contents) {
+                            let mut byte = [0u8];
+                            reader.read_exact(&mut byte).unwrap();
+                            contents.push(byte[0]);
+                        }
+                    }
+                    contents
+                })
+            })
+            .collect::<Vec<_>>();
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        for thread in threads {
+            for contents in thread.join().expect("Reading thread panicked") {
+                assert!(contents == b"Hello World" || contents == b"Hello again");
+            }
+        }
+    }
+
+    #[test]
+    fn concurrent_updates_are_serialized() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+
+        let threads = (0..4)
+            .map(|_| {
+                let file = file.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..10 {
+                        BufferedFile::new(&file)
+                            .expect("Can not find files")
+                            .update(|old| {
+                                let count = old.first().copied().unwrap_or(0);
+                                vec![count + 1]
+                            })
+                            .expect("Can not update the file");
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().expect("Updating thread panicked");
+        }
+
+        let contents = BufferedFile::new(&file)
+            .expect("Can not find files")
+            .read_or_default()
+            .expect("Can not read the file");
+        assert_eq!(contents, [40]);
+    }
+
+    #[test]
+    fn writers_do_not_lose_concurrent_updates() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFile::new(&file).expect("Can not find files");
+
+        let updating = {
+            let managed_file = managed_file.clone();
+            std::thread::spawn(move || {
+                for _ in 0..20 {
+                    managed_file
+                        .update(|old| vec![old.first().copied().unwrap_or(0) + 1])
+                        .expect("Can not update the file");
+                }
+            })
+        };
+        for _ in 0..20 {
+            // no update is committed between opening the writer and committing it
+            let mut writer = managed_file.write().expect("Can not write the file");
+            let old = managed_file
+                .read_or_default()
+                .expect("Can not read the file");
+            writer
+                .write_all(&[old.first().copied().unwrap_or(0) + 1])
+                .unwrap();
+            writer.commit().unwrap();
+        }
+        updating.join().expect("Updating thread panicked");
+
+        assert_eq!(managed_file.read_or_default().unwrap(), [40]);
+    }
+
+    #[test]
+    fn reentrant_writers_fail_instead_of_waiting() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file =
+            BufferedFile::create_with(&file, b"Hello World").expect("Can not create the file");
+        let would_block = |result: Result<(), BufferedFileErrors>| {
+            assert!(
+                matches!(&result, Err(BufferedFileErrors::IoError(err)) if err.kind() == std::io::ErrorKind::WouldBlock),
+                "Expected WouldBlock but got {result:?}"
+            );
+        };
+
+        let mut writer = managed_file.write().expect("Can not write the file");
+        would_block(managed_file.write().map(drop));
+        would_block(managed_file.update(|old| old.to_vec()));
+        let other = BufferedFile::new(&file).expect("Can not find files");
+        would_block(other.write().map(drop));
+        writer.write_all(b"Hello again").unwrap();
+        writer.commit().unwrap();
+
+        managed_file
+            .update(|_| {
+                would_block(managed_file.update(|old| old.to_vec()));
+                b"Hello".to_vec()
+            })
+            .unwrap();
+        assert_eq!(other.read_or_default().unwrap(), b"Hello");
+    }
+
+    #[test]
+    fn delete_removes_all_backing_files() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .slot_dir(".buffered")
+            .buffer_count(3)
+            .create_with(&file, b"Hello World")
+            .expect("Can not create the file");
+        managed_file
+            .update(|_| b"Hello again".to_vec())
+            .expect("Can not update the file");
+
+        let mut removed = managed_file.delete().expect("Can not delete the file");
+        removed.sort();
+        let slots = dir.path().join(".buffered");
+        assert_eq!(
+            removed,
+            [
+                slots.join("data-file.txt.1"),
+                slots.join("data-file.txt.2"),
+                slots.join("data-file.txt.lock"),
+            ]
+        );
+        assert!(!slots.exists());
+
+        let reader = BufferedFile::new(&file).expect("Can not find files").read();
+        assert!(matches!(
+            reader,
+            Err(BufferedFileErrors::AllFilesInvalidError)
+        ));
+    }
+
+    #[test]
+    fn rename_moves_all_backing_files() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file =
+            BufferedFile::create_with(&file, b"Hello World").expect("Can not create the file");
+        managed_file
+            .update(|_| b"Hello again".to_vec())
+            .expect("Can not update the file");
+
+        let renamed = managed_file
+            .rename(dir.path().join("renamed.txt"))
+            .expect("Can not rename the file");
+        assert_eq!(renamed.read_or_default().unwrap(), b"Hello again");
+        assert!(dir.path().join("renamed.txt.1").exists());
+        assert!(dir.path().join("renamed.txt.2").exists());
+        assert!(!dir.path().join("data-file.txt.1").exists());
+        assert!(!dir.path().join("data-file.txt.2").exists());
+
+        let blocker = BufferedFile::create_with(&file, b"blocker").expect("Can not create");
+        let result = blocker.rename(dir.path().join("renamed.txt"));
+        assert!(
+            matches!(&result, Err(BufferedFileErrors::IoError(err)) if err.kind() == std::io::ErrorKind::AlreadyExists),
+            "Expected AlreadyExists but got {result:?}"
+        );
+        assert_eq!(
+            BufferedFile::new(&file).unwrap().read_or_default().unwrap(),
+            b"blocker"
+        );
+    }
+
+    #[test]
+    fn copy_to_creates_independent_file() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file =
+            BufferedFile::create_with(&file, b"Hello World").expect("Can not create the file");
+
+        let copy = managed_file
+            .copy_to(dir.path().join("copy.txt"))
+            .expect("Can not copy the file");
+        managed_file
+            .update(|_| b"Hello again".to_vec())
+            .expect("Can not update the file");
+
+        assert_eq!(copy.read_or_default().unwrap(), b"Hello World");
+        assert_eq!(managed_file.read_or_default().unwrap(), b"Hello again");
+    }
+
+    #[test]
+    fn copy_to_waits_for_writers_of_the_destination() {
+        let dir = TempDir::new();
+        let managed_file = BufferedFile::create_with(dir.path().join("data-file.txt"), b"copied")
+            .expect("Can not create the file");
+        let destination = BufferedFile::new(dir.path().join("copy.txt")).unwrap();
+
+        let (opened, wait) = std::sync::mpsc::channel();
+        let writing = std::thread::spawn(move || {
+            let mut writer = destination.write().expect("Can not write the file");
+            opened.send(()).unwrap();
+            std::thread::sleep(Duration::from_millis(100));
+            writer.write_all(b"written").unwrap();
+            writer.commit().unwrap();
+        });
+        wait.recv().unwrap();
+        let copy = managed_file
+            .copy_to(dir.path().join("copy.txt"))
+            .expect("Can not copy the file");
+        writing.join().expect("Writing thread panicked");
+
+        assert_eq!(copy.read_or_default().unwrap(), b"copied");
+        let generations = copy
+            .history()
+            .iter()
+            .map(|entry| entry.generation)
+            .collect::<Vec<_>>();
+        assert_eq!(generations, [2, 1]);
+    }
+
+    #[test]
+    fn replicates_and_copies_the_contents() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file =
+            BufferedFile::create_with(&file, b"Hello World").expect("Can not create the file");
+
+        let replica = BufferedFileOptions::new()
+            .block_checksums(NonZeroU32::new(4).unwrap())
+            .open_in(crate::MemoryStorage::new(), "replica.txt")
+            .unwrap();
+        assert_eq!(managed_file.replicate_to(&replica).unwrap(), 11);
+        assert_eq!(replica.read_or_default().unwrap(), b"Hello World");
+
+        // the bytes read ahead are copied as well
+        let mut reader = managed_file.read().unwrap();
+        reader.read_exact_at(0, &mut [0u8; 6]).unwrap();
+        std::io::BufRead::fill_buf(&mut reader).unwrap();
+        let target = dir.path().join("plain.txt");
+        let mut plain = std::fs::File::create(&target).unwrap();
+        assert_eq!(reader.copy_into(&mut plain).unwrap(), 5);
+        assert_eq!(std::fs::read(&target).unwrap(), b"World");
+        let mut blocked = replica.read().unwrap();
+        assert_eq!(blocked.copy_into(&mut Vec::new()).unwrap(), 11);
+        let mut range = replica.read().unwrap().take_range(6, 5).unwrap();
+        let mut world = Vec::new();
+        range.read_to_end(&mut world).unwrap();
+        assert_eq!(world, b"World");
+    }
+
+    #[test]
+    fn repair_copies_backing_files_of_the_configured_version() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let options = BufferedFileOptions::new()
+            .format_version(FormatVersion::V6)
+            .clone();
+        let managed_file = options.open(&file).expect("Can not find files");
+        let mut metadata = UserMetadata::new();
+        metadata.insert("schema", "2");
+        let mut writer = managed_file.write_with_metadata(&metadata).unwrap();
+        writer.write_all(b"Hello World").unwrap();
+        writer.commit().unwrap();
+
+        let written = managed_file.latest_generation().unwrap();
+        managed_file.repair().expect("Can not repair");
+        let first = std::fs::read(dir.path().join("data-file.txt.1")).unwrap();
+        let second = std::fs::read(dir.path().join("data-file.txt.2")).unwrap();
+        assert_eq!(first[24..], second[24..]);
+        let reader = managed_file.read().unwrap();
+        assert_eq!(reader.generation(), written + 1);
+        assert_eq!(reader.user_metadata().get("schema"), Some(&b"2"[..]));
+        let copy = managed_file
+            .copy_to(dir.path().join("copy.txt"))
+            .expect("Can not copy the file");
+        assert_eq!(copy.read_or_default().unwrap(), b"Hello World");
+
+        // a backing file of another version is streamed into the configured version
+        std::fs::remove_file(dir.path().join("data-file.txt.2")).unwrap();
+        let older = BufferedFileOptions::new()
+            .format_version(FormatVersion::V2)
+            .open(dir.path().join("older.txt"))
+            .unwrap();
+        older.update(|_| b"Hello again".to_vec()).unwrap();
+        let older = options.open(dir.path().join("older.txt")).unwrap();
+        older.repair().expect("Can not repair");
+        let repaired = std::fs::read(dir.path().join("older.txt.2")).unwrap();
+        assert_eq!(FormatVersion::detect(&repaired), Ok(FormatVersion::V6));
+        assert_eq!(older.read_or_default().unwrap(), b"Hello again");
+    }
+
+    #[test]
+    fn reports_generations() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFile::new(&file).expect("Can not find files");
+        assert_eq!(managed_file.latest_generation(), None);
+
+        managed_file.update(|_| b"first".to_vec()).unwrap();
+        assert_eq!(managed_file.latest_generation(), Some(1));
+        assert_eq!(managed_file.read().unwrap().generation(), 1);
+
+        let other = BufferedFile::new(&file).expect("Can not find files");
+        other.update(|_| b"second".to_vec()).unwrap();
+        assert_eq!(managed_file.latest_generation(), Some(1));
+        managed_file.refresh();
+        assert_eq!(managed_file.latest_generation(), Some(2));
+        assert_eq!(managed_file.read().unwrap().generation(), 2);
+    }
+
+    #[test]
+    fn retains_history() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .history(3)
+            .open(&file)
+            .expect("Can not find files");
+        for i in 1..=6u8 {
+            managed_file.update(|_| vec![i]).unwrap();
+        }
+
+        let history = managed_file
+            .history()
+            .into_iter()
+            .map(|entry| entry.generation)
+            .collect::<Vec<_>>();
+        assert_eq!(history, [6, 5, 4, 3]);
+
+        let mut contents = Vec::new();
+        managed_file
+            .read_generation(4)
+            .expect("Generation 4 should be retained")
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert_eq!(contents, [4]);
+        assert!(matches!(
+            managed_file.read_generation(2),
+            Err(BufferedFileErrors::GenerationNotFound(2))
+        ));
+
+        let reduced = BufferedFileOptions::new()
+            .history(1)
+            .open(&file)
+            .expect("Can not find files");
+        let removed = reduced.prune().expect("Can not prune");
+        assert_eq!(
+            removed,
+            [
+                dir.path().join("data-file.txt.3"),
+                dir.path().join("data-file.txt.4")
+            ]
+        );
+        assert_eq!(reduced.history().len(), 2);
+    }
+
+    #[test]
+    fn rollback_restores_previous_content() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file =
+            BufferedFile::create_with(&file, b"good").expect("Can not create the file");
+        assert!(matches!(
+            managed_file.rollback(),
+            Err(BufferedFileErrors::NoPreviousGeneration)
+        ));
+
+        managed_file.update(|_| b"bad".to_vec()).unwrap();
+        assert_eq!(managed_file.rollback().unwrap(), 3);
+        assert_eq!(managed_file.read_or_default().unwrap(), b"good");
+        assert_eq!(
+            BufferedFile::new(&file).unwrap().read_or_default().unwrap(),
+            b"good"
+        );
+
+        managed_file.update(|_| b"next".to_vec()).unwrap();
+        assert_eq!(managed_file.read_or_default().unwrap(), b"next");
+        assert_eq!(managed_file.history()[1].generation, 3);
+    }
+
+    #[test]
+    fn repair_restores_redundancy() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file =
+            BufferedFile::create_with(&file, b"Hello World").expect("Can not create the file");
+        assert!(matches!(
+            BufferedFile::new(dir.path().join("other.txt"))
+                .unwrap()
+                .repair(),
+            Err(BufferedFileErrors::AllFilesInvalidError)
+        ));
+
+        let repaired = managed_file.repair().expect("Can not repair");
+        assert_eq!(repaired, [dir.path().join("data-file.txt.2")]);
+        assert_eq!(managed_file.history().len(), 2);
+        assert!(managed_file.repair().expect("Can not repair").is_empty());
+
+        std::fs::write(dir.path().join("data-file.txt.2"), b"corrupt").unwrap();
+        let managed_file = BufferedFile::new(&file).unwrap();
+        assert_eq!(
+            managed_file.repair().expect("Can not repair"),
+            [dir.path().join("data-file.txt.2")]
+        );
+        for entry in managed_file.history() {
+            let mut contents = Vec::new();
+            managed_file
+                .read_generation(entry.generation)
+                .unwrap()
+                .read_to_end(&mut contents)
+                .unwrap();
+            assert_eq!(contents, b"Hello World");
+        }
+    }
+
+    #[test]
+    fn lazy_validation_defers_checksum() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file =
+            BufferedFile::create_with(&file, b"Hello World").expect("Can not create the file");
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        let mut corrupted = std::fs::read(dir.path().join("data-file.txt.2")).unwrap();
+        corrupted[3] ^= 0xff;
+        std::fs::write(dir.path().join("data-file.txt.2"), corrupted).unwrap();
+
+        let lazy = BufferedFileOptions::new()
+            .lazy_validation(true)
+            .open(&file)
+            .expect("Can not find files");
+        assert_eq!(lazy.status().unwrap().slots[1].generation, None);
+        assert_eq!(lazy.read_or_default().unwrap(), b"Hello World");
+        assert_eq!(lazy.latest_generation(), Some(1));
+
+        let mut writer = BufferedFileOptions::new()
+            .lazy_validation(true)
+            .open(&file)
             .expect("Can not find files")
-            .read()
-            .expect("Can not read the file");
+            .write()
+            .expect("Can not write the file");
+        writer.write_all(b"Third").unwrap();
+        writer.commit().unwrap();
+        let strict = BufferedFile::new(&file).expect("Can not find files");
+        assert_eq!(strict.latest_generation(), Some(2));
+        assert_eq!(strict.read_or_default().unwrap(), b"Third");
+        // the corrupted backing file has been replaced instead of the last valid one
+        assert_eq!(strict.history().last().unwrap().generation, 1);
+    }
 
-        let mut contents = Vec::new();
-        reader
-            .read_to_end(&mut contents)
-            .expect("Error reading from file");
+    #[test]
+    fn verifies_while_reading() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file =
+            BufferedFile::create_with(&file, b"Hello World").expect("Can not create the file");
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
 
-        assert_eq!(contents.as_slice(), b"Hello World")
+        let streaming = BufferedFileOptions::new()
+            .verify_while_reading(true)
+            .open(&file)
+            .expect("Can not find files");
+        assert_eq!(streaming.read_or_default().unwrap(), b"Hello again");
+
+        let mut corrupted = std::fs::read(dir.path().join("data-file.txt.2")).unwrap();
+        let last = corrupted.len() - 6;
+        corrupted[last] ^= 0xff;
+        std::fs::write(dir.path().join("data-file.txt.2"), corrupted).unwrap();
+        streaming.refresh();
+        assert_eq!(streaming.latest_generation(), Some(2));
+
+        let mut reader = streaming.read().unwrap();
+        let mut contents = [0u8; 5];
+        reader.read_exact(&mut contents).unwrap();
+        assert_eq!(&contents, b"Hello");
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(
+            reader.read(&mut contents).unwrap_err().kind(),
+            std::io::ErrorKind::InvalidData
+        );
+
+        // the corrupted backing file is skipped from now on
+        assert_eq!(streaming.latest_generation(), Some(1));
+        assert_eq!(streaming.read_or_default().unwrap(), b"Hello World");
     }
 
     #[test]
-    fn can_write_new_file() {
+    fn reverifies_while_reading() {
         let dir = TempDir::new();
         let file = dir.path().join("data-file.txt");
+        BufferedFile::create_with(&file, b"Hello World").expect("Can not create the file");
+        let managed_file = BufferedFileOptions::new()
+            .reverify_while_reading(true)
+            .open(&file)
+            .expect("Can not find files");
+        assert_eq!(managed_file.latest_generation(), Some(1));
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        assert_eq!(managed_file.read_or_default().unwrap(), b"Hello again");
 
-        let mut expected_generation: u8 = 0;
-        for i in 1..300 {
-            let managed_file = BufferedFile::new(&file)
-                .expect("It should be possible to create for not yet existing files.");
+        // corrupted after the backing file has been verified
+        let mut corrupted = std::fs::read(dir.path().join("data-file.txt.2")).unwrap();
+        let last = corrupted.len() - 6;
+        corrupted[last] ^= 0xff;
+        std::fs::write(dir.path().join("data-file.txt.2"), corrupted).unwrap();
+        let err = managed_file
+            .read()
+            .unwrap()
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(managed_file.latest_generation(), Some(1));
+    }
+
+    #[test]
+    fn rotates_through_configured_buffer_count() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
 
+        for i in 0..7u8 {
+            let managed_file = BufferedFile::with_buffer_count(&file, 3)
+                .expect("It should be possible to create for not yet existing files.");
             let mut writer = managed_file
                 .write()
                 .expect("A new file should be writeable");
+            writer.write_all(&[i]).expect("Can not write into the file");
+            writer.commit().unwrap();
 
-            writer
-                .write_all(b"Hello World")
-                .expect("Can not write into the file");
+            let expected_file = dir.path().join(format!("data-file.txt.{}", i % 3 + 1));
+            let mut contents = Vec::new();
+            std::fs::File::open(&expected_file)
+                .expect("Could not open File")
+                .read_to_end(&mut contents)
+                .expect("Could not verify written file");
+            assert_eq!(contents[0], i + 1, "Unexpected generation in run {i}");
 
-            drop(writer);
+            let mut reader = BufferedFile::with_buffer_count(&file, 3)
+                .expect("Can not find files")
+                .read()
+                .expect("Can not read the file");
+            let mut contents = Vec::new();
+            reader
+                .read_to_end(&mut contents)
+                .expect("Error reading from file");
+            assert_eq!(contents.as_slice(), &[i], "Read stale data in run {i}");
+        }
+        assert!(!dir.path().join("data-file.txt.4").exists());
+    }
 
-            expected_generation = expected_generation.wrapping_add(1u8);
-            let file_number = if i.bitand(1) > 0 { 1 } else { 2 };
-            let expected_file = dir.path().join(format!("data-file.txt.{file_number}"));
+    #[test]
+    fn rejects_unsupported_buffer_count() {
+        for count in [0, 1, 129, 255] {
+            let result = BufferedFile::with_buffer_count("data-file.txt", count);
             assert!(
-                expected_file.exists(),
-                "The file {expected_file:?} does not exist"
+                matches!(result, Err(BufferedFileErrors::InvalidBufferCount(c)) if c == count),
+                "Expected an error for buffer count {count}"
             );
+        }
+        // generations stored in eight bytes do not wrap around
+        let dir = TempDir::new();
+        let managed_file = BufferedFileOptions::new()
+            .format_version(FormatVersion::V5)
+            .buffer_count(255)
+            .create_with(dir.path().join("data-file.txt"), b"Hello World")
+            .expect("Can not create the file");
+        assert_eq!(managed_file.read_or_default().unwrap(), b"Hello World");
+        assert!(matches!(
+            BufferedFileOptions::new()
+                .format_version(FormatVersion::V4)
+                .buffer_count(129)
+                .open(dir.path().join("data-file.txt")),
+            Err(BufferedFileErrors::InvalidBufferCount(129))
+        ));
+    }
 
-            let mut contents = Vec::new();
-            let mut file = std::fs::File::open(expected_file).expect("Could not open File");
-            file.read_to_end(&mut contents)
-                .expect("Could not verify written file");
+    #[test]
+    fn rename_strategy_keeps_old_generation_until_commit() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let mut options = BufferedFileOptions::new();
+        options.commit_strategy(CommitStrategy::Rename);
+        let managed_file = options
+            .create_with(&file, b"first")
+            .expect("Can not create the file");
+        managed_file.update(|_| b"second".to_vec()).unwrap();
 
-            assert_eq!(
-                contents.as_slice()[0],
-                expected_generation,
-                "Expected generation {expected_generation} in run {i}"
-            );
-            assert_eq!(&contents.as_slice()[1..], b"Hello World\xDA\x89\x5C\x06")
+        // the process dies while writing the third generation, which releases the lock held by its writer
+        std::fs::write(dir.path().join("data-file.txt.1.tmp"), b"\x03thi").unwrap();
+        let history = BufferedFile::new(&file).unwrap().history();
+        assert_eq!(history.len(), 2);
+        assert!(dir.path().join("data-file.txt.1.tmp").exists());
+
+        let mut writer = managed_file.write().expect("Can not write the file");
+        writer.write_all(b"third").unwrap();
+        writer.commit().unwrap();
+        let reopened = BufferedFile::new(&file).unwrap();
+        assert_eq!(reopened.read_or_default().unwrap(), b"third");
+        assert_eq!(reopened.history().len(), 2);
+        assert!(!dir.path().join("data-file.txt.1.tmp").exists());
+    }
+
+    #[test]
+    fn fsync_durability_levels_commit() {
+        let dir = TempDir::new();
+        let cases = [
+            (Durability::Fsync, CommitStrategy::InPlace),
+            (Durability::FsyncAndDir, CommitStrategy::InPlace),
+            (Durability::FsyncAndDir, CommitStrategy::Rename),
+        ];
+        for (durability, strategy) in cases {
+            let file = dir.path().join(format!("{durability:?}-{strategy:?}.txt"));
+            let mut options = BufferedFileOptions::new();
+            options.durability(durability).commit_strategy(strategy);
+            let managed_file = options.open(&file).expect("Can not find files");
+            managed_file.update(|_| b"Hello World".to_vec()).unwrap();
+            managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+            assert_eq!(managed_file.rollback().unwrap(), 3);
+
+            let reopened = BufferedFile::new(&file).unwrap();
+            assert_eq!(reopened.read_or_default().unwrap(), b"Hello World");
         }
     }
 
     #[test]
-    fn can_write_empty_file() {
+    fn preallocation_keeps_the_length() {
         let dir = TempDir::new();
         let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFile::new(&file).expect("Can not find files");
+        let mut writer = managed_file.write().expect("Can not write the file");
+        writer
+            .preallocate(1024 * 1024)
+            .expect("Can not preallocate");
+        writer.write_all(b"Hello World").unwrap();
+        writer.commit().unwrap();
 
-        let managed_file = BufferedFile::new(&file)
-            .expect("It should be possible to create for not yet existing files.");
+        let metadata = std::fs::metadata(dir.path().join("data-file.txt.1")).unwrap();
+        assert_eq!(metadata.len(), 1 + 11 + 4);
+        assert_eq!(managed_file.read_or_default().unwrap(), b"Hello World");
+    }
 
-        let mut writer = managed_file
-            .write()
-            .expect("A new file should be writeable");
+    #[test]
+    fn reader_accepts_access_hints() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file =
+            BufferedFile::create_with(&file, b"Hello World").expect("Can not create the file");
+        let mut reader = managed_file.read().unwrap();
+        reader.advise(Advice::Sequential).unwrap();
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents).unwrap();
+        reader.advise(Advice::DontNeed).unwrap();
+        assert_eq!(contents, b"Hello World");
+    }
 
-        writer.write_all(b"").expect("Can not write into the file");
+    #[test]
+    fn sparse_writer_skips_zero_blocks() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .sparse(true)
+            .open(&file)
+            .expect("Can not find files");
+        let mut contents = vec![0u8; 1024 * 1024];
+        contents[..5].copy_from_slice(b"start");
+        contents.extend_from_slice(b"end");
+        managed_file.update(|_| contents.clone()).unwrap();
 
-        drop(writer);
+        let reader = managed_file.read().unwrap();
+        assert_eq!(reader.len(), contents.len() as u64);
+        assert_eq!(managed_file.read_or_default().unwrap(), contents);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let metadata = std::fs::metadata(dir.path().join("data-file.txt.1")).unwrap();
+            assert!(metadata.blocks() * 512 < metadata.len());
+        }
+    }
 
-        let expected_generation = 1;
-        let file_number = 1;
-        let expected_file = dir.path().join(format!("data-file.txt.{file_number}"));
-        assert!(
-            expected_file.exists(),
-            "The file {expected_file:?} does not exist"
+    #[test]
+    fn preserve_valid_strategy_stages_only_over_valid_generations() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let mut options = BufferedFileOptions::new();
+        options.commit_strategy(CommitStrategy::PreserveValid);
+        let managed_file = options.open(&file).expect("Can not find files");
+
+        let writer = managed_file.write().expect("Can not write the file");
+        assert!(!dir.path().join("data-file.txt.1.tmp").exists());
+        writer.commit().unwrap();
+        managed_file.update(|_| b"second".to_vec()).unwrap();
+
+        // the process dies while writing the third generation
+        let mut writer = managed_file.write().expect("Can not write the file");
+        writer.write_all(b"thi").unwrap();
+        std::mem::forget(writer);
+        assert!(dir.path().join("data-file.txt.1.tmp").exists());
+        let reopened = BufferedFile::new(&file).unwrap();
+        assert_eq!(reopened.history().len(), 2);
+        assert_eq!(reopened.read_or_default().unwrap(), b"second");
+    }
+
+    #[test]
+    fn block_checksums_detect_corruption_near_the_point_of_use() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .block_checksums(NonZeroU32::new(4).unwrap())
+            .create_with(&file, b"Hello World!")
+            .expect("Can not create the file");
+        assert_eq!(managed_file.read_or_default().unwrap(), b"Hello World!");
+        managed_file.update(|_| b"Hello World".to_vec()).unwrap();
+        assert_eq!(
+            std::fs::metadata(dir.path().join("data-file.txt.2"))
+                .unwrap()
+                .len(),
+            1 + 11 + 3 * 4
+        );
+
+        // damage the third block "rld"
+        let slot = dir.path().join("data-file.txt.2");
+        let mut contents = std::fs::read(&slot).unwrap();
+        contents[1 + 2 * 8] = b'R';
+        std::fs::write(&slot, contents).unwrap();
+
+        let mut reader = managed_file.read().unwrap();
+        let mut start = [0u8; 8];
+        reader.read_exact(&mut start).unwrap();
+        assert_eq!(&start, b"Hello Wo");
+        let err = reader.read(&mut start).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        let salvaged = managed_file.salvage().unwrap();
+        assert_eq!(salvaged.contents, b"Hello Wo");
+        assert_eq!(salvaged.generation, Some(2));
+        assert!(!salvaged.complete);
+        managed_file.refresh();
+        assert_eq!(managed_file.read_or_default().unwrap(), b"Hello World!");
+    }
+
+    #[test]
+    fn block_checksums_verify_random_access() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .block_checksums(NonZeroU32::new(4).unwrap())
+            .create_with(&file, b"Hello World!")
+            .expect("Can not create the file");
+        let mut reader = managed_file.read().unwrap();
+
+        // damage the second block "o Wo" after the reader has been opened
+        let slot = dir.path().join("data-file.txt.1");
+        let mut contents = std::fs::read(&slot).unwrap();
+        contents[1 + 8] = b'0';
+        std::fs::write(&slot, contents).unwrap();
+
+        let mut buf = [0u8; 4];
+        reader.read_exact_at(8, &mut buf).unwrap();
+        assert_eq!(&buf, b"rld!");
+        assert_eq!(reader.read_at(2, &mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"ll");
+        let err = reader.read_exact_at(3, &mut buf).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(reader.read_at(12, &mut buf).unwrap(), 0);
+
+        // positioned reads share the reader and verify the blocks as well
+        let reader = managed_file.read().unwrap();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let mut buf = [0u8; 4];
+                reader.pread_exact(8, &mut buf).unwrap();
+                assert_eq!(&buf, b"rld!");
+            });
+            scope.spawn(|| {
+                let mut buf = [0u8; 4];
+                assert_eq!(reader.pread(2, &mut buf).unwrap(), 2);
+                assert_eq!(&buf[..2], b"ll");
+                let err = reader.pread_exact(3, &mut buf).unwrap_err();
+                assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+            });
+        });
+        assert_eq!(reader.pread(12, &mut [0u8; 4]).unwrap(), 0);
+    }
+
+    #[test]
+    fn sector_size_aligns_the_trailer() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let mut options = BufferedFileOptions::new();
+        options
+            .format_version(FormatVersion::V6)
+            .sector_size(NonZeroU32::new(512).unwrap());
+        let managed_file = options
+            .create_with(&file, b"Hello World")
+            .expect("Can not create the file");
+        managed_file.update(|_| vec![7u8; 1000]).unwrap();
+
+        for (slot, generation) in [("data-file.txt.1", 256), ("data-file.txt.2", 257)] {
+            let contents = std::fs::read(dir.path().join(slot)).unwrap();
+            assert_eq!(contents.len() % 512, 0);
+            let header = SlotHeader::parse(&contents).unwrap();
+            assert_eq!(header.generation(), generation);
+        }
+        assert_eq!(managed_file.read_or_default().unwrap(), vec![7u8; 1000]);
+        let reopened = options.open(&file).unwrap();
+        assert_eq!(reopened.history().len(), 2);
+
+        // the padding is only accepted with the same sector size
+        let unaligned = BufferedFileOptions::new()
+            .format_version(FormatVersion::V6)
+            .open(&file)
+            .unwrap();
+        assert!(matches!(
+            unaligned.read(),
+            Err(BufferedFileErrors::AllFilesInvalidError)
+        ));
+    }
+
+    #[test]
+    fn salvage_searches_the_checksum_of_damaged_backing_files() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .format_version(FormatVersion::V4)
+            .create_with(&file, b"Hello World")
+            .expect("Can not create the file");
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        let first = managed_file.salvage().unwrap();
+        assert_eq!(first.contents, b"Hello again");
+        assert!(first.complete);
+
+        // damage the older backing file and append garbage to the newer one
+        let older = dir.path().join("data-file.txt.1");
+        let mut contents = std::fs::read(&older).unwrap();
+        contents[16] ^= 1;
+        std::fs::write(&older, contents).unwrap();
+        let newer = dir.path().join("data-file.txt.2");
+        let mut contents = std::fs::read(&newer).unwrap();
+        contents.extend_from_slice(b"garbage");
+        std::fs::write(&newer, contents).unwrap();
+
+        let managed_file = BufferedFile::new(&file).unwrap();
+        assert!(!managed_file.exists());
+        let salvaged = managed_file.salvage().unwrap();
+        assert_eq!(salvaged.contents, b"Hello again");
+        assert_eq!(salvaged.path, Some(newer));
+        assert_eq!(salvaged.generation, Some(2));
+        assert!(!salvaged.complete);
+    }
+
+    #[test]
+    fn stored_lengths_detect_truncation_with_matching_checksums() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .format_version(FormatVersion::V2)
+            .create_with(&file, b"Hello World")
+            .expect("Can not create the file");
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        let reader = managed_file.read().unwrap();
+        assert_eq!(reader.len(), 11);
+
+        // cut off the end of the contents, as if the remaining bytes happened to match the checksum
+        let slot = dir.path().join("data-file.txt.2");
+        let contents = std::fs::read(&slot).unwrap();
+        assert_eq!(contents.len(), 5 + 11 + 8 + 4);
+        let mut truncated = contents[..5 + 8 + 8].to_vec();
+        let checksum = crate::ChecksumAlgorithm::default()
+            .crc()
+            .checksum(&truncated[5..]);
+        truncated.extend_from_slice(&checksum.to_le_bytes());
+        std::fs::write(&slot, truncated).unwrap();
+
+        let reports = managed_file.validate();
+        assert!(matches!(reports[0].outcome, SlotOutcome::Valid(1)));
+        assert!(matches!(reports[1].outcome, SlotOutcome::LengthMismatch));
+        assert_eq!(managed_file.read_or_default().unwrap(), b"Hello World");
+    }
+
+    #[test]
+    fn write_times_are_recorded_and_break_ties() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let before = SystemTime::now() - Duration::from_secs(1);
+        let managed_file = BufferedFileOptions::new()
+            .format_version(FormatVersion::V3)
+            .create_with(&file, b"Hello World")
+            .expect("Can not create the file");
+        let written = managed_file.status().unwrap().slots[0]
+            .written
+            .expect("The time should be recorded");
+        assert!(written >= before && written <= SystemTime::now());
+        let metadata = managed_file.read().unwrap().metadata();
+        assert_eq!(metadata.generation, 1);
+        assert_eq!(metadata.len, 11);
+        assert_eq!(metadata.written, Some(written));
+        assert_eq!(metadata.path, dir.path().join("data-file.txt.1"));
+        assert_eq!(
+            metadata.checksum,
+            Some(crate::ChecksumAlgorithm::Crc32Bzip2)
         );
 
+        // a second backing file with the same generation, which has been written earlier
+        let millis = written.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        let mut contents = FormatVersion::V3
+            .header_written_at(1, millis - 1000)
+            .as_ref()
+            .to_vec();
+        contents.extend_from_slice(b"Hello again");
+        contents.extend_from_slice(&11u64.to_le_bytes());
+        let checksum = crate::ChecksumAlgorithm::default()
+            .crc()
+            .checksum(&contents[13..]);
+        contents.extend_from_slice(&checksum.to_le_bytes());
+        std::fs::write(dir.path().join("data-file.txt.2"), contents).unwrap();
+
+        let reopened = BufferedFile::new(&file).unwrap();
+        assert_eq!(reopened.read_or_default().unwrap(), b"Hello World");
+    }
+
+    #[test]
+    fn wide_generations_continue_the_existing_ones() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFile::create_with(&file, b"Hello World").unwrap();
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        assert_eq!(managed_file.latest_generation(), Some(2));
+
+        let wide = BufferedFileOptions::new()
+            .format_version(FormatVersion::V5)
+            .open(&file)
+            .unwrap();
+        wide.update(|_| b"Hello wide".to_vec()).unwrap();
+        assert_eq!(wide.latest_generation(), Some(256));
+        // the previous generation can not follow in a single byte
+        assert!(matches!(
+            wide.rollback(),
+            Err(BufferedFileErrors::NoPreviousGeneration)
+        ));
+
+        // once a generation above 255 exists, narrow options keep writing wide generations
+        let narrow = BufferedFile::new(&file).unwrap();
+        assert_eq!(narrow.read_or_default().unwrap(), b"Hello wide");
+        narrow.update(|_| b"Hello narrow".to_vec()).unwrap();
+        assert_eq!(narrow.latest_generation(), Some(257));
+        assert_eq!(narrow.rollback().unwrap(), 258);
+        assert_eq!(narrow.read_or_default().unwrap(), b"Hello wide");
+
+        let reopened = BufferedFile::new(&file).unwrap();
+        assert_eq!(reopened.latest_generation(), Some(258));
+        assert_eq!(reopened.read_or_default().unwrap(), b"Hello wide");
+        assert!(reopened
+            .status()
+            .unwrap()
+            .slots
+            .iter()
+            .all(|slot| slot.is_valid()));
+    }
+
+    #[test]
+    fn header_checksums_protect_the_generation() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .format_version(FormatVersion::V6)
+            .create_with(&file, b"Hello World")
+            .expect("Can not create the file");
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        assert_eq!(managed_file.rollback().unwrap(), 258);
+
+        // a flipped bit in the generation would make the undone generation the newest one
+        let slot = dir.path().join("data-file.txt.2");
+        let mut contents = std::fs::read(&slot).unwrap();
+        contents[16] ^= 1;
+        std::fs::write(&slot, contents).unwrap();
+
+        let reports = managed_file.validate();
+        assert!(matches!(reports[0].outcome, SlotOutcome::Valid(258)));
+        assert!(matches!(reports[1].outcome, SlotOutcome::HeaderInvalid));
+        let reopened = BufferedFile::new(&file).unwrap();
+        assert_eq!(reopened.read_or_default().unwrap(), b"Hello World");
+    }
+
+    #[test]
+    fn upgrades_keep_generations_and_contents() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFile::create_with(&file, b"Hello World").unwrap();
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+
+        let upgraded = BufferedFileOptions::new()
+            .format_version(FormatVersion::V4)
+            .open(&file)
+            .unwrap();
+        assert_eq!(upgraded.upgrade_format().unwrap().len(), 2);
+        assert!(upgraded.upgrade_format().unwrap().is_empty());
+        for slot in ["data-file.txt.1", "data-file.txt.2"] {
+            assert!(std::fs::read(dir.path().join(slot))
+                .unwrap()
+                .starts_with(b"MBF\x04"));
+        }
+        let generations = upgraded
+            .history()
+            .iter()
+            .map(|entry| entry.generation)
+            .collect::<Vec<_>>();
+        assert_eq!(generations, [2, 1]);
         let mut contents = Vec::new();
-        let mut file = std::fs::File::open(expected_file).expect("Could not open File");
-        file.read_to_end(&mut contents)
-            .expect("Could not verify written file");
+        upgraded
+            .read_generation(1)
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert_eq!(contents, b"Hello World");
+
+        let mut metadata = UserMetadata::new();
+        metadata.insert("schema", "2");
+        let mut writer = upgraded.write_with_metadata(&metadata).unwrap();
+        writer.write_all(b"Hello metadata").unwrap();
+        writer.commit().unwrap();
+        let written = upgraded.read().unwrap().metadata().written;
+
+        let wide = BufferedFileOptions::new()
+            .format_version(FormatVersion::V5)
+            .open(&file)
+            .unwrap();
+        assert_eq!(wide.upgrade_format().unwrap().len(), 2);
+        let reader = wide.read().unwrap();
+        assert_eq!(reader.generation(), 3);
+        assert_eq!(reader.user_metadata(), &metadata);
+        assert_eq!(reader.metadata().written, written);
+        assert_eq!(wide.read_or_default().unwrap(), b"Hello metadata");
+    }
+
+    #[test]
+    fn user_metadata_is_read_without_the_contents() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let mut metadata = UserMetadata::new();
+        metadata
+            .insert("schema", "2")
+            .insert("producer", b"test".to_vec());
+
+        let unsupported = BufferedFile::new(&file).unwrap();
+        assert!(matches!(
+            unsupported.write_with_metadata(&metadata),
+            Err(BufferedFileErrors::MetadataNotSupported(FormatVersion::V0))
+        ));
 
+        let managed_file = BufferedFileOptions::new()
+            .format_version(FormatVersion::V4)
+            .block_checksums(NonZeroU32::new(4).unwrap())
+            .open(&file)
+            .unwrap();
+        let mut writer = managed_file.write_with_metadata(&metadata).unwrap();
+        writer.write_all(b"Hello World").unwrap();
+        writer.commit().unwrap();
+
+        let mut reader = managed_file.read().unwrap();
+        assert_eq!(reader.user_metadata(), &metadata);
+        assert_eq!(reader.len(), 11);
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, b"Hello World");
+        assert_eq!(managed_file.salvage().unwrap().contents, b"Hello World");
+
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        assert!(managed_file.read().unwrap().user_metadata().is_empty());
+    }
+
+    #[test]
+    fn content_types_are_checked_before_reading() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .format_version(FormatVersion::V4)
+            .content_type("greeting")
+            .create_with(&file, b"Hello World")
+            .expect("Can not create the file");
+
+        let reader = managed_file.read_expecting("greeting").unwrap();
+        assert_eq!(reader.content_type(), Some("greeting"));
+        match managed_file.read_expecting("config") {
+            Err(BufferedFileErrors::ContentTypeMismatch { expected, found }) => {
+                assert_eq!(expected, "config");
+                assert_eq!(found.as_deref(), Some("greeting"));
+            }
+            other => panic!("Unexpected result {other:?}"),
+        }
+
+        let untyped = BufferedFile::create_with(dir.path().join("other.txt"), b"Hello").unwrap();
+        assert!(matches!(
+            untyped.read_expecting("greeting"),
+            Err(BufferedFileErrors::ContentTypeMismatch { found: None, .. })
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "hmac")]
+    fn authentication_codes_detect_tampering_with_recomputed_checksums() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .hmac_key(b"secret")
+            .create_with(&file, b"Hello World")
+            .expect("Can not create the file");
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        assert_eq!(managed_file.read_or_default().unwrap(), b"Hello again");
+
+        // modify the contents and fix the checksum, like an attacker without the key could do
+        let slot = dir.path().join("data-file.txt.2");
+        let mut contents = std::fs::read(&slot).unwrap();
+        assert_eq!(contents.len(), 1 + 11 + 32 + 4);
+        contents[1] = b'J';
+        let body = contents.len() - 4;
+        let checksum = crate::ChecksumAlgorithm::default()
+            .crc()
+            .checksum(&contents[1..body]);
+        contents[body..].copy_from_slice(&checksum.to_le_bytes());
+        std::fs::write(&slot, contents).unwrap();
+
+        let reports = managed_file.validate();
+        assert!(matches!(reports[0].outcome, SlotOutcome::Valid(1)));
+        assert!(matches!(reports[1].outcome, SlotOutcome::MacMismatch));
+        assert_eq!(managed_file.read_or_default().unwrap(), b"Hello World");
+
+        let other_key = BufferedFileOptions::new()
+            .hmac_key(b"guessed")
+            .open(&file)
+            .unwrap();
+        assert_eq!(other_key.latest_generation(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn encrypted_contents_are_decrypted_while_reading() {
+        use std::io::{Seek, SeekFrom};
+
+        let dir = TempDir::new();
+        let contents = (0..200_000u32).map(|i| i as u8).collect::<Vec<_>>();
+        for block_size in [None, NonZeroU32::new(4096)] {
+            let mut options = BufferedFileOptions::new();
+            options.encryption_key(&[7; 32]);
+            if let Some(block_size) = block_size {
+                options.block_checksums(block_size);
+            }
+            let file = dir.path().join(format!("data-file-{block_size:?}.txt"));
+            let managed_file = options.create_with(&file, &contents).unwrap();
+            assert_eq!(managed_file.read_to_vec().unwrap(), contents);
+
+            let mut reader = managed_file.read().unwrap();
+            assert_eq!(reader.len(), contents.len() as u64);
+            let mut buf = [0u8; 10];
+            // across the end of the first chunk of 64 KiB
+            reader.read_exact_at(65_530, &mut buf).unwrap();
+            assert_eq!(buf, contents[65_530..65_540]);
+            reader.pread_exact(131_070, &mut buf).unwrap();
+            assert_eq!(buf, contents[131_070..131_080]);
+            reader.seek(SeekFrom::End(-10)).unwrap();
+            let mut rest = Vec::new();
+            reader.read_to_end(&mut rest).unwrap();
+            assert_eq!(rest, contents[contents.len() - 10..]);
+
+            let mut range = reader.take_range(65_000, 70_000).unwrap();
+            let mut section = Vec::new();
+            range.read_to_end(&mut section).unwrap();
+            assert_eq!(section, contents[65_000..135_000]);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn encrypted_contents_are_authenticated() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .encryption_key(&[7; 32])
+            .create_with(&file, b"Hello World")
+            .expect("Can not create the file");
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        assert_eq!(managed_file.read_or_default().unwrap(), b"Hello again");
+
+        // the generation stays in the clear, the contents do not
+        let slot = dir.path().join("data-file.txt.2");
+        let mut contents = std::fs::read(&slot).unwrap();
+        assert_eq!(contents.len(), 1 + 19 + 11 + 16 + 4);
+        assert_eq!(contents[0], 2);
+        assert!(!contents.windows(5).any(|window| window == b"Hello"));
         assert_eq!(
-            contents.as_slice()[0],
-            expected_generation,
-            "Expected generation {expected_generation}"
+            BufferedFile::new(&file).unwrap().latest_generation(),
+            Some(2)
+        );
+
+        // modify the ciphertext and fix the checksum
+        contents[1 + 19] ^= 1;
+        let body = contents.len() - 4;
+        let checksum = crate::ChecksumAlgorithm::default()
+            .crc()
+            .checksum(&contents[1..body]);
+        contents[body..].copy_from_slice(&checksum.to_le_bytes());
+        std::fs::write(&slot, contents).unwrap();
+
+        assert!(matches!(
+            managed_file.read(),
+            Err(BufferedFileErrors::AuthenticationFailed(path)) if path == slot
+        ));
+        assert_eq!(managed_file.latest_generation(), Some(1));
+        let reports = managed_file.validate();
+        assert!(matches!(reports[0].outcome, SlotOutcome::Valid(1)));
+        assert!(matches!(reports[1].outcome, SlotOutcome::MacMismatch));
+        assert_eq!(managed_file.read_or_default().unwrap(), b"Hello World");
+
+        let other_key = BufferedFileOptions::new()
+            .encryption_key(&[8; 32])
+            .open(&file)
+            .unwrap();
+        assert_eq!(other_key.latest_generation(), None);
+    }
+
+    #[test]
+    fn versioned_headers_are_read_alongside_version_0() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        BufferedFile::create_with(&file, b"Hello World").expect("Can not create the file");
+        let managed_file = BufferedFileOptions::new()
+            .format_version(FormatVersion::V1)
+            .open(&file)
+            .expect("Can not find files");
+        assert_eq!(managed_file.read_or_default().unwrap(), b"Hello World");
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+
+        let slot = dir.path().join("data-file.txt.2");
+        assert!(std::fs::read(&slot).unwrap().starts_with(b"MBF\x01\x02"));
+        let reopened = BufferedFile::new(&file).unwrap();
+        assert_eq!(reopened.read_or_default().unwrap(), b"Hello again");
+        assert_eq!(reopened.rollback().unwrap(), 3);
+        assert_eq!(reopened.read_or_default().unwrap(), b"Hello World");
+        assert_eq!(reopened.rollback().unwrap(), 4);
+        assert_eq!(reopened.read_or_default().unwrap(), b"Hello again");
+
+        let mut contents = std::fs::read(&slot).unwrap();
+        contents[3] = 9;
+        std::fs::write(&slot, contents).unwrap();
+        let reports = reopened.validate();
+        assert!(matches!(
+            reports[1].outcome,
+            SlotOutcome::UnsupportedVersion(9)
+        ));
+        assert_eq!(reopened.read_or_default().unwrap(), b"Hello World");
+    }
+
+    #[test]
+    fn version_0_files_starting_like_the_magic_bytes_are_read() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let content = b"BF\x01hello world";
+        let mut slot = vec![b'M'];
+        slot.extend_from_slice(content);
+        slot.extend_from_slice(&crate::DEFAULT_CHECKSUM.checksum(content).to_le_bytes());
+        std::fs::write(dir.path().join("data-file.txt.1"), slot).unwrap();
+
+        let managed_file = BufferedFile::new(&file).unwrap();
+        assert_eq!(managed_file.latest_generation(), Some(u64::from(b'M')));
+        assert_eq!(managed_file.read_to_vec().unwrap(), content);
+    }
+
+    #[test]
+    fn rejects_directories() {
+        let dir = TempDir::new();
+        let result = BufferedFile::new(dir.path());
+        assert!(
+            matches!(&result, Err(BufferedFileErrors::NotAFile(path)) if path == dir.path()),
+            "Unexpected result {result:?}"
+        );
+
+        std::fs::create_dir(dir.path().join("data-file.txt.2")).unwrap();
+        let result = BufferedFile::new(dir.path().join("data-file.txt"));
+        assert!(
+            matches!(&result, Err(BufferedFileErrors::NotAFile(path)) if path.ends_with("data-file.txt.2")),
+            "Unexpected result {result:?}"
         );
-        assert_eq!(&contents.as_slice()[1..], b"\x00\x00\x00\x00")
     }
 
-    mod utils {
+    pub(crate) mod utils {
         use std::{
             env, fs,
             path::{Path, PathBuf},
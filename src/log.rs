@@ -0,0 +1,469 @@
+//! An append-only log of records kept in rotating backing files.
+//!
+//! Every backing file starts with the magic bytes `MBL`, the version 1 and the generation in eight bytes in
+//! little endian, followed by the checksum of these bytes. The records are appended behind the header,
+//! each prefixed by its length in four bytes in little endian and followed by the checksum of its position,
+//! its length and its contents. So a record torn by a crash or left behind by an earlier append ends the log.
+
+use std::{
+    io::{BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use crc::Crc;
+
+use crate::{
+    checksum::{checksum, ChecksumDigest},
+    BufferedFileErrors, BufferedFileOptions, Durability, FsStorage, Storage, StorageFile,
+};
+
+/// Identifies the backing files of a log and the version of their layout
+const LOG_MAGIC: [u8; 4] = *b"MBL\x01";
+
+/// The number of bytes preceding the records: the magic bytes, the generation and their checksum
+const LOG_HEADER_LEN: u64 = 16;
+
+/// The number of bytes added to every record: its length and its checksum
+const RECORD_OVERHEAD: u64 = 8;
+
+/// The backing file records are appended to
+#[derive(Debug, Clone)]
+struct ActiveSlot {
+    /// The number of the backing file
+    slot: u8,
+    path: PathBuf,
+    generation: u64,
+    /// The position behind the last valid record
+    end: u64,
+}
+
+///
+/// An append-only log of records, which is compacted into the next backing file.
+///
+/// Records are appended to the backing file holding the newest generation. `compact` writes the given records
+/// into the next backing file as a new generation, which replaces the oldest one, so the previous generation
+/// is retained until the next compaction. Only one instance should modify a log at a time.
+///
+/// # Example
+///
+/// ```
+/// use multibufferedfile::BufferedLog;
+/// # let dir = std::env::temp_dir().join("multibufferedfile-log-doc");
+/// # std::fs::create_dir_all(&dir).unwrap();
+///
+/// let log = BufferedLog::open(dir.join("events.log")).unwrap();
+/// log.append(b"started").unwrap();
+/// log.append(b"stopped").unwrap();
+///
+/// let records = log.records().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+/// assert_eq!(records, [b"started", b"stopped"]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct BufferedLog<S: Storage = FsStorage> {
+    path: PathBuf,
+    options: BufferedFileOptions,
+    storage: S,
+    active: Mutex<Option<ActiveSlot>>,
+}
+
+impl BufferedLog {
+    ///
+    /// Opens the append-only log at `path` with the default options.
+    /// The backing files are stored with a suffix of .1 and .2 respectively.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, BufferedFileErrors> {
+        BufferedFileOptions::new().open_log(path)
+    }
+}
+
+impl<S: Storage> BufferedLog<S> {
+    /// Finds the backing file with the newest valid generation and the end of its valid records
+    pub(crate) fn scan(
+        storage: S,
+        path: impl AsRef<Path>,
+        options: BufferedFileOptions,
+    ) -> Result<Self, BufferedFileErrors> {
+        let crc = options.checksum.crc();
+        let mut active: Option<ActiveSlot> = None;
+        for slot in 1..=options.buffer_count {
+            let path = options.slot_path(path.as_ref(), slot);
+            let generation = match read_header(&storage, &path, crc) {
+                Ok(Some(generation)) => generation,
+                Ok(None) => continue,
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => {
+                    tracing::warn!("Could not check {}: {err}", path.display());
+                    continue;
+                }
+            };
+            if active
+                .as_ref()
+                .is_some_and(|active| active.generation >= generation)
+            {
+                continue;
+            }
+            active = Some(ActiveSlot {
+                slot,
+                path,
+                generation,
+                end: LOG_HEADER_LEN,
+            });
+        }
+        if let Some(active) = &mut active {
+            let mut records = LogRecords::new(
+                storage.open(&active.path)?,
+                crc,
+                storage.metadata(&active.path)?.len,
+            )?;
+            while records.next().transpose()?.is_some() {}
+            active.end = records.offset;
+        }
+
+        Ok(BufferedLog {
+            path: path.as_ref().to_path_buf(),
+            options,
+            storage,
+            active: Mutex::new(active),
+        })
+    }
+
+    /// The generation of the backing file records are appended to, if the log has been started
+    pub fn generation(&self) -> Option<u64> {
+        self.active().as_ref().map(|active| active.generation)
+    }
+
+    ///
+    /// Appends a record to the newest generation.
+    ///
+    /// The record is flushed and synchronized according to the configured `Durability` before this returns.
+    /// If the append fails, the record may be left incomplete and is overwritten by the next append.
+    pub fn append(&self, record: &[u8]) -> Result<(), BufferedFileErrors> {
+        let len = u32::try_from(record.len()).map_err(|_| {
+            std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "A record must be shorter than 4 GiB",
+            )
+        })?;
+        let mut active = self.active();
+        let slot = match active.as_mut() {
+            Some(slot) => slot,
+            None => active.insert(self.start_generation(1, 1, std::iter::empty::<&[u8]>())?),
+        };
+
+        let encoded = encode_record(self.options.checksum.crc(), slot.end, len, record);
+        let mut file = self.storage.open_write(&slot.path)?;
+        file.seek(SeekFrom::Start(slot.end))?;
+        file.write_all(&encoded)?;
+        self.finish(&mut file)?;
+        slot.end += encoded.len() as u64;
+        Ok(())
+    }
+
+    ///
+    /// Reads the records of the newest generation in the order they have been appended.
+    ///
+    /// The records are read up to the end known to this instance. The iteration stops at the first corrupt
+    /// record, only errors reading the backing file are reported.
+    pub fn records(&self) -> Result<LogRecords<S::File>, BufferedFileErrors> {
+        let active = self.active().clone();
+        match active {
+            Some(active) => Ok(LogRecords::new(
+                self.storage.open(&active.path)?,
+                self.options.checksum.crc(),
+                active.end,
+            )?),
+            None => Ok(LogRecords::empty()),
+        }
+    }
+
+    ///
+    /// Replaces the records by `records`, e.g. a snapshot of the state built from the current records.
+    ///
+    /// The records are written as a new generation into the next backing file, which replaces the oldest
+    /// generation once it is complete. The current generation is retained until the next compaction.
+    pub fn compact<R: AsRef<[u8]>>(
+        &self,
+        records: impl IntoIterator<Item = R>,
+    ) -> Result<(), BufferedFileErrors> {
+        let mut active = self.active();
+        let (slot, generation) = match active.as_ref() {
+            Some(active) => (
+                active.slot % self.options.buffer_count + 1,
+                active.generation + 1,
+            ),
+            None => (1, 1),
+        };
+        *active = Some(self.start_generation(slot, generation, records)?);
+        Ok(())
+    }
+
+    /// Writes a backing file holding `generation` and `records` next to `slot` and moves it in place
+    fn start_generation<R: AsRef<[u8]>>(
+        &self,
+        slot: u8,
+        generation: u64,
+        records: impl IntoIterator<Item = R>,
+    ) -> Result<ActiveSlot, BufferedFileErrors> {
+        let crc = self.options.checksum.crc();
+        let path = self.options.slot_path(&self.path, slot);
+        if let Some(parent) = path.parent().filter(|_| self.options.slot_dir.is_some()) {
+            self.storage.create_dir_all(parent)?;
+        }
+        let mut name = path.clone().into_os_string();
+        name.push(".tmp");
+        let staged = PathBuf::from(name);
+
+        let mut file = self.storage.create(&staged, self.options.mode)?;
+        let mut header = Vec::with_capacity(LOG_HEADER_LEN as usize);
+        header.extend_from_slice(&LOG_MAGIC);
+        header.extend_from_slice(&generation.to_le_bytes());
+        header.extend_from_slice(&checksum(crc, &header).to_le_bytes());
+        file.write_all(&header)?;
+        let mut end = LOG_HEADER_LEN;
+        for record in records {
+            let record = record.as_ref();
+            let len = u32::try_from(record.len()).map_err(|_| {
+                std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "A record must be shorter than 4 GiB",
+                )
+            })?;
+            let encoded = encode_record(crc, end, len, record);
+            file.write_all(&encoded)?;
+            end += encoded.len() as u64;
+        }
+        self.finish(&mut file)?;
+        drop(file);
+
+        self.storage.rename(&staged, &path)?;
+        if self.options.durability >= Durability::FsyncAndDir {
+            if let Some(parent) = path.parent() {
+                self.storage.sync_dir(parent)?;
+            }
+        }
+        Ok(ActiveSlot {
+            slot,
+            path,
+            generation,
+            end,
+        })
+    }
+
+    /// Flushes and synchronizes a written backing file according to the configured durability
+    fn finish(&self, file: &mut S::File) -> std::io::Result<()> {
+        if self.options.durability >= Durability::Flush {
+            file.flush()?;
+        }
+        if self.options.durability >= Durability::Fsync {
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// provides access to the backing file records are appended to
+    fn active(&self) -> MutexGuard<'_, Option<ActiveSlot>> {
+        self.active.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Reads the generation from the header of a backing file, if the header is intact
+fn read_header(
+    storage: &impl Storage,
+    path: &Path,
+    crc: &'static Crc<u32>,
+) -> std::io::Result<Option<u64>> {
+    let mut header = [0u8; LOG_HEADER_LEN as usize];
+    match storage.open(path)?.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let (content, stored) = header.split_at(12);
+    if !content.starts_with(&LOG_MAGIC) || checksum(crc, content).to_le_bytes() != stored[..] {
+        return Ok(None);
+    }
+    let generation = content[LOG_MAGIC.len()..]
+        .try_into()
+        .expect("the generation has 8 bytes");
+    Ok(Some(u64::from_le_bytes(generation)))
+}
+
+/// Encodes a record stored at `offset` with its length and checksum
+fn encode_record(crc: &'static Crc<u32>, offset: u64, len: u32, record: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(record.len() + RECORD_OVERHEAD as usize);
+    encoded.extend_from_slice(&len.to_le_bytes());
+    encoded.extend_from_slice(record);
+    let checksum = record_checksum(crc, offset, &encoded);
+    encoded.extend_from_slice(&checksum.to_le_bytes());
+    encoded
+}
+
+/// Computes the checksum of the length and the contents of a record, which is bound to its position,
+/// so an outdated record following the end of the log is not mistaken for a valid one
+fn record_checksum(crc: &'static Crc<u32>, offset: u64, encoded: &[u8]) -> u32 {
+    let mut digest = ChecksumDigest::new(crc);
+    digest.update(&offset.to_le_bytes());
+    digest.update(encoded);
+    digest.finalize()
+}
+
+///
+/// Iterates over the records of a `BufferedLog`, stopping at the first corrupt record.
+pub struct LogRecords<F: Read> {
+    reader: Option<BufReader<F>>,
+    crc: &'static Crc<u32>,
+    offset: u64,
+    end: u64,
+}
+
+impl<F: Read> std::fmt::Debug for LogRecords<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LogRecords")
+            .field("offset", &self.offset)
+            .field("end", &self.end)
+            .finish()
+    }
+}
+
+impl<F: Read + Seek> LogRecords<F> {
+    /// Reads the records of `file` up to `end`
+    fn new(mut file: F, crc: &'static Crc<u32>, end: u64) -> std::io::Result<Self> {
+        file.seek(SeekFrom::Start(LOG_HEADER_LEN))?;
+        Ok(LogRecords {
+            reader: Some(BufReader::new(file)),
+            crc,
+            offset: LOG_HEADER_LEN,
+            end,
+        })
+    }
+
+    /// Iterates over no records at all
+    fn empty() -> Self {
+        LogRecords {
+            reader: None,
+            crc: &crate::DEFAULT_CHECKSUM,
+            offset: LOG_HEADER_LEN,
+            end: LOG_HEADER_LEN,
+        }
+    }
+
+    /// Reads the record at the current position, or `None` if it is incomplete or corrupt
+    fn read_record(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        let reader = match &mut self.reader {
+            Some(reader) => reader,
+            None => return Ok(None),
+        };
+        let mut len = [0u8; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        let record_len = u64::from(u32::from_le_bytes(len));
+        if self.end - self.offset < record_len + RECORD_OVERHEAD {
+            return Ok(None);
+        }
+        let mut encoded = vec![0u8; record_len as usize + len.len()];
+        encoded[..len.len()].copy_from_slice(&len);
+        let mut stored = [0u8; 4];
+        match reader
+            .read_exact(&mut encoded[len.len()..])
+            .and_then(|()| reader.read_exact(&mut stored))
+        {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        if record_checksum(self.crc, self.offset, &encoded) != u32::from_le_bytes(stored) {
+            return Ok(None);
+        }
+        self.offset += record_len + RECORD_OVERHEAD;
+        encoded.drain(..len.len());
+        Ok(Some(encoded))
+    }
+}
+
+impl<F: Read + Seek> Iterator for LogRecords<F> {
+    type Item = std::io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.end {
+            return None;
+        }
+        let record = self.read_record().transpose();
+        if !matches!(record, Some(Ok(_))) {
+            // the records following a corrupt record can not be located
+            self.reader = None;
+        }
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::{tests::utils::TempDir, BufferedFileOptions, BufferedLog};
+
+    fn records(log: &BufferedLog) -> Vec<Vec<u8>> {
+        log.records()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn appends_stop_at_torn_records() {
+        let dir = TempDir::new();
+        let file = dir.path().join("events.log");
+        let log = BufferedLog::open(&file).unwrap();
+        assert_eq!(log.generation(), None);
+        assert!(records(&log).is_empty());
+        for record in [&b"first"[..], b"second", b""] {
+            log.append(record).unwrap();
+        }
+
+        // a record torn by a crash
+        let mut slot = std::fs::OpenOptions::new()
+            .append(true)
+            .open(dir.path().join("events.log.1"))
+            .unwrap();
+        slot.write_all(&[9, 0, 0, 0, b'b', b'r']).unwrap();
+        drop(slot);
+
+        let log = BufferedLog::open(&file).unwrap();
+        assert_eq!(log.generation(), Some(1));
+        assert_eq!(records(&log), [&b"first"[..], b"second", b""]);
+        log.append(b"third").unwrap();
+        let log = BufferedLog::open(&file).unwrap();
+        assert_eq!(records(&log), [&b"first"[..], b"second", b"", b"third"]);
+    }
+
+    #[test]
+    fn compaction_rotates_backing_files() {
+        let dir = TempDir::new();
+        let file = dir.path().join("events.log");
+        let log = BufferedFileOptions::new()
+            .buffer_count(3)
+            .open_log(&file)
+            .unwrap();
+        log.append(b"a").unwrap();
+        log.append(b"b").unwrap();
+        log.compact([b"ab"]).unwrap();
+        log.append(b"c").unwrap();
+        assert_eq!(log.generation(), Some(2));
+        assert_eq!(records(&log), [&b"ab"[..], b"c"]);
+        assert!(dir.path().join("events.log.2").exists());
+        assert!(!dir.path().join("events.log.3").exists());
+
+        // a damaged header falls back to the retained generation
+        let slot = dir.path().join("events.log.2");
+        let mut contents = std::fs::read(&slot).unwrap();
+        contents[5] ^= 1;
+        std::fs::write(&slot, contents).unwrap();
+        let log = BufferedLog::open(&file).unwrap();
+        assert_eq!(log.generation(), Some(1));
+        assert_eq!(records(&log), [b"a", b"b"]);
+    }
+}
@@ -0,0 +1,123 @@
+//! Authenticates the contents of the backing files with HMAC-SHA256.
+//!
+//! The authentication code is computed over the contents and stored right behind them, so it is covered by the
+//! checksums as well. In contrast to a checksum it can not be recomputed without the key, so deliberate
+//! modifications of a backing file are detected even if its checksums have been updated to match.
+
+use std::{io::Read, sync::Arc};
+
+use hmac_sha256::HMAC;
+
+/// The length of the authentication code in bytes
+pub(crate) const MAC_LEN: u64 = 32;
+
+/// The secret key of the authentication code, which is not revealed by `Debug`
+#[derive(Clone)]
+pub(crate) struct MacKey(Arc<[u8]>);
+
+impl std::fmt::Debug for MacKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MacKey(..)")
+    }
+}
+
+impl MacKey {
+    pub(crate) fn new(key: &[u8]) -> Self {
+        MacKey(Arc::from(key))
+    }
+
+    /// Starts the computation of an authentication code
+    pub(crate) fn authenticator(&self) -> HMAC {
+        HMAC::new(&self.0)
+    }
+
+    /// Checks the authentication code stored at the end of the `len` bytes read from `contents`
+    pub(crate) fn verify(&self, contents: &mut impl Read, len: u64) -> std::io::Result<bool> {
+        let mut remaining = match len.checked_sub(MAC_LEN) {
+            Some(remaining) => remaining,
+            None => return Ok(false),
+        };
+        let mut mac = self.authenticator();
+        let mut buf = [0u8; 8192];
+        while remaining > 0 {
+            let chunk = usize::try_from(remaining)
+                .unwrap_or(usize::MAX)
+                .min(buf.len());
+            contents.read_exact(&mut buf[..chunk])?;
+            mac.update(&buf[..chunk]);
+            remaining -= chunk as u64;
+        }
+        let mut stored = [0u8; MAC_LEN as usize];
+        contents.read_exact(&mut stored)?;
+        Ok(mac.finalize_verify(&stored))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, ErrorKind};
+
+    use super::{MacKey, MAC_LEN};
+    use crate::{tests::utils::TempDir, BufferedFileOptions, FileCheckResult, FsStorage};
+
+    /// The contents followed by their authentication code computed with `key`
+    fn authenticated(key: &MacKey, contents: &[u8]) -> Vec<u8> {
+        let mut mac = key.authenticator();
+        mac.update(contents);
+        let mut stored = contents.to_vec();
+        stored.extend_from_slice(&mac.finalize());
+        stored
+    }
+
+    #[test]
+    fn matching_codes_are_accepted() {
+        let key = MacKey::new(b"secret");
+        let stored = authenticated(&key, b"Hello World");
+        let len = stored.len() as u64;
+        assert!(key.verify(&mut Cursor::new(stored), len).unwrap());
+        let empty = authenticated(&key, b"");
+        assert!(key.verify(&mut Cursor::new(empty), MAC_LEN).unwrap());
+    }
+
+    #[test]
+    fn codes_of_other_keys_are_rejected() {
+        let stored = authenticated(&MacKey::new(b"secret"), b"Hello World");
+        let len = stored.len() as u64;
+        assert!(!MacKey::new(b"other")
+            .verify(&mut Cursor::new(stored), len)
+            .unwrap());
+
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        BufferedFileOptions::new()
+            .hmac_key(b"secret")
+            .create_with(&file, b"Hello World")
+            .unwrap();
+        let mut options = BufferedFileOptions::new();
+        options.hmac_key(b"other");
+        let result =
+            crate::verify_backing_file(&FsStorage, &dir.path().join("data-file.txt.1"), &options);
+        assert_eq!(result.unwrap(), FileCheckResult::MacMismatch);
+    }
+
+    #[test]
+    fn truncated_codes_are_rejected() {
+        let key = MacKey::new(b"secret");
+        let stored = authenticated(&key, b"Hello World");
+        let truncated = &stored[..stored.len() - 1];
+        // the last byte of the contents is taken as the first byte of the code
+        assert!(!key
+            .verify(&mut Cursor::new(truncated), truncated.len() as u64)
+            .unwrap());
+        assert!(!key
+            .verify(
+                &mut Cursor::new(&stored[..MAC_LEN as usize - 1]),
+                MAC_LEN - 1
+            )
+            .unwrap());
+        let err = key
+            .verify(&mut Cursor::new(truncated), stored.len() as u64)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+}
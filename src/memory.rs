@@ -0,0 +1,330 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    time::SystemTime,
+};
+
+use crate::{Storage, StorageFile, StorageMetadata};
+
+/// The content of a file kept in memory
+#[derive(Debug)]
+struct Entry {
+    data: Vec<u8>,
+    modified: SystemTime,
+}
+
+type SharedEntry = Arc<Mutex<Entry>>;
+
+fn lock_entry(entry: &SharedEntry) -> MutexGuard<'_, Entry> {
+    entry.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+///
+/// Keeps the backing files in memory instead of the file system.
+///
+/// Intended for tests of code consuming managed files, which should not touch the file system.
+/// Clones share the same files, so a clone can be used to inspect or corrupt the backing files.
+///
+/// # Example
+///
+/// ```
+/// use multibufferedfile::{BufferedFileOptions, MemoryStorage};
+///
+/// let storage = MemoryStorage::new();
+/// let file = BufferedFileOptions::new()
+///     .open_in(storage.clone(), "config.txt")
+///     .unwrap();
+/// file.update(|_| b"Hello World".to_vec()).unwrap();
+///
+/// assert_eq!(file.read_or_default().unwrap(), b"Hello World");
+/// assert!(storage.contents("config.txt.1").is_some());
+/// assert!(!std::path::Path::new("config.txt.1").exists());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    files: Arc<Mutex<BTreeMap<PathBuf, SharedEntry>>>,
+    locks: LockTable,
+}
+
+impl MemoryStorage {
+    /// Creates an empty storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn files(&self) -> MutexGuard<'_, BTreeMap<PathBuf, SharedEntry>> {
+        self.files.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn entry(&self, path: &Path) -> std::io::Result<SharedEntry> {
+        self.files()
+            .get(path)
+            .cloned()
+            .ok_or_else(|| not_found(path))
+    }
+
+    /// The paths of all stored files
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.files().keys().cloned().collect()
+    }
+
+    /// A copy of the raw content of a stored file
+    pub fn contents(&self, path: impl AsRef<Path>) -> Option<Vec<u8>> {
+        let entry = self.files().get(path.as_ref()).cloned()?;
+        let data = lock_entry(&entry).data.clone();
+        Some(data)
+    }
+
+    /// Replaces the raw content of a stored file, creating it if necessary
+    pub fn insert(&self, path: impl AsRef<Path>, contents: impl Into<Vec<u8>>) {
+        let entry = Entry {
+            data: contents.into(),
+            modified: SystemTime::now(),
+        };
+        self.files()
+            .insert(path.as_ref().to_path_buf(), Arc::new(Mutex::new(entry)));
+    }
+}
+
+fn not_found(path: &Path) -> std::io::Error {
+    std::io::Error::new(
+        ErrorKind::NotFound,
+        format!("{} does not exist", path.display()),
+    )
+}
+
+///
+/// An opened file of a `MemoryStorage`.
+#[derive(Debug)]
+pub struct MemoryFile {
+    entry: SharedEntry,
+    pos: u64,
+    writable: bool,
+}
+
+impl Read for MemoryFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let entry = lock_entry(&self.entry);
+        let start = usize::try_from(self.pos)
+            .unwrap_or(usize::MAX)
+            .min(entry.data.len());
+        let count = buf.len().min(entry.data.len() - start);
+        buf[..count].copy_from_slice(&entry.data[start..start + count]);
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if !self.writable {
+            return Err(std::io::Error::new(
+                ErrorKind::PermissionDenied,
+                "file is opened read-only",
+            ));
+        }
+        let mut entry = lock_entry(&self.entry);
+        let start = usize::try_from(self.pos)
+            .map_err(|_| std::io::Error::new(ErrorKind::InvalidInput, "position too large"))?;
+        let end = start + buf.len();
+        if entry.data.len() < end {
+            entry.data.resize(end, 0);
+        }
+        entry.data[start..end].copy_from_slice(buf);
+        entry.modified = SystemTime::now();
+        self.pos = end as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The content is kept in memory, so there is nothing to synchronize
+impl StorageFile for MemoryFile {
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemoryFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let len = lock_entry(&self.entry).data.len() as u64;
+        let new_pos = match pos {
+            SeekFrom::Start(start) => Some(start),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+            SeekFrom::End(delta) => len.checked_add_signed(delta),
+        };
+        self.pos = new_pos.ok_or_else(|| {
+            std::io::Error::new(ErrorKind::InvalidInput, "seek before the start of the file")
+        })?;
+        Ok(self.pos)
+    }
+}
+
+/// The locks held within this process, shared by the clones of a storage
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LockTable(Arc<(Mutex<BTreeSet<PathBuf>>, Condvar)>);
+
+impl LockTable {
+    /// Acquires the lock identified by the path, blocking until it is available
+    pub(crate) fn acquire(&self, path: &Path) -> MemoryLock {
+        let (held, released) = &*self.0;
+        let mut held = held.lock().unwrap_or_else(PoisonError::into_inner);
+        while held.contains(path) {
+            held = released.wait(held).unwrap_or_else(PoisonError::into_inner);
+        }
+        held.insert(path.to_path_buf());
+        MemoryLock {
+            locks: self.clone(),
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+///
+/// Holds a lock, which is only visible within this process, until it is dropped.
+#[derive(Debug)]
+pub struct MemoryLock {
+    locks: LockTable,
+    path: PathBuf,
+}
+
+impl Drop for MemoryLock {
+    fn drop(&mut self) {
+        let (held, released) = &*self.locks.0;
+        held.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.path);
+        released.notify_all();
+    }
+}
+
+impl Storage for MemoryStorage {
+    type File = MemoryFile;
+    type Lock = MemoryLock;
+
+    fn open(&self, path: &Path) -> std::io::Result<Self::File> {
+        Ok(MemoryFile {
+            entry: self.entry(path)?,
+            pos: 0,
+            writable: false,
+        })
+    }
+
+    fn open_write(&self, path: &Path) -> std::io::Result<Self::File> {
+        Ok(MemoryFile {
+            entry: self.entry(path)?,
+            pos: 0,
+            writable: true,
+        })
+    }
+
+    /// Permissions are not supported, so the mode is ignored
+    fn create(&self, path: &Path, _mode: Option<u32>) -> std::io::Result<Self::File> {
+        let mut files = self.files();
+        let entry = files
+            .entry(path.to_path_buf())
+            .or_insert_with(|| {
+                Arc::new(Mutex::new(Entry {
+                    data: Vec::new(),
+                    modified: SystemTime::now(),
+                }))
+            })
+            .clone();
+        {
+            let mut opened = lock_entry(&entry);
+            opened.data.clear();
+            opened.modified = SystemTime::now();
+        }
+        Ok(MemoryFile {
+            entry,
+            pos: 0,
+            writable: true,
+        })
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        let mut files = self.files();
+        let entry = files.remove(from).ok_or_else(|| not_found(from))?;
+        files.insert(to.to_path_buf(), entry);
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        self.files()
+            .remove(path)
+            .map(|_| ())
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn metadata(&self, path: &Path) -> std::io::Result<StorageMetadata> {
+        let entry = self.entry(path)?;
+        let entry = lock_entry(&entry);
+        Ok(StorageMetadata {
+            len: entry.data.len() as u64,
+            modified: Some(entry.modified),
+            mode: None,
+            owner: None,
+            is_dir: false,
+        })
+    }
+
+    /// Directories exist implicitly, as long as they contain files
+    fn create_dir_all(&self, _path: &Path) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &Path) -> std::io::Result<()> {
+        if self.files().keys().any(|file| file.starts_with(path)) {
+            return Err(std::io::Error::new(
+                ErrorKind::DirectoryNotEmpty,
+                format!("{} is not empty", path.display()),
+            ));
+        }
+        Ok(())
+    }
+
+    fn lock(&self, path: &Path) -> std::io::Result<Self::Lock> {
+        Ok(self.locks.acquire(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BufferedFileOptions, MemoryStorage};
+
+    #[test]
+    fn keeps_backing_files_in_memory() {
+        let storage = MemoryStorage::new();
+        let managed_file = BufferedFileOptions::new()
+            .open_in(storage.clone(), "data-file.txt")
+            .expect("Can not find files");
+        managed_file.update(|_| b"Hello World".to_vec()).unwrap();
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        assert_eq!(
+            storage.paths(),
+            [
+                std::path::PathBuf::from("data-file.txt.1"),
+                "data-file.txt.2".into()
+            ]
+        );
+        assert!(!std::path::Path::new("data-file.txt.1").exists());
+
+        // a corrupted generation is skipped like on the file system
+        let mut corrupted = storage.contents("data-file.txt.2").unwrap();
+        corrupted[1] = b'J';
+        storage.insert("data-file.txt.2", corrupted);
+        let reopened = BufferedFileOptions::new()
+            .open_in(storage.clone(), "data-file.txt")
+            .unwrap();
+        assert_eq!(reopened.read_or_default().unwrap(), b"Hello World");
+
+        let removed = reopened.delete().unwrap();
+        assert_eq!(removed.len(), 2);
+        assert!(storage.paths().is_empty());
+    }
+}
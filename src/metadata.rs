@@ -0,0 +1,206 @@
+use std::{collections::BTreeMap, io::ErrorKind};
+
+use crate::BufferedFileErrors;
+
+/// The maximum encoded size of the user metadata, as its length is stored in two bytes
+pub const MAX_METADATA_LEN: usize = u16::MAX as usize;
+
+/// The key of the content type, see `BufferedFileOptions::content_type`
+pub const CONTENT_TYPE_KEY: &str = "content-type";
+
+/// The key marking generations written in delta mode, see `BufferedFileOptions::delta_writes`
+pub const DELTA_KEY: &str = "delta";
+
+///
+/// Small key/value pairs attached to a generation, e.g. a schema version, the producer or a comment.
+///
+/// The metadata is stored in front of the contents of a backing file (see `FormatVersion::V4`),
+/// so it can be retrieved from a reader without reading the contents.
+/// It is protected by the checksums, but neither encrypted nor covered by an authentication code.
+///
+/// # Example
+///
+/// ```
+/// use std::io::Write;
+/// use multibufferedfile::{BufferedFileOptions, FormatVersion, UserMetadata};
+/// # let dir = std::env::temp_dir().join("multibufferedfile-metadata-doc");
+/// # std::fs::create_dir_all(&dir).unwrap();
+///
+/// let file = BufferedFileOptions::new()
+///     .format_version(FormatVersion::V4)
+///     .open(dir.join("file.txt"))
+///     .unwrap();
+/// let mut metadata = UserMetadata::new();
+/// metadata.insert("schema", "2");
+/// let mut writer = file.write_with_metadata(&metadata).unwrap();
+/// writer.write_all(b"Hello World").unwrap();
+/// writer.commit().unwrap();
+///
+/// let reader = file.read().unwrap();
+/// assert_eq!(reader.user_metadata().get("schema"), Some(&b"2"[..]));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserMetadata {
+    entries: BTreeMap<String, Vec<u8>>,
+}
+
+impl UserMetadata {
+    /// Creates empty metadata
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the value of `key`, replacing a previous value
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<Vec<u8>>) -> &mut Self {
+        self.entries.insert(key.into(), value.into());
+        self
+    }
+
+    /// The value of `key`, if it has been set
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.entries.get(key).map(Vec::as_slice)
+    }
+
+    /// The content type, if it has been set and is valid UTF-8
+    pub fn content_type(&self) -> Option<&str> {
+        self.get(CONTENT_TYPE_KEY)
+            .and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    /// Iterates over all key/value pairs ordered by their keys
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_slice()))
+    }
+
+    /// The number of key/value pairs
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no key/value pair has been set
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Encodes every pair as the lengths of key and value (two bytes in little endian each) followed by both
+    pub(crate) fn encode(&self) -> Result<Vec<u8>, BufferedFileErrors> {
+        let mut encoded = Vec::new();
+        for (key, value) in &self.entries {
+            for part in [key.as_bytes(), value.as_slice()] {
+                // every part is shorter than the whole, which is checked below
+                encoded.extend_from_slice(&(part.len() as u16).to_le_bytes());
+                encoded.extend_from_slice(part);
+            }
+        }
+        if encoded.len() > MAX_METADATA_LEN {
+            return Err(BufferedFileErrors::MetadataTooLarge(encoded.len()));
+        }
+        Ok(encoded)
+    }
+
+    /// Decodes the metadata written by `encode`
+    pub(crate) fn decode(mut encoded: &[u8]) -> std::io::Result<Self> {
+        let mut metadata = UserMetadata::new();
+        while !encoded.is_empty() {
+            let key =
+                String::from_utf8(take_part(&mut encoded)?.to_vec()).map_err(|_| malformed())?;
+            let value = take_part(&mut encoded)?.to_vec();
+            metadata.entries.insert(key, value);
+        }
+        Ok(metadata)
+    }
+}
+
+fn malformed() -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, "The metadata is malformed")
+}
+
+/// Splits a part prefixed by its length off the start of `encoded`
+fn take_part<'a>(encoded: &mut &'a [u8]) -> std::io::Result<&'a [u8]> {
+    let (len, rest) = encoded.split_first_chunk::<2>().ok_or_else(malformed)?;
+    let (part, rest) = rest
+        .split_at_checked(usize::from(u16::from_le_bytes(*len)))
+        .ok_or_else(malformed)?;
+    *encoded = rest;
+    Ok(part)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use super::{UserMetadata, MAX_METADATA_LEN};
+    use crate::BufferedFileErrors;
+
+    #[test]
+    fn pairs_survive_a_round_trip() {
+        let mut metadata = UserMetadata::new();
+        metadata
+            .insert("schema", "2")
+            .insert("empty", Vec::new())
+            .insert("", b"\0\xff".to_vec())
+            .insert("schema", "3");
+        let encoded = metadata.encode().unwrap();
+        let decoded = UserMetadata::decode(&encoded).unwrap();
+        assert_eq!(decoded, metadata);
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(decoded.get("schema"), Some(&b"3"[..]));
+        assert_eq!(decoded.get("empty"), Some(&[][..]));
+        assert_eq!(
+            decoded.iter().map(|(key, _)| key).collect::<Vec<_>>(),
+            ["", "empty", "schema"]
+        );
+
+        assert!(UserMetadata::new().encode().unwrap().is_empty());
+        assert!(UserMetadata::decode(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn encodes_the_lengths_in_two_bytes() {
+        let mut metadata = UserMetadata::new();
+        metadata.insert("k", "value");
+        assert_eq!(
+            metadata.encode().unwrap(),
+            b"\x01\x00k\x05\x00value".to_vec()
+        );
+    }
+
+    #[test]
+    fn rejects_metadata_exceeding_the_limit() {
+        // two length prefixes and the key take 5 bytes
+        let mut metadata = UserMetadata::new();
+        metadata.insert("k", vec![7u8; MAX_METADATA_LEN - 5]);
+        let encoded = metadata.encode().unwrap();
+        assert_eq!(encoded.len(), MAX_METADATA_LEN);
+        assert_eq!(UserMetadata::decode(&encoded).unwrap(), metadata);
+
+        metadata.insert("k", vec![7u8; MAX_METADATA_LEN - 4]);
+        assert!(matches!(
+            metadata.encode(),
+            Err(BufferedFileErrors::MetadataTooLarge(len)) if len == MAX_METADATA_LEN + 1
+        ));
+        // a single value longer than its two byte length is rejected instead of being truncated
+        metadata.insert("k", vec![7u8; u16::MAX as usize + 1]);
+        assert!(matches!(
+            metadata.encode(),
+            Err(BufferedFileErrors::MetadataTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_metadata() {
+        for encoded in [
+            &b"\x01"[..],
+            b"\x02\x00k",
+            b"\x01\x00k",
+            b"\x01\x00k\x05\x00val",
+            b"\x01\x00\xff\x00\x00",
+        ] {
+            let err = UserMetadata::decode(encoded).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData, "{encoded:?}");
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::{io::ErrorKind, ops::Deref};
+
+use memmap2::{Mmap, MmapOptions};
+
+use crate::{verify_file, BufferedFile, BufferedFileErrors, FileCheckResult, FsStorage};
+
+///
+/// The contents of the newest generation mapped into memory, see `BufferedFile::read_mmap`.
+///
+/// Derefs to the contents only, without the header, the user metadata and the trailer of the backing file.
+#[derive(Debug)]
+pub struct MappedContents {
+    /// The mapping, which is missing for empty contents
+    map: Option<Mmap>,
+    generation: u64,
+}
+
+impl MappedContents {
+    /// The generation of the mapped backing file
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+impl Deref for MappedContents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.map.as_deref().unwrap_or_default()
+    }
+}
+
+impl AsRef<[u8]> for MappedContents {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl BufferedFile<FsStorage> {
+    ///
+    /// Maps the contents of the newest valid generation into memory, e.g. for zero-copy parsing of large contents.
+    ///
+    /// The checksum is verified before the contents are mapped, also with `BufferedFileOptions::verify_while_reading`
+    /// or `reverify_while_reading`.
+    /// Fails with `ErrorKind::Unsupported` for contents, which are not stored in one piece: with block checksums,
+    /// encryption or delta writes.
+    ///
+    /// The mapped backing file must not be modified while it is mapped, otherwise the contents change
+    /// under the hands of the caller. Writers of this instance overwrite the oldest backing file only,
+    /// so the mapped generation is safe as long as fewer generations than `buffer_count - 1` are written
+    /// meanwhile. Use `pin_current` or `CommitStrategy::Rename` to keep it safe from any number of writes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multibufferedfile::BufferedFileOptions;
+    /// # let dir = std::env::temp_dir().join("multibufferedfile-mmap-doc");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    ///
+    /// let file = BufferedFileOptions::new()
+    ///     .create_with(dir.join("blob.bin"), b"Hello World")
+    ///     .unwrap();
+    /// let contents = file.read_mmap().unwrap();
+    /// assert_eq!(&contents[..5], b"Hello");
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn read_mmap(&self) -> Result<MappedContents, BufferedFileErrors> {
+        let reader = self.read()?;
+        let generation = reader.generation();
+        let path = reader.metadata().path;
+        let (file, offset, len) = reader.into_contiguous().ok_or_else(|| {
+            std::io::Error::new(
+                ErrorKind::Unsupported,
+                "The contents are not stored in one piece",
+            )
+        })?;
+        if self.options.streams_checksum()
+            && !matches!(
+                verify_file(&*self.storage, &path, &self.options)?,
+                FileCheckResult::Good { .. }
+            )
+        {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "The checksum of the contents does not match",
+            )
+            .into());
+        }
+        if len == 0 {
+            return Ok(MappedContents {
+                map: None,
+                generation,
+            });
+        }
+        let len = usize::try_from(len).map_err(std::io::Error::other)?;
+        // SAFETY: the backing file is not modified while it is mapped, see above
+        let map = unsafe { MmapOptions::new().offset(offset).len(len).map(&file)? };
+        Ok(MappedContents {
+            map: Some(map),
+            generation,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use crate::{tests::utils::TempDir, BufferedFileOptions, FormatVersion};
+
+    #[test]
+    fn maps_only_the_contents() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let mut options = BufferedFileOptions::new();
+        options
+            .format_version(FormatVersion::V4)
+            .content_type("text/plain")
+            .verify_while_reading(true);
+        let managed_file = options.create_with(&file, b"Hello World").unwrap();
+        let contents = managed_file.read_mmap().unwrap();
+        assert_eq!(&*contents, b"Hello World");
+        assert_eq!(contents.generation(), 1);
+
+        managed_file.update(|_| Vec::new()).unwrap();
+        assert!(managed_file.read_mmap().unwrap().is_empty());
+
+        let blocked = BufferedFileOptions::new()
+            .block_checksums(NonZeroU32::new(4).unwrap())
+            .create_with(dir.path().join("blocked.txt"), b"Hello World")
+            .unwrap();
+        assert!(blocked.read_mmap().is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+};
+
+///
+/// Generates the paths of the backing files of a managed file.
+///
+/// The default strategy appends the suffix pattern of the `BufferedFileOptions` to the file name.
+/// Implement this trait to store the backing files with a different naming convention.
+///
+/// # Example
+///
+/// ```
+/// use std::path::{Path, PathBuf};
+/// use multibufferedfile::{BufferedFileOptions, NamingStrategy};
+///
+/// /// Stores the backing files as hidden files next to the managed file
+/// #[derive(Debug)]
+/// struct Hidden;
+///
+/// impl NamingStrategy for Hidden {
+///     fn slot_path(&self, path: &Path, slot: u8) -> PathBuf {
+///         let name = path.file_name().unwrap().to_string_lossy();
+///         path.with_file_name(format!(".{name}.{}", (b'a' + slot - 1) as char))
+///     }
+/// }
+///
+/// let file = BufferedFileOptions::new().naming_strategy(Hidden).open("file.txt");
+/// assert!(file.is_ok());
+/// ```
+pub trait NamingStrategy: Debug + Send + Sync {
+    /// Returns the path of the backing file with the number `slot` of the managed file at `path`.
+    ///
+    /// The slots are numbered starting at 1. Every slot must map to a distinct path.
+    fn slot_path(&self, path: &Path, slot: u8) -> PathBuf;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::NamingStrategy;
+    use crate::BufferedFileOptions;
+
+    /// Numbers the backing files by letters in front of the extension
+    #[derive(Debug)]
+    struct Lettered;
+
+    impl NamingStrategy for Lettered {
+        fn slot_path(&self, path: &Path, slot: u8) -> PathBuf {
+            path.with_extension(format!("{}.txt", (b'a' + slot - 1) as char))
+        }
+    }
+
+    #[test]
+    fn the_suffix_pattern_is_appended_to_the_file_name() {
+        let path = Path::new("dir/data-file.txt");
+        let options = BufferedFileOptions::new();
+        assert_eq!(options.slot_path(path, 1), Path::new("dir/data-file.txt.1"));
+        assert_eq!(
+            options.slot_path(path, 128),
+            Path::new("dir/data-file.txt.128")
+        );
+        assert_eq!(
+            BufferedFileOptions::new()
+                .suffix_pattern("-{}.bak")
+                .slot_path(path, 2),
+            Path::new("dir/data-file.txt-2.bak")
+        );
+    }
+
+    #[test]
+    fn strategies_replace_the_suffix_pattern() {
+        let path = Path::new("dir/data-file.txt");
+        let mut options = BufferedFileOptions::new();
+        options.suffix_pattern(".{}.bak").naming_strategy(Lettered);
+        assert_eq!(options.slot_path(path, 1), Path::new("dir/data-file.a.txt"));
+        assert_eq!(options.slot_path(path, 2), Path::new("dir/data-file.b.txt"));
+        // the sidecar files keep their names
+        assert_eq!(options.lock_path(path), Path::new("dir/data-file.txt.lock"));
+        assert_eq!(options.pin_path(path), Path::new("dir/data-file.txt.pin"));
+    }
+
+    #[test]
+    fn slot_directories_hold_all_backing_files() {
+        let path = Path::new("dir/data-file.txt");
+        let mut options = BufferedFileOptions::new();
+        options.slot_dir(".buffered");
+        assert_eq!(
+            options.slot_path(path, 1),
+            Path::new("dir/.buffered/data-file.txt.1")
+        );
+        assert_eq!(
+            options.lock_path(path),
+            Path::new("dir/.buffered/data-file.txt.lock")
+        );
+        options.naming_strategy(Lettered);
+        assert_eq!(
+            options.slot_path(path, 2),
+            Path::new("dir/.buffered/data-file.b.txt")
+        );
+        options.slot_dir("/var/lib/slots");
+        assert_eq!(
+            options.pin_path(path),
+            Path::new("/var/lib/slots/data-file.txt.pin")
+        );
+    }
+}
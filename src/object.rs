@@ -0,0 +1,310 @@
+use std::{
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Component, Path},
+    sync::Arc,
+};
+
+use object_store::{path::Path as ObjectPath, ObjectStore};
+use tokio::runtime::Handle;
+
+use crate::{memory::LockTable, MemoryLock, Storage, StorageFile, StorageMetadata};
+
+/// The number of bytes fetched by a single ranged request while reading
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+///
+/// Keeps the backing files as objects of an `ObjectStore`, e.g. in S3 or GCS.
+///
+/// Every backing file is mapped to the object key made of the normal components of its path.
+/// Wrap the store into an `object_store::prefix::PrefixStore` to place the objects below a common prefix.
+///
+/// Reads are performed with ranged requests, while the content of a writer is uploaded with a single request
+/// when it is flushed. Committing a writer flushes it regardless of the durability, so the upload has finished
+/// before a new generation becomes visible to readers and a failed upload fails the commit.
+///
+/// The asynchronous requests are driven by blocking on the given runtime, so the storage must not be used from
+/// within an asynchronous task of that runtime. Locks are only visible within this process.
+#[derive(Debug, Clone)]
+pub struct ObjectStoreStorage {
+    store: Arc<dyn ObjectStore>,
+    runtime: Handle,
+    locks: LockTable,
+}
+
+impl ObjectStoreStorage {
+    /// Creates a storage keeping the backing files in `store`, while performing the requests on `runtime`
+    pub fn new(store: Arc<dyn ObjectStore>, runtime: Handle) -> Self {
+        ObjectStoreStorage {
+            store,
+            runtime,
+            locks: LockTable::default(),
+        }
+    }
+
+    /// The object store holding the backing files
+    pub fn store(&self) -> &Arc<dyn ObjectStore> {
+        &self.store
+    }
+
+    /// Maps the path of a backing file to the key of its object
+    fn location(path: &Path) -> ObjectPath {
+        ObjectPath::from_iter(path.components().filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        }))
+    }
+
+    fn file(&self, path: &Path, size: u64, pending: Option<Vec<u8>>) -> ObjectFile {
+        ObjectFile {
+            store: Arc::clone(&self.store),
+            runtime: self.runtime.clone(),
+            location: Self::location(path),
+            size,
+            chunk: Vec::new(),
+            chunk_start: 0,
+            pending,
+            dirty: false,
+            pos: 0,
+        }
+    }
+}
+
+///
+/// An opened object of an `ObjectStoreStorage`.
+///
+/// Objects opened for reading are fetched in chunks with ranged requests.
+/// Objects opened for writing are kept in memory and uploaded on `flush` or when dropped.
+#[derive(Debug)]
+pub struct ObjectFile {
+    store: Arc<dyn ObjectStore>,
+    runtime: Handle,
+    location: ObjectPath,
+    size: u64,
+    chunk: Vec<u8>,
+    chunk_start: u64,
+    pending: Option<Vec<u8>>,
+    dirty: bool,
+    pos: u64,
+}
+
+impl ObjectFile {
+    fn len(&self) -> u64 {
+        match &self.pending {
+            Some(pending) => pending.len() as u64,
+            None => self.size,
+        }
+    }
+
+    /// Fetches the chunk containing the current position
+    fn fetch(&mut self) -> std::io::Result<()> {
+        let end = self.size.min(self.pos.saturating_add(CHUNK_SIZE));
+        let range = to_usize(self.pos)?..to_usize(end)?;
+        let bytes = self
+            .runtime
+            .block_on(self.store.get_range(&self.location, range))
+            .map_err(std::io::Error::from)?;
+        self.chunk = bytes.to_vec();
+        self.chunk_start = self.pos;
+        Ok(())
+    }
+}
+
+fn to_usize(value: u64) -> std::io::Result<usize> {
+    usize::try_from(value)
+        .map_err(|_| std::io::Error::new(ErrorKind::InvalidInput, "object too large"))
+}
+
+impl Read for ObjectFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if let Some(pending) = &self.pending {
+            let start = to_usize(self.pos)?.min(pending.len());
+            let count = buf.len().min(pending.len() - start);
+            buf[..count].copy_from_slice(&pending[start..start + count]);
+            self.pos += count as u64;
+            return Ok(count);
+        }
+
+        if self.pos >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+        let chunk_end = self.chunk_start + self.chunk.len() as u64;
+        if !(self.chunk_start..chunk_end).contains(&self.pos) {
+            self.fetch()?;
+        }
+        let offset = to_usize(self.pos - self.chunk_start)?;
+        let count = buf.len().min(self.chunk.len() - offset);
+        buf[..count].copy_from_slice(&self.chunk[offset..offset + count]);
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+impl Write for ObjectFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let start = to_usize(self.pos)?;
+        let pending = self.pending.as_mut().ok_or_else(|| {
+            std::io::Error::new(ErrorKind::PermissionDenied, "object is opened read-only")
+        })?;
+        let end = start + buf.len();
+        if pending.len() < end {
+            pending.resize(end, 0);
+        }
+        pending[start..end].copy_from_slice(buf);
+        self.dirty = true;
+        self.pos = end as u64;
+        Ok(buf.len())
+    }
+
+    /// Uploads the content, if it has been modified since the last upload
+    fn flush(&mut self) -> std::io::Result<()> {
+        if let (true, Some(pending)) = (self.dirty, &self.pending) {
+            self.runtime
+                .block_on(self.store.put(&self.location, pending.clone().into()))
+                .map_err(std::io::Error::from)?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+impl Seek for ObjectFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(start) => Some(start),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.len().checked_add_signed(delta),
+        };
+        self.pos = new_pos.ok_or_else(|| {
+            std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "seek before the start of the object",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+/// An object is durable once it has been uploaded
+impl StorageFile for ObjectFile {
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        self.flush()
+    }
+}
+
+impl Drop for ObjectFile {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            tracing::error!("Could not upload {}: {err}", self.location);
+        }
+    }
+}
+
+impl Storage for ObjectStoreStorage {
+    type File = ObjectFile;
+    type Lock = MemoryLock;
+
+    fn open(&self, path: &Path) -> std::io::Result<Self::File> {
+        let metadata = self.metadata(path)?;
+        Ok(self.file(path, metadata.len, None))
+    }
+
+    fn open_write(&self, path: &Path) -> std::io::Result<Self::File> {
+        let location = Self::location(path);
+        let contents = self
+            .runtime
+            .block_on(async {
+                let result = self.store.get(&location).await?;
+                result.bytes().await
+            })
+            .map_err(std::io::Error::from)?;
+        Ok(self.file(path, contents.len() as u64, Some(contents.to_vec())))
+    }
+
+    /// Object stores have no permissions, so the mode is ignored
+    fn create(&self, path: &Path, _mode: Option<u32>) -> std::io::Result<Self::File> {
+        let mut file = self.file(path, 0, Some(Vec::new()));
+        file.dirty = true;
+        Ok(file)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        self.runtime
+            .block_on(
+                self.store
+                    .rename(&Self::location(from), &Self::location(to)),
+            )
+            .map_err(std::io::Error::from)
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        // deleting a missing object succeeds for most stores, but a missing file is reported by the file system
+        self.metadata(path)?;
+        self.runtime
+            .block_on(self.store.delete(&Self::location(path)))
+            .map_err(std::io::Error::from)
+    }
+
+    fn metadata(&self, path: &Path) -> std::io::Result<StorageMetadata> {
+        let meta = self
+            .runtime
+            .block_on(self.store.head(&Self::location(path)))
+            .map_err(std::io::Error::from)?;
+        Ok(StorageMetadata {
+            len: meta.size as u64,
+            modified: Some(meta.last_modified.into()),
+            mode: None,
+            owner: None,
+            is_dir: false,
+        })
+    }
+
+    /// Object stores have no directories, the keys of the objects are just prefixed
+    fn create_dir_all(&self, _path: &Path) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Object stores have no directories, the keys of the objects are just prefixed
+    fn remove_dir(&self, _path: &Path) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn lock(&self, path: &Path) -> std::io::Result<Self::Lock> {
+        Ok(self.locks.acquire(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use object_store::{memory::InMemory, path::Path, ObjectStore};
+
+    use crate::{BufferedFileOptions, Durability, ObjectStoreStorage};
+
+    #[test]
+    fn keeps_backing_files_as_objects() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let store = Arc::new(InMemory::new());
+        let storage = ObjectStoreStorage::new(store.clone(), runtime.handle().clone());
+        let managed_file = BufferedFileOptions::new()
+            .durability(Durability::Flush)
+            .open_in(storage.clone(), "/devices/state.bin")
+            .expect("Can not find files");
+        managed_file.update(|_| b"Hello World".to_vec()).unwrap();
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+
+        let object = runtime
+            .block_on(store.head(&Path::from("devices/state.bin.2")))
+            .expect("backing file should be stored as object");
+        assert_eq!(object.size, 1 + 11 + 4);
+
+        let reopened = BufferedFileOptions::new()
+            .open_in(storage, "/devices/state.bin")
+            .unwrap();
+        assert_eq!(reopened.read_or_default().unwrap(), b"Hello again");
+        assert_eq!(reopened.rollback().unwrap(), 3);
+        assert_eq!(reopened.read_or_default().unwrap(), b"Hello World");
+    }
+}
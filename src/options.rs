@@ -0,0 +1,944 @@
+use std::{
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crc::{Crc, CRC_32_ISCSI, CRC_32_ISO_HDLC};
+
+use crate::{
+    corruption::CorruptionCallback, BufferedFile, BufferedFileErrors, BufferedLog, CorruptionEvent,
+    FormatVersion, FsStorage, JournaledFile, NamingStrategy, Storage, ValidationCache,
+    DEFAULT_BUFFER_COUNT, DEFAULT_CHECKSUM, MAX_BUFFER_COUNT,
+};
+
+#[cfg(feature = "encryption")]
+use crate::encryption::EncryptionKey;
+#[cfg(feature = "hmac")]
+use crate::mac::MacKey;
+
+/// The placeholder in the suffix pattern, which is replaced by the number of the backing file.
+const SLOT_PLACEHOLDER: &str = "{}";
+
+/// Describes how much effort is spent to persist the contents when a writer is finished.
+///
+/// The levels are ordered, every level includes the guarantees of the lower levels.
+/// Higher levels survive more kinds of crashes, but make finishing a writer slower.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Durability {
+    /// The data is handed to the operating system without any further guarantees.
+    /// The writer is only flushed, once it has been finished completely.
+    #[default]
+    None,
+    /// The writer is flushed after the checksum has been written.
+    Flush,
+    /// The backing file is synchronized to disk, before the new generation is considered committed.
+    /// The directory entry of a newly created or renamed backing file may still be lost on power loss.
+    Fsync,
+    /// The backing file and its parent directory are synchronized to disk, before the new generation is
+    /// considered committed. So a committed generation survives a power loss.
+    FsyncAndDir,
+}
+
+/// Converts the levels numbered from `None` (0) to `FsyncAndDir` (3), e.g. when handed over through the C interface.
+/// Unknown levels are returned as the error.
+impl TryFrom<u32> for Durability {
+    type Error = u32;
+
+    fn try_from(level: u32) -> Result<Self, Self::Error> {
+        match level {
+            0 => Ok(Durability::None),
+            1 => Ok(Durability::Flush),
+            2 => Ok(Durability::Fsync),
+            3 => Ok(Durability::FsyncAndDir),
+            level => Err(level),
+        }
+    }
+}
+
+/// Describes how writers treat the backing files held by open readers, see `BufferedFileOptions::reader_leases`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ReaderLeases {
+    /// Readers are not tracked, so writers may overwrite the backing file a reader is reading.
+    #[default]
+    Ignore,
+    /// Writers skip the backing files held by readers and fail, if no other backing file is left.
+    Refuse,
+    /// Writers skip the backing files held by readers and wait for a reader to be dropped,
+    /// if no other backing file is left.
+    Wait,
+}
+
+/// Describes how a writer replaces the content of the backing file of the oldest generation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum CommitStrategy {
+    /// The backing file is truncated and written directly.
+    /// If the process dies while writing, the backing file is left invalid until the next write.
+    #[default]
+    InPlace,
+    /// The content is written to a temporary file next to the backing file,
+    /// which replaces the backing file atomically once the writer is finished.
+    /// The old generation stays available until then.
+    ///
+    /// If writing fails, e.g. because the disk is full, the temporary file is removed and the backing file
+    /// is left untouched. Combine it with `Durability::FsyncAndDir`, so the backing file is only replaced
+    /// after the new generation has been written durably.
+    Rename,
+    /// Invalid or missing backing files are written directly like `InPlace`.
+    /// If every backing file holds a valid generation, the content is staged in a temporary file like `Rename`,
+    /// so a crash while writing never reduces the number of intact generations.
+    PreserveValid,
+}
+
+/// The checksum algorithms available to protect the contents of the backing files.
+///
+/// The algorithm is not stored inside the backing files,
+/// so a file has to be read with the same algorithm it has been written with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum ChecksumAlgorithm {
+    /// CRC-32/BZIP2
+    #[default]
+    Crc32Bzip2,
+    /// CRC-32/ISO-HDLC as used by zlib, gzip and png
+    Crc32IsoHdlc,
+    /// CRC-32/ISCSI (also known as CRC-32C or Castagnoli)
+    Crc32Iscsi,
+}
+
+const CRC_ISO_HDLC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+const CRC_ISCSI: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
+impl ChecksumAlgorithm {
+    /// Provides the crc implementation for this algorithm
+    pub(crate) fn crc(self) -> &'static Crc<u32> {
+        match self {
+            ChecksumAlgorithm::Crc32Bzip2 => &DEFAULT_CHECKSUM,
+            ChecksumAlgorithm::Crc32IsoHdlc => &CRC_ISO_HDLC,
+            ChecksumAlgorithm::Crc32Iscsi => &CRC_ISCSI,
+        }
+    }
+}
+
+///
+/// Configures how a `BufferedFile` is stored and accessed.
+///
+/// # Example
+///
+/// ```
+/// use multibufferedfile::{BufferedFileOptions, ChecksumAlgorithm, Durability};
+///
+/// let file = BufferedFileOptions::new()
+///     .buffer_count(3)
+///     .suffix_pattern(".backup-{}")
+///     .durability(Durability::Flush)
+///     .checksum(ChecksumAlgorithm::Crc32Iscsi)
+///     .open("file.txt");
+/// assert!(file.is_ok());
+/// ```
+#[derive(Debug, Clone)]
+pub struct BufferedFileOptions {
+    pub(crate) buffer_count: u8,
+    pub(crate) suffix_pattern: String,
+    pub(crate) naming: Option<Arc<dyn NamingStrategy>>,
+    pub(crate) slot_dir: Option<PathBuf>,
+    pub(crate) durability: Durability,
+    pub(crate) checksum: ChecksumAlgorithm,
+    pub(crate) lazy_validation: bool,
+    pub(crate) verify_while_reading: bool,
+    pub(crate) reverify_while_reading: bool,
+    pub(crate) max_read_len: Option<u64>,
+    pub(crate) delta_writes: bool,
+    pub(crate) plain_fallback: bool,
+    pub(crate) validation_cache: Option<ValidationCache>,
+    pub(crate) on_corruption: Option<CorruptionCallback>,
+    pub(crate) mode: Option<u32>,
+    pub(crate) preserve_permissions: bool,
+    pub(crate) preserve_owner: bool,
+    pub(crate) commit_strategy: CommitStrategy,
+    pub(crate) reader_leases: ReaderLeases,
+    pub(crate) sparse: bool,
+    pub(crate) block_size: Option<NonZeroU32>,
+    pub(crate) sector_size: Option<NonZeroU32>,
+    pub(crate) format_version: FormatVersion,
+    pub(crate) content_type: Option<String>,
+    #[cfg(feature = "hmac")]
+    pub(crate) mac_key: Option<MacKey>,
+    #[cfg(feature = "encryption")]
+    pub(crate) encryption_key: Option<EncryptionKey>,
+}
+
+impl Default for BufferedFileOptions {
+    fn default() -> Self {
+        BufferedFileOptions {
+            buffer_count: DEFAULT_BUFFER_COUNT,
+            suffix_pattern: String::from(".{}"),
+            naming: None,
+            slot_dir: None,
+            durability: Durability::default(),
+            checksum: ChecksumAlgorithm::default(),
+            lazy_validation: false,
+            verify_while_reading: false,
+            reverify_while_reading: false,
+            max_read_len: None,
+            delta_writes: false,
+            plain_fallback: false,
+            validation_cache: None,
+            on_corruption: None,
+            mode: None,
+            preserve_permissions: true,
+            preserve_owner: false,
+            commit_strategy: CommitStrategy::InPlace,
+            reader_leases: ReaderLeases::default(),
+            sparse: false,
+            block_size: None,
+            sector_size: None,
+            format_version: FormatVersion::default(),
+            content_type: None,
+            #[cfg(feature = "hmac")]
+            mac_key: None,
+            #[cfg(feature = "encryption")]
+            encryption_key: None,
+        }
+    }
+}
+
+impl BufferedFileOptions {
+    /// Creates the default options: two backing files suffixed with .1 and .2
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of rotating backing files. Must be between 2 and 128, or up to 255 with format versions
+    /// storing the generation in eight bytes (`FormatVersion::V5` and later).
+    pub fn buffer_count(&mut self, count: u8) -> &mut Self {
+        self.buffer_count = count;
+        self
+    }
+
+    /// Keeps the given number of previous generations in addition to the newest one.
+    ///
+    /// This is equivalent to a buffer count of `generations + 1`.
+    pub fn history(&mut self, generations: u8) -> &mut Self {
+        self.buffer_count = generations.saturating_add(1);
+        self
+    }
+
+    /// Sets the suffix appended to the path to get the backing files.
+    /// The pattern must contain `{}` which is replaced by the number of the backing file.
+    ///
+    /// This replaces a previously configured naming strategy.
+    pub fn suffix_pattern(&mut self, pattern: impl Into<String>) -> &mut Self {
+        self.suffix_pattern = pattern.into();
+        self.naming = None;
+        self
+    }
+
+    /// Sets a custom strategy to generate the paths of the backing files.
+    ///
+    /// This replaces the suffix pattern.
+    pub fn naming_strategy(&mut self, strategy: impl NamingStrategy + 'static) -> &mut Self {
+        self.naming = Some(Arc::new(strategy));
+        self
+    }
+
+    /// Stores the backing files in a separate directory instead of next to the managed file.
+    ///
+    /// A relative directory is resolved against the directory of the managed file.
+    /// The directory is created on the first write.
+    pub fn slot_dir(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.slot_dir = Some(dir.into());
+        self
+    }
+
+    /// Sets the effort spent to persist the contents when a writer is finished.
+    pub fn durability(&mut self, durability: Durability) -> &mut Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Sets the checksum algorithm used to validate the backing files.
+    pub fn checksum(&mut self, algorithm: ChecksumAlgorithm) -> &mut Self {
+        self.checksum = algorithm;
+        self
+    }
+
+    /// Defers the verification of the checksums until a reader is opened.
+    ///
+    /// Only the generations of the backing files are read on creation, which is sufficient to open writers.
+    /// Opening a reader verifies the backing files starting with the newest one, until a valid one is found.
+    ///
+    /// Opening a writer verifies all backing files not verified yet before selecting the one to replace,
+    /// so a corrupted newer backing file never causes the last valid one to be overwritten.
+    pub fn lazy_validation(&mut self, lazy: bool) -> &mut Self {
+        self.lazy_validation = lazy;
+        self
+    }
+
+    ///
+    /// Verifies the checksum while the contents are read instead of before a reader is opened.
+    ///
+    /// Like with `lazy_validation` only the generations of the backing files are read on creation.
+    /// Readers are opened on the newest backing file without verifying it first, the checksum is computed
+    /// while the contents are read sequentially and reading fails with `ErrorKind::InvalidData` at the end
+    /// of the contents, if it does not match. A corrupted backing file is skipped by later readers then.
+    ///
+    /// Seeking to a position other than the end of the bytes read so far ends the verification, so only
+    /// sequential reads up to the end of the contents are protected. Without block checksums the contents
+    /// read before the end must not be acted upon, until the end has been reached.
+    /// The option has no effect with an authentication key or an encryption key,
+    /// whose verification requires the whole backing file anyway.
+    pub fn verify_while_reading(&mut self, verify: bool) -> &mut Self {
+        self.verify_while_reading = verify;
+        self
+    }
+
+    ///
+    /// Verifies the checksum once more while the contents are read, although the backing file has been verified before.
+    ///
+    /// Reading fails with `ErrorKind::InvalidData` at the end of the contents like with `verify_while_reading`,
+    /// if the backing file has been corrupted after it has been verified, e.g. while it is kept open for a long time.
+    /// The backing files are still verified before readers are opened, unless `verify_while_reading` is set as well.
+    /// The same restrictions regarding seeking and keys apply.
+    pub fn reverify_while_reading(&mut self, reverify: bool) -> &mut Self {
+        self.reverify_while_reading = reverify;
+        self
+    }
+
+    ///
+    /// Limits the length of the contents read in one piece by `BufferedFile::read_to_vec`, `read_to_string`
+    /// and `read_or_default` to `len` bytes.
+    ///
+    /// Longer contents fail with `BufferedFileErrors::ContentsTooLarge` before any memory is allocated for them,
+    /// e.g. to protect against backing files replaced by unexpectedly large ones. There is no limit by default.
+    /// `BufferedFile::update` is exempt, so oversized contents can always be replaced by shorter ones.
+    pub fn max_read_len(&mut self, len: u64) -> &mut Self {
+        self.max_read_len = Some(len);
+        self
+    }
+
+    /// Remembers the validation results in the given cache, so unchanged backing files are not verified again.
+    pub fn validation_cache(&mut self, cache: &ValidationCache) -> &mut Self {
+        self.validation_cache = Some(cache.clone());
+        self
+    }
+
+    /// Sets the unix permission bits (e.g. `0o600`) of newly created backing files.
+    ///
+    /// Existing backing files keep their permissions. The mode is still restricted by the umask of the process.
+    /// On other platforms the backing files are created with the default permissions.
+    pub fn mode(&mut self, mode: u32) -> &mut Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Copies the permissions of the newest backing file to the backing file of the next generation (default).
+    ///
+    /// This keeps permissions changed on the backing files across writes. The `mode` is only used,
+    /// if no previous generation exists.
+    pub fn preserve_permissions(&mut self, preserve: bool) -> &mut Self {
+        self.preserve_permissions = preserve;
+        self
+    }
+
+    /// Copies the user and group owning the newest backing file to the backing file of the next generation.
+    ///
+    /// Changing the owner usually requires elevated privileges, so writers fail if the owner can not be changed.
+    pub fn preserve_owner(&mut self, preserve: bool) -> &mut Self {
+        self.preserve_owner = preserve;
+        self
+    }
+
+    /// Selects how writers replace the content of the backing files
+    pub fn commit_strategy(&mut self, strategy: CommitStrategy) -> &mut Self {
+        self.commit_strategy = strategy;
+        self
+    }
+
+    ///
+    /// Protects the backing files held by open readers from being overwritten by writers.
+    ///
+    /// Every reader holds a lease on its backing file until it is dropped. Writers select the oldest backing file,
+    /// which is neither leased nor pinned, and refuse or wait according to `leases`, if none is left.
+    /// Leases are shared by the clones of a `BufferedFile` only, readers of other instances or processes
+    /// are not known to the writers.
+    pub fn reader_leases(&mut self, leases: ReaderLeases) -> &mut Self {
+        self.reader_leases = leases;
+        self
+    }
+
+    ///
+    /// Sets whether writers skip blocks of zeros instead of writing them, so large mostly empty contents
+    /// are stored as sparse files on file systems supporting holes.
+    ///
+    /// Readers are not affected, the holes read as zeros.
+    pub fn sparse(&mut self, sparse: bool) -> &mut Self {
+        self.sparse = sparse;
+        self
+    }
+
+    ///
+    /// Protects the contents with a checksum per block of `block_size` bytes instead of a single trailing checksum.
+    ///
+    /// Readers verify every block before handing out its bytes, so corruption is detected close to the point of use,
+    /// and the intact blocks of a damaged backing file can be recovered with `salvage`.
+    /// Random access with `BufferedFileReader::read_at` or seeking only verifies the blocks it touches.
+    /// The layout is not stored inside the backing files, so they have to be read with the same block size.
+    pub fn block_checksums(&mut self, block_size: NonZeroU32) -> &mut Self {
+        self.block_size = Some(block_size);
+        self
+    }
+
+    ///
+    /// Aligns the end of new backing files to sectors of `sector_size` bytes, e.g. 512 or 4096.
+    ///
+    /// The contents are padded with zeros in front of the stored length, so the stored length and the checksum
+    /// end on a sector boundary and are never torn apart by an interrupted write. The header holding
+    /// the generation is written last, once the rest of the backing file has been written (and synchronized,
+    /// depending on the durability), so a torn write can not leave a backing file claiming the new generation
+    /// with parts of the old contents behind.
+    ///
+    /// Only applies to `FormatVersion::V2` or later without block checksums, as the padding is told apart
+    /// from the contents by the stored length. The padding is not stored inside the backing files,
+    /// so they have to be read with the same sector size.
+    pub fn sector_size(&mut self, sector_size: NonZeroU32) -> &mut Self {
+        self.sector_size = Some(sector_size);
+        self
+    }
+
+    ///
+    /// Stores a new generation as the difference to the newest valid generation, if that is shorter.
+    ///
+    /// This reduces the amount of data written for large contents with small changes, e.g. on flash memory.
+    /// Writers collect the contents in memory and compare them to the newest generation when they are finished,
+    /// readers rebuild the contents transparently. A generation stored as a difference is never used as the base
+    /// of the next one, which is written as it is then, so the contents are rebuilt from a single base.
+    ///
+    /// New generations are always staged, as the backing file being replaced may hold the base of the newest
+    /// generation. Older generations become unreadable, once their base has been replaced.
+    /// Requires `FormatVersion::V4` or later, as the generations are marked in their user metadata
+    /// (see `DELTA_KEY`), otherwise the option has no effect.
+    pub fn delta_writes(&mut self, delta: bool) -> &mut Self {
+        self.delta_writes = delta;
+        self
+    }
+
+    ///
+    /// Invokes `callback` whenever a backing file fails its verification, e.g. because of a checksum failure
+    /// or truncation, to count such events in telemetry without wrapping every call site.
+    ///
+    /// The callback runs while the backing files are verified, e.g. on open, `validate` or `status`, and should
+    /// return quickly. Results remembered by a `ValidationCache` are not reported again. Every damaged backing
+    /// file is additionally reported as a tracing event with the fields `path`, `generation` and `kind`.
+    pub fn on_corruption(
+        &mut self,
+        callback: impl Fn(&CorruptionEvent) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.on_corruption = Some(CorruptionCallback(Arc::new(callback)));
+        self
+    }
+
+    ///
+    /// Reads the ordinary file at the path of the managed file, as long as no backing file exists at all.
+    ///
+    /// This allows adopting managed files for existing ordinary files without migrating them first:
+    /// `read` hands out the ordinary file as generation 0 without verifying it, as it has no checksum,
+    /// and the first write creates the first backing file. The ordinary file is left untouched.
+    /// Once any backing file exists, even a damaged one, the ordinary file is ignored.
+    pub fn plain_fallback(&mut self, fallback: bool) -> &mut Self {
+        self.plain_fallback = fallback;
+        self
+    }
+
+    ///
+    /// Sets the layout of the header written to new backing files.
+    ///
+    /// Backing files of every supported version can be read regardless of this setting.
+    /// `FormatVersion::V1` marks the backing files with magic bytes, so they can be told apart from arbitrary data.
+    /// `FormatVersion::V2` additionally stores the length of the contents, so truncated backing files are detected
+    /// even if the remaining bytes happen to match the checksum.
+    /// `FormatVersion::V3` additionally records the time the backing file has been written.
+    /// `FormatVersion::V4` additionally stores `UserMetadata` in front of the contents.
+    /// `FormatVersion::V5` additionally stores the generation in eight bytes, so it never wraps around.
+    /// Existing generations are continued from 256 on, afterwards every write uses `FormatVersion::V5`.
+    /// `FormatVersion::V6` additionally protects the header by its own checksum, so a damaged generation is detected.
+    pub fn format_version(&mut self, version: FormatVersion) -> &mut Self {
+        self.format_version = version;
+        self
+    }
+
+    ///
+    /// Marks every written generation with the kind of its contents, e.g. the name of a schema.
+    ///
+    /// The content type is stored in the `UserMetadata`, so it requires `FormatVersion::V4`.
+    /// It can be checked before reading the contents with `BufferedFile::read_expecting`.
+    pub fn content_type(&mut self, content_type: impl Into<String>) -> &mut Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    ///
+    /// Authenticates the contents with HMAC-SHA256 using the secret `key`, in addition to the checksum.
+    ///
+    /// Backing files whose authentication code does not match are treated as invalid, so contents modified
+    /// without knowledge of the key are never read, even if the checksums have been updated as well.
+    /// Backing files written without a key or with another key are invalid as well.
+    #[cfg(feature = "hmac")]
+    pub fn hmac_key(&mut self, key: &[u8]) -> &mut Self {
+        self.mac_key = Some(MacKey::new(key));
+        self
+    }
+
+    ///
+    /// Encrypts the contents with XChaCha20-Poly1305 using the secret `key`.
+    ///
+    /// The contents are encrypted in chunks of 64 KiB while they are written and decrypted chunk by chunk while
+    /// they are read, so neither side holds more than one chunk in memory. Every chunk is authenticated by its own
+    /// tag before any of it is handed out, and the chunks can neither be reordered nor truncated unnoticed.
+    /// Readers seek freely within the decrypted contents. Backing files which can not be decrypted and
+    /// authenticated with the key are treated as invalid. If a backing file has been modified after it has been
+    /// verified, `BufferedFile::read` fails with `BufferedFileErrors::AuthenticationFailed` for a modified first
+    /// chunk and reading fails with `ErrorKind::InvalidData` on reaching a later modified chunk.
+    /// The generations stay readable without the key, so e.g. `status` and `rollback` keep working.
+    /// The key should be random or derived from a password with a key derivation function.
+    #[cfg(feature = "encryption")]
+    pub fn encryption_key(&mut self, key: &[u8; 32]) -> &mut Self {
+        self.encryption_key = Some(EncryptionKey::new(key));
+        self
+    }
+
+    /// Whether the contents are encrypted
+    pub(crate) fn is_encrypted(&self) -> bool {
+        #[cfg(feature = "encryption")]
+        if self.encryption_key.is_some() {
+            return true;
+        }
+        false
+    }
+
+    /// The length of the authentication code stored behind the contents
+    pub(crate) fn mac_len(&self) -> u64 {
+        #[cfg(feature = "hmac")]
+        if self.mac_key.is_some() {
+            return crate::mac::MAC_LEN;
+        }
+        0
+    }
+
+    /// Whether readers verify the checksum while reading instead of before they are opened
+    pub(crate) fn verifies_while_reading(&self) -> bool {
+        #[cfg(feature = "encryption")]
+        if self.encryption_key.is_some() {
+            return false;
+        }
+        self.verify_while_reading && self.mac_len() == 0
+    }
+
+    /// Whether readers compute the checksum while reading, see `verify_while_reading` and `reverify_while_reading`
+    pub(crate) fn streams_checksum(&self) -> bool {
+        #[cfg(feature = "encryption")]
+        if self.encryption_key.is_some() {
+            return false;
+        }
+        (self.verify_while_reading || self.reverify_while_reading) && self.mac_len() == 0
+    }
+
+    /// The size of the sectors the backing files are aligned to, which is ignored with block checksums
+    pub(crate) fn aligned_sector_size(&self) -> Option<u64> {
+        match self.block_size {
+            Some(_) => None,
+            None => self.sector_size.map(|size| u64::from(size.get())),
+        }
+    }
+
+    /// The size of the checksummed blocks, if the contents are protected per block
+    pub(crate) fn block_size(&self) -> Option<u64> {
+        self.block_size.map(|size| u64::from(size.get()))
+    }
+
+    ///
+    /// Creates the managed file with these options and scans the backing files for their validity and generation.
+    pub fn open(&self, path: impl AsRef<Path>) -> Result<BufferedFile, BufferedFileErrors> {
+        self.open_in(FsStorage, path)
+    }
+
+    ///
+    /// Creates the managed file with these options, keeping the backing files in `storage`,
+    /// and scans the backing files for their validity and generation.
+    pub fn open_in<S: Storage>(
+        &self,
+        storage: S,
+        path: impl AsRef<Path>,
+    ) -> Result<BufferedFile<S>, BufferedFileErrors> {
+        self.check(path.as_ref())?;
+        BufferedFile::scan(storage, path, self.clone())
+    }
+
+    ///
+    /// Opens the append-only log with these options and finds its newest valid backing file.
+    pub fn open_log(&self, path: impl AsRef<Path>) -> Result<BufferedLog, BufferedFileErrors> {
+        self.open_log_in(FsStorage, path)
+    }
+
+    ///
+    /// Opens the append-only log with these options, keeping the backing files in `storage`.
+    pub fn open_log_in<S: Storage>(
+        &self,
+        storage: S,
+        path: impl AsRef<Path>,
+    ) -> Result<BufferedLog<S>, BufferedFileErrors> {
+        self.check(path.as_ref())?;
+        BufferedLog::scan(storage, path, self.clone())
+    }
+
+    ///
+    /// Opens the journaled file with these options, applying the updates in its journal.
+    pub fn open_journaled(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<JournaledFile, BufferedFileErrors> {
+        self.open_journaled_in(FsStorage, path)
+    }
+
+    ///
+    /// Opens the journaled file with these options, keeping the backing files and the journal in `storage`.
+    pub fn open_journaled_in<S: Storage + Clone>(
+        &self,
+        storage: S,
+        path: impl AsRef<Path>,
+    ) -> Result<JournaledFile<S>, BufferedFileErrors> {
+        self.check(path.as_ref())?;
+        JournaledFile::scan(storage, path, self.clone())
+    }
+
+    /// Ensures the options are consistent and can be applied to the path
+    fn check(&self, path: &Path) -> Result<(), BufferedFileErrors> {
+        let max = match self.format_version.generation_len() {
+            1 => MAX_BUFFER_COUNT,
+            _ => u8::MAX,
+        };
+        if !(DEFAULT_BUFFER_COUNT..=max).contains(&self.buffer_count) {
+            return Err(BufferedFileErrors::InvalidBufferCount(self.buffer_count));
+        }
+        if self.naming.is_none() && self.suffix_pattern.matches(SLOT_PLACEHOLDER).count() != 1 {
+            return Err(BufferedFileErrors::InvalidSuffixPattern(
+                self.suffix_pattern.clone(),
+            ));
+        }
+        Self::check_path(path)
+    }
+
+    /// Ensures the path names a file, so the backing files can be placed next to it
+    pub(crate) fn check_path(path: &Path) -> Result<(), BufferedFileErrors> {
+        match (path.file_name(), path.parent()) {
+            (Some(_), Some(_)) => Ok(()),
+            _ => Err(BufferedFileErrors::InvalidPath(path.to_path_buf())),
+        }
+    }
+
+    ///
+    /// Creates the managed file with these options and writes the initial contents.
+    /// Fails with `BufferedFileErrors::AlreadyExists` if a valid backing file exists already.
+    pub fn create_with(
+        &self,
+        path: impl AsRef<Path>,
+        contents: &[u8],
+    ) -> Result<BufferedFile, BufferedFileErrors> {
+        let file = self.open(path)?;
+        file.initialize(contents)?;
+        Ok(file)
+    }
+
+    /// Moves the path into the slot directory, if one is configured
+    fn relocate(&self, path: &Path) -> PathBuf {
+        match &self.slot_dir {
+            Some(dir) => {
+                let stem = path.file_name().expect("the path has been checked on open");
+                let ancestor = path.parent().expect("the path has been checked on open");
+                ancestor.join(dir).join(stem)
+            }
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// Generates the path of the lock file guarding updates of the managed file
+    pub(crate) fn lock_path(&self, path: &Path) -> PathBuf {
+        self.sidecar_path(path, ".lock")
+    }
+
+    /// Generates the path of the file recording the pinned backing file, see `BufferedFile::pin_current`
+    pub(crate) fn pin_path(&self, path: &Path) -> PathBuf {
+        self.sidecar_path(path, ".pin")
+    }
+
+    /// Generates the path of a file next to the backing files, named after the managed file with `suffix`
+    fn sidecar_path(&self, path: &Path, suffix: &str) -> PathBuf {
+        let path = self.relocate(path);
+        let mut file_name = path
+            .file_name()
+            .expect("the path has been checked on open")
+            .to_os_string();
+        file_name.push(suffix);
+        path.with_file_name(file_name)
+    }
+
+    /// Generates the path of the backing file with the given number
+    pub(crate) fn slot_path(&self, path: &Path, slot: u8) -> PathBuf {
+        let path = self.relocate(path);
+        let path = path.as_path();
+
+        match &self.naming {
+            Some(naming) => naming.slot_path(path, slot),
+            None => {
+                let stem = path.file_name().expect("the path has been checked on open");
+                let ancestor = path.parent().expect("the path has been checked on open");
+
+                let mut file_name = stem.to_os_string();
+                file_name.push(
+                    self.suffix_pattern
+                        .replace(SLOT_PLACEHOLDER, &slot.to_string()),
+                );
+                ancestor.join(file_name)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        path::{Path, PathBuf},
+    };
+
+    use crate::{
+        tests::utils::TempDir, BufferedFile, BufferedFileErrors, BufferedFileOptions,
+        ChecksumAlgorithm, NamingStrategy,
+    };
+
+    #[test]
+    fn custom_suffix_and_checksum() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let mut options = BufferedFileOptions::new();
+        options
+            .suffix_pattern("~{}.bak")
+            .checksum(ChecksumAlgorithm::Crc32IsoHdlc);
+
+        let mut writer = options
+            .open(&file)
+            .expect("Can not find files")
+            .write()
+            .expect("A new file should be writeable");
+        writer
+            .write_all(b"Hello World")
+            .expect("Can not write into the file");
+        writer.commit().unwrap();
+
+        let mut contents = Vec::new();
+        std::fs::File::open(dir.path().join("data-file.txt~1.bak"))
+            .expect("Could not open File")
+            .read_to_end(&mut contents)
+            .expect("Could not verify written file");
+        let checksum = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC).checksum(b"Hello World");
+        assert_eq!(&contents[1..12], b"Hello World");
+        assert_eq!(&contents[12..], checksum.to_le_bytes());
+
+        let mut reader = options
+            .open(&file)
+            .expect("Can not find files")
+            .read()
+            .expect("Can not read the file");
+        let mut contents = Vec::new();
+        reader
+            .read_to_end(&mut contents)
+            .expect("Error reading from file");
+        assert_eq!(contents.as_slice(), b"Hello World");
+
+        let reader = BufferedFileOptions::new()
+            .suffix_pattern("~{}.bak")
+            .open(&file)
+            .expect("Can not find files")
+            .read();
+        assert!(
+            matches!(reader, Err(BufferedFileErrors::AllFilesInvalidError)),
+            "A different checksum algorithm should not validate the file"
+        );
+    }
+
+    #[derive(Debug)]
+    struct Letters;
+
+    impl NamingStrategy for Letters {
+        fn slot_path(&self, path: &Path, slot: u8) -> PathBuf {
+            let mut name = path.file_name().unwrap().to_os_string();
+            name.push(format!(".{}", char::from(b'a' + slot - 1)));
+            path.with_file_name(name)
+        }
+    }
+
+    #[derive(Debug)]
+    struct Constant;
+
+    impl NamingStrategy for Constant {
+        fn slot_path(&self, path: &Path, _slot: u8) -> PathBuf {
+            path.with_extension("bak")
+        }
+    }
+
+    #[test]
+    fn custom_naming_strategy() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let mut options = BufferedFileOptions::new();
+        options.naming_strategy(Letters);
+
+        for _ in 0..2 {
+            let mut writer = options
+                .open(&file)
+                .expect("Can not find files")
+                .write()
+                .expect("A new file should be writeable");
+            writer
+                .write_all(b"Hello World")
+                .expect("Can not write into the file");
+            writer.commit().unwrap();
+        }
+
+        assert!(dir.path().join("data-file.txt.a").exists());
+        assert!(dir.path().join("data-file.txt.b").exists());
+        assert!(!dir.path().join("data-file.txt.1").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restricts_permissions_of_new_backing_files() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new();
+        let file = dir.path().join("secret.txt");
+        let managed_file = BufferedFileOptions::new()
+            .mode(0o600)
+            .create_with(&file, b"Hello World")
+            .expect("Can not create the file");
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+
+        for slot in ["secret.txt.1", "secret.txt.2"] {
+            let metadata = std::fs::metadata(dir.path().join(slot)).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600, "{slot}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn preserves_permissions_of_previous_generation() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .preserve_owner(true)
+            .create_with(&file, b"Hello World")
+            .expect("Can not create the file");
+        let first = dir.path().join("data-file.txt.1");
+        std::fs::set_permissions(&first, std::fs::Permissions::from_mode(0o640)).unwrap();
+
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        let metadata = std::fs::metadata(dir.path().join("data-file.txt.2")).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+    }
+
+    #[test]
+    fn slot_dir_keeps_data_directory_clean() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let mut options = BufferedFileOptions::new();
+        options.slot_dir(".buffered");
+
+        let reader = options.open(&file).expect("Can not find files").read();
+        assert!(
+            matches!(reader, Err(BufferedFileErrors::AllFilesInvalidError)),
+            "A missing slot directory should behave like missing files"
+        );
+
+        let mut writer = options
+            .open(&file)
+            .expect("Can not find files")
+            .write()
+            .expect("A new file should be writeable");
+        writer
+            .write_all(b"Hello World")
+            .expect("Can not write into the file");
+        writer.commit().unwrap();
+
+        assert!(dir
+            .path()
+            .join(".buffered")
+            .join("data-file.txt.1")
+            .exists());
+        assert!(!dir.path().join("data-file.txt.1").exists());
+
+        let mut reader = options
+            .open(&file)
+            .expect("Can not find files")
+            .read()
+            .expect("Can not read the file");
+        let mut contents = Vec::new();
+        reader
+            .read_to_end(&mut contents)
+            .expect("Error reading from file");
+        assert_eq!(contents.as_slice(), b"Hello World");
+    }
+
+    #[test]
+    fn rejects_duplicate_slot_paths() {
+        let result = BufferedFileOptions::new()
+            .naming_strategy(Constant)
+            .open("data-file.txt");
+        assert!(
+            matches!(&result, Err(BufferedFileErrors::DuplicateSlotPath(path)) if path == Path::new("data-file.bak")),
+            "Expected an error for the duplicate path but got {result:?}"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_suffix_pattern() {
+        for pattern in ["", ".bak", ".{}.{}"] {
+            let result = BufferedFileOptions::new()
+                .suffix_pattern(pattern)
+                .open("data-file.txt");
+            assert!(
+                matches!(result, Err(BufferedFileErrors::InvalidSuffixPattern(_))),
+                "Expected an error for pattern {pattern:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_paths() {
+        for path in ["/", "", "data/.."] {
+            let result = BufferedFile::new(path);
+            assert!(
+                matches!(result, Err(BufferedFileErrors::InvalidPath(_))),
+                "Expected an error for path {path:?}"
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn accepts_non_utf8_paths() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let dir = TempDir::new();
+        let file = dir.path().join(OsStr::from_bytes(b"data-\xff.txt"));
+        BufferedFile::create_with(&file, b"Hello World").expect("Can not create the file");
+        assert!(dir
+            .path()
+            .join(OsStr::from_bytes(b"data-\xff.txt.1"))
+            .exists());
+        assert_eq!(
+            BufferedFile::new(&file).unwrap().read_or_default().unwrap(),
+            b"Hello World"
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! Keeps a known-good generation, e.g. the factory defaults, from being overwritten.
+//!
+//! The pinned backing file is recorded next to the lock file with the suffix `.pin`: its number in one byte
+//! and the generation it held when it was pinned in eight bytes in little endian, followed by the checksum
+//! (CRC-32/BZIP2) of these bytes. A damaged record is ignored, so no backing file is pinned then.
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    checksum::checksum, BufferedFile, BufferedFileErrors, Durability, Storage, StorageFile,
+    DEFAULT_CHECKSUM,
+};
+
+/// The number of bytes of the record of the pinned backing file
+const PIN_LEN: usize = 13;
+
+impl<S: Storage> BufferedFile<S> {
+    ///
+    /// Pins the backing file holding the newest valid generation, so it is never selected to write
+    /// a new generation to, until `unpin` is called.
+    ///
+    /// The pinned generation stays readable as a fallback, e.g. to `rollback` to the last known good state.
+    /// New generations rotate through the remaining backing files, so at least three backing files should be
+    /// configured to keep double buffering. The pin is shared by all instances of the managed file,
+    /// `import_bundle` replaces the pinned backing file nevertheless. Replaces an earlier pin.
+    /// Returns the pinned generation.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multibufferedfile::BufferedFileOptions;
+    /// # let dir = std::env::temp_dir()
+    /// #     .join(format!("multibufferedfile-pin-doc-{}", std::process::id()));
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    ///
+    /// let file = BufferedFileOptions::new()
+    ///     .buffer_count(3)
+    ///     .create_with(dir.join("config.bin"), b"defaults")
+    ///     .unwrap();
+    /// assert_eq!(file.pin_current().unwrap(), 1);
+    /// for _ in 0..5 {
+    ///     file.update(|_| b"changed".to_vec()).unwrap();
+    /// }
+    /// assert_eq!(file.history().last().unwrap().generation, 1);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn pin_current(&self) -> Result<u64, BufferedFileErrors> {
+        let _lock = self.lock()?;
+        let (path, generation) = self.select_newest_valid()?;
+        let slot = self
+            .files()
+            .iter()
+            .position(|(file, _)| *file == path)
+            .expect("the newest generation is held by a backing file");
+
+        let mut record = Vec::with_capacity(PIN_LEN);
+        record.push(slot as u8 + 1);
+        record.extend_from_slice(&generation.to_le_bytes());
+        record.extend_from_slice(&checksum(&DEFAULT_CHECKSUM, &record).to_le_bytes());
+        let pin_path = self.options.pin_path(&self.path);
+        let mut name = pin_path.clone().into_os_string();
+        name.push(".tmp");
+        let staged = PathBuf::from(name);
+        let mut file = self.storage.create(&staged, self.options.mode)?;
+        file.write_all(&record)?;
+        if self.options.durability >= Durability::Flush {
+            file.flush()?;
+        }
+        if self.options.durability >= Durability::Fsync {
+            file.sync_all()?;
+        }
+        drop(file);
+        self.storage.rename(&staged, &pin_path)?;
+        Ok(generation)
+    }
+
+    /// Releases the pinned backing file, so it is overwritten by the following generations again
+    pub fn unpin(&self) -> Result<(), BufferedFileErrors> {
+        let _lock = self.lock()?;
+        match self.storage.remove(&self.options.pin_path(&self.path)) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// The generation held by the pinned backing file when it has been pinned, if any backing file is pinned
+    pub fn pinned(&self) -> Option<u64> {
+        self.read_pin().map(|(_, generation)| generation)
+    }
+
+    /// The index of the pinned backing file, which must not be written to
+    pub(crate) fn pinned_index(&self) -> Option<usize> {
+        self.read_pin().map(|(index, _)| index)
+    }
+
+    /// Reads the record of the pinned backing file, a missing or damaged record pins nothing
+    fn read_pin(&self) -> Option<(usize, u64)> {
+        let path = self.options.pin_path(&self.path);
+        let mut record = Vec::with_capacity(PIN_LEN);
+        match self.storage.open(&path) {
+            Ok(file) => file
+                .take(PIN_LEN as u64 + 1)
+                .read_to_end(&mut record)
+                .ok()?,
+            Err(err) if err.kind() == ErrorKind::NotFound => return None,
+            Err(err) => {
+                tracing::warn!("Could not read {}: {err}", path.display());
+                return None;
+            }
+        };
+        if record.len() != PIN_LEN
+            || checksum(&DEFAULT_CHECKSUM, &record[..9]).to_le_bytes() != record[9..]
+        {
+            tracing::warn!("Ignoring the damaged pin {}", path.display());
+            return None;
+        }
+        let index = usize::from(record[0]).checked_sub(1)?;
+        let generation =
+            u64::from_le_bytes(record[1..9].try_into().expect("the generation has 8 bytes"));
+        (index < usize::from(self.options.buffer_count)).then_some((index, generation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tests::utils::TempDir, BufferedFileOptions};
+
+    #[test]
+    fn pinned_backing_files_are_not_overwritten() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .buffer_count(3)
+            .create_with(&file, b"defaults")
+            .expect("Can not create the file");
+        managed_file.update(|_| b"second".to_vec()).unwrap();
+        assert_eq!(managed_file.pin_current().unwrap(), 2);
+        assert_eq!(managed_file.pinned(), Some(2));
+
+        let reopened = BufferedFileOptions::new()
+            .buffer_count(3)
+            .open(&file)
+            .unwrap();
+        for contents in [&b"third"[..], b"fourth", b"fifth"] {
+            reopened.update(|_| contents.to_vec()).unwrap();
+        }
+        let generations = reopened
+            .history()
+            .iter()
+            .map(|entry| entry.generation)
+            .collect::<Vec<_>>();
+        assert_eq!(generations, [5, 4, 2]);
+        assert_eq!(
+            std::fs::read(dir.path().join("data-file.txt.2")).unwrap()[1..7],
+            *b"second"
+        );
+
+        reopened.unpin().unwrap();
+        assert_eq!(reopened.pinned(), None);
+        reopened.update(|_| b"sixth".to_vec()).unwrap();
+        assert!(!reopened.history().iter().any(|entry| entry.generation == 2));
+    }
+
+    #[test]
+    fn pins_move_with_the_managed_file() {
+        let dir = TempDir::new();
+        let managed_file = BufferedFileOptions::new()
+            .buffer_count(3)
+            .create_with(dir.path().join("data-file.txt"), b"defaults")
+            .expect("Can not create the file");
+        assert_eq!(managed_file.pin_current().unwrap(), 1);
+
+        let renamed = managed_file
+            .rename(dir.path().join("renamed.txt"))
+            .expect("Can not rename the file");
+        assert_eq!(renamed.pinned(), Some(1));
+        for contents in [&b"second"[..], b"third", b"fourth"] {
+            renamed.update(|_| contents.to_vec()).unwrap();
+        }
+        assert_eq!(renamed.history().last().unwrap().generation, 1);
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    fs::File,
+    io::{ErrorKind, Write},
+    path::Path,
+};
+
+use crate::{BufferedFile, BufferedFileErrors, BufferedFileOptions, BufferedFileReader, Storage};
+
+impl BufferedFile {
+    ///
+    /// Migrates an ordinary file into a managed file.
+    ///
+    /// The content of `src` is streamed into the first generation of the managed file at `dst`.
+    /// Fails with `BufferedFileErrors::AlreadyExists` if a valid backing file exists already.
+    /// The source file is left untouched.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multibufferedfile::BufferedFile;
+    /// # let dir = std::env::temp_dir().join("multibufferedfile-import-doc");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    ///
+    /// std::fs::write(dir.join("legacy.conf"), b"key=value").unwrap();
+    /// let file = BufferedFile::import_plain(dir.join("legacy.conf"), dir.join("config.conf")).unwrap();
+    /// assert_eq!(file.read_or_default().unwrap(), b"key=value");
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn import_plain(
+        src: impl AsRef<Path>,
+        dst: impl AsRef<Path>,
+    ) -> Result<BufferedFile, BufferedFileErrors> {
+        BufferedFileOptions::new().import_plain(src, dst)
+    }
+}
+
+impl<S: Storage> BufferedFile<S> {
+    ///
+    /// Writes the newest valid content into the ordinary file `target`.
+    ///
+    /// The content is streamed into a temporary file next to `target`, which replaces `target` atomically
+    /// once all data has been synchronized to disk. So `target` either holds its previous or the complete new content.
+    pub fn export_plain(&self, target: impl AsRef<Path>) -> Result<(), BufferedFileErrors> {
+        let target = target.as_ref();
+        let mut reader = self.read()?;
+
+        let mut temp_name = target
+            .file_name()
+            .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidInput, "target is no file path"))?
+            .to_os_string();
+        temp_name.push(format!(".{}.tmp", std::process::id()));
+        let temp = target.with_file_name(temp_name);
+
+        let result = File::create(&temp).and_then(|mut file| {
+            std::io::copy(&mut reader, &mut file)?;
+            file.flush()?;
+            file.sync_all()?;
+            std::fs::rename(&temp, target)
+        });
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temp);
+        }
+        Ok(result?)
+    }
+
+    ///
+    /// Opens the ordinary file at the path of the managed file as generation 0, if no backing file exists at all.
+    ///
+    /// The contents are handed out as they are, without a checksum to verify, see `BufferedFileOptions::plain_fallback`.
+    pub(crate) fn open_plain(&self) -> Result<BufferedFileReader<S::File>, BufferedFileErrors> {
+        for slot in self.slot_paths() {
+            match self.storage.metadata(&slot) {
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+                Ok(_) => return Err(BufferedFileErrors::AllFilesInvalidError),
+            }
+        }
+        let len = match self.storage.metadata(&self.path) {
+            Ok(metadata) if !metadata.is_dir => metadata.len,
+            Ok(_) => return Err(BufferedFileErrors::AllFilesInvalidError),
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return Err(BufferedFileErrors::AllFilesInvalidError)
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let file = self.storage.open(&self.path)?;
+        Ok(BufferedFileReader::new(file, len, 0)
+            .header_len(0)
+            .backing_file(&self.path, None))
+    }
+}
+
+impl BufferedFileOptions {
+    ///
+    /// Migrates an ordinary file into a managed file created with these options.
+    /// Fails with `BufferedFileErrors::AlreadyExists` if a valid backing file exists already.
+    pub fn import_plain(
+        &self,
+        src: impl AsRef<Path>,
+        dst: impl AsRef<Path>,
+    ) -> Result<BufferedFile, BufferedFileErrors> {
+        let source = File::open(src)?;
+        let file = self.open(dst)?;
+        file.initialize(source)?;
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::{tests::utils::TempDir, BufferedFile, BufferedFileErrors, BufferedFileOptions};
+
+    #[test]
+    fn export_plain_strips_format() {
+        let dir = TempDir::new();
+        let plain = dir.path().join("plain.txt");
+        std::fs::write(&plain, b"previous").unwrap();
+
+        let file = BufferedFile::create_with(dir.path().join("managed.txt"), b"Hello World")
+            .expect("Can not create the file");
+        file.export_plain(&plain).expect("Can not export");
+        assert_eq!(std::fs::read(&plain).unwrap(), b"Hello World");
+
+        let leftovers = std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .ends_with(".tmp")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[test]
+    fn import_plain_keeps_existing_generations() {
+        let dir = TempDir::new();
+        let plain = dir.path().join("plain.txt");
+        let managed = dir.path().join("managed.txt");
+        std::fs::write(&plain, b"Hello World").unwrap();
+
+        let file = BufferedFile::import_plain(&plain, &managed).expect("Can not import");
+        assert_eq!(file.read_or_default().unwrap(), b"Hello World");
+        assert_eq!(file.status().unwrap().slots[0].generation, Some(1));
+        assert_eq!(std::fs::read(&plain).unwrap(), b"Hello World");
+
+        std::fs::write(&plain, b"Other content").unwrap();
+        let again = BufferedFile::import_plain(&plain, &managed);
+        assert!(matches!(again, Err(BufferedFileErrors::AlreadyExists)));
+        assert_eq!(
+            BufferedFile::new(&managed)
+                .unwrap()
+                .read_or_default()
+                .unwrap(),
+            b"Hello World"
+        );
+    }
+
+    #[test]
+    fn plain_fallback_reads_the_ordinary_file() {
+        let dir = TempDir::new();
+        let path = dir.path().join("legacy.conf");
+        std::fs::write(&path, b"key=value").unwrap();
+        assert!(matches!(
+            BufferedFile::new(&path).unwrap().read(),
+            Err(BufferedFileErrors::AllFilesInvalidError)
+        ));
+
+        let file = BufferedFileOptions::new()
+            .plain_fallback(true)
+            .open(&path)
+            .unwrap();
+        let reader = file.read().unwrap();
+        assert_eq!(reader.generation(), 0);
+        assert_eq!(file.read_or_default().unwrap(), b"key=value");
+
+        let mut writer = file.write().unwrap();
+        writer.write_all(b"key=other").unwrap();
+        writer.commit().unwrap();
+        assert_eq!(file.read_or_default().unwrap(), b"key=other");
+        assert_eq!(std::fs::read(&path).unwrap(), b"key=value");
+
+        // a damaged backing file is never replaced by the ordinary file
+        std::fs::write(dir.path().join("legacy.conf.1"), b"garbage").unwrap();
+        let file = BufferedFileOptions::new()
+            .plain_fallback(true)
+            .open(&path)
+            .unwrap();
+        assert!(matches!(
+            file.read(),
+            Err(BufferedFileErrors::AllFilesInvalidError)
+        ));
+    }
+}
@@ -0,0 +1,868 @@
+//! The format of the backing files and the rotation of the generations.
+//!
+//! Nothing in here depends on `std`, so the same protocol can be implemented on top of other I/O traits.
+
+use core::cmp::Ordering;
+
+use crc::{Crc, CRC_32_BZIP2};
+
+use crate::checksum::ChecksumDigest;
+
+/// The checksum algorithm used for the backing files, if nothing else is configured
+pub const DEFAULT_CHECKSUM: Crc<u32> = Crc::<u32>::new(&CRC_32_BZIP2);
+
+/// The number of bytes preceding the content of a backing file (the generation)
+pub const HEADER_LEN: u64 = 1;
+
+/// The maximum number of bytes preceding the content of a backing file of any version
+pub const MAX_HEADER_LEN: u64 = 24;
+
+/// The number of bytes preceding the content of a backing file with magic bytes and without a timestamp
+const VERSIONED_HEADER_LEN: u64 = 5;
+
+/// The number of bytes preceding the content of a backing file with a timestamp and a generation of one byte
+const TIMESTAMPED_HEADER_LEN: u64 = 13;
+
+/// The number of bytes preceding the content of a backing file with a timestamp and a generation of eight bytes
+const WIDE_HEADER_LEN: u64 = 20;
+
+/// Identifies backing files with a versioned header
+pub const MAGIC: [u8; 3] = *b"MBF";
+
+/// The number of bytes following the content of a backing file (the checksum)
+pub const TRAILER_LEN: u64 = 4;
+
+/// The number of bytes of the length stored behind the content by `FormatVersion::V2`
+pub const LENGTH_FOOTER_LEN: u64 = 8;
+
+///
+/// The layout of the header of a backing file.
+///
+/// The generation is always stored at the end of the header, its lowest byte is the last byte of the header.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum FormatVersion {
+    /// Only the generation precedes the content
+    #[default]
+    V0,
+    /// The magic bytes and the version 1 precede the generation
+    V1,
+    /// Like `V1` with the version 2, the length of the content is stored in little endian
+    /// between the content and the checksum
+    V2,
+    /// Like `V2` with the version 3, the time the backing file has been written precedes the generation
+    /// as milliseconds since the unix epoch in little endian
+    V3,
+    /// Like `V3` with the version 4, the content starts with the user metadata: its length in two bytes
+    /// in little endian followed by the encoded key/value pairs
+    V4,
+    /// Like `V4` with the version 5, the generation is stored in eight bytes in big endian.
+    /// It increases monotonically instead of wrapping around after 255.
+    V5,
+    /// Like `V5` with the version 6, the checksum of the other header fields in four bytes in little endian
+    /// precedes the generation
+    V6,
+}
+
+impl FormatVersion {
+    /// The number of bytes preceding the content
+    pub const fn header_len(self) -> u64 {
+        match self {
+            FormatVersion::V0 => HEADER_LEN,
+            FormatVersion::V1 | FormatVersion::V2 => VERSIONED_HEADER_LEN,
+            FormatVersion::V3 | FormatVersion::V4 => TIMESTAMPED_HEADER_LEN,
+            FormatVersion::V5 => WIDE_HEADER_LEN,
+            FormatVersion::V6 => MAX_HEADER_LEN,
+        }
+    }
+
+    /// The number of bytes between the content and the checksum
+    pub const fn footer_len(self) -> u64 {
+        match self {
+            FormatVersion::V0 | FormatVersion::V1 => 0,
+            _ => LENGTH_FOOTER_LEN,
+        }
+    }
+
+    /// The number of bytes of the generation at the end of the header
+    pub const fn generation_len(self) -> u64 {
+        match self {
+            FormatVersion::V5 | FormatVersion::V6 => 8,
+            _ => 1,
+        }
+    }
+
+    /// Whether the content starts with the user metadata
+    pub const fn has_user_metadata(self) -> bool {
+        matches!(
+            self,
+            FormatVersion::V4 | FormatVersion::V5 | FormatVersion::V6
+        )
+    }
+
+    /// Whether the header is protected by its own checksum
+    pub const fn has_header_checksum(self) -> bool {
+        matches!(self, FormatVersion::V6)
+    }
+
+    /// Whether the time the backing file has been written is stored in the header
+    const fn has_timestamp(self) -> bool {
+        !matches!(
+            self,
+            FormatVersion::V0 | FormatVersion::V1 | FormatVersion::V2
+        )
+    }
+
+    /// Encodes the header of a backing file holding `generation`.
+    /// Versions storing the generation in a single byte only keep its lowest byte.
+    pub fn header(self, generation: u64) -> SlotHeader {
+        self.header_written_at(generation, 0)
+    }
+
+    /// Encodes the header of a backing file holding `generation`, which is written at `written`
+    /// milliseconds since the unix epoch. The time is only stored by versions supporting it.
+    pub fn header_written_at(self, generation: u64, written: u64) -> SlotHeader {
+        let mut bytes = [0; MAX_HEADER_LEN as usize];
+        let len = self.header_len() as usize;
+        if self != FormatVersion::V0 {
+            bytes[..MAGIC.len()].copy_from_slice(&MAGIC);
+            bytes[MAGIC.len()] = self as u8;
+        }
+        if self.has_timestamp() {
+            bytes[VERSIONED_HEADER_LEN as usize - 1..TIMESTAMPED_HEADER_LEN as usize - 1]
+                .copy_from_slice(&written.to_le_bytes());
+        }
+        let generation_len = self.generation_len() as usize;
+        bytes[len - generation_len..len]
+            .copy_from_slice(&generation.to_be_bytes()[8 - generation_len..]);
+        if self.has_header_checksum() {
+            let checksum = header_checksum(&bytes[..len]);
+            bytes[TIMESTAMPED_HEADER_LEN as usize - 1..WIDE_HEADER_LEN as usize - 4]
+                .copy_from_slice(&checksum.to_le_bytes());
+        }
+        SlotHeader {
+            bytes,
+            len,
+            version: self,
+        }
+    }
+
+    /// The number of bytes following the content of a backing file without blocks
+    pub const fn trailer_len(self) -> u64 {
+        self.footer_len() + TRAILER_LEN
+    }
+
+    ///
+    /// Parses the header of a backing file of this version from its first bytes.
+    ///
+    /// Unlike `SlotHeader::parse`, the magic bytes are not inspected, e.g. to read a file with an unknown version
+    /// as a version 0 file.
+    pub fn parse_header(self, prefix: &[u8]) -> Result<SlotHeader, HeaderError> {
+        let len = self.header_len() as usize;
+        let header = prefix.get(..len).ok_or(HeaderError::Truncated)?;
+        if !self.verify_header(header) {
+            return Err(HeaderError::ChecksumMismatch);
+        }
+        let mut bytes = [0; MAX_HEADER_LEN as usize];
+        bytes[..len].copy_from_slice(header);
+        Ok(SlotHeader {
+            bytes,
+            len,
+            version: self,
+        })
+    }
+
+    /// Reads the time the backing file has been written in milliseconds since the unix epoch from its header
+    pub fn written(self, header: &[u8]) -> Option<u64> {
+        if !self.has_timestamp() {
+            return None;
+        }
+        let timestamp =
+            header.get(VERSIONED_HEADER_LEN as usize - 1..TIMESTAMPED_HEADER_LEN as usize - 1)?;
+        Some(u64::from_le_bytes(timestamp.try_into().ok()?))
+    }
+
+    /// Whether the checksum of the header matches, which holds for every header without a checksum
+    pub fn verify_header(self, header: &[u8]) -> bool {
+        if !self.has_header_checksum() {
+            return true;
+        }
+        let checksum = match header.get(..self.header_len() as usize) {
+            Some(header) => header_checksum(header),
+            None => return false,
+        };
+        header[TIMESTAMPED_HEADER_LEN as usize - 1..WIDE_HEADER_LEN as usize - 4]
+            == checksum.to_le_bytes()
+    }
+
+    /// Reads the generation from the header of a backing file
+    pub fn generation(self, header: &[u8]) -> Option<u64> {
+        let len = self.header_len() as usize;
+        let generation = header.get(len - self.generation_len() as usize..len)?;
+        Some(
+            generation
+                .iter()
+                .fold(0, |generation, byte| generation << 8 | u64::from(*byte)),
+        )
+    }
+
+    ///
+    /// Detects the version from the first bytes of a backing file.
+    ///
+    /// Files without the magic bytes are version 0. Returns the version number as error, if the magic bytes
+    /// are followed by an unknown version. Such a file might still be a version 0 file, whose generation
+    /// and content happen to start like the magic bytes.
+    pub fn detect(prefix: &[u8]) -> Result<Self, u8> {
+        match prefix.strip_prefix(&MAGIC) {
+            Some([1, ..]) => Ok(FormatVersion::V1),
+            Some([2, ..]) => Ok(FormatVersion::V2),
+            Some([3, ..]) => Ok(FormatVersion::V3),
+            Some([4, ..]) => Ok(FormatVersion::V4),
+            Some([5, ..]) => Ok(FormatVersion::V5),
+            Some([6, ..]) => Ok(FormatVersion::V6),
+            Some([version, ..]) => Err(*version),
+            _ => Ok(FormatVersion::V0),
+        }
+    }
+}
+
+/// Computes the checksum of a header of `FormatVersion::V6`, skipping the checksum itself
+fn header_checksum(header: &[u8]) -> u32 {
+    let (fields, generation) = header.split_at(TIMESTAMPED_HEADER_LEN as usize - 1);
+    let mut digest = ChecksumDigest::new(&DEFAULT_CHECKSUM);
+    digest.update(fields);
+    digest.update(&generation[4..]);
+    digest.finalize()
+}
+
+///
+/// The encoded header of a backing file.
+///
+/// Headers are encoded by `FormatVersion::header_written_at` and parsed by `SlotHeader::parse`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SlotHeader {
+    bytes: [u8; MAX_HEADER_LEN as usize],
+    len: usize,
+    version: FormatVersion,
+}
+
+impl SlotHeader {
+    ///
+    /// Parses the header from the first bytes of a backing file, at least `MAX_HEADER_LEN` of them if available.
+    ///
+    /// The header is only returned, if it is complete and its checksum matches. The content is not verified.
+    pub fn parse(prefix: &[u8]) -> Result<Self, HeaderError> {
+        FormatVersion::detect(prefix)
+            .map_err(HeaderError::UnsupportedVersion)?
+            .parse_header(prefix)
+    }
+
+    /// The version of the backing file
+    pub fn version(&self) -> FormatVersion {
+        self.version
+    }
+
+    /// The generation stored in the header
+    pub fn generation(&self) -> u64 {
+        self.version
+            .generation(self.as_ref())
+            .expect("the header is complete")
+    }
+
+    /// The time the backing file has been written in milliseconds since the unix epoch, if it is recorded
+    pub fn written(&self) -> Option<u64> {
+        self.version.written(self.as_ref())
+    }
+}
+
+impl AsRef<[u8]> for SlotHeader {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+/// The reasons a header can not be parsed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HeaderError {
+    /// The backing file ends before its header
+    Truncated,
+    /// The magic bytes are followed by an unknown version
+    UnsupportedVersion(u8),
+    /// The checksum of the header does not match
+    ChecksumMismatch,
+}
+
+///
+/// The fields following the content of a backing file without blocks: the length of the content
+/// for versions storing it and the checksum.
+///
+/// Backing files with blocks end with the checksum of the last block instead, the stored length is part
+/// of the last block then.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SlotTrailer {
+    /// The length of the content, including the authentication code if any
+    pub length: Option<u64>,
+    /// The checksum of the content and the stored length
+    pub checksum: u32,
+}
+
+impl SlotTrailer {
+    /// Parses the trailer from the last `FormatVersion::trailer_len` bytes of `tail`
+    pub fn parse(version: FormatVersion, tail: &[u8]) -> Option<Self> {
+        let trailer = tail.get(tail.len().checked_sub(version.trailer_len() as usize)?..)?;
+        let (footer, checksum) = trailer.split_at(version.footer_len() as usize);
+        Some(SlotTrailer {
+            length: Self::parse_length(footer),
+            checksum: u32::from_le_bytes(checksum.try_into().ok()?),
+        })
+    }
+
+    /// Parses the stored length, which is covered by the checksum unlike the rest of the trailer
+    pub fn parse_length(footer: &[u8]) -> Option<u64> {
+        Some(u64::from_le_bytes(footer.try_into().ok()?))
+    }
+
+    /// Encodes the stored length, which is covered by the checksum unlike the rest of the trailer
+    pub fn encode_length(length: u64) -> [u8; LENGTH_FOOTER_LEN as usize] {
+        length.to_le_bytes()
+    }
+
+    /// Encodes the checksum, which ends every backing file and every block
+    pub fn encode_checksum(checksum: u32) -> [u8; TRAILER_LEN as usize] {
+        checksum.to_le_bytes()
+    }
+
+    /// Encodes the trailer into the start of `out` and returns the number of bytes written.
+    /// The stored length is only written, if it is set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `out` is too short to hold the trailer.
+    pub fn encode(&self, out: &mut [u8]) -> usize {
+        let mut len = 0;
+        if let Some(length) = self.length {
+            out[..LENGTH_FOOTER_LEN as usize].copy_from_slice(&Self::encode_length(length));
+            len += LENGTH_FOOTER_LEN as usize;
+        }
+        out[len..len + TRAILER_LEN as usize].copy_from_slice(&Self::encode_checksum(self.checksum));
+        len + TRAILER_LEN as usize
+    }
+}
+
+/// The result of verifying the content of a backing file
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FileCheckResult {
+    /// The checksum matches, the backing file holds the given generation
+    Good {
+        /// The generation stored in the backing file
+        generation: u64,
+    },
+    /// The backing file is too short to hold a generation and a checksum
+    Truncated,
+    /// The stored checksum does not match the checksum of the content
+    ChecksumFailure {
+        /// The checksum stored in the backing file
+        expected: u32,
+        /// The checksum computed from the content
+        actual: u32,
+    },
+    /// The backing file has been written with an unknown version of the format
+    UnsupportedVersion {
+        /// The version stored in the header
+        version: u8,
+    },
+    /// The checksum of the header does not match, so its generation can not be trusted
+    HeaderChecksumFailure,
+    /// The checksum matches, but the stored length does not match the length of the content
+    LengthMismatch,
+    /// The checksum matches, but the authentication code or the tag of the encryption does not match the
+    /// configured key
+    MacMismatch,
+}
+
+///
+/// Computes the length of the content of a backing file with a checksum per block of `block_size` bytes.
+///
+/// Every block is followed by its checksum. The last block is shorter than `block_size`, possibly empty,
+/// so the end of the content is known from the length `body` following the header alone.
+/// Returns `None` if the backing file is too short to hold the last checksum.
+pub fn blocked_content_len(body: u64, block_size: u64) -> Option<u64> {
+    let chunk = block_size + TRAILER_LEN;
+    let last = (body % chunk).checked_sub(TRAILER_LEN)?;
+    Some(body / chunk * block_size + last)
+}
+
+///
+/// Verifies a backing file, while its bytes are fed in arbitrary pieces.
+///
+/// The last four bytes seen are held back as the potential checksum, so the pieces need not be aligned
+/// to the end of the content.
+pub struct SlotVerifier {
+    crc: &'static Crc<u32>,
+    digest: ChecksumDigest,
+    generation: Option<u8>,
+    tail: [u8; 4],
+    tail_len: usize,
+    block_size: Option<u64>,
+    chunk_len: u64,
+    failure: Option<FileCheckResult>,
+}
+
+impl SlotVerifier {
+    /// Starts the verification of a backing file with the given checksum algorithm
+    pub fn new(crc: &'static Crc<u32>) -> Self {
+        SlotVerifier {
+            crc,
+            digest: ChecksumDigest::new(crc),
+            generation: None,
+            tail: [0; 4],
+            tail_len: 0,
+            block_size: None,
+            chunk_len: 0,
+            failure: None,
+        }
+    }
+
+    /// Starts the verification of a backing file with a checksum per block of `block_size` bytes
+    pub fn with_block_size(crc: &'static Crc<u32>, block_size: u64) -> Self {
+        SlotVerifier {
+            block_size: Some(block_size),
+            ..Self::new(crc)
+        }
+    }
+
+    /// Feeds the next bytes of the backing file
+    pub fn update(&mut self, mut data: &[u8]) {
+        if self.generation.is_none() {
+            match data.split_first() {
+                Some((generation, rest)) => {
+                    self.generation = Some(*generation);
+                    data = rest;
+                }
+                None => return,
+            }
+        }
+
+        let chunk = match self.block_size {
+            Some(block_size) => block_size + TRAILER_LEN,
+            None => return self.feed(data),
+        };
+        while !data.is_empty() {
+            let count = usize::try_from(chunk - self.chunk_len)
+                .unwrap_or(usize::MAX)
+                .min(data.len());
+            let (current, rest) = data.split_at(count);
+            self.feed(current);
+            self.chunk_len += count as u64;
+            if self.chunk_len == chunk {
+                self.finish_chunk();
+            }
+            data = rest;
+        }
+    }
+
+    /// Feeds bytes of the current chunk, holding back the last four bytes as its potential checksum
+    fn feed(&mut self, data: &[u8]) {
+        if data.len() >= self.tail.len() {
+            self.digest.update(&self.tail[..self.tail_len]);
+            let (content, tail) = data.split_at(data.len() - self.tail.len());
+            self.digest.update(content);
+            self.tail.copy_from_slice(tail);
+            self.tail_len = self.tail.len();
+        } else {
+            let overflow = (self.tail_len + data.len()).saturating_sub(self.tail.len());
+            self.digest.update(&self.tail[..overflow]);
+            self.tail.copy_within(overflow..self.tail_len, 0);
+            self.tail_len -= overflow;
+            self.tail[self.tail_len..self.tail_len + data.len()].copy_from_slice(data);
+            self.tail_len += data.len();
+        }
+    }
+
+    /// Compares the held back checksum with the checksum of the chunk and starts the next chunk
+    fn finish_chunk(&mut self) {
+        let expected = u32::from_le_bytes(self.tail);
+        let actual = core::mem::replace(&mut self.digest, ChecksumDigest::new(self.crc)).finalize();
+        if expected != actual && self.failure.is_none() {
+            self.failure = Some(FileCheckResult::ChecksumFailure { expected, actual });
+        }
+        self.tail_len = 0;
+        self.chunk_len = 0;
+    }
+
+    /// Compares the checksum of the content with the stored checksum after all bytes have been fed
+    pub fn finish(mut self) -> FileCheckResult {
+        match self.generation {
+            Some(generation) if self.tail_len == self.tail.len() => {
+                self.finish_chunk();
+                self.failure.unwrap_or(FileCheckResult::Good {
+                    generation: generation.into(),
+                })
+            }
+            _ => FileCheckResult::Truncated,
+        }
+    }
+}
+
+///
+/// Compares the generations with wrapping behaviour (assumes increments of 1)
+pub fn compare_generations(a: u8, b: u8) -> Ordering {
+    match a.wrapping_sub(b) {
+        0 => Ordering::Equal,
+        x if x < 128 => Ordering::Greater,
+        _ => Ordering::Less,
+    }
+}
+
+///
+/// Compares generations, which may have been stored in one or in eight bytes (see `FormatVersion::V5`).
+///
+/// Generations up to 255 wrap around like in `compare_generations`. Larger generations increase monotonically,
+/// they are compared by their value and are newer than any generation up to 255.
+pub fn compare_wide_generations(a: u64, b: u64) -> Ordering {
+    match (u8::try_from(a), u8::try_from(b)) {
+        (Ok(a), Ok(b)) => compare_generations(a, b),
+        _ => a.cmp(&b),
+    }
+}
+
+///
+/// Selects the backing file holding the newest valid generation.
+///
+/// `generations` holds the generation of every backing file, or `None` for invalid or missing backing files.
+pub fn select_newest<G: Copy + Into<u64>>(generations: &[Option<G>]) -> Option<usize> {
+    generations
+        .iter()
+        .enumerate()
+        .filter_map(|(index, generation)| generation.map(|generation| (index, generation.into())))
+        .max_by(|(_, a), (_, b)| compare_wide_generations(*a, *b))
+        .map(|(index, _)| index)
+}
+
+///
+/// Selects the backing file to be overwritten by the next generation and the generation to write.
+///
+/// Invalid or missing backing files are used first, otherwise the oldest generation is replaced.
+/// Returns `None` if there are no backing files at all.
+pub fn select_target(generations: &[Option<u8>]) -> Option<(usize, u8)> {
+    let index = generations
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| match (a, b) {
+            (Some(a), Some(b)) => compare_generations(*a, *b),
+            (None, None) => Ordering::Equal,
+            (None, _) => Ordering::Less,
+            (_, None) => Ordering::Greater,
+        })
+        .map(|(index, _)| index)?;
+    let current = select_newest(generations)
+        .and_then(|newest| generations[newest])
+        .unwrap_or(0);
+    Some((index, current.wrapping_add(1)))
+}
+
+///
+/// Like `select_target` for generations stored in one or in eight bytes.
+///
+/// With `wide` generations and after a generation above 255 has been written, the next generation is larger
+/// than all existing ones, starting at 256. Otherwise it wraps around after 255.
+pub fn select_target_wide(generations: &[Option<u64>], wide: bool) -> Option<(usize, u64)> {
+    select_target_unpinned(generations, wide, None)
+}
+
+///
+/// Selects the backing file to write the next generation to like `select_target_wide`, but never the `pinned` one.
+///
+/// The next generation still follows the newest generation, even if it is held by the pinned backing file.
+pub fn select_target_unpinned(
+    generations: &[Option<u64>],
+    wide: bool,
+    pinned: Option<usize>,
+) -> Option<(usize, u64)> {
+    select_target_excluding(generations, wide, pinned.as_slice())
+}
+
+///
+/// Selects the backing file to write the next generation to like `select_target_wide`, but none of the `excluded`.
+///
+/// Returns `None` if every backing file is excluded.
+pub fn select_target_excluding(
+    generations: &[Option<u64>],
+    wide: bool,
+    excluded: &[usize],
+) -> Option<(usize, u64)> {
+    let index = generations
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !excluded.contains(index))
+        .min_by(|(_, a), (_, b)| match (a, b) {
+            (Some(a), Some(b)) => compare_wide_generations(*a, *b),
+            (None, None) => Ordering::Equal,
+            (None, _) => Ordering::Less,
+            (_, None) => Ordering::Greater,
+        })
+        .map(|(index, _)| index)?;
+    let current = select_newest(generations).and_then(|newest| generations[newest]);
+    Some((index, next_generation(current, wide)))
+}
+
+///
+/// The generation following `current`, which is `None` if there is no valid generation yet.
+///
+/// See `select_target_wide` for the meaning of `wide`.
+pub fn next_generation(current: Option<u64>, wide: bool) -> u64 {
+    let current = current.unwrap_or(0);
+    match u8::try_from(current) {
+        Ok(current) if !wide => u64::from(current.wrapping_add(1)),
+        _ => current.saturating_add(1).max(u64::from(u8::MAX) + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cmp::Ordering;
+
+    use super::{
+        blocked_content_len, compare_generations, compare_wide_generations, select_newest,
+        select_target, select_target_unpinned, select_target_wide, FileCheckResult, FormatVersion,
+        HeaderError, SlotHeader, SlotTrailer, SlotVerifier, DEFAULT_CHECKSUM,
+    };
+
+    #[test]
+    fn detects_format_versions() {
+        let header = FormatVersion::V1.header(7);
+        assert_eq!(header.as_ref(), b"MBF\x01\x07");
+        assert_eq!(FormatVersion::V0.header(7).as_ref(), [7]);
+
+        assert_eq!(
+            FormatVersion::detect(header.as_ref()),
+            Ok(FormatVersion::V1)
+        );
+        assert_eq!(FormatVersion::detect(b"\x07Hello"), Ok(FormatVersion::V0));
+        assert_eq!(FormatVersion::detect(b"MB"), Ok(FormatVersion::V0));
+        assert_eq!(
+            FormatVersion::detect(FormatVersion::V2.header(7).as_ref()),
+            Ok(FormatVersion::V2)
+        );
+        let header = FormatVersion::V3.header_written_at(7, 1_700_000_000_000);
+        assert_eq!(header.as_ref().len(), 13);
+        assert_eq!(header.as_ref()[12], 7);
+        assert_eq!(
+            FormatVersion::detect(header.as_ref()),
+            Ok(FormatVersion::V3)
+        );
+        assert_eq!(
+            FormatVersion::V3.written(header.as_ref()),
+            Some(1_700_000_000_000)
+        );
+        assert_eq!(FormatVersion::V1.written(b"MBF\x01\x07"), None);
+        assert_eq!(FormatVersion::V3.generation(header.as_ref()), Some(7));
+
+        let header = FormatVersion::V5.header_written_at(0x0102_0304_0506_0708, 1_700_000_000_000);
+        assert_eq!(header.as_ref().len(), 20);
+        assert_eq!(header.as_ref()[12..], [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(
+            FormatVersion::detect(header.as_ref()),
+            Ok(FormatVersion::V5)
+        );
+        assert_eq!(
+            FormatVersion::V5.generation(header.as_ref()),
+            Some(0x0102_0304_0506_0708)
+        );
+        assert_eq!(
+            FormatVersion::V5.written(header.as_ref()),
+            Some(1_700_000_000_000)
+        );
+
+        let mut header = FormatVersion::V6
+            .header_written_at(300, 1_700_000_000_000)
+            .as_ref()
+            .to_vec();
+        assert_eq!(header.len(), 24);
+        assert_eq!(FormatVersion::detect(&header), Ok(FormatVersion::V6));
+        assert_eq!(FormatVersion::V6.generation(&header), Some(300));
+        assert!(FormatVersion::V6.verify_header(&header));
+        assert!(!FormatVersion::V6.verify_header(&header[..20]));
+        header[23] ^= 1;
+        assert!(!FormatVersion::V6.verify_header(&header));
+        assert_eq!(FormatVersion::detect(b"MBF\x09\x07"), Err(9));
+    }
+
+    #[test]
+    fn parses_headers_and_trailers() {
+        let mut file = FormatVersion::V6
+            .header_written_at(300, 1_700_000_000_000)
+            .as_ref()
+            .to_vec();
+        file.extend_from_slice(b"Hello");
+        let trailer = SlotTrailer {
+            length: Some(5),
+            checksum: 0x0102_0304,
+        };
+        let mut encoded = [0u8; 12];
+        assert_eq!(trailer.encode(&mut encoded), 12);
+        file.extend_from_slice(&encoded);
+
+        let header = SlotHeader::parse(&file).unwrap();
+        assert_eq!(header.version(), FormatVersion::V6);
+        assert_eq!(header.generation(), 300);
+        assert_eq!(header.written(), Some(1_700_000_000_000));
+        assert_eq!(
+            header,
+            FormatVersion::V6.header_written_at(300, 1_700_000_000_000)
+        );
+        assert_eq!(SlotTrailer::parse(FormatVersion::V6, &file), Some(trailer));
+        assert_eq!(
+            SlotTrailer::parse(FormatVersion::V1, &file).map(|trailer| trailer.length),
+            Some(None)
+        );
+
+        assert_eq!(SlotHeader::parse(&file[..20]), Err(HeaderError::Truncated));
+        assert_eq!(
+            SlotHeader::parse(b"MBF\x09\x07"),
+            Err(HeaderError::UnsupportedVersion(9))
+        );
+        file[23] ^= 1;
+        assert_eq!(SlotHeader::parse(&file), Err(HeaderError::ChecksumMismatch));
+        assert_eq!(SlotHeader::parse(b"").unwrap_err(), HeaderError::Truncated);
+        assert_eq!(SlotTrailer::parse(FormatVersion::V2, b"short"), None);
+    }
+
+    #[test]
+    fn compare_generations_wraps() {
+        assert_eq!(compare_generations(0, 0), Ordering::Equal);
+        assert_eq!(compare_generations(1, 1), Ordering::Equal);
+        assert_eq!(compare_generations(0, 1), Ordering::Less);
+        assert_eq!(compare_generations(1, 0), Ordering::Greater);
+        assert_eq!(compare_generations(255, 0), Ordering::Less);
+        assert_eq!(compare_generations(0, 255), Ordering::Greater);
+    }
+
+    #[test]
+    fn selects_slots() {
+        assert_eq!(select_newest::<u8>(&[None, None]), None);
+        assert_eq!(select_newest(&[Some(255u8), Some(0)]), Some(1));
+        assert_eq!(select_target(&[]), None);
+        assert_eq!(select_target(&[None, None]), Some((0, 1)));
+        assert_eq!(select_target(&[Some(4), None]), Some((1, 5)));
+        assert_eq!(select_target(&[Some(255), Some(0)]), Some((0, 1)));
+        assert_eq!(
+            select_target_unpinned(&[Some(3), Some(1), Some(2)], false, Some(1)),
+            Some((2, 4))
+        );
+        assert_eq!(select_target_unpinned(&[Some(3)], false, Some(0)), None);
+    }
+
+    #[test]
+    fn wide_generations_are_ordered_monotonically() {
+        assert_eq!(compare_wide_generations(255, 0), Ordering::Less);
+        assert_eq!(compare_wide_generations(256, 255), Ordering::Greater);
+        assert_eq!(compare_wide_generations(256, 0), Ordering::Greater);
+        assert_eq!(compare_wide_generations(1000, 300), Ordering::Greater);
+
+        assert_eq!(
+            select_target_wide(&[Some(255), Some(0)], false),
+            Some((0, 1))
+        );
+        assert_eq!(
+            select_target_wide(&[Some(255), Some(0)], true),
+            Some((0, 256))
+        );
+        assert_eq!(select_target_wide(&[None, None], true), Some((0, 256)));
+        assert_eq!(
+            select_target_wide(&[Some(256), Some(0)], false),
+            Some((1, 257))
+        );
+        assert_eq!(select_newest(&[Some(256u64), Some(0), Some(300)]), Some(2));
+    }
+
+    #[test]
+    fn verifies_arbitrary_pieces() {
+        let mut file = vec![7u8];
+        file.extend_from_slice(b"Hello World");
+        file.extend_from_slice(&DEFAULT_CHECKSUM.checksum(b"Hello World").to_le_bytes());
+
+        for piece in 1..file.len() {
+            let mut verifier = SlotVerifier::new(&DEFAULT_CHECKSUM);
+            for chunk in file.chunks(piece) {
+                verifier.update(chunk);
+            }
+            assert_eq!(verifier.finish(), FileCheckResult::Good { generation: 7 });
+        }
+
+        let mut verifier = SlotVerifier::new(&DEFAULT_CHECKSUM);
+        verifier.update(&file[..4]);
+        assert_eq!(verifier.finish(), FileCheckResult::Truncated);
+
+        file[3] = b'L';
+        let mut verifier = SlotVerifier::new(&DEFAULT_CHECKSUM);
+        verifier.update(&file);
+        assert!(matches!(
+            verifier.finish(),
+            FileCheckResult::ChecksumFailure { .. }
+        ));
+    }
+
+    #[test]
+    fn verifies_the_boundaries_of_the_trailer() {
+        let checksum = DEFAULT_CHECKSUM.checksum(b"").to_le_bytes();
+        for len in 0..checksum.len() {
+            let mut verifier = SlotVerifier::new(&DEFAULT_CHECKSUM);
+            verifier.update(&[7]);
+            verifier.update(&checksum[..len]);
+            assert_eq!(verifier.finish(), FileCheckResult::Truncated);
+        }
+        let mut verifier = SlotVerifier::new(&DEFAULT_CHECKSUM);
+        verifier.update(&[7]);
+        verifier.update(&checksum);
+        assert_eq!(verifier.finish(), FileCheckResult::Good { generation: 7 });
+
+        let mut file = vec![7u8];
+        file.extend_from_slice(b"Hello World");
+        file.extend_from_slice(&DEFAULT_CHECKSUM.checksum(b"Hello World").to_le_bytes());
+        for split in file.len() - 8..file.len() {
+            let mut verifier = SlotVerifier::new(&DEFAULT_CHECKSUM);
+            verifier.update(&file[..split]);
+            for byte in &file[split..] {
+                verifier.update(core::slice::from_ref(byte));
+            }
+            assert_eq!(verifier.finish(), FileCheckResult::Good { generation: 7 });
+        }
+
+        // the generation is not covered by the checksum, only the header checksum of `FormatVersion::V6` is
+        file[0] = 8;
+        let mut verifier = SlotVerifier::new(&DEFAULT_CHECKSUM);
+        verifier.update(&file);
+        assert_eq!(verifier.finish(), FileCheckResult::Good { generation: 8 });
+    }
+
+    #[test]
+    fn verifies_blocks() {
+        let mut file = vec![7u8];
+        for block in [&b"Hell"[..], b"o Wo", b"rld"] {
+            file.extend_from_slice(block);
+            file.extend_from_slice(&DEFAULT_CHECKSUM.checksum(block).to_le_bytes());
+        }
+        assert_eq!(blocked_content_len(file.len() as u64 - 1, 4), Some(11));
+        assert_eq!(blocked_content_len(8 + 2, 4), None);
+
+        for piece in 1..file.len() {
+            let mut verifier = SlotVerifier::with_block_size(&DEFAULT_CHECKSUM, 4);
+            for chunk in file.chunks(piece) {
+                verifier.update(chunk);
+            }
+            assert_eq!(verifier.finish(), FileCheckResult::Good { generation: 7 });
+        }
+
+        let mut verifier = SlotVerifier::with_block_size(&DEFAULT_CHECKSUM, 4);
+        verifier.update(&file[..1 + 16]);
+        assert_eq!(verifier.finish(), FileCheckResult::Truncated);
+
+        file[6] = b'O';
+        let mut verifier = SlotVerifier::with_block_size(&DEFAULT_CHECKSUM, 4);
+        verifier.update(&file);
+        assert!(matches!(
+            verifier.finish(),
+            FileCheckResult::ChecksumFailure { .. }
+        ));
+    }
+}
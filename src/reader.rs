@@ -1,9 +1,135 @@
-use std::io::{Read, Seek, SeekFrom};
+use std::{
+    io::{BufRead, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::SystemTime,
+};
+
+use crc::Crc;
+
+#[cfg(feature = "encryption")]
+use crate::encryption::{Decryptor, CHUNK_LEN};
+use crate::{
+    checksum::{checksum, ChecksumDigest},
+    lease::Lease,
+    Advice, BufferedFileErrors, ChecksumAlgorithm, SlotTrailer, StorageFile, UserMetadata,
+    HEADER_LEN, LENGTH_FOOTER_LEN, TRAILER_LEN,
+};
+
+/// The currently loaded block of contents protected by a checksum per block
+#[derive(Clone)]
+struct Blocks {
+    crc: &'static Crc<u32>,
+    size: u64,
+    /// The length of the stored body without the checksums, which may extend beyond the contents
+    body_len: u64,
+    /// The number of bytes of the body preceding the contents
+    skipped: u64,
+    index: Option<u64>,
+    buffer: Vec<u8>,
+}
+
+impl std::fmt::Debug for Blocks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Blocks")
+            .field("size", &self.size)
+            .field("body_len", &self.body_len)
+            .field("skipped", &self.skipped)
+            .field("index", &self.index)
+            .finish()
+    }
+}
+
+/// The currently decrypted chunk of encrypted contents, see `BufferedFileOptions::encryption_key`
+#[cfg(feature = "encryption")]
+#[derive(Clone)]
+struct Sealed {
+    decryptor: Decryptor,
+    /// The number of bytes of the decrypted contents preceding the contents handed out
+    skipped: u64,
+    index: Option<u64>,
+    buffer: Vec<u8>,
+}
+
+#[cfg(feature = "encryption")]
+impl std::fmt::Debug for Sealed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sealed")
+            .field("decryptor", &self.decryptor)
+            .field("skipped", &self.skipped)
+            .field("index", &self.index)
+            .finish()
+    }
+}
+
+/// Invoked once, when the checksum verified while reading does not match
+pub(crate) type CorruptHook = Box<dyn FnOnce() + Send + Sync>;
+
+/// The checksum of the backing file, which is computed while the contents are read sequentially
+struct Streaming {
+    digest: ChecksumDigest,
+    /// The position in `inner` up to which the bytes have been fed into the digest
+    fed: u64,
+    /// The position of the stored checksum in `inner`
+    end: u64,
+    failed: bool,
+    on_corrupt: Option<CorruptHook>,
+}
+
+impl std::fmt::Debug for Streaming {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Streaming")
+            .field("fed", &self.fed)
+            .field("end", &self.end)
+            .field("failed", &self.failed)
+            .finish()
+    }
+}
+
+/// Invoked with the number of bytes read so far and the length of the contents, see `BufferedFileReader::set_progress`
+type ProgressHook = Box<dyn FnMut(u64, u64) + Send + Sync>;
+
+/// The number of bytes handed out by the reader
+#[derive(Default)]
+struct Progress {
+    read: u64,
+    hook: Option<ProgressHook>,
+}
+
+impl std::fmt::Debug for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Progress")
+            .field("read", &self.read)
+            .field("hook", &self.hook.is_some())
+            .finish()
+    }
+}
+
+/// The number of bytes read ahead by `BufRead::fill_buf`
+const READ_BUFFER_LEN: usize = 8 * 1024;
+
+///
+/// Describes the generation a reader has been opened on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReaderMetadata {
+    /// The generation of the backing file
+    pub generation: u64,
+    /// The path of the backing file
+    pub path: PathBuf,
+    /// The length of the contents in bytes
+    pub len: u64,
+    /// The time the backing file has been written, if it has been recorded (see `FormatVersion::V3`)
+    pub written: Option<SystemTime>,
+    /// The algorithm the checksums of the backing file have been verified with, `None` for a plain file
+    /// read by `BufferedFileOptions::plain_fallback`
+    pub checksum: Option<ChecksumAlgorithm>,
+}
 
 ///
 /// Represents the read-only access to the file.
 /// Validation has been performed on open. This provides an `impl std::io::Read` to the contents of the file.
-/// 
+/// It buffers the contents itself for `std::io::BufRead`, so it does not need to be wrapped in a `BufReader`.
+///
 #[derive(Debug)]
 pub struct BufferedFileReader<T>
 where
@@ -12,25 +138,926 @@ where
     inner: T,
     useful_file_size: u64,
     pos: u64,
+    generation: u64,
+    path: PathBuf,
+    written: Option<SystemTime>,
+    checksum: Option<ChecksumAlgorithm>,
+    user_metadata: UserMetadata,
+    header_len: u64,
+    blocks: Option<Blocks>,
+    streaming: Option<Streaming>,
+    in_memory: Option<Vec<u8>>,
+    #[cfg(feature = "encryption")]
+    sealed: Option<Sealed>,
+    /// The bytes read ahead by `fill_buf`, starting at `read_start`, which precede `pos`
+    read_buffer: Vec<u8>,
+    read_start: usize,
+    /// Keeps writers from overwriting the backing file, see `BufferedFileOptions::reader_leases`
+    lease: Option<Lease>,
+    progress: Progress,
+    /// Whether the position of `inner` is shared with a clone, so it is set before every read
+    shared_offset: AtomicBool,
 }
 
 impl<T: Read + Seek> BufferedFileReader<T> {
-    pub(crate) fn new(inner: T, len: u64) -> BufferedFileReader<T> {
+    pub(crate) fn new(inner: T, len: u64, generation: u64) -> BufferedFileReader<T> {
         BufferedFileReader {
             inner,
             useful_file_size: len,
             pos: 0,
+            generation,
+            path: PathBuf::new(),
+            written: None,
+            checksum: None,
+            user_metadata: UserMetadata::new(),
+            header_len: HEADER_LEN,
+            blocks: None,
+            streaming: None,
+            in_memory: None,
+            #[cfg(feature = "encryption")]
+            sealed: None,
+            read_buffer: Vec::new(),
+            read_start: 0,
+            lease: None,
+            progress: Progress::default(),
+            shared_offset: AtomicBool::new(false),
+        }
+    }
+
+    /// Sets the time the backing file has been written, as recorded in its header
+    pub(crate) fn written_at(mut self, written: Option<SystemTime>) -> Self {
+        self.written = written;
+        self
+    }
+
+    /// Sets the backing file `inner` has been opened on and the algorithm its checksums are verified with
+    pub(crate) fn backing_file(mut self, path: &Path, checksum: Option<ChecksumAlgorithm>) -> Self {
+        self.path = path.to_path_buf();
+        self.checksum = checksum;
+        self
+    }
+
+    /// Holds `lease` on the backing file as long as the reader exists
+    pub(crate) fn leased(mut self, lease: Option<Lease>) -> Self {
+        self.lease = lease;
+        self
+    }
+
+    /// Sets the number of bytes preceding the contents in `inner`
+    pub(crate) fn header_len(mut self, header_len: u64) -> Self {
+        self.header_len = header_len;
+        self
+    }
+
+    ///
+    /// Takes the length of the contents from the footer stored behind them.
+    ///
+    /// Fails with `ErrorKind::InvalidData` if the stored length does not match the length of the stored contents,
+    /// e.g. because the backing file has been truncated. With a `sector_size`, the contents may be followed by
+    /// the zeros padding the backing file to the end of its last sector.
+    pub(crate) fn length_footer(mut self, sector_size: Option<u64>) -> std::io::Result<Self> {
+        let len = self
+            .useful_file_size
+            .checked_sub(LENGTH_FOOTER_LEN)
+            .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidData, "The length is missing"))?;
+        self.seek(SeekFrom::Start(len))?;
+        let mut footer = [0u8; LENGTH_FOOTER_LEN as usize];
+        self.read_exact(&mut footer)?;
+        let stored = SlotTrailer::parse_length(&footer).expect("the footer has 8 bytes");
+        let padded = |sector_size: u64| {
+            stored < len
+                && len - stored < sector_size
+                && (self.header_len + len + LENGTH_FOOTER_LEN + TRAILER_LEN)
+                    .is_multiple_of(sector_size)
+        };
+        if stored != len && !sector_size.is_some_and(padded) {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "The stored length {stored} does not match the length of the contents {len}"
+                ),
+            ));
+        }
+        self.useful_file_size = stored;
+        self.seek(SeekFrom::Start(0))?;
+        Ok(self)
+    }
+
+    /// Reads the user metadata preceding the contents and hides it from the contents
+    pub(crate) fn user_metadata_section(mut self) -> std::io::Result<Self> {
+        let mut len = [0u8; 2];
+        self.read_exact(&mut len)?;
+        let mut section = vec![0u8; usize::from(u16::from_le_bytes(len))];
+        self.read_exact(&mut section)?;
+        self.user_metadata = UserMetadata::decode(&section)?;
+        // the inner file is positioned right behind the section
+        let skipped = self.pos;
+        match &mut self.blocks {
+            Some(blocks) => blocks.skipped += skipped,
+            None => self.header_len += skipped,
+        }
+        self.useful_file_size -= skipped;
+        self.pos = 0;
+        Ok(self)
+    }
+
+    /// Hides the last `len` bytes of the contents, which are not part of the actual contents
+    pub(crate) fn exclude_trailer(mut self, len: u64) -> Self {
+        self.useful_file_size = self.useful_file_size.saturating_sub(len);
+        self
+    }
+
+    /// Verifies the checksum of every block of `size` bytes, before its contents are handed out
+    pub(crate) fn blocks(mut self, crc: &'static Crc<u32>, size: u64) -> Self {
+        self.blocks = Some(Blocks {
+            crc,
+            size,
+            body_len: self.useful_file_size,
+            skipped: 0,
+            index: None,
+            buffer: Vec::new(),
+        });
+        self
+    }
+
+    ///
+    /// Verifies the checksum stored at `end` in `inner`, once the contents have been read up to their end.
+    ///
+    /// Must be called while `inner` is positioned at the start of the body. The verification is abandoned,
+    /// as soon as the contents are not read sequentially anymore.
+    pub(crate) fn verify_while_reading(mut self, crc: &'static Crc<u32>, end: u64) -> Self {
+        self.streaming = Some(Streaming {
+            digest: ChecksumDigest::new(crc),
+            fed: self.header_len,
+            end,
+            failed: false,
+            on_corrupt: None,
+        });
+        self
+    }
+
+    /// Registers `hook` to be invoked, when the checksum verified while reading does not match
+    pub(crate) fn on_corrupt(mut self, hook: CorruptHook) -> Self {
+        if let Some(streaming) = &mut self.streaming {
+            streaming.on_corrupt = Some(hook);
+        }
+        self
+    }
+
+    /// Feeds the rest of the backing file into the digest and compares it with the stored checksum
+    fn finish_verification(&mut self) -> std::io::Result<()> {
+        let streaming = match &mut self.streaming {
+            Some(streaming) if streaming.fed == self.header_len + self.pos => streaming,
+            _ => return Ok(()),
+        };
+        // the footer and the authentication code are covered by the checksum as well
+        let mut rest =
+            vec![0u8; (streaming.end.saturating_sub(streaming.fed) + TRAILER_LEN) as usize];
+        // a reader cloned by `try_clone` may have moved the shared position of `inner`
+        self.inner.seek(SeekFrom::Start(streaming.fed))?;
+        self.inner.read_exact(&mut rest)?;
+        let (data, stored) = rest.split_at(rest.len() - TRAILER_LEN as usize);
+        streaming.digest.update(data);
+        let expected = u32::from_le_bytes(stored.try_into().expect("the checksum has 4 bytes"));
+        // the position of `inner` must match the logical position again for relative seeks
+        self.inner.seek(SeekFrom::Start(streaming.fed))?;
+        if streaming.digest.clone().finalize() != expected {
+            streaming.failed = true;
+            if let Some(hook) = streaming.on_corrupt.take() {
+                hook();
+            }
+            return Err(corrupt_contents());
+        }
+        self.streaming = None;
+        Ok(())
+    }
+
+    /// Serves the contents from memory instead of `inner`, starting at the beginning
+    pub(crate) fn in_memory(mut self, contents: Vec<u8>) -> Self {
+        self.useful_file_size = contents.len() as u64;
+        self.pos = 0;
+        self.blocks = None;
+        self.streaming = None;
+        #[cfg(feature = "encryption")]
+        {
+            self.sealed = None;
+        }
+        self.discard_buffer();
+        self.in_memory = Some(contents);
+        self.progress.read = 0;
+        self
+    }
+
+    /// Splits off `inner` with the offset and the length of the contents, if they are stored in one piece
+    #[cfg_attr(not(feature = "mmap"), allow(dead_code))]
+    pub(crate) fn into_contiguous(self) -> Option<(T, u64, u64)> {
+        if self.is_positioned_logically() {
+            return None;
+        }
+        Some((self.inner, self.header_len, self.useful_file_size))
+    }
+
+    /// The number of bytes read ahead, which have not been consumed yet
+    fn buffered(&self) -> usize {
+        self.read_buffer.len() - self.read_start
+    }
+
+    /// Drops the bytes read ahead, e.g. before seeking
+    fn discard_buffer(&mut self) {
+        self.read_buffer.clear();
+        self.read_start = 0;
+    }
+
+    /// Whether the position is tracked independently of the position in `inner`
+    fn is_positioned_logically(&self) -> bool {
+        #[cfg(feature = "encryption")]
+        if self.sealed.is_some() {
+            return true;
+        }
+        self.in_memory.is_some() || self.blocks.is_some()
+    }
+
+    ///
+    /// Decrypts the contents chunk by chunk while they are read, starting at the beginning.
+    ///
+    /// Must be called while the reader is positioned behind the nonce prefix. The first chunk is decrypted
+    /// right away, so contents fitting into one chunk have been authenticated completely before they are read.
+    #[cfg(feature = "encryption")]
+    pub(crate) fn decrypted(mut self, decryptor: Decryptor) -> std::io::Result<Self> {
+        self.useful_file_size = decryptor.len();
+        self.pos = 0;
+        self.streaming = None;
+        self.discard_buffer();
+        self.sealed = Some(Sealed {
+            decryptor,
+            skipped: 0,
+            index: None,
+            buffer: Vec::new(),
+        });
+        self.load_chunk(0)?;
+        self.progress.read = 0;
+        Ok(self)
+    }
+
+    /// Decrypts the chunk at `index` unless it is the current one
+    #[cfg(feature = "encryption")]
+    fn load_chunk(&mut self, index: u64) -> std::io::Result<()> {
+        let (start, len) = match &self.sealed {
+            Some(sealed) if sealed.index != Some(index) => sealed.decryptor.chunk_range(index),
+            _ => return Ok(()),
+        };
+        let mut chunk = vec![0u8; len];
+        self.read_stored_exact(start, &mut chunk)?;
+        if let Some(sealed) = &mut self.sealed {
+            sealed.index = None;
+            sealed.buffer = sealed.decryptor.decrypt_chunk(index, &chunk)?;
+            sealed.index = Some(index);
+        }
+        Ok(())
+    }
+
+    /// Reads from the decrypted chunk containing the current position
+    #[cfg(feature = "encryption")]
+    fn read_sealed(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let body_pos = match &self.sealed {
+            Some(sealed) => sealed.skipped + self.pos,
+            None => return Ok(0),
+        };
+        if self.pos >= self.useful_file_size || buf.is_empty() {
+            return Ok(0);
+        }
+        let index = body_pos / CHUNK_LEN;
+        self.load_chunk(index)?;
+        let sealed = self.sealed.as_ref().expect("the chunk has been decrypted");
+        let offset = (body_pos - index * CHUNK_LEN) as usize;
+        let remaining = usize::try_from(self.useful_file_size - self.pos).unwrap_or(usize::MAX);
+        let count = buf.len().min(sealed.buffer.len() - offset).min(remaining);
+        buf[..count].copy_from_slice(&sealed.buffer[offset..offset + count]);
+        self.pos += count as u64;
+        Ok(count)
+    }
+
+    /// Fills `buf` with the stored contents at `pos`, before they are decrypted
+    #[cfg(feature = "encryption")]
+    fn read_stored_exact(&mut self, mut pos: u64, mut buf: &mut [u8]) -> std::io::Result<()> {
+        if self.blocks.is_none() {
+            self.inner.seek(SeekFrom::Start(self.header_len + pos))?;
+            return self.inner.read_exact(buf);
+        }
+        let end = pos + buf.len() as u64;
+        while !buf.is_empty() {
+            match self.read_block_at(pos, end, buf)? {
+                0 => return Err(ErrorKind::UnexpectedEof.into()),
+                count => {
+                    buf = &mut buf[count..];
+                    pos += count as u64;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads from the verified block containing the current position
+    fn read_block(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.read_block_at(self.pos, self.useful_file_size, buf)?;
+        self.pos += count as u64;
+        Ok(count)
+    }
+
+    /// Reads the bytes of the contents at `pos` up to `end` from the verified block containing them
+    fn read_block_at(&mut self, pos: u64, end: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        let blocks = match &mut self.blocks {
+            Some(blocks) => blocks,
+            None => return Ok(0),
+        };
+        if pos >= end || buf.is_empty() {
+            return Ok(0);
+        }
+        let body_pos = blocks.skipped + pos;
+        let index = body_pos / blocks.size;
+        if blocks.index != Some(index) {
+            blocks.index = None;
+            let len = blocks.size.min(blocks.body_len - index * blocks.size);
+            let start = self.header_len + index * (blocks.size + TRAILER_LEN);
+            self.inner.seek(SeekFrom::Start(start))?;
+            blocks.buffer.resize((len + TRAILER_LEN) as usize, 0);
+            self.inner.read_exact(&mut blocks.buffer)?;
+            verify_block(blocks.crc, index, &blocks.buffer)?;
+            blocks.buffer.truncate(len as usize);
+            blocks.index = Some(index);
+        }
+        let offset = (body_pos - index * blocks.size) as usize;
+        let remaining = usize::try_from(end - pos).unwrap_or(usize::MAX);
+        let count = buf.len().min(blocks.buffer.len() - offset).min(remaining);
+        buf[..count].copy_from_slice(&blocks.buffer[offset..offset + count]);
+        Ok(count)
+    }
+}
+
+/// Verifies a block of contents followed by its checksum
+fn verify_block(crc: &'static Crc<u32>, index: u64, block: &[u8]) -> std::io::Result<()> {
+    let (data, stored) = block.split_at(block.len() - TRAILER_LEN as usize);
+    let expected = u32::from_le_bytes(stored.try_into().expect("the checksum has 4 bytes"));
+    if checksum(crc, data) != expected {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("The checksum of block {index} does not match"),
+        ));
+    }
+    Ok(())
+}
+
+/// Reads from `file` at `offset` without moving its position
+fn read_file_at(file: &std::fs::File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+    #[cfg(unix)]
+    return std::os::unix::fs::FileExt::read_at(file, buf, offset);
+    #[cfg(windows)]
+    return std::os::windows::fs::FileExt::seek_read(file, buf, offset);
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = (file, buf, offset);
+        Err(std::io::Error::from(ErrorKind::Unsupported))
+    }
+}
+
+/// Fills `buf` from `file` at `offset` without moving its position
+fn read_file_exact_at(
+    file: &std::fs::File,
+    mut buf: &mut [u8],
+    mut offset: u64,
+) -> std::io::Result<()> {
+    while !buf.is_empty() {
+        match read_file_at(file, buf, offset) {
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(count) => {
+                buf = &mut buf[count..];
+                offset += count as u64;
+            }
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// The error reported, once the checksum verified while reading does not match
+fn corrupt_contents() -> std::io::Error {
+    std::io::Error::new(
+        ErrorKind::InvalidData,
+        "The checksum of the contents does not match",
+    )
+}
+
+impl<T: Read> BufferedFileReader<T> {
+    /// The generation of the backing file this reader has been opened on
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// The length of the contents in bytes, including the holes of sparse files
+    pub fn len(&self) -> u64 {
+        self.useful_file_size
+    }
+
+    /// Whether the contents are empty
+    pub fn is_empty(&self) -> bool {
+        self.useful_file_size == 0
+    }
+
+    /// The length of the contents the reader can seek within, the same as `len`
+    pub fn stream_len(&self) -> u64 {
+        self.useful_file_size
+    }
+
+    /// The number of bytes of the contents behind the current position, e.g. to report the progress of reading
+    pub fn remaining(&self) -> u64 {
+        self.useful_file_size
+            .saturating_sub(self.pos)
+            .saturating_add((self.read_buffer.len() - self.read_start) as u64)
+    }
+
+    ///
+    /// The number of bytes handed out by `Read`, `BufRead` and `copy_into` so far, e.g. to display the progress
+    /// of loading large contents together with `len`.
+    ///
+    /// Bytes skipped by seeking or read at a given position with `read_at` or `pread` are not counted.
+    pub fn bytes_read(&self) -> u64 {
+        self.progress.read
+    }
+
+    ///
+    /// Invokes `progress` with `bytes_read` and `len` whenever bytes have been handed out by the reader.
+    ///
+    /// The callback runs on the reading thread and should return quickly.
+    pub fn set_progress(&mut self, progress: impl FnMut(u64, u64) + Send + Sync + 'static) {
+        self.progress.hook = Some(Box::new(progress));
+    }
+
+    /// Counts the bytes handed out and reports the progress
+    fn advance(&mut self, count: u64) {
+        if count == 0 {
+            return;
+        }
+        self.progress.read += count;
+        if let Some(hook) = &mut self.progress.hook {
+            hook(self.progress.read, self.useful_file_size);
+        }
+    }
+
+    /// The user metadata stored in front of the contents, which is empty for format versions without it
+    pub fn user_metadata(&self) -> &UserMetadata {
+        &self.user_metadata
+    }
+
+    /// The content type stored in the user metadata, see `BufferedFileOptions::content_type`
+    pub fn content_type(&self) -> Option<&str> {
+        self.user_metadata.content_type()
+    }
+
+    /// Describes the generation this reader has been opened on
+    pub fn metadata(&self) -> ReaderMetadata {
+        ReaderMetadata {
+            generation: self.generation,
+            path: self.path.clone(),
+            len: self.useful_file_size,
+            written: self.written,
+            checksum: self.checksum,
+        }
+    }
+
+    /// Provides access to the underlying backing file, e.g. for platform-specific calls
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    ///
+    /// Provides mutable access to the underlying backing file, e.g. for platform-specific calls.
+    ///
+    /// The reader expects the backing file at the position it has left it. Seek the reader afterwards,
+    /// if the position of the backing file has been changed.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    ///
+    /// Returns the underlying backing file, positioned anywhere behind the header.
+    ///
+    /// The backing file contains the header, the user metadata and the trailer besides the contents, and
+    /// it is no longer protected by the lease of the reader (see `BufferedFileOptions::reader_leases`).
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: StorageFile> BufferedFileReader<T> {
+    /// Announces how the contents are going to be read, e.g. to enable read-ahead for sequential reads
+    pub fn advise(&mut self, advice: Advice) -> std::io::Result<()> {
+        self.inner.advise(advice)
+    }
+}
+
+impl<T: Read + Seek> BufferedFileReader<T> {
+    ///
+    /// Restricts the reader to the `len` bytes of the contents starting at `offset`, e.g. to hand out a reader
+    /// per section of the contents. Positions and lengths are relative to the range afterwards.
+    ///
+    /// Fails with `ErrorKind::InvalidInput` if the range exceeds the contents. Block checksums are still verified,
+    /// the verification of `BufferedFileOptions::verify_while_reading` ends like with seeking.
+    pub fn take_range(mut self, offset: u64, len: u64) -> std::io::Result<Self> {
+        let end = offset
+            .checked_add(len)
+            .filter(|end| *end <= self.useful_file_size)
+            .ok_or_else(|| {
+                std::io::Error::new(ErrorKind::InvalidInput, "The range exceeds the contents")
+            })?;
+        self.discard_buffer();
+        self.streaming = None;
+        #[cfg(feature = "encryption")]
+        let sealed = self
+            .sealed
+            .as_mut()
+            .map(|sealed| sealed.skipped += offset)
+            .is_some();
+        #[cfg(not(feature = "encryption"))]
+        let sealed = false;
+        match (&mut self.in_memory, &mut self.blocks) {
+            (Some(contents), _) => {
+                contents.truncate(end as usize);
+                contents.drain(..offset as usize);
+            }
+            // only the decrypted contents are skipped, the chunks are decrypted as they are stored
+            _ if sealed => {}
+            (None, Some(blocks)) => blocks.skipped += offset,
+            (None, None) => self.header_len += offset,
+        }
+        self.useful_file_size = len;
+        self.rewind()?;
+        Ok(self)
+    }
+
+    /// Moves the position back to the start of the contents
+    pub fn rewind(&mut self) -> std::io::Result<()> {
+        self.seek(SeekFrom::Start(0)).map(|_| ())
+    }
+
+    ///
+    /// Reads the contents starting at `pos` into `buf`, returning the number of bytes read.
+    ///
+    /// With `BufferedFileOptions::block_checksums` only the blocks touched by the read are verified, so
+    /// random access stays protected without reading the whole backing file. Without block checksums the bytes
+    /// are not verified again, the trailing checksum has been verified before the reader has been opened.
+    /// The reader is positioned behind the bytes read afterwards.
+    pub fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.seek(SeekFrom::Start(pos))?;
+        self.read(buf)
+    }
+
+    /// Reads exactly `buf.len()` bytes of the contents starting at `pos`, see `read_at`
+    pub fn read_exact_at(&mut self, pos: u64, buf: &mut [u8]) -> std::io::Result<()> {
+        self.seek(SeekFrom::Start(pos))?;
+        self.read_exact(buf)
+    }
+
+    ///
+    /// Copies the contents from the current position to their end into `writer`, returning the number of bytes.
+    ///
+    /// Contents stored in one piece are copied directly from the backing file with `std::io::copy`,
+    /// which uses `copy_file_range`, `sendfile` or `splice` on linux, if `writer` is a file, a socket or a pipe.
+    /// The contents are copied through a buffer with block checksums, encryption, delta writes and while
+    /// the checksum is verified with `BufferedFileOptions::verify_while_reading`.
+    pub fn copy_into<W: Write + ?Sized>(&mut self, writer: &mut W) -> std::io::Result<u64> {
+        if self.is_positioned_logically() || self.streaming.is_some() {
+            return std::io::copy(self, writer);
+        }
+        let buffered = self.buffered() as u64;
+        writer.write_all(&self.read_buffer[self.read_start..])?;
+        self.discard_buffer();
+        let remaining = self.useful_file_size.saturating_sub(self.pos);
+        self.inner
+            .seek(SeekFrom::Start(self.header_len + self.pos))?;
+        let copied = std::io::copy(&mut Read::by_ref(&mut self.inner).take(remaining), writer)?;
+        self.pos += copied;
+        self.advance(buffered + copied);
+        Ok(buffered + copied)
+    }
+
+    ///
+    /// Reads the contents from the current position to their end, if they do not exceed `max_bytes`.
+    ///
+    /// Longer contents fail with `BufferedFileErrors::ContentsTooLarge` before any memory is allocated for them,
+    /// e.g. to protect a service against an unexpectedly large upload. The position of the reader is kept then.
+    pub fn read_to_vec_limited(&mut self, max_bytes: u64) -> Result<Vec<u8>, BufferedFileErrors> {
+        let len = self.remaining();
+        if len > max_bytes {
+            return Err(BufferedFileErrors::ContentsTooLarge {
+                len,
+                limit: max_bytes,
+            });
+        }
+        let mut contents = Vec::with_capacity(usize::try_from(len).unwrap_or_default());
+        self.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    ///
+    /// Reads the next line including its line ending into `buf`, like `BufRead::read_line` but without importing it.
+    ///
+    /// Lines are never read beyond the end of the contents, so the trailer of the backing file does not leak into
+    /// the last line. Fails with `ErrorKind::InvalidData`, if the line is not valid UTF-8.
+    pub fn read_line(&mut self, buf: &mut String) -> std::io::Result<usize> {
+        BufRead::read_line(self, buf)
+    }
+
+    /// Iterates over the lines of the contents without their line endings, see `read_line` and `BufRead::lines`
+    pub fn lines(self) -> std::io::Lines<Self> {
+        BufRead::lines(self)
+    }
+
+    ///
+    /// Checks whether the contents from the current position to their end are valid UTF-8 without loading them
+    /// into memory, e.g. before a text file is handed to a parser.
+    ///
+    /// Fails with `ErrorKind::InvalidData` naming the position of the first invalid byte.
+    /// The reader returns to its position afterwards. As the contents are read to their end,
+    /// the checksum is verified as well with `BufferedFileOptions::verify_while_reading`.
+    pub fn validate_utf8(&mut self) -> std::io::Result<()> {
+        let start = self.stream_position()?;
+        // the bytes are read again by the caller, so they do not count as progress
+        let progress = std::mem::take(&mut self.progress);
+        let result = self.scan_utf8(start);
+        self.progress = progress;
+        self.seek(SeekFrom::Start(start))?;
+        result
+    }
+
+    /// Validates the remaining contents chunk by chunk, carrying incomplete characters over to the next chunk
+    fn scan_utf8(&mut self, mut pos: u64) -> std::io::Result<()> {
+        let mut carried = Vec::with_capacity(4);
+        loop {
+            let available = self.fill_buf()?;
+            if available.is_empty() {
+                if carried.is_empty() {
+                    return Ok(());
+                }
+                return Err(invalid_utf8(pos));
+            }
+            if carried.is_empty() {
+                let len = available.len();
+                let valid = valid_utf8_prefix(available, pos)?;
+                // an incomplete character at the end of the chunk is completed by the next one
+                carried.extend_from_slice(&available[valid..]);
+                self.consume(len);
+                pos += valid as u64;
+            } else {
+                // completes the carried character, which has at most four bytes
+                let split = carried.len();
+                let added = available.len().min(4 - split);
+                carried.extend_from_slice(&available[..added]);
+                let valid = valid_utf8_prefix(&carried, pos)?;
+                if valid > split {
+                    self.consume(valid - split);
+                    pos += valid as u64;
+                    carried.clear();
+                } else {
+                    self.consume(added);
+                }
+            }
+        }
+    }
+}
+
+/// The length of the valid UTF-8 in `chunk` starting at `pos`, which is only followed by an incomplete character
+fn valid_utf8_prefix(chunk: &[u8], pos: u64) -> std::io::Result<usize> {
+    match std::str::from_utf8(chunk) {
+        Ok(_) => Ok(chunk.len()),
+        Err(err) if err.error_len().is_some() => Err(invalid_utf8(pos + err.valid_up_to() as u64)),
+        Err(err) => Ok(err.valid_up_to()),
+    }
+}
+
+/// The error for contents, which are not valid UTF-8 at the position `pos`
+fn invalid_utf8(pos: u64) -> std::io::Error {
+    std::io::Error::new(
+        ErrorKind::InvalidData,
+        format!("The contents are not valid UTF-8 at position {pos}"),
+    )
+}
+
+impl BufferedFileReader<std::fs::File> {
+    ///
+    /// Creates an independent reader on the same backing file at the same position, like `std::fs::File::try_clone`.
+    ///
+    /// The handle of the backing file is duplicated, so the backing file is not opened again and the clone reads
+    /// the same generation, even if it has been replaced meanwhile. Both readers move independently afterwards.
+    /// The verification of `BufferedFileOptions::verify_while_reading` continues in both readers, but only the
+    /// original reader excludes a corrupted backing file from later reads. A progress callback is not cloned.
+    pub fn try_clone(&self) -> std::io::Result<Self> {
+        let inner = self.inner.try_clone()?;
+        // the duplicated handles share their position, so both readers position it before every read
+        self.shared_offset.store(true, Ordering::Relaxed);
+        Ok(BufferedFileReader {
+            inner,
+            useful_file_size: self.useful_file_size,
+            pos: self.pos,
+            generation: self.generation,
+            path: self.path.clone(),
+            written: self.written,
+            checksum: self.checksum,
+            user_metadata: self.user_metadata.clone(),
+            header_len: self.header_len,
+            blocks: self.blocks.clone(),
+            streaming: self.streaming.as_ref().map(|streaming| Streaming {
+                digest: streaming.digest.clone(),
+                fed: streaming.fed,
+                end: streaming.end,
+                failed: streaming.failed,
+                on_corrupt: None,
+            }),
+            in_memory: self.in_memory.clone(),
+            #[cfg(feature = "encryption")]
+            sealed: self.sealed.clone(),
+            read_buffer: self.read_buffer.clone(),
+            read_start: self.read_start,
+            lease: self.lease.clone(),
+            progress: Progress {
+                read: self.progress.read,
+                hook: None,
+            },
+            shared_offset: AtomicBool::new(true),
+        })
+    }
+
+    ///
+    /// Reads the contents starting at `pos` into `buf` without using or moving the position of the reader.
+    ///
+    /// Only a shared reference is needed, so several threads can read different parts of the contents
+    /// concurrently. Like with `read_at`, only the blocks touched by the read are verified with
+    /// `BufferedFileOptions::block_checksums`. The checksum verified by `BufferedFileOptions::verify_while_reading`
+    /// does not cover the bytes read this way. Uses `pread` on unix and `seek_read` on windows.
+    pub fn pread(&self, pos: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available =
+            usize::try_from(self.useful_file_size.saturating_sub(pos)).unwrap_or(usize::MAX);
+        let len = buf.len().min(available);
+        if len == 0 {
+            return Ok(0);
+        }
+        if let Some(contents) = &self.in_memory {
+            let start = pos as usize;
+            buf[..len].copy_from_slice(&contents[start..start + len]);
+            return Ok(len);
+        }
+        #[cfg(feature = "encryption")]
+        if let Some(sealed) = &self.sealed {
+            let body_pos = sealed.skipped + pos;
+            let index = body_pos / CHUNK_LEN;
+            let offset = (body_pos - index * CHUNK_LEN) as usize;
+            let decrypted;
+            let chunk = match sealed.index {
+                Some(current) if current == index => &sealed.buffer,
+                _ => {
+                    let (start, chunk_len) = sealed.decryptor.chunk_range(index);
+                    let mut chunk = vec![0u8; chunk_len];
+                    let mut filled = 0;
+                    while filled < chunk_len {
+                        match self.pread_stored(start + filled as u64, &mut chunk[filled..])? {
+                            0 => return Err(ErrorKind::UnexpectedEof.into()),
+                            count => filled += count,
+                        }
+                    }
+                    decrypted = sealed.decryptor.decrypt_chunk(index, &chunk)?;
+                    &decrypted
+                }
+            };
+            let count = len.min(chunk.len() - offset);
+            buf[..count].copy_from_slice(&chunk[offset..offset + count]);
+            return Ok(count);
+        }
+        self.pread_stored(pos, &mut buf[..len])
+    }
+
+    /// Reads the stored contents at `pos` into `buf`, which does not exceed the end of the stored contents
+    fn pread_stored(&self, pos: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len();
+        let blocks = match &self.blocks {
+            Some(blocks) => blocks,
+            None => return read_file_at(&self.inner, buf, self.header_len + pos),
+        };
+        let body_pos = blocks.skipped + pos;
+        let index = body_pos / blocks.size;
+        let block_len = blocks.size.min(blocks.body_len - index * blocks.size);
+        let mut block = vec![0u8; (block_len + TRAILER_LEN) as usize];
+        read_file_exact_at(
+            &self.inner,
+            &mut block,
+            self.header_len + index * (blocks.size + TRAILER_LEN),
+        )?;
+        verify_block(blocks.crc, index, &block)?;
+        let offset = (body_pos - index * blocks.size) as usize;
+        let count = len.min(block_len as usize - offset);
+        buf[..count].copy_from_slice(&block[offset..offset + count]);
+        Ok(count)
+    }
+
+    /// Reads exactly `buf.len()` bytes of the contents starting at `pos`, see `pread`
+    pub fn pread_exact(&self, mut pos: u64, mut buf: &mut [u8]) -> std::io::Result<()> {
+        while !buf.is_empty() {
+            match self.pread(pos, buf) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(count) => {
+                    buf = &mut buf[count..];
+                    pos += count as u64;
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Read + Seek> Read for BufferedFileReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.buffered() == 0 {
+            let count = self.read_unbuffered(buf)?;
+            self.advance(count as u64);
+            return Ok(count);
         }
+        let available = self.fill_buf()?;
+        let count = buf.len().min(available.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.consume(count);
+        Ok(count)
     }
 }
 
-impl<T: Read> Read for BufferedFileReader<T> {
-    fn read(&mut self, mut buf: &mut [u8]) -> std::io::Result<usize> {
+impl<T: Read + Seek> BufRead for BufferedFileReader<T> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.buffered() == 0 {
+            self.read_buffer.resize(READ_BUFFER_LEN, 0);
+            let mut buffer = std::mem::take(&mut self.read_buffer);
+            let result = self.read_unbuffered(&mut buffer);
+            self.read_buffer = buffer;
+            self.read_start = 0;
+            let count = result.inspect_err(|_| self.read_buffer.clear())?;
+            self.read_buffer.truncate(count);
+        }
+        Ok(&self.read_buffer[self.read_start..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let start = self.read_start;
+        self.read_start = (start + amt).min(self.read_buffer.len());
+        self.advance((self.read_start - start) as u64);
+    }
+}
+
+impl<T: Read + Seek> BufferedFileReader<T> {
+    /// Reads the contents behind the bytes read ahead
+    fn read_unbuffered(&mut self, mut buf: &mut [u8]) -> std::io::Result<usize> {
+        if let Some(contents) = &self.in_memory {
+            let start = usize::try_from(self.pos)
+                .unwrap_or(usize::MAX)
+                .min(contents.len());
+            let count = buf.len().min(contents.len() - start);
+            buf[..count].copy_from_slice(&contents[start..start + count]);
+            self.pos += count as u64;
+            return Ok(count);
+        }
+        #[cfg(feature = "encryption")]
+        if self.sealed.is_some() {
+            return self.read_sealed(buf);
+        }
+        if self.blocks.is_some() {
+            return self.read_block(buf);
+        }
+        if self
+            .streaming
+            .as_ref()
+            .is_some_and(|streaming| streaming.failed)
+        {
+            return Err(corrupt_contents());
+        }
         let limit = usize::try_from(self.useful_file_size - self.pos).unwrap_or(0);
+        if limit == 0 && !buf.is_empty() {
+            self.finish_verification()?;
+        }
         if buf.len() > limit {
             buf = &mut buf[..limit]
         }
+        let start = self.header_len + self.pos;
+        if self.shared_offset.load(Ordering::Relaxed) {
+            self.inner.seek(SeekFrom::Start(start))?;
+        }
         let read = self.inner.read(buf)?;
+        match &mut self.streaming {
+            Some(streaming) if streaming.fed == start => {
+                streaming.digest.update(&buf[..read]);
+                streaming.fed += read as u64;
+            }
+            // the contents are not read sequentially, e.g. after seeking
+            Some(_) => self.streaming = None,
+            None => {}
+        }
         self.pos = self.pos.saturating_add(
             u64::try_from(read)
                 .expect("buffer len should fit into a u64. see calculation of limit above."),
@@ -40,24 +1067,43 @@ impl<T: Read> Read for BufferedFileReader<T> {
 }
 
 impl<T: Seek + Read> Seek for BufferedFileReader<T> {
+    ///
+    /// Moves the position within the contents, the header and the trailer of the backing file are not reachable.
+    ///
+    /// All variants are relative to the contents, e.g. `SeekFrom::End(0)` is the end of the contents.
+    /// Positions beyond the end are clamped to the end, positions before the start fail with
+    /// `ErrorKind::InvalidInput`.
     fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
-        let inner_pos = match pos {
-            SeekFrom::Start(start) => SeekFrom::Start(start.saturating_add(1)),
-            SeekFrom::Current(delta) => SeekFrom::Current(delta),
-            SeekFrom::End(distance) => SeekFrom::End(distance.saturating_add(4)),
-        };
-
-        let new_start = self.inner.seek(inner_pos)?.saturating_sub(1);
-        self.pos = new_start;
-        Ok(new_start)
+        // the position of the reader precedes the bytes read ahead
+        let current = self.pos - self.buffered() as u64;
+        let new_pos = match pos {
+            SeekFrom::Start(start) => Some(start),
+            SeekFrom::Current(delta) => current.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.useful_file_size.checked_add_signed(delta),
+        }
+        .ok_or_else(|| {
+            std::io::Error::new(ErrorKind::InvalidInput, "seek before the start of the file")
+        })?
+        .min(self.useful_file_size);
+        self.discard_buffer();
+        // the blocks and the contents in memory are positioned on the next read
+        if !self.is_positioned_logically() {
+            self.inner
+                .seek(SeekFrom::Start(self.header_len + new_pos))?;
+        }
+        self.pos = new_pos;
+        Ok(new_pos)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::{Cursor, Read, Seek, SeekFrom};
+    use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom};
 
-    use crate::BufferedFileReader;
+    use super::READ_BUFFER_LEN;
+    use crate::{
+        tests::utils::TempDir, BufferedFileErrors, BufferedFileOptions, BufferedFileReader,
+    };
 
     #[test]
     fn simple() {
@@ -66,7 +1112,7 @@ mod tests {
         inner
             .seek(SeekFrom::Start(1))
             .expect("Cursor should be seekable");
-        let mut reader = BufferedFileReader::new(inner, u64::try_from(data.len() - 1).unwrap());
+        let mut reader = BufferedFileReader::new(inner, u64::try_from(data.len() - 1).unwrap(), 0);
         let mut content = Vec::new();
         reader
             .read_to_end(&mut content)
@@ -81,16 +1127,217 @@ mod tests {
         inner
             .seek(SeekFrom::Start(1))
             .expect("Cursor should be seekable");
-        let mut reader = BufferedFileReader::new(inner, u64::try_from(data.len() - 1).unwrap());
+        let mut reader = BufferedFileReader::new(inner, u64::try_from(data.len() - 1).unwrap(), 0);
         let mut content = [0u8; 10];
         reader
             .read_exact(&mut content)
             .expect("Should be able to read");
 
         assert_eq!(&data[1..11], content.as_slice());
+        assert_eq!(reader.len(), 11);
+        assert_eq!(reader.remaining(), 1);
         let count = reader.read(&mut content).expect("Should be able to read");
 
         assert_eq!(count, 1);
-        assert_eq!(&data[11], &content[0])
+        assert_eq!(&data[11], &content[0]);
+        assert_eq!(reader.remaining(), 0);
+        assert!(!reader.is_empty());
+    }
+
+    #[test]
+    fn seeks_within_the_contents() {
+        let data = b"\0Hello world\x01\x02\x03\x04";
+        let mut inner = Cursor::new(data);
+        inner
+            .seek(SeekFrom::Start(1))
+            .expect("Cursor should be seekable");
+        let mut reader = BufferedFileReader::new(inner, 11, 0);
+        let mut byte = [0u8; 1];
+        assert_eq!(reader.seek(SeekFrom::End(-1)).unwrap(), 10);
+        reader.read_exact(&mut byte).unwrap();
+        assert_eq!(&byte, b"d");
+        // the trailer is not reachable
+        assert_eq!(reader.seek(SeekFrom::End(0)).unwrap(), 11);
+        assert_eq!(reader.read(&mut byte).unwrap(), 0);
+        assert_eq!(reader.seek(SeekFrom::End(3)).unwrap(), 11);
+        assert_eq!(reader.seek(SeekFrom::Start(100)).unwrap(), 11);
+        assert_eq!(reader.seek(SeekFrom::Current(-11)).unwrap(), 0);
+        reader.read_exact(&mut byte).unwrap();
+        assert_eq!(&byte, b"H");
+        assert!(reader.seek(SeekFrom::Current(-2)).is_err());
+        assert!(reader.seek(SeekFrom::End(-12)).is_err());
+
+        reader.seek(SeekFrom::Start(6)).unwrap();
+        reader.rewind().unwrap();
+        reader.read_exact(&mut byte).unwrap();
+        assert_eq!(&byte, b"H");
+        assert_eq!(reader.stream_len(), 11);
+    }
+
+    #[test]
+    fn ranges_are_bounded() {
+        let data = b"\0Hello world\x01\x02\x03\x04";
+        let reader = BufferedFileReader::new(Cursor::new(data), 11, 0);
+        assert!(BufferedFileReader::new(Cursor::new(data), 11, 0)
+            .take_range(6, 6)
+            .is_err());
+        let mut range = reader.take_range(6, 3).unwrap();
+        assert_eq!(range.len(), 3);
+        let mut contents = String::new();
+        range.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "wor");
+        assert_eq!(range.seek(SeekFrom::End(-1)).unwrap(), 2);
+        let mut rest = Vec::new();
+        range.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"r");
+
+        let in_memory = BufferedFileReader::new(Cursor::new(data), 11, 0)
+            .in_memory(b"Hello world".to_vec())
+            .take_range(0, 5)
+            .unwrap();
+        assert_eq!(in_memory.bytes().count(), 5);
+    }
+
+    #[test]
+    fn buffered_lines() {
+        let data = b"\0first\nsecond\nthird";
+        let mut inner = Cursor::new(data);
+        inner
+            .seek(SeekFrom::Start(1))
+            .expect("Cursor should be seekable");
+        let mut reader = BufferedFileReader::new(inner, u64::try_from(data.len() - 1).unwrap(), 0);
+        let mut line = String::new();
+        reader.read_line(&mut line).expect("Should be able to read");
+        assert_eq!(line, "first\n");
+        // the bytes read ahead are not skipped by seeking relative to the current position
+        assert_eq!(reader.stream_position().unwrap(), 6);
+        assert_eq!(reader.remaining(), 12);
+        reader.seek(SeekFrom::Current(1)).unwrap();
+        let lines: Vec<_> = reader.lines().map(Result::unwrap).collect();
+        assert_eq!(lines, ["econd", "third"]);
+    }
+
+    #[test]
+    fn reports_the_progress() {
+        let data = b"\0first\nsecond\nthird";
+        let mut inner = Cursor::new(data);
+        inner.set_position(1);
+        let mut reader = BufferedFileReader::new(inner, u64::try_from(data.len() - 1).unwrap(), 0);
+        let reports = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        reader.set_progress({
+            let reports = std::sync::Arc::clone(&reports);
+            move |read, len| reports.lock().unwrap().push((read, len))
+        });
+        reader.read_exact(&mut [0u8; 2]).unwrap();
+        reader.validate_utf8().unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(reader.bytes_read(), 6);
+        reader.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(reader.bytes_read(), 18);
+        assert_eq!(*reports.lock().unwrap(), [(2, 18), (6, 18), (18, 18)]);
+    }
+
+    #[test]
+    fn validates_utf8_across_chunks() {
+        // the three bytes of the euro sign are split by the end of the read buffer
+        let mut data = vec![0];
+        data.resize(READ_BUFFER_LEN, b'a');
+        data.extend_from_slice("€uro\n".as_bytes());
+        let len = data.len() as u64 - 1;
+        let mut inner = Cursor::new(data.clone());
+        inner.set_position(1);
+        let mut reader = BufferedFileReader::new(inner, len, 0);
+        reader.validate_utf8().unwrap();
+        assert_eq!(reader.stream_position().unwrap(), 0);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.ends_with("€uro\n"));
+
+        data[READ_BUFFER_LEN + 1] = b'a';
+        let mut inner = Cursor::new(data);
+        inner.set_position(1);
+        let mut reader = BufferedFileReader::new(inner, len, 0);
+        let err = reader.validate_utf8().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData, "{err}");
+        assert!(err
+            .to_string()
+            .ends_with(&format!("{}", READ_BUFFER_LEN - 1)));
+    }
+
+    #[test]
+    fn reads_at_positions_around_the_end() {
+        let dir = TempDir::new();
+        let managed_file = BufferedFileOptions::new()
+            .create_with(dir.path().join("data-file.txt"), b"Hello World")
+            .unwrap();
+        let mut reader = managed_file.read().unwrap();
+        reader.seek(SeekFrom::Start(2)).unwrap();
+        let mut buf = [0u8; 8];
+
+        assert_eq!(reader.pread(11, &mut buf).unwrap(), 0);
+        assert_eq!(reader.pread(20, &mut buf).unwrap(), 0);
+        assert_eq!(reader.pread(8, &mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], b"rld");
+        let err = reader.pread_exact(8, &mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(reader.stream_position().unwrap(), 2);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"llo World");
+
+        reader.seek(SeekFrom::Start(2)).unwrap();
+        assert_eq!(reader.read_at(11, &mut buf).unwrap(), 0);
+        assert_eq!(reader.read_at(20, &mut buf).unwrap(), 0);
+        assert_eq!(reader.read_at(8, &mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], b"rld");
+        assert_eq!(reader.stream_position().unwrap(), 11);
+    }
+
+    #[test]
+    fn limited_reads_check_the_length_first() {
+        let data = b"\0Hello world";
+        let mut inner = Cursor::new(data);
+        inner.seek(SeekFrom::Start(1)).unwrap();
+        let mut reader = BufferedFileReader::new(inner, 11, 0);
+        assert!(matches!(
+            reader.read_to_vec_limited(10),
+            Err(BufferedFileErrors::ContentsTooLarge { len: 11, limit: 10 })
+        ));
+        assert_eq!(reader.remaining(), 11);
+        assert_eq!(reader.read_to_vec_limited(11).unwrap(), b"Hello world");
+        assert_eq!(reader.read_to_vec_limited(0).unwrap(), b"");
+
+        let mut empty = BufferedFileReader::new(Cursor::new(b"\0"), 0, 0);
+        assert_eq!(empty.read_to_vec_limited(0).unwrap(), b"");
+
+        // allocating the announced length would abort the test
+        let mut huge = BufferedFileReader::new(Cursor::new(b"\0"), u64::MAX - 1, 0);
+        assert!(matches!(
+            huge.read_to_vec_limited(u64::MAX - 2),
+            Err(BufferedFileErrors::ContentsTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn cloned_readers_verify_independently() {
+        let dir = TempDir::new();
+        let contents = vec![7u8; 3 * READ_BUFFER_LEN];
+        let managed_file = BufferedFileOptions::new()
+            .verify_while_reading(true)
+            .create_with(dir.path().join("data-file.txt"), &contents)
+            .unwrap();
+        let mut reader = managed_file.read().unwrap();
+        let mut clone = reader.try_clone().unwrap();
+
+        let mut read = vec![0u8; contents.len()];
+        clone.read_exact(&mut read).unwrap();
+        // moves the shared offset of the backing file away from the end of the contents
+        reader.read_exact(&mut [0u8]).unwrap();
+        assert_eq!(clone.read(&mut [0u8]).unwrap(), 0);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest.len(), contents.len() - 1);
+        assert_eq!(managed_file.read_to_vec().unwrap(), contents);
     }
 }
@@ -0,0 +1,485 @@
+//! Keeps the generation and the checksum of the backing files in sidecar files, so the backing files hold
+//! the plain contents, e.g. to be parsed by other software directly.
+//!
+//! The sidecar of a file is named like the file with the suffix `.meta`. It starts with the magic bytes `MBS`
+//! and the version 1, followed by the length of the header, the length of the trailer and the number of bytes
+//! of the trailer written so far, each in one byte. Then the header and the trailer follow, each padded with
+//! zeros to its maximum length (24 and 12 bytes), and the checksum (CRC-32/BZIP2) of all preceding bytes.
+
+use std::{
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    checksum::checksum, Advice, FormatVersion, FsStorage, Storage, StorageFile, StorageMetadata,
+    DEFAULT_CHECKSUM, LENGTH_FOOTER_LEN, MAX_HEADER_LEN, TRAILER_LEN,
+};
+
+/// Identifies a sidecar file and the version of its layout
+const META_MAGIC: [u8; 4] = *b"MBS\x01";
+
+/// The maximum number of bytes of a trailer: the stored length and the checksum
+const MAX_TAIL_LEN: usize = (LENGTH_FOOTER_LEN + TRAILER_LEN) as usize;
+
+/// The offset of the header in a sidecar file
+const META_HEADER: usize = META_MAGIC.len() + 3;
+
+/// The offset of the trailer in a sidecar file
+const META_TAIL: usize = META_HEADER + MAX_HEADER_LEN as usize;
+
+/// The number of bytes of a sidecar file
+const META_LEN: usize = META_TAIL + MAX_TAIL_LEN + 4;
+
+/// Generates the path of the sidecar file of `path`
+fn meta_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".meta");
+    PathBuf::from(name)
+}
+
+///
+/// Stores the header and the trailer of every file in a sidecar file, so the file holds the plain contents.
+///
+/// Files are presented to the managed file as usual, joined from the sidecar file and the file itself,
+/// so all features verifying and rotating the backing files keep working. The header is split off
+/// the first bytes written to a file according to its format version, the trailer (the stored length and
+/// the checksum) are held back from the end of the file. Renaming, copying or removing a file
+/// does the same to its sidecar file. A missing or damaged sidecar file leaves the plain contents,
+/// which fail their verification then.
+///
+/// The backing files only hold the plain contents with `FormatVersion::V3` or earlier and without block
+/// checksums, authentication or encryption, as these add data to the contents themselves. Sparse files
+/// and `BufferedFileOptions::sector_size` are not supported, as they skip parts of the files.
+///
+/// # Example
+///
+/// ```
+/// use multibufferedfile::{BufferedFileOptions, FsStorage, SidecarStorage};
+/// # let dir = std::env::temp_dir().join("multibufferedfile-sidecar-doc");
+/// # std::fs::create_dir_all(&dir).unwrap();
+///
+/// let file = BufferedFileOptions::new()
+///     .open_in(SidecarStorage::new(FsStorage), dir.join("config.txt"))
+///     .unwrap();
+/// file.update(|_| b"volume = 7".to_vec()).unwrap();
+///
+/// assert_eq!(std::fs::read(dir.join("config.txt.1")).unwrap(), b"volume = 7");
+/// assert!(dir.join("config.txt.1.meta").exists());
+/// assert_eq!(file.read_or_default().unwrap(), b"volume = 7");
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct SidecarStorage<S: Storage = FsStorage> {
+    inner: S,
+}
+
+impl<S: Storage> SidecarStorage<S> {
+    /// Keeps the files and their sidecar files in `inner`
+    pub fn new(inner: S) -> Self {
+        SidecarStorage { inner }
+    }
+
+    /// The storage holding the files and their sidecar files
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Opens the file at `path` with the header and the trailer from its sidecar file
+    fn load(
+        &self,
+        path: &Path,
+        payload: S::File,
+        meta: Option<S::File>,
+    ) -> std::io::Result<SidecarFile<S::File>> {
+        let (header, tail, tail_len) = match self.inner.open(&meta_path(path)) {
+            Ok(mut file) => {
+                let mut encoded = Vec::with_capacity(META_LEN);
+                Read::by_ref(&mut file)
+                    .take(META_LEN as u64 + 1)
+                    .read_to_end(&mut encoded)?;
+                decode_meta(&encoded).unwrap_or_default()
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => Default::default(),
+            Err(err) => return Err(err),
+        };
+        let mut file = SidecarFile {
+            payload,
+            meta,
+            header,
+            split: true,
+            tail,
+            tail_len,
+            payload_len: 0,
+            pos: 0,
+            dirty: false,
+        };
+        file.payload_len = file.payload.seek(SeekFrom::End(0))?;
+        Ok(file)
+    }
+}
+
+/// Splits a sidecar file into the header, the trailer and the length of a complete trailer
+fn decode_meta(encoded: &[u8]) -> Option<(Vec<u8>, Vec<u8>, usize)> {
+    if encoded.len() != META_LEN
+        || !encoded.starts_with(&META_MAGIC)
+        || checksum(&DEFAULT_CHECKSUM, &encoded[..META_LEN - 4]).to_le_bytes()
+            != encoded[META_LEN - 4..]
+    {
+        return None;
+    }
+    let header_len = usize::from(encoded[META_MAGIC.len()]);
+    let tail_len = usize::from(encoded[META_MAGIC.len() + 1]);
+    let tail_count = usize::from(encoded[META_MAGIC.len() + 2]);
+    if header_len > MAX_HEADER_LEN as usize || tail_len > MAX_TAIL_LEN || tail_count > tail_len {
+        return None;
+    }
+    Some((
+        encoded[META_HEADER..META_HEADER + header_len].to_vec(),
+        encoded[META_TAIL..META_TAIL + tail_count].to_vec(),
+        tail_len,
+    ))
+}
+
+impl<S: Storage> Storage for SidecarStorage<S> {
+    type File = SidecarFile<S::File>;
+    type Lock = S::Lock;
+
+    fn open(&self, path: &Path) -> std::io::Result<Self::File> {
+        self.load(path, self.inner.open(path)?, None)
+    }
+
+    fn open_write(&self, path: &Path) -> std::io::Result<Self::File> {
+        let payload = self.inner.open_write(path)?;
+        let meta = match self.inner.open_write(&meta_path(path)) {
+            Ok(meta) => meta,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                self.inner.create(&meta_path(path), None)?
+            }
+            Err(err) => return Err(err),
+        };
+        self.load(path, payload, Some(meta))
+    }
+
+    fn create(&self, path: &Path, mode: Option<u32>) -> std::io::Result<Self::File> {
+        let payload = self.inner.create(path, mode)?;
+        let meta = self.inner.create(&meta_path(path), mode)?;
+        Ok(SidecarFile {
+            payload,
+            meta: Some(meta),
+            header: Vec::new(),
+            split: false,
+            tail: Vec::new(),
+            tail_len: 0,
+            payload_len: 0,
+            pos: 0,
+            dirty: true,
+        })
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        match self.inner.rename(&meta_path(from), &meta_path(to)) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        self.inner.rename(from, to)
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        match self.inner.remove(&meta_path(path)) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        self.inner.remove(path)
+    }
+
+    /// The length includes the header and the trailer stored in the sidecar file
+    fn metadata(&self, path: &Path) -> std::io::Result<StorageMetadata> {
+        let mut metadata = self.inner.metadata(path)?;
+        let file = self.open(path)?;
+        metadata.len = file.len();
+        Ok(metadata)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        self.inner.create_dir_all(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> std::io::Result<()> {
+        self.inner.remove_dir(path)
+    }
+
+    fn lock(&self, path: &Path) -> std::io::Result<Self::Lock> {
+        self.inner.lock(path)
+    }
+
+    fn sync_dir(&self, path: &Path) -> std::io::Result<()> {
+        self.inner.sync_dir(path)
+    }
+
+    fn set_mode(&self, path: &Path, mode: u32) -> std::io::Result<()> {
+        self.inner.set_mode(&meta_path(path), mode)?;
+        self.inner.set_mode(path, mode)
+    }
+
+    fn set_owner(&self, path: &Path, owner: (u32, u32)) -> std::io::Result<()> {
+        self.inner.set_owner(&meta_path(path), owner)?;
+        self.inner.set_owner(path, owner)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        match self.inner.copy(&meta_path(from), &meta_path(to)) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        self.inner.copy(from, to)
+    }
+}
+
+///
+/// A file of a `SidecarStorage`, joined from the header and the trailer in its sidecar file and the plain
+/// contents in the file itself.
+#[derive(Debug)]
+pub struct SidecarFile<F: StorageFile> {
+    payload: F,
+    /// The sidecar file, if the file has been opened for writing
+    meta: Option<F>,
+    header: Vec<u8>,
+    /// Whether the header is complete, so the following bytes belong to the contents
+    split: bool,
+    /// The last bytes written, which are held back as the trailer
+    tail: Vec<u8>,
+    tail_len: usize,
+    payload_len: u64,
+    pos: u64,
+    /// Whether the sidecar file has to be written
+    dirty: bool,
+}
+
+impl<F: StorageFile> SidecarFile<F> {
+    /// The length of the joined file
+    fn len(&self) -> u64 {
+        self.header.len() as u64 + self.payload_len + self.tail.len() as u64
+    }
+
+    /// Splits the header off the bytes written so far according to the format version they start with
+    fn split_header(&mut self) -> std::io::Result<()> {
+        let version = FormatVersion::detect(&self.header).unwrap_or_default();
+        let len = (version.header_len() as usize).min(self.header.len());
+        let rest = self.header.split_off(len);
+        self.split = true;
+        self.tail_len = version.trailer_len() as usize;
+        self.append(&rest)
+    }
+
+    /// Appends `data` to the end of the file, holding back the trailer
+    fn append(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.tail.extend_from_slice(data);
+        if self.tail.len() > self.tail_len {
+            let excess = self.tail.len() - self.tail_len;
+            self.payload.seek(SeekFrom::Start(self.payload_len))?;
+            self.payload.write_all(&self.tail[..excess])?;
+            self.payload_len += excess as u64;
+            self.tail.drain(..excess);
+        }
+        Ok(())
+    }
+
+    /// Writes the header and the trailer to the sidecar file
+    fn write_meta(&mut self) -> std::io::Result<()> {
+        if !self.split {
+            self.split_header()?;
+        }
+        let meta = match (&mut self.meta, self.dirty) {
+            (Some(meta), true) => meta,
+            _ => return Ok(()),
+        };
+        let mut encoded = [0u8; META_LEN];
+        encoded[..META_MAGIC.len()].copy_from_slice(&META_MAGIC);
+        encoded[META_MAGIC.len()] = self.header.len() as u8;
+        encoded[META_MAGIC.len() + 1] = self.tail_len as u8;
+        encoded[META_MAGIC.len() + 2] = self.tail.len() as u8;
+        encoded[META_HEADER..META_HEADER + self.header.len()].copy_from_slice(&self.header);
+        encoded[META_TAIL..META_TAIL + self.tail.len()].copy_from_slice(&self.tail);
+        let crc = checksum(&DEFAULT_CHECKSUM, &encoded[..META_LEN - 4]);
+        encoded[META_LEN - 4..].copy_from_slice(&crc.to_le_bytes());
+        meta.seek(SeekFrom::Start(0))?;
+        meta.write_all(&encoded)?;
+        meta.flush()?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+impl<F: StorageFile> Read for SidecarFile<F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let header = self.header.len() as u64;
+        let tail = header + self.payload_len;
+        let count = if self.pos < header {
+            let available = &self.header[self.pos as usize..];
+            let count = available.len().min(buf.len());
+            buf[..count].copy_from_slice(&available[..count]);
+            count
+        } else if self.pos < tail {
+            let len = buf
+                .len()
+                .min(usize::try_from(tail - self.pos).unwrap_or(usize::MAX));
+            self.payload.seek(SeekFrom::Start(self.pos - header))?;
+            self.payload.read(&mut buf[..len])?
+        } else if self.pos < self.len() {
+            let available = &self.tail[(self.pos - tail) as usize..];
+            let count = available.len().min(buf.len());
+            buf[..count].copy_from_slice(&available[..count]);
+            count
+        } else {
+            0
+        };
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+impl<F: StorageFile> Write for SidecarFile<F> {
+    /// Writes are only supported at the end of the file or in place of existing bytes
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let header = self.header.len() as u64;
+        let tail = header + self.payload_len;
+        let count = if self.pos > self.len() {
+            return Err(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "Files with sidecar files can not have holes",
+            ));
+        } else if !self.split || self.pos == self.len() {
+            if self.split {
+                self.append(buf)?;
+            } else {
+                self.header.extend_from_slice(buf);
+                if self.header.len() >= MAX_HEADER_LEN as usize {
+                    self.split_header()?;
+                }
+            }
+            buf.len()
+        } else if self.pos < header {
+            let count = buf.len().min((header - self.pos) as usize);
+            self.header[self.pos as usize..][..count].copy_from_slice(&buf[..count]);
+            count
+        } else if self.pos < tail {
+            let len = buf
+                .len()
+                .min(usize::try_from(tail - self.pos).unwrap_or(usize::MAX));
+            self.payload.seek(SeekFrom::Start(self.pos - header))?;
+            self.payload.write(&buf[..len])?
+        } else {
+            let start = (self.pos - tail) as usize;
+            let count = buf.len().min(self.tail.len() - start);
+            self.tail[start..start + count].copy_from_slice(&buf[..count]);
+            count
+        };
+        self.pos += count as u64;
+        self.dirty = true;
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.payload.flush()?;
+        self.write_meta()
+    }
+}
+
+impl<F: StorageFile> Seek for SidecarFile<F> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(start) => (start, 0),
+            SeekFrom::End(offset) => (self.len(), offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+        self.pos = base.checked_add_signed(offset).ok_or_else(|| {
+            std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+impl<F: StorageFile> StorageFile for SidecarFile<F> {
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        self.flush()?;
+        self.payload.sync_all()?;
+        match &mut self.meta {
+            Some(meta) => meta.sync_all(),
+            None => Ok(()),
+        }
+    }
+
+    fn allocate(&mut self, len: u64) -> std::io::Result<()> {
+        let overhead = (MAX_HEADER_LEN as usize + MAX_TAIL_LEN) as u64;
+        self.payload.allocate(len.saturating_sub(overhead))
+    }
+
+    fn advise(&mut self, advice: Advice) -> std::io::Result<()> {
+        self.payload.advise(advice)
+    }
+}
+
+impl<F: StorageFile> Drop for SidecarFile<F> {
+    fn drop(&mut self) {
+        if let Err(err) = self.write_meta() {
+            tracing::error!("Could not write the sidecar file: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        tests::utils::TempDir, BufferedFileOptions, FormatVersion, FsStorage, SidecarStorage,
+    };
+
+    #[test]
+    fn backing_files_hold_the_plain_contents() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let mut options = BufferedFileOptions::new();
+        options.format_version(FormatVersion::V3);
+        let managed_file = options
+            .open_in(SidecarStorage::new(FsStorage), &file)
+            .unwrap();
+        managed_file.update(|_| b"Hello World".to_vec()).unwrap();
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join("data-file.txt.1")).unwrap(),
+            b"Hello World"
+        );
+        assert_eq!(
+            std::fs::read(dir.path().join("data-file.txt.2")).unwrap(),
+            b"Hello again"
+        );
+
+        let reopened = options
+            .open_in(SidecarStorage::new(FsStorage), &file)
+            .unwrap();
+        assert_eq!(reopened.latest_generation(), Some(2));
+        assert_eq!(reopened.read_or_default().unwrap(), b"Hello again");
+        assert!(reopened
+            .validate()
+            .iter()
+            .all(|slot| slot.outcome.is_valid()));
+
+        // the contents changed by other software no longer match the checksum in the sidecar file
+        std::fs::write(dir.path().join("data-file.txt.2"), b"Hello agaim").unwrap();
+        let reopened = options
+            .open_in(SidecarStorage::new(FsStorage), &file)
+            .unwrap();
+        assert_eq!(reopened.read_or_default().unwrap(), b"Hello World");
+        std::fs::remove_file(dir.path().join("data-file.txt.1.meta")).unwrap();
+        let reopened = options
+            .open_in(SidecarStorage::new(FsStorage), &file)
+            .unwrap();
+        assert_eq!(reopened.latest_generation(), None);
+    }
+}
@@ -0,0 +1,521 @@
+use std::{
+    io::{ErrorKind, Read, Seek, SeekFrom},
+    path::PathBuf,
+    time::SystemTime,
+};
+
+use crate::{
+    blocked_content_len, read_prefix, verify_file, written_at, BufferedFile, BufferedFileErrors,
+    ChecksumAlgorithm, FileCheckResult, FormatVersion, Generation, HeaderError, SlotHeader,
+    SlotTrailer, Storage, TRAILER_LEN,
+};
+
+///
+/// Describes the state of a single backing file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotStatus {
+    /// The path of the backing file
+    pub path: PathBuf,
+    /// Whether the backing file exists on the filesystem
+    pub exists: bool,
+    /// The generation of the backing file, if it is valid
+    pub generation: Option<u64>,
+    /// The size of the backing file in bytes (including generation and checksum), if it exists
+    pub size: Option<u64>,
+    /// The time the backing file has been written, if it is valid and the time has been recorded
+    pub written: Option<SystemTime>,
+}
+
+impl SlotStatus {
+    /// Checks if the backing file holds a valid generation
+    pub fn is_valid(&self) -> bool {
+        self.generation.is_some()
+    }
+}
+
+///
+/// Describes the state of all backing files of a managed file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStatus {
+    /// The state of every backing file in the order of their numbers
+    pub slots: Vec<SlotStatus>,
+    /// The backing file a reader would currently be opened on
+    pub newest: Option<PathBuf>,
+}
+
+impl FileStatus {
+    /// Checks if opening a reader would currently succeed
+    pub fn is_readable(&self) -> bool {
+        self.newest.is_some()
+    }
+}
+
+///
+/// The contents recovered by `BufferedFile::salvage`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Salvaged {
+    /// The longest prefix of the contents, which could be verified
+    pub contents: Vec<u8>,
+    /// The backing file the contents have been recovered from, if any backing file could be read
+    pub path: Option<PathBuf>,
+    /// The generation of the backing file the contents have been recovered from
+    pub generation: Option<u64>,
+    /// Whether the backing file is valid, so the contents are complete
+    pub complete: bool,
+}
+
+///
+/// The result of validating a single backing file.
+#[derive(Debug)]
+pub enum SlotOutcome {
+    /// The backing file is valid and holds the given generation
+    Valid(u64),
+    /// The backing file does not exist
+    Missing,
+    /// The backing file is too short to hold a generation and a checksum
+    Truncated,
+    /// The stored checksum (first value) does not match the checksum of the content (second value)
+    ChecksumMismatch(u32, u32),
+    /// The header of the backing file can not be interpreted or its checksum does not match
+    HeaderInvalid,
+    /// The backing file has been written with an unknown version of the format
+    UnsupportedVersion(u8),
+    /// The checksums match, but the stored length does not match the length of the content
+    LengthMismatch,
+    /// The checksums match, but the authentication code or the tag of the encryption does not match the
+    /// configured key
+    MacMismatch,
+    /// The backing file could not be read
+    IoError(std::io::Error),
+}
+
+impl SlotOutcome {
+    /// Checks if the backing file is valid
+    pub fn is_valid(&self) -> bool {
+        matches!(self, SlotOutcome::Valid(_))
+    }
+}
+
+///
+/// The detailed validation result of a single backing file.
+#[derive(Debug)]
+pub struct SlotReport {
+    /// The path of the backing file
+    pub path: PathBuf,
+    /// The result of the validation
+    pub outcome: SlotOutcome,
+    /// Another valid backing file holds the same generation, e.g. after restoring a backup.
+    /// The newer one is selected by the time they have been written, then by their modification time.
+    pub ambiguous: bool,
+}
+
+///
+/// The result of scrubbing the backing files, see `BufferedFile::scrub`.
+#[derive(Debug)]
+pub struct ScrubReport {
+    /// The detailed result for every backing file, taken before any repair
+    pub slots: Vec<SlotReport>,
+    /// The paths of the backing files, which have been repaired afterwards
+    pub repaired: Vec<PathBuf>,
+}
+
+impl ScrubReport {
+    /// Checks if every backing file has been found valid
+    pub fn is_intact(&self) -> bool {
+        self.slots.iter().all(|slot| slot.outcome.is_valid())
+    }
+}
+
+///
+/// The parsed header and trailer of a single backing file, see `BufferedFile::inspect`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotInspection {
+    /// The path of the backing file
+    pub path: PathBuf,
+    /// The size of the backing file in bytes, if it exists
+    pub size: Option<u64>,
+    /// The parsed header with the version, the generation and the time it has been written,
+    /// if the backing file exists
+    pub header: Option<Result<SlotHeader, HeaderError>>,
+    /// The stored length and checksum following the contents, if the header could be parsed
+    /// and the backing file is long enough. With block checksums, only the checksum of the last block is read.
+    pub trailer: Option<SlotTrailer>,
+    /// The length of the contents including the user metadata and the authentication code,
+    /// as stored or derived from the size
+    pub payload_len: Option<u64>,
+    /// The configured checksum algorithm, which is not stored inside the backing files
+    pub checksum: ChecksumAlgorithm,
+}
+
+impl<S: Storage> BufferedFile<S> {
+    ///
+    /// Parses the header and the trailer of every backing file without verifying the contents.
+    ///
+    /// This is the cheap counterpart to `validate` for tooling and debugging: it reads a few bytes at both ends
+    /// of every backing file, so the reported fields are not trustworthy unless `validate` succeeds as well.
+    /// The known state of the backing files is left untouched.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multibufferedfile::BufferedFile;
+    /// # let dir = std::env::temp_dir().join("multibufferedfile-inspect-doc");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    ///
+    /// let file = BufferedFile::create_with(dir.join("config.bin"), b"Hello World").unwrap();
+    /// let slots = file.inspect().unwrap();
+    /// let header = slots[0].header.unwrap().unwrap();
+    /// assert_eq!(header.generation(), 1);
+    /// assert_eq!(slots[0].payload_len, Some(11));
+    /// assert_eq!(slots[1].header, None);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn inspect(&self) -> Result<Vec<SlotInspection>, BufferedFileErrors> {
+        self.slot_paths()
+            .into_iter()
+            .map(|path| {
+                let mut inspection = SlotInspection {
+                    path,
+                    size: None,
+                    header: None,
+                    trailer: None,
+                    payload_len: None,
+                    checksum: self.options.checksum,
+                };
+                let mut file = match self.storage.open(&inspection.path) {
+                    Ok(file) => file,
+                    Err(err) if err.kind() == ErrorKind::NotFound => return Ok(inspection),
+                    Err(err) => return Err(err.into()),
+                };
+                let size = self.storage.metadata(&inspection.path)?.len;
+                inspection.size = Some(size);
+                let header = match SlotHeader::parse(&read_prefix(&mut file)?) {
+                    Ok(header) => header,
+                    Err(err) => {
+                        inspection.header = Some(Err(err));
+                        return Ok(inspection);
+                    }
+                };
+                inspection.header = Some(Ok(header));
+
+                let version = header.version();
+                let body = size.saturating_sub(version.header_len());
+                let tail_len = match self.options.block_size() {
+                    Some(_) => TRAILER_LEN,
+                    None => version.trailer_len(),
+                };
+                if body < tail_len {
+                    return Ok(inspection);
+                }
+                let mut tail = vec![0u8; tail_len as usize];
+                file.seek(SeekFrom::End(-(tail_len as i64)))?;
+                file.read_exact(&mut tail)?;
+                let tail_version = match self.options.block_size() {
+                    Some(_) => FormatVersion::V0,
+                    None => version,
+                };
+                inspection.trailer = SlotTrailer::parse(tail_version, &tail);
+                inspection.payload_len = match (self.options.block_size(), &inspection.trailer) {
+                    (
+                        _,
+                        Some(SlotTrailer {
+                            length: Some(length),
+                            ..
+                        }),
+                    ) => Some(*length),
+                    (Some(block_size), _) => blocked_content_len(body, block_size)
+                        .map(|len| len.saturating_sub(version.footer_len())),
+                    (None, _) => Some(body - tail_len),
+                };
+                Ok(inspection)
+            })
+            .collect()
+    }
+
+    ///
+    /// Validates all backing files again and reports the detailed result for every backing file.
+    ///
+    /// In contrast to `status` this distinguishes missing backing files from corrupted ones,
+    /// and marks valid backing files holding the same generation as ambiguous.
+    /// The known state of the backing files is updated with the results.
+    pub fn validate(&self) -> Vec<SlotReport> {
+        let paths = self.slot_paths();
+        let reports = self.check_reports(&paths);
+        for (path, generation) in self.files().iter_mut() {
+            if let Some(report) = reports.iter().find(|report| report.path == *path) {
+                *generation = match report.outcome {
+                    SlotOutcome::Valid(gen) => Generation::Valid(gen),
+                    _ => Generation::None,
+                };
+            }
+        }
+        reports
+    }
+
+    ///
+    /// Reads and verifies every backing file again to detect bit rot, e.g. on a regular schedule.
+    ///
+    /// In contrast to `validate` the known state of the backing files is left untouched, so readers keep
+    /// selecting the same backing file. Writers are blocked while the backing files are read.
+    /// If `repair` is set and a backing file turned out to be invalid or missing, `repair` is run afterwards
+    /// and the repaired backing files are reported.
+    pub fn scrub(&self, repair: bool) -> Result<ScrubReport, BufferedFileErrors> {
+        let lock = self.lock()?;
+        let slots = self.check_reports(&self.slot_paths());
+        drop(lock);
+        let repaired = if repair && slots.iter().any(|slot| !slot.outcome.is_valid()) {
+            self.repair()?
+        } else {
+            Vec::new()
+        };
+        Ok(ScrubReport { slots, repaired })
+    }
+
+    /// The paths of all backing files
+    pub(crate) fn slot_paths(&self) -> Vec<PathBuf> {
+        self.files().iter().map(|(path, _)| path.clone()).collect()
+    }
+
+    /// Verifies the given backing files, marking valid backing files holding the same generation as ambiguous
+    fn check_reports(&self, paths: &[PathBuf]) -> Vec<SlotReport> {
+        let mut reports = paths
+            .iter()
+            .map(|path| {
+                let outcome = match verify_file(&*self.storage, path, &self.options) {
+                    Ok(FileCheckResult::Good { generation }) => SlotOutcome::Valid(generation),
+                    Ok(FileCheckResult::Truncated) => SlotOutcome::Truncated,
+                    Ok(FileCheckResult::ChecksumFailure { expected, actual }) => {
+                        SlotOutcome::ChecksumMismatch(expected, actual)
+                    }
+                    Ok(FileCheckResult::UnsupportedVersion { version }) => {
+                        SlotOutcome::UnsupportedVersion(version)
+                    }
+                    Ok(FileCheckResult::HeaderChecksumFailure) => SlotOutcome::HeaderInvalid,
+                    Ok(FileCheckResult::LengthMismatch) => SlotOutcome::LengthMismatch,
+                    Ok(FileCheckResult::MacMismatch) => SlotOutcome::MacMismatch,
+                    Err(err) if err.kind() == ErrorKind::NotFound => SlotOutcome::Missing,
+                    Err(err) => SlotOutcome::IoError(err),
+                };
+                SlotReport {
+                    path: path.clone(),
+                    outcome,
+                    ambiguous: false,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let generations = reports
+            .iter()
+            .map(|report| match report.outcome {
+                SlotOutcome::Valid(generation) => Some(generation),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        for (report, generation) in reports.iter_mut().zip(&generations) {
+            report.ambiguous = generation.is_some()
+                && generations
+                    .iter()
+                    .filter(|other| *other == generation)
+                    .count()
+                    > 1;
+        }
+        reports
+    }
+
+    ///
+    /// Describes the state of the backing files.
+    ///
+    /// The validity is taken from the last scan of the backing files, while existence and size are queried from
+    /// the filesystem.
+    pub fn status(&self) -> Result<FileStatus, BufferedFileErrors> {
+        self.verify_pending();
+        let known = self.files().clone();
+        let mut slots = Vec::with_capacity(known.len());
+        for (path, generation) in known {
+            let size = match self.storage.metadata(&path) {
+                Ok(metadata) => Some(metadata.len),
+                Err(err) if err.kind() == ErrorKind::NotFound => None,
+                Err(err) => return Err(err.into()),
+            };
+            let generation = match generation {
+                Generation::Valid(gen) => Some(gen),
+                _ => None,
+            };
+            slots.push(SlotStatus {
+                exists: size.is_some(),
+                written: generation.and_then(|_| written_at(&*self.storage, &path, &self.options)),
+                generation,
+                size,
+                path,
+            });
+        }
+
+        let newest = match self.select_newest_valid() {
+            Ok((path, _)) => Some(path),
+            Err(BufferedFileErrors::AllFilesInvalidError) => None,
+            Err(err) => return Err(err),
+        };
+
+        Ok(FileStatus { slots, newest })
+    }
+
+    ///
+    /// Checks if a valid generation of the managed file exists.
+    pub fn exists(&self) -> bool {
+        self.verify_pending();
+        self.files().iter().any(|(_, gen)| gen.is_valid())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        time::{Duration, SystemTime},
+    };
+
+    use crate::{
+        tests::utils::TempDir, BufferedFile, BufferedFileOptions, FormatVersion, HeaderError,
+        SlotOutcome,
+    };
+
+    #[test]
+    fn validate_distinguishes_failures() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .buffer_count(4)
+            .create_with(&file, b"Hello World")
+            .expect("Can not create the file");
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        std::fs::write(
+            dir.path().join("data-file.txt.2"),
+            b"\x02Hello agaim\x00\x00\x00\x00",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("data-file.txt.3"), b"\x01\x00").unwrap();
+
+        let reports = managed_file.validate();
+        assert_eq!(reports[0].path, dir.path().join("data-file.txt.1"));
+        assert!(matches!(reports[0].outcome, SlotOutcome::Valid(1)));
+        assert!(
+            matches!(reports[1].outcome, SlotOutcome::ChecksumMismatch(0, actual) if actual != 0),
+            "Unexpected outcome {:?}",
+            reports[1].outcome
+        );
+        assert!(matches!(reports[2].outcome, SlotOutcome::Truncated));
+        assert!(matches!(reports[3].outcome, SlotOutcome::Missing));
+        assert_eq!(managed_file.latest_generation(), Some(1));
+    }
+
+    #[test]
+    fn validate_reports_shared_generations() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        BufferedFile::create_with(&file, b"Hello World").expect("Can not create the file");
+        // a restored backup holding the same generation, which has been modified earlier
+        let mut contents = b"\x01Hello again".to_vec();
+        let checksum = crate::DEFAULT_CHECKSUM.checksum(&contents[1..]);
+        contents.extend_from_slice(&checksum.to_le_bytes());
+        let backup = std::fs::File::create(dir.path().join("data-file.txt.2")).unwrap();
+        (&backup).write_all(&contents).unwrap();
+        backup
+            .set_modified(SystemTime::now() - Duration::from_secs(3600))
+            .unwrap();
+
+        let managed_file = BufferedFile::new(&file).expect("Can not find files");
+        assert_eq!(managed_file.read_or_default().unwrap(), b"Hello World");
+        let reports = managed_file.validate();
+        assert!(reports
+            .iter()
+            .all(|report| report.outcome.is_valid() && report.ambiguous));
+
+        managed_file.update(|_| b"Hello there".to_vec()).unwrap();
+        assert!(managed_file
+            .validate()
+            .iter()
+            .all(|report| !report.ambiguous));
+    }
+
+    #[test]
+    fn scrub_keeps_selection_and_repairs() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file =
+            BufferedFile::create_with(&file, b"Hello World").expect("Can not create the file");
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        assert!(managed_file.scrub(true).unwrap().is_intact());
+
+        let mut corrupted = std::fs::read(dir.path().join("data-file.txt.2")).unwrap();
+        corrupted[3] ^= 0xff;
+        std::fs::write(dir.path().join("data-file.txt.2"), corrupted).unwrap();
+        let report = managed_file.scrub(false).unwrap();
+        assert!(!report.is_intact());
+        assert!(matches!(
+            report.slots[1].outcome,
+            SlotOutcome::ChecksumMismatch(..)
+        ));
+        assert!(report.repaired.is_empty());
+        assert_eq!(managed_file.latest_generation(), Some(2));
+
+        let report = managed_file.scrub(true).unwrap();
+        assert_eq!(report.repaired, vec![dir.path().join("data-file.txt.2")]);
+        assert!(managed_file.scrub(false).unwrap().is_intact());
+        assert_eq!(managed_file.read_or_default().unwrap(), b"Hello World");
+    }
+
+    #[test]
+    fn inspect_parses_headers_without_validating() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .format_version(FormatVersion::V6)
+            .create_with(&file, b"Hello World")
+            .expect("Can not create the file");
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+        let mut corrupted = std::fs::read(dir.path().join("data-file.txt.2")).unwrap();
+        corrupted[8] ^= 0xff;
+        std::fs::write(dir.path().join("data-file.txt.2"), corrupted).unwrap();
+
+        let slots = managed_file.inspect().unwrap();
+        let header = slots[0].header.unwrap().unwrap();
+        assert_eq!(header.generation(), 256);
+        assert!(header.written().is_some());
+        // the contents start with the length of the empty user metadata
+        assert_eq!(slots[0].payload_len, Some(13));
+        assert_eq!(slots[0].trailer.unwrap().length, Some(13));
+        assert_eq!(slots[0].size, Some(24 + 13 + 8 + 4));
+        assert_eq!(slots[1].header, Some(Err(HeaderError::ChecksumMismatch)));
+        assert_eq!(slots[1].trailer, None);
+    }
+
+    #[test]
+    fn status_describes_slots() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFile::new(&file).expect("Can not find files");
+
+        let status = managed_file.status().expect("Can not query status");
+        assert!(!managed_file.exists());
+        assert!(!status.is_readable());
+        assert!(status.slots.iter().all(|slot| !slot.exists));
+
+        let mut writer = managed_file.write().expect("Can not write the file");
+        writer.write_all(b"Hello World").expect("Can not write");
+        writer.commit().unwrap();
+        std::fs::write(dir.path().join("data-file.txt.2"), b"garbage").unwrap();
+
+        let managed_file = BufferedFile::new(&file).expect("Can not find files");
+        let status = managed_file.status().expect("Can not query status");
+        assert!(managed_file.exists());
+        assert_eq!(status.newest, Some(dir.path().join("data-file.txt.1")));
+
+        let first = &status.slots[0];
+        assert_eq!(first.generation, Some(1));
+        assert_eq!(first.size, Some(16));
+        let second = &status.slots[1];
+        assert!(second.exists);
+        assert!(!second.is_valid());
+        assert_eq!(second.size, Some(7));
+    }
+}
@@ -0,0 +1,361 @@
+use std::{
+    fmt::Debug,
+    fs::OpenOptions,
+    io::{Read, Seek, Write},
+    path::Path,
+    time::SystemTime,
+};
+
+///
+/// The properties of a stored file needed to manage the backing files.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StorageMetadata {
+    /// The size of the file in bytes
+    pub len: u64,
+    /// The time of the last modification, if the storage keeps track of it
+    pub modified: Option<SystemTime>,
+    /// The unix permission bits, if the storage supports permissions
+    pub mode: Option<u32>,
+    /// The user and group owning the file, if the storage supports ownership
+    pub owner: Option<(u32, u32)>,
+    /// Whether the path refers to a directory instead of a file
+    pub is_dir: bool,
+}
+
+///
+/// Describes how a file is going to be accessed, so the storage can adapt its caching.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Advice {
+    /// The file is read from start to end, so reading ahead pays off
+    Sequential,
+    /// The file is accessed at random positions, so reading ahead is wasted
+    Random,
+    /// The cached contents of the file are not needed anymore
+    DontNeed,
+}
+
+///
+/// An opened file of a `Storage`.
+pub trait StorageFile: Read + Write + Seek {
+    /// Synchronizes the content of the file to its durable medium
+    fn sync_all(&mut self) -> std::io::Result<()>;
+
+    /// Reserves the space for the first `len` bytes of the file without changing its length.
+    /// Storages without a notion of allocated space ignore this.
+    fn allocate(&mut self, _len: u64) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Announces how the file is going to be accessed. Storages without caching ignore this.
+    fn advise(&mut self, _advice: Advice) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl StorageFile for std::fs::File {
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        std::fs::File::sync_all(self)
+    }
+
+    /// The space is only reserved on linux, file systems without support for `fallocate` are ignored
+    #[cfg(target_os = "linux")]
+    fn allocate(&mut self, len: u64) -> std::io::Result<()> {
+        use std::os::fd::AsRawFd;
+        let len = libc::off_t::try_from(len).map_err(std::io::Error::other)?;
+        // SAFETY: the file descriptor is owned by self and stays open for the duration of the call
+        let result =
+            unsafe { libc::fallocate(self.as_raw_fd(), libc::FALLOC_FL_KEEP_SIZE, 0, len) };
+        if result == 0 {
+            return Ok(());
+        }
+        match std::io::Error::last_os_error() {
+            err if err.raw_os_error() == Some(libc::EOPNOTSUPP) => Ok(()),
+            err => Err(err),
+        }
+    }
+
+    /// The advice is only passed to the kernel on linux
+    #[cfg(target_os = "linux")]
+    fn advise(&mut self, advice: Advice) -> std::io::Result<()> {
+        use std::os::fd::AsRawFd;
+        let advice = match advice {
+            Advice::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+            Advice::Random => libc::POSIX_FADV_RANDOM,
+            Advice::DontNeed => libc::POSIX_FADV_DONTNEED,
+        };
+        // SAFETY: the file descriptor is owned by self and stays open for the duration of the call
+        match unsafe { libc::posix_fadvise(self.as_raw_fd(), 0, 0, advice) } {
+            0 => Ok(()),
+            err => Err(std::io::Error::from_raw_os_error(err)),
+        }
+    }
+}
+
+///
+/// Provides access to the files of a storage, which holds the backing files of managed files.
+///
+/// All file system access of a `BufferedFile` goes through this trait, so the backing files can be kept
+/// somewhere else than the local file system. `FsStorage` implements it for the local file system.
+///
+/// # Example
+///
+/// ```
+/// use std::{io, path::Path};
+/// use multibufferedfile::{BufferedFileOptions, FsStorage, Storage, StorageMetadata};
+///
+/// /// Refuses to remove any backing file
+/// #[derive(Debug)]
+/// struct AppendOnly(FsStorage);
+///
+/// impl Storage for AppendOnly {
+///     type File = <FsStorage as Storage>::File;
+///     type Lock = <FsStorage as Storage>::Lock;
+///
+///     fn open(&self, path: &Path) -> io::Result<Self::File> { self.0.open(path) }
+///     fn open_write(&self, path: &Path) -> io::Result<Self::File> { self.0.open_write(path) }
+///     fn create(&self, path: &Path, mode: Option<u32>) -> io::Result<Self::File> {
+///         self.0.create(path, mode)
+///     }
+///     fn rename(&self, from: &Path, to: &Path) -> io::Result<()> { self.0.rename(from, to) }
+///     fn remove(&self, _path: &Path) -> io::Result<()> {
+///         Err(io::Error::new(io::ErrorKind::PermissionDenied, "append only"))
+///     }
+///     fn metadata(&self, path: &Path) -> io::Result<StorageMetadata> { self.0.metadata(path) }
+///     fn create_dir_all(&self, path: &Path) -> io::Result<()> { self.0.create_dir_all(path) }
+///     fn remove_dir(&self, path: &Path) -> io::Result<()> { self.0.remove_dir(path) }
+///     fn lock(&self, path: &Path) -> io::Result<Self::Lock> { self.0.lock(path) }
+/// }
+///
+/// let file = BufferedFileOptions::new().open_in(AppendOnly(FsStorage), "file.txt");
+/// assert!(file.is_ok());
+/// ```
+pub trait Storage: Debug + Send + Sync + 'static {
+    /// An opened file of the storage
+    type File: StorageFile;
+    /// Holds an exclusive lock until it is dropped
+    type Lock: Send + 'static;
+
+    /// Opens an existing file for reading
+    fn open(&self, path: &Path) -> std::io::Result<Self::File>;
+    /// Opens an existing file for writing without truncating it
+    fn open_write(&self, path: &Path) -> std::io::Result<Self::File>;
+    /// Creates a file for writing, truncating it if it exists already.
+    /// A newly created file receives the unix permission bits `mode`, if the storage supports permissions.
+    fn create(&self, path: &Path, mode: Option<u32>) -> std::io::Result<Self::File>;
+    /// Renames a file, replacing the destination if it exists already
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()>;
+    /// Removes a file
+    fn remove(&self, path: &Path) -> std::io::Result<()>;
+    /// Queries the size and modification time of a file
+    fn metadata(&self, path: &Path) -> std::io::Result<StorageMetadata>;
+    /// Creates a directory and all of its missing parents
+    fn create_dir_all(&self, path: &Path) -> std::io::Result<()>;
+    /// Removes an empty directory
+    fn remove_dir(&self, path: &Path) -> std::io::Result<()>;
+    /// Acquires an exclusive lock identified by the path, blocking until it is available
+    fn lock(&self, path: &Path) -> std::io::Result<Self::Lock>;
+
+    /// Synchronizes the entries of a directory to disk, e.g. after a file has been created or renamed.
+    /// Storages without directories ignore this.
+    fn sync_dir(&self, _path: &Path) -> std::io::Result<()> {
+        Ok(())
+    }
+    /// Changes the unix permission bits of a file. Storages without permissions ignore this.
+    fn set_mode(&self, _path: &Path, _mode: u32) -> std::io::Result<()> {
+        Ok(())
+    }
+    /// Changes the user and group owning a file. Storages without ownership ignore this.
+    fn set_owner(&self, _path: &Path, _owner: (u32, u32)) -> std::io::Result<()> {
+        Ok(())
+    }
+    /// Copies a file, replacing the destination if it exists already.
+    /// Storages able to share the contents between both files, e.g. by reflinks, should do so.
+    fn copy(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        let mut source = self.open(from)?;
+        let mut destination = self.create(to, None)?;
+        std::io::copy(&mut source, &mut destination)?;
+        destination.flush()
+    }
+}
+
+///
+/// Stores the backing files on the local file system.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct FsStorage;
+
+impl Storage for FsStorage {
+    type File = std::fs::File;
+    type Lock = std::fs::File;
+
+    fn open(&self, path: &Path) -> std::io::Result<Self::File> {
+        OpenOptions::new().read(true).open(path)
+    }
+
+    fn open_write(&self, path: &Path) -> std::io::Result<Self::File> {
+        OpenOptions::new().write(true).open(path)
+    }
+
+    /// The mode is only applied on unix, other platforms use their default permissions
+    fn create(&self, path: &Path, mode: Option<u32>) -> std::io::Result<Self::File> {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        if let Some(mode) = mode {
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, mode);
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+        options.open(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    fn metadata(&self, path: &Path) -> std::io::Result<StorageMetadata> {
+        let metadata = std::fs::metadata(path)?;
+        #[cfg(unix)]
+        let (mode, owner) = {
+            use std::os::unix::fs::MetadataExt;
+            (
+                Some(metadata.mode() & 0o7777),
+                Some((metadata.uid(), metadata.gid())),
+            )
+        };
+        #[cfg(not(unix))]
+        let (mode, owner) = (None, None);
+        Ok(StorageMetadata {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            mode,
+            owner,
+            is_dir: metadata.is_dir(),
+        })
+    }
+
+    fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::remove_dir(path)
+    }
+
+    fn lock(&self, path: &Path) -> std::io::Result<Self::Lock> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        file.lock()?;
+        Ok(file)
+    }
+
+    /// Only supported on unix, elsewhere directories can not be opened for synchronization
+    fn sync_dir(&self, path: &Path) -> std::io::Result<()> {
+        #[cfg(unix)]
+        std::fs::File::open(path)?.sync_all()?;
+        #[cfg(not(unix))]
+        let _ = path;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn set_mode(&self, path: &Path, mode: u32) -> std::io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+    }
+
+    #[cfg(unix)]
+    fn set_owner(&self, path: &Path, owner: (u32, u32)) -> std::io::Result<()> {
+        std::os::unix::fs::chown(path, Some(owner.0), Some(owner.1))
+    }
+
+    /// Uses `copy_file_range` on linux and `clonefile` on macOS, which share the contents on file systems
+    /// supporting reflinks like btrfs, XFS or APFS
+    fn copy(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        std::fs::copy(from, to).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::{Path, PathBuf},
+        sync::Mutex,
+    };
+
+    use crate::{tests::utils::TempDir, BufferedFileOptions, FsStorage, Storage, StorageMetadata};
+
+    /// Records every created file, while storing them on the local file system
+    #[derive(Debug, Default)]
+    struct Recording {
+        created: Mutex<Vec<PathBuf>>,
+    }
+
+    impl Storage for Recording {
+        type File = std::fs::File;
+        type Lock = std::fs::File;
+
+        fn open(&self, path: &Path) -> std::io::Result<Self::File> {
+            FsStorage.open(path)
+        }
+
+        fn open_write(&self, path: &Path) -> std::io::Result<Self::File> {
+            FsStorage.open_write(path)
+        }
+
+        fn create(&self, path: &Path, mode: Option<u32>) -> std::io::Result<Self::File> {
+            self.created.lock().unwrap().push(path.to_path_buf());
+            FsStorage.create(path, mode)
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+            FsStorage.rename(from, to)
+        }
+
+        fn remove(&self, path: &Path) -> std::io::Result<()> {
+            FsStorage.remove(path)
+        }
+
+        fn metadata(&self, path: &Path) -> std::io::Result<StorageMetadata> {
+            FsStorage.metadata(path)
+        }
+
+        fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+            FsStorage.create_dir_all(path)
+        }
+
+        fn remove_dir(&self, path: &Path) -> std::io::Result<()> {
+            FsStorage.remove_dir(path)
+        }
+
+        fn lock(&self, path: &Path) -> std::io::Result<Self::Lock> {
+            FsStorage.lock(path)
+        }
+    }
+
+    #[test]
+    fn routes_access_through_storage() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .open_in(Recording::default(), &file)
+            .expect("Can not find files");
+        managed_file.update(|_| b"Hello World".to_vec()).unwrap();
+        managed_file.update(|_| b"Hello again".to_vec()).unwrap();
+
+        assert_eq!(managed_file.read_or_default().unwrap(), b"Hello again");
+        assert_eq!(
+            *managed_file.storage().created.lock().unwrap(),
+            [
+                dir.path().join("data-file.txt.1"),
+                dir.path().join("data-file.txt.2")
+            ]
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    collections::VecDeque,
+    io::Read,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, Waker},
+};
+
+use bytes::Bytes;
+use futures_core::Stream;
+
+use crate::BufferedFileReader;
+
+/// The number of chunks read ahead, before the reading thread waits for the consumer
+const CHUNKS_AHEAD: usize = 2;
+
+/// The chunks handed from the reading thread to the stream
+#[derive(Debug, Default)]
+struct Chunks {
+    queue: VecDeque<std::io::Result<Bytes>>,
+    /// Whether the reading thread has reached the end of the contents or an error
+    finished: bool,
+    /// Whether the stream has been dropped, so the reading thread stops
+    cancelled: bool,
+    waker: Option<Waker>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    chunks: Mutex<Chunks>,
+    /// Signals the reading thread, once a chunk has been taken or the stream has been dropped
+    taken: Condvar,
+}
+
+impl Shared {
+    fn chunks(&self) -> MutexGuard<'_, Chunks> {
+        self.chunks.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+///
+/// The contents of a reader as a `futures_core::Stream` of chunks, see `BufferedFileReader::into_stream`.
+///
+/// The contents are read by a separate thread, so polling the stream never blocks the executor.
+/// The thread reads at most two chunks ahead and stops, once the stream has been dropped.
+#[derive(Debug)]
+pub struct ContentStream {
+    shared: Arc<Shared>,
+}
+
+impl<T: Read + std::io::Seek + Send + 'static> BufferedFileReader<T> {
+    ///
+    /// Turns the reader into a stream of chunks of at most `chunk_size` bytes, e.g. to feed the contents into
+    /// an asynchronous HTTP response.
+    ///
+    /// The contents are read from the current position to their end. A failed read, e.g. a checksum mismatch
+    /// detected by `BufferedFileOptions::verify_while_reading`, is the last item of the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero or the reading thread can not be spawned.
+    pub fn into_stream(mut self, chunk_size: usize) -> ContentStream {
+        assert!(chunk_size > 0, "The chunks must not be empty");
+        let shared = Arc::new(Shared::default());
+        let producer = Arc::clone(&shared);
+        std::thread::Builder::new()
+            .name(String::from("multibufferedfile-stream"))
+            .spawn(move || loop {
+                let mut chunk = vec![0u8; chunk_size];
+                let result = self.read(&mut chunk);
+                let mut chunks = producer.chunks();
+                while chunks.queue.len() >= CHUNKS_AHEAD && !chunks.cancelled {
+                    chunks = producer
+                        .taken
+                        .wait(chunks)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+                if chunks.cancelled {
+                    return;
+                }
+                match result {
+                    Ok(0) => chunks.finished = true,
+                    Ok(count) => {
+                        chunk.truncate(count);
+                        chunks.queue.push_back(Ok(Bytes::from(chunk)));
+                    }
+                    Err(err) => {
+                        chunks.queue.push_back(Err(err));
+                        chunks.finished = true;
+                    }
+                }
+                if let Some(waker) = chunks.waker.take() {
+                    waker.wake();
+                }
+                if chunks.finished {
+                    return;
+                }
+            })
+            .expect("Can not spawn the reading thread");
+        ContentStream { shared }
+    }
+}
+
+impl Stream for ContentStream {
+    type Item = std::io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut chunks = self.shared.chunks();
+        match chunks.queue.pop_front() {
+            Some(chunk) => {
+                self.shared.taken.notify_one();
+                Poll::Ready(Some(chunk))
+            }
+            None if chunks.finished => Poll::Ready(None),
+            None => {
+                chunks.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for ContentStream {
+    fn drop(&mut self) {
+        self.shared.chunks().cancelled = true;
+        self.shared.taken.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::Pin,
+        sync::Arc,
+        task::{Context, Poll, Wake},
+    };
+
+    use futures_core::Stream;
+
+    use crate::{tests::utils::TempDir, BufferedFileOptions};
+
+    /// Resumes the polling test thread
+    struct Unpark(std::thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    #[test]
+    fn streams_the_contents_in_chunks() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .create_with(&file, b"Hello World")
+            .unwrap();
+        let mut stream = managed_file.read().unwrap().into_stream(4);
+
+        let waker = Arc::new(Unpark(std::thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        let mut chunks = Vec::new();
+        loop {
+            match Pin::new(&mut stream).poll_next(&mut cx) {
+                Poll::Ready(Some(chunk)) => chunks.push(chunk.unwrap()),
+                Poll::Ready(None) => break,
+                Poll::Pending => std::thread::park(),
+            }
+        }
+        assert_eq!(chunks, ["Hell", "o Wo", "rld"]);
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    ops::Deref,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{BufferedFile, BufferedFileErrors, BufferedFileOptions, Storage};
+
+/// Distinguishes the temporary files created by this process
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of attempts to find an unused name for a temporary file
+const TEMP_ATTEMPTS: usize = 16;
+
+///
+/// A uniquely named managed file, whose backing files are removed when it is dropped.
+///
+/// Use `persist` to keep the content under its final name.
+///
+/// # Example
+///
+/// ```
+/// use multibufferedfile::BufferedFile;
+/// # let dir = std::env::temp_dir().join("multibufferedfile-temp-doc");
+/// # std::fs::create_dir_all(&dir).unwrap();
+///
+/// let staged = BufferedFile::temp_in(&dir).unwrap();
+/// staged.update(|_| b"staged content".to_vec()).unwrap();
+/// let file = staged.persist(dir.join("final.txt")).unwrap();
+/// assert_eq!(file.read_or_default().unwrap(), b"staged content");
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct TempBufferedFile {
+    file: Option<BufferedFile>,
+}
+
+impl BufferedFile {
+    ///
+    /// Creates a uniquely named managed file in `dir`, which is removed again when the handle is dropped.
+    pub fn temp_in(dir: impl AsRef<Path>) -> Result<TempBufferedFile, BufferedFileErrors> {
+        BufferedFileOptions::new().temp_in(dir)
+    }
+}
+
+impl BufferedFileOptions {
+    ///
+    /// Creates a uniquely named managed file with these options in `dir`,
+    /// which is removed again when the handle is dropped.
+    pub fn temp_in(&self, dir: impl AsRef<Path>) -> Result<TempBufferedFile, BufferedFileErrors> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or_default();
+        for _ in 0..TEMP_ATTEMPTS {
+            let name = format!(
+                ".tmp-{}-{}-{nanos:08x}",
+                std::process::id(),
+                TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+            );
+            let file = self.open(dir.as_ref().join(name))?;
+            let unused = file
+                .files()
+                .iter()
+                .all(|(path, _)| file.storage.metadata(path).is_err());
+            if unused {
+                return Ok(TempBufferedFile { file: Some(file) });
+            }
+        }
+        Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            "Could not find an unused name for a temporary file",
+        )
+        .into())
+    }
+}
+
+impl TempBufferedFile {
+    ///
+    /// Moves the backing files to the managed file at `path`, so they are kept when the handle is dropped.
+    ///
+    /// If moving fails, the temporary backing files are removed.
+    pub fn persist(mut self, path: impl AsRef<Path>) -> Result<BufferedFile, BufferedFileErrors> {
+        let file = self.file.take().expect("the file is only taken once");
+        let options = file.options.clone();
+        let temp_path = file.path.clone();
+        file.rename(path).inspect_err(|_| {
+            if let Err(err) = options.open(&temp_path).and_then(BufferedFile::delete) {
+                tracing::warn!("Could not remove {}: {err}", temp_path.display());
+            }
+        })
+    }
+}
+
+impl Deref for TempBufferedFile {
+    type Target = BufferedFile;
+
+    fn deref(&self) -> &Self::Target {
+        self.file
+            .as_ref()
+            .expect("the file is only taken by persist")
+    }
+}
+
+impl Drop for TempBufferedFile {
+    fn drop(&mut self) {
+        if let Some(file) = self.file.take() {
+            let path = file.path.clone();
+            if let Err(err) = file.delete() {
+                tracing::warn!("Could not remove {}: {err}", path.display());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tests::utils::TempDir, BufferedFile};
+
+    #[test]
+    fn temp_files_are_removed_on_drop() {
+        let dir = TempDir::new();
+        let first = BufferedFile::temp_in(dir.path()).expect("Can not create temporary file");
+        let second = BufferedFile::temp_in(dir.path()).expect("Can not create temporary file");
+        assert_ne!(first.path(), second.path());
+
+        first.update(|_| b"Hello World".to_vec()).unwrap();
+        second.update(|_| b"Hello again".to_vec()).unwrap();
+        drop(first);
+        let file = second
+            .persist(dir.path().join("data-file.txt"))
+            .expect("Can not persist");
+
+        let mut remaining = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        remaining.sort();
+        assert_eq!(remaining, ["data-file.txt.1"]);
+        assert_eq!(file.read_or_default().unwrap(), b"Hello again");
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    io::{ErrorKind, Read, Seek, SeekFrom},
+    marker::PhantomData,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    time::Duration,
+};
+
+use crate::BufferedFileReader;
+
+/// The maximum number of bytes read by the reading thread at once
+const MAX_CHUNK_LEN: usize = 64 * 1024;
+
+/// An operation performed by the reading thread
+#[derive(Debug)]
+enum Request {
+    Read(Vec<u8>),
+    Seek(SeekFrom),
+}
+
+/// The result of an operation handed back by the reading thread
+#[derive(Debug)]
+enum Response {
+    Read(Vec<u8>, std::io::Result<usize>),
+    Seek(std::io::Result<u64>),
+}
+
+///
+/// Limits the time every read or seek of a `BufferedFileReader` may take, see `BufferedFileReader::with_timeout`.
+///
+/// The operations are performed by a separate thread, which owns the reader. An operation, which does not finish
+/// in time, fails with `ErrorKind::TimedOut` and every later operation fails the same way, as the position of the
+/// reader is unknown from then on. The hanging thread keeps the backing file open, until the operation returns.
+#[derive(Debug)]
+pub struct TimeoutReader<T: Read> {
+    requests: Sender<Request>,
+    responses: Receiver<Response>,
+    timeout: Duration,
+    /// The buffer handed to the reading thread
+    buffer: Vec<u8>,
+    timed_out: bool,
+    _reader: PhantomData<T>,
+}
+
+impl<T: Read + Seek + Send + 'static> BufferedFileReader<T> {
+    ///
+    /// Fails reads and seeks with `ErrorKind::TimedOut`, which take longer than `timeout`,
+    /// e.g. for backing files on network filesystems, which may hang indefinitely.
+    ///
+    /// Fails, if the reading thread can not be spawned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::{io::Read, time::Duration};
+    ///
+    /// use multibufferedfile::BufferedFileOptions;
+    /// # let dir = std::env::temp_dir().join("multibufferedfile-timeout-doc");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    ///
+    /// let file = BufferedFileOptions::new()
+    ///     .create_with(dir.join("remote.bin"), b"Hello World")
+    ///     .unwrap();
+    /// let mut reader = file.read().unwrap().with_timeout(Duration::from_secs(5)).unwrap();
+    /// let mut contents = String::new();
+    /// reader.read_to_string(&mut contents).unwrap();
+    /// assert_eq!(contents, "Hello World");
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn with_timeout(mut self, timeout: Duration) -> std::io::Result<TimeoutReader<T>> {
+        let (requests, pending) = mpsc::channel();
+        let (finished, responses) = mpsc::channel();
+        std::thread::Builder::new()
+            .name(String::from("multibufferedfile-timeout"))
+            .spawn(move || {
+                for request in pending {
+                    let response = match request {
+                        Request::Read(mut buffer) => {
+                            let result = self.read(&mut buffer);
+                            Response::Read(buffer, result)
+                        }
+                        Request::Seek(pos) => Response::Seek(self.seek(pos)),
+                    };
+                    if finished.send(response).is_err() {
+                        return;
+                    }
+                }
+            })?;
+        Ok(TimeoutReader {
+            requests,
+            responses,
+            timeout,
+            buffer: Vec::new(),
+            timed_out: false,
+            _reader: PhantomData,
+        })
+    }
+}
+
+impl<T: Read> TimeoutReader<T> {
+    /// The time every operation may take
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Hands the request to the reading thread and waits for its response
+    fn perform(&mut self, request: Request) -> std::io::Result<Response> {
+        if self.timed_out {
+            return Err(std::io::Error::new(
+                ErrorKind::TimedOut,
+                "A previous operation on the backing file has timed out",
+            ));
+        }
+        self.requests
+            .send(request)
+            .map_err(|_| std::io::Error::other("The reading thread has stopped"))?;
+        match self.responses.recv_timeout(self.timeout) {
+            Ok(response) => Ok(response),
+            Err(RecvTimeoutError::Timeout) => {
+                self.timed_out = true;
+                Err(std::io::Error::new(
+                    ErrorKind::TimedOut,
+                    "The operation on the backing file has timed out",
+                ))
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                Err(std::io::Error::other("The reading thread has stopped"))
+            }
+        }
+    }
+}
+
+impl<T: Read> Read for TimeoutReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.resize(buf.len().min(MAX_CHUNK_LEN), 0);
+        match self.perform(Request::Read(buffer))? {
+            Response::Read(buffer, result) => {
+                let count = result?;
+                buf[..count].copy_from_slice(&buffer[..count]);
+                self.buffer = buffer;
+                Ok(count)
+            }
+            Response::Seek(_) => Err(std::io::Error::other("Unexpected response")),
+        }
+    }
+}
+
+impl<T: Read> Seek for TimeoutReader<T> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self.perform(Request::Seek(pos))? {
+            Response::Seek(result) => result,
+            Response::Read(..) => Err(std::io::Error::other("Unexpected response")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{ErrorKind, Read, Seek, SeekFrom},
+        time::Duration,
+    };
+
+    use crate::BufferedFileReader;
+
+    /// Hangs on every operation, like a backing file on an unreachable network filesystem
+    struct Hanging;
+
+    impl Read for Hanging {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            std::thread::sleep(Duration::from_secs(1));
+            Ok(0)
+        }
+    }
+
+    impl Seek for Hanging {
+        fn seek(&mut self, _pos: SeekFrom) -> std::io::Result<u64> {
+            std::thread::sleep(Duration::from_secs(1));
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn hanging_reads_time_out() {
+        let mut reader = BufferedFileReader::new(Hanging, 11, 1)
+            .with_timeout(Duration::from_millis(50))
+            .unwrap();
+        let err = reader.read(&mut [0u8; 4]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+        let err = reader.rewind().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+    }
+}
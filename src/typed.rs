@@ -0,0 +1,267 @@
+use std::{
+    io::{ErrorKind, Read, Seek, Write},
+    marker::PhantomData,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    BufferedFile, BufferedFileErrors, BufferedFileReader, BufferedFileWriter, FrameRecords, Storage,
+};
+
+impl<S: Storage> BufferedFile<S> {
+    ///
+    /// Serializes `value` with postcard and writes it as the next generation.
+    ///
+    /// Values failing to serialize are reported as `ErrorKind::InvalidData`, the new generation is not committed then.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::collections::BTreeMap;
+    /// use multibufferedfile::BufferedFile;
+    /// # let dir = std::env::temp_dir().join("multibufferedfile-typed-doc");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    ///
+    /// let file = BufferedFile::new(dir.join("config.bin")).unwrap();
+    /// let config = BTreeMap::from([(String::from("volume"), 7u32)]);
+    /// file.store(&config).unwrap();
+    /// assert_eq!(file.load::<BTreeMap<String, u32>>().unwrap(), config);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn store<T: Serialize + ?Sized>(&self, value: &T) -> Result<(), BufferedFileErrors> {
+        let contents = postcard::to_allocvec(value)
+            .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))?;
+        self.write_contents(&contents)
+    }
+
+    ///
+    /// Reads the newest valid generation and deserializes it with postcard.
+    ///
+    /// Contents failing to deserialize into `T` are reported as `ErrorKind::InvalidData`.
+    pub fn load<T: DeserializeOwned>(&self) -> Result<T, BufferedFileErrors> {
+        let contents = self.read_contents()?;
+        postcard::from_bytes(&contents)
+            .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err).into())
+    }
+
+    ///
+    /// Serializes `value` as pretty printed JSON and writes it as the next generation.
+    ///
+    /// The contents stay readable with a text editor, see `store` for the handling of errors.
+    #[cfg(feature = "json")]
+    pub fn store_json<T: Serialize + ?Sized>(&self, value: &T) -> Result<(), BufferedFileErrors> {
+        let contents = serde_json::to_vec_pretty(value)
+            .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))?;
+        self.write_contents(&contents)
+    }
+
+    /// Reads the newest valid generation and deserializes it from JSON, see `load`
+    #[cfg(feature = "json")]
+    pub fn load_json<T: DeserializeOwned>(&self) -> Result<T, BufferedFileErrors> {
+        let contents = self.read_contents()?;
+        serde_json::from_slice(&contents)
+            .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err).into())
+    }
+
+    ///
+    /// Serializes `value` as TOML and writes it as the next generation.
+    ///
+    /// The contents stay readable with a text editor, see `store` for the handling of errors.
+    #[cfg(feature = "toml")]
+    pub fn store_toml<T: Serialize + ?Sized>(&self, value: &T) -> Result<(), BufferedFileErrors> {
+        let contents = toml::to_string_pretty(value)
+            .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))?;
+        self.write_contents(contents.as_bytes())
+    }
+
+    /// Reads the newest valid generation and deserializes it from TOML, see `load`
+    #[cfg(feature = "toml")]
+    pub fn load_toml<T: DeserializeOwned>(&self) -> Result<T, BufferedFileErrors> {
+        let contents = String::from_utf8(self.read_contents()?)
+            .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))?;
+        toml::from_str(&contents)
+            .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err).into())
+    }
+
+    /// Writes the serialized contents as the next generation
+    fn write_contents(&self, contents: &[u8]) -> Result<(), BufferedFileErrors> {
+        let mut writer = self.write()?;
+        writer.write_all(contents)?;
+        writer.flush()?;
+        writer.commit()?;
+        Ok(())
+    }
+
+    /// Reads the serialized contents of the newest valid generation
+    fn read_contents(&self) -> Result<Vec<u8>, BufferedFileErrors> {
+        let mut contents = Vec::new();
+        self.read()?.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+}
+
+impl<T: Write> BufferedFileWriter<T> {
+    ///
+    /// Serializes `value` with postcard and writes it as a frame, see `write_frame`.
+    ///
+    /// The values are read back one at a time by `BufferedFileReader::deserialize_seq`, so large collections
+    /// can be written and read without holding all of them in memory. Values failing to serialize are reported as
+    /// `ErrorKind::InvalidData` without writing anything.
+    pub fn serialize_item<V: Serialize + ?Sized>(&mut self, value: &V) -> std::io::Result<()> {
+        let message = postcard::to_allocvec(value)
+            .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))?;
+        self.write_frame(&message)
+    }
+}
+
+impl<T: Read + Seek> BufferedFileReader<T> {
+    ///
+    /// Iterates over the values written by `BufferedFileWriter::serialize_item`, deserializing one frame at a time.
+    ///
+    /// Frames failing to deserialize into `V` are reported as `ErrorKind::InvalidData`.
+    /// The iteration ends after the first error like with `records`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multibufferedfile::BufferedFile;
+    /// # let dir = std::env::temp_dir().join("multibufferedfile-seq-doc");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    ///
+    /// let file = BufferedFile::new(dir.join("measurements.bin")).unwrap();
+    /// let mut writer = file.write().unwrap();
+    /// for value in 0..1000u32 {
+    ///     writer.serialize_item(&(value, value * 2)).unwrap();
+    /// }
+    /// writer.commit().unwrap();
+    ///
+    /// let mut reader = file.read().unwrap();
+    /// let sum: u32 = reader
+    ///     .deserialize_seq::<(u32, u32)>()
+    ///     .map(|item| item.unwrap().1)
+    ///     .sum();
+    /// assert_eq!(sum, 999_000);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn deserialize_seq<V: DeserializeOwned>(&mut self) -> DeserializeSeq<'_, T, V> {
+        DeserializeSeq {
+            records: self.records(),
+            failed: false,
+            _value: PhantomData,
+        }
+    }
+}
+
+///
+/// The values deserialized from the frames of a reader, see `BufferedFileReader::deserialize_seq`.
+#[derive(Debug)]
+pub struct DeserializeSeq<'a, T: Read, V> {
+    records: FrameRecords<'a, T>,
+    failed: bool,
+    _value: PhantomData<fn() -> V>,
+}
+
+impl<T: Read + Seek, V: DeserializeOwned> Iterator for DeserializeSeq<'_, T, V> {
+    type Item = std::io::Result<V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let value = self.records.next()?.and_then(|message| {
+            postcard::from_bytes(&message)
+                .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))
+        });
+        self.failed = value.is_err();
+        Some(value)
+    }
+}
+
+impl<T: Read + Seek, V: DeserializeOwned> std::iter::FusedIterator for DeserializeSeq<'_, T, V> {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use crate::{tests::utils::TempDir, BufferedFile, BufferedFileErrors};
+
+    #[test]
+    fn stores_and_loads_values() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFile::new(&file).unwrap();
+        assert!(matches!(
+            managed_file.load::<u32>(),
+            Err(BufferedFileErrors::AllFilesInvalidError)
+        ));
+
+        let value = (String::from("Hello World"), vec![1u16, 2, 3], Some(true));
+        managed_file.store(&value).unwrap();
+        managed_file
+            .store(&(String::from("Hello again"), 4u8))
+            .unwrap();
+        assert_eq!(managed_file.latest_generation(), Some(2));
+        let loaded: (String, u8) = managed_file.load().unwrap();
+        assert_eq!(loaded, (String::from("Hello again"), 4));
+
+        match managed_file.load::<(String, u8, u64)>() {
+            Err(BufferedFileErrors::IoError(err)) => assert_eq!(err.kind(), ErrorKind::InvalidData),
+            other => panic!("Unexpected result {other:?}"),
+        }
+    }
+
+    #[test]
+    fn deserializes_sequences_item_by_item() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFile::new(&file).unwrap();
+        let mut writer = managed_file.write().unwrap();
+        writer.serialize_item("first").unwrap();
+        writer.serialize_item("second").unwrap();
+        writer.write_frame(&[0xff]).unwrap();
+        writer.serialize_item("unreachable").unwrap();
+        writer.commit().unwrap();
+
+        let mut reader = managed_file.read().unwrap();
+        let mut values = reader.deserialize_seq::<String>();
+        assert_eq!(values.next().unwrap().unwrap(), "first");
+        assert_eq!(values.next().unwrap().unwrap(), "second");
+        assert_eq!(
+            values.next().unwrap().unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+        assert!(values.next().is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn json_stays_readable() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.json");
+        let managed_file = BufferedFile::new(&file).unwrap();
+        let value = std::collections::BTreeMap::from([("volume", 7)]);
+        managed_file.store_json(&value).unwrap();
+
+        assert_eq!(
+            managed_file.read_or_default().unwrap(),
+            b"{\n  \"volume\": 7\n}"
+        );
+        let loaded: std::collections::BTreeMap<String, u32> = managed_file.load_json().unwrap();
+        assert_eq!(loaded.get("volume"), Some(&7));
+    }
+
+    #[test]
+    #[cfg(feature = "toml")]
+    fn toml_stays_readable() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.toml");
+        let managed_file = BufferedFile::new(&file).unwrap();
+        let value = std::collections::BTreeMap::from([("volume", 7)]);
+        managed_file.store_toml(&value).unwrap();
+
+        assert_eq!(managed_file.read_or_default().unwrap(), b"volume = 7\n");
+        let loaded: std::collections::BTreeMap<String, u32> = managed_file.load_toml().unwrap();
+        assert_eq!(loaded.get("volume"), Some(&7));
+    }
+}
@@ -0,0 +1,116 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{BufferedFile, BufferedFileErrors, FsStorage, Storage};
+
+///
+/// A value persisted in a managed file, which is kept in memory.
+///
+/// Every change is written through as a new generation with `BufferedFile::store`, before it becomes visible
+/// through `get`. If no valid generation exists yet, e.g. on the first run, the value starts as the default
+/// and is only written on the first change.
+///
+/// # Example
+///
+/// ```
+/// use multibufferedfile::{BufferedFile, BufferedValue};
+/// # let dir = std::env::temp_dir().join("multibufferedfile-value-doc");
+/// # std::fs::create_dir_all(&dir).unwrap();
+///
+/// let file = BufferedFile::new(dir.join("counter.bin")).unwrap();
+/// let mut counter = BufferedValue::<u64>::new(file).unwrap();
+/// assert_eq!(*counter.get(), 0);
+/// counter.modify(|count| *count += 1).unwrap();
+///
+/// let file = BufferedFile::new(dir.join("counter.bin")).unwrap();
+/// assert_eq!(*BufferedValue::<u64>::new(file).unwrap().get(), 1);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct BufferedValue<T, S: Storage = FsStorage> {
+    file: BufferedFile<S>,
+    value: T,
+}
+
+impl<T: Serialize + DeserializeOwned, S: Storage> BufferedValue<T, S> {
+    /// Loads the value from the newest valid generation of `file`, or starts with the default value
+    pub fn new(file: BufferedFile<S>) -> Result<Self, BufferedFileErrors>
+    where
+        T: Default,
+    {
+        Self::with_default(file, T::default)
+    }
+
+    /// Loads the value from the newest valid generation of `file`, or starts with the value returned by `init`
+    pub fn with_default(
+        file: BufferedFile<S>,
+        init: impl FnOnce() -> T,
+    ) -> Result<Self, BufferedFileErrors> {
+        let value = match file.load() {
+            Ok(value) => value,
+            Err(BufferedFileErrors::AllFilesInvalidError) => init(),
+            Err(err) => return Err(err),
+        };
+        Ok(BufferedValue { file, value })
+    }
+
+    /// The current value
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Writes `value` as a new generation and replaces the current value, once it has been written
+    pub fn set(&mut self, value: T) -> Result<(), BufferedFileErrors> {
+        self.file.store(&value)?;
+        self.value = value;
+        Ok(())
+    }
+
+    ///
+    /// Changes a copy of the current value with `f` and writes it as a new generation.
+    ///
+    /// The current value is only replaced, once the changed value has been written.
+    pub fn modify(&mut self, f: impl FnOnce(&mut T)) -> Result<(), BufferedFileErrors>
+    where
+        T: Clone,
+    {
+        let mut value = self.value.clone();
+        f(&mut value);
+        self.set(value)
+    }
+
+    /// The managed file holding the value
+    pub fn file(&self) -> &BufferedFile<S> {
+        &self.file
+    }
+
+    /// Releases the managed file, discarding the value kept in memory
+    pub fn into_file(self) -> BufferedFile<S> {
+        self.file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tests::utils::TempDir, BufferedFile, BufferedFileErrors, BufferedValue};
+
+    #[test]
+    fn values_are_written_through() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let mut value =
+            BufferedValue::with_default(BufferedFile::new(&file).unwrap(), || vec![1u8]).unwrap();
+        assert_eq!(value.get(), &[1]);
+        assert!(!value.file().exists());
+
+        value.modify(|value| value.push(2)).unwrap();
+        value.set(vec![3, 4]).unwrap();
+        assert_eq!(value.file().latest_generation(), Some(2));
+
+        let reopened = BufferedValue::<Vec<u8>>::new(BufferedFile::new(&file).unwrap()).unwrap();
+        assert_eq!(reopened.get(), &[3, 4]);
+        assert!(matches!(
+            BufferedValue::<(u64, u64, u64, u64)>::new(reopened.into_file()),
+            Err(BufferedFileErrors::IoError(_))
+        ));
+    }
+}
@@ -1,67 +1,701 @@
-use std::{io::Write, mem::ManuallyDrop};
+use std::{
+    io::{Seek, SeekFrom, Write},
+    mem::ManuallyDrop,
+};
 
-use crc::Digest;
+use crc::Crc;
+
+use crate::{
+    checksum::ChecksumDigest,
+    delta::{self, DeltaBase},
+    Durability, SlotHeader, SlotTrailer, StorageFile, LENGTH_FOOTER_LEN, TRAILER_LEN,
+};
+
+#[cfg(feature = "encryption")]
+use crate::encryption::{self, EncryptionKey, Encryptor};
+#[cfg(feature = "hmac")]
+use crate::mac::{MacKey, MAC_LEN};
+
+/// Invoked after the target has been closed, either to commit or to discard the written file.
+pub(crate) type CommitHook = Box<dyn FnOnce() -> std::io::Result<()> + Send>;
+
+/// Synchronizes the target to disk.
+pub(crate) type SyncHook<T> = fn(&mut T) -> std::io::Result<()>;
+
+/// Advances the target by the given number of zero bytes without writing them.
+pub(crate) type SkipHook<T> = fn(&mut T, u64) -> std::io::Result<()>;
+
+/// Writes the given header at the start of the target and returns to the end.
+pub(crate) type HeaderHook<T> = fn(&mut T, &[u8]) -> std::io::Result<()>;
+
+/// The size of the zero blocks, which are skipped instead of written in sparse mode
+const HOLE_SIZE: usize = 4096;
+
+fn is_hole(block: &[u8]) -> bool {
+    block.len() == HOLE_SIZE && block.iter().all(|byte| *byte == 0)
+}
+
+///
+/// Describes the generation committed by `BufferedFileWriter::commit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommitInfo {
+    /// The number of bytes of contents written, excluding the header, the user metadata and the trailer
+    pub bytes_written: u64,
+    /// The generation of the committed backing file
+    pub generation: Option<u64>,
+    /// The checksum stored at the end of the backing file
+    pub checksum: u32,
+}
 
 ///
 /// Represents write access to the file.
 /// Generates the checksum of the file while writing the contents.
-/// 
+///
+/// The new generation is only committed by `commit`. A writer dropped without being committed, e.g. while
+/// a panic unwinds, discards the generation: a staged backing file is removed, a backing file written in place
+/// is left invalid.
+///
 pub struct BufferedFileWriter<T: Write> {
-    inner: T,
-    digest: ManuallyDrop<Digest<'static, u32>>,
+    inner: ManuallyDrop<T>,
+    crc: &'static Crc<u32>,
+    digest: ManuallyDrop<ChecksumDigest>,
+    block_size: Option<u64>,
+    block_len: u64,
+    written: u64,
+    length_footer: bool,
+    sector: Option<(u64, u64)>,
+    header: Option<(SlotHeader, HeaderHook<T>)>,
+    durability: Durability,
+    on_sync: Option<SyncHook<T>>,
+    on_skip: Option<SkipHook<T>>,
+    on_commit: Option<CommitHook>,
+    on_abort: Option<CommitHook>,
+    delta: Option<(Option<DeltaBase>, Vec<u8>)>,
+    #[cfg(feature = "hmac")]
+    mac: Option<hmac_sha256::HMAC>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<Encryptor>,
+    failed: bool,
+    /// Whether the generation has been finished by `commit` or `into_inner`
+    finished: bool,
+    /// The number of bytes of contents handed to the writer
+    bytes_written: u64,
+    generation: Option<u64>,
+    /// The lock of the managed file, released once the generation has been committed or discarded
+    lock: Option<Box<dyn Send>>,
 }
 
 impl<T: Write> std::io::Write for BufferedFileWriter<T> {
+    /// After a failed write the contents are incomplete, so the writer will not be committed anymore.
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        let count = self.inner.write(buf)?;
-        self.digest.update(&buf[..count]);
-        Ok(count)
+        if let Some((_, contents)) = &mut self.delta {
+            contents.extend_from_slice(buf);
+            self.bytes_written += buf.len() as u64;
+            return Ok(buf.len());
+        }
+        #[cfg(feature = "encryption")]
+        if let Some(mut encryptor) = self.encryption.take() {
+            let result = encryptor
+                .update(buf)
+                .and_then(|sealed| self.write_all_payload(&sealed));
+            self.encryption = Some(encryptor);
+            return match result {
+                Ok(()) => {
+                    self.bytes_written += buf.len() as u64;
+                    Ok(buf.len())
+                }
+                Err(err) => {
+                    self.failed = true;
+                    Err(err)
+                }
+            };
+        }
+        let result = self.write_payload(buf);
+        match &result {
+            Ok(count) => self.bytes_written += *count as u64,
+            Err(_) => self.failed = true,
+        }
+        result
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
-        self.inner.flush()
+        let result = self.inner.flush();
+        self.failed |= result.is_err();
+        result
     }
 }
 
 impl<T: Write> BufferedFileWriter<T> {
-    pub(crate) fn new(target: T) -> Self {
-        let digest = crate::CRC.digest();
+    /// Writes a part of the stored contents, which are covered by the authentication code
+    fn write_payload(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let count = self.write_contents(buf)?;
+        #[cfg(feature = "hmac")]
+        if let Some(mac) = &mut self.mac {
+            mac.update(&buf[..count]);
+        }
+        Ok(count)
+    }
+
+    /// Writes a part of `buf`, which does not cross the end of the current block
+    fn write_contents(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let buf = match self.block_size {
+            Some(block_size) => {
+                let remaining = usize::try_from(block_size - self.block_len).unwrap_or(usize::MAX);
+                &buf[..buf.len().min(remaining)]
+            }
+            None => buf,
+        };
+        let count = self.write_block(buf)?;
+        self.written += count as u64;
+        self.block_len += count as u64;
+        if Some(self.block_len) == self.block_size {
+            let digest = std::mem::replace(&mut *self.digest, ChecksumDigest::new(self.crc));
+            self.inner
+                .write_all(&SlotTrailer::encode_checksum(digest.finalize()))?;
+            self.block_len = 0;
+        }
+        Ok(count)
+    }
+
+    fn write_block(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let skip = match self.on_skip {
+            Some(skip) => skip,
+            None => {
+                let count = self.inner.write(buf)?;
+                self.digest.update(&buf[..count]);
+                return Ok(count);
+            }
+        };
+
+        // only the leading run of either zero blocks or data is handled, write_all takes care of the rest
+        let leading_hole = buf.chunks(HOLE_SIZE).next().is_some_and(is_hole);
+        let run = buf
+            .chunks(HOLE_SIZE)
+            .take_while(|block| is_hole(block) == leading_hole)
+            .map(<[u8]>::len)
+            .sum::<usize>();
+        let count = if leading_hole {
+            skip(&mut self.inner, run as u64)?;
+            run
+        } else {
+            self.inner.write(&buf[..run])?
+        };
+        self.digest.update(&buf[..count]);
+        Ok(count)
+    }
+
+    pub(crate) fn new(target: T, crc: &'static Crc<u32>, durability: Durability) -> Self {
         BufferedFileWriter {
-            inner: target,
-            digest: ManuallyDrop::new(digest),
+            inner: ManuallyDrop::new(target),
+            crc,
+            digest: ManuallyDrop::new(ChecksumDigest::new(crc)),
+            block_size: None,
+            block_len: 0,
+            written: 0,
+            length_footer: false,
+            sector: None,
+            header: None,
+            durability,
+            on_sync: None,
+            on_skip: None,
+            on_commit: None,
+            on_abort: None,
+            delta: None,
+            #[cfg(feature = "hmac")]
+            mac: None,
+            #[cfg(feature = "encryption")]
+            encryption: None,
+            failed: false,
+            finished: false,
+            bytes_written: 0,
+            generation: None,
+            lock: None,
+        }
+    }
+
+    /// Sets the generation the target holds once it has been committed
+    pub(crate) fn generation(mut self, generation: u64) -> Self {
+        self.generation = Some(generation);
+        self
+    }
+
+    /// Writes a checksum after every block of `block_size` bytes, the last block is finished on commit.
+    pub(crate) fn blocks(mut self, block_size: u64) -> Self {
+        self.block_size = Some(block_size);
+        self
+    }
+
+    /// Registers the function synchronizing the target to disk, if the durability requires it.
+    pub(crate) fn on_sync(mut self, sync: SyncHook<T>) -> Self {
+        self.on_sync = Some(sync);
+        self
+    }
+
+    /// Registers a hook which is invoked once the file has been finished successfully.
+    pub(crate) fn on_commit(mut self, hook: CommitHook) -> Self {
+        self.on_commit = Some(hook);
+        self
+    }
+
+    /// Registers a hook which is invoked instead of the commit, if the file could not be finished.
+    pub(crate) fn on_abort(mut self, hook: CommitHook) -> Self {
+        self.on_abort = Some(hook);
+        self
+    }
+
+    /// Holds `lock` until the generation has been committed or discarded
+    pub(crate) fn holding(mut self, lock: Box<dyn Send>) -> Self {
+        self.lock = Some(lock);
+        self
+    }
+
+    /// Appends the authentication code of the contents with `key`, before the checksum is written on commit.
+    #[cfg(feature = "hmac")]
+    pub(crate) fn authenticate(mut self, key: &MacKey) -> Self {
+        self.mac = Some(key.authenticator());
+        self
+    }
+
+    /// Writes the authentication code as part of the contents, so the checksums cover it
+    #[cfg(feature = "hmac")]
+    fn write_mac(&mut self) -> std::io::Result<()> {
+        match self.mac.take() {
+            Some(mac) => self.write_all_payload(&mac.finalize()),
+            None => Ok(()),
         }
     }
+
+    /// Collects the contents until the writer is finished, to store them as the difference to `base` if that is shorter.
+    pub(crate) fn delta(mut self, base: Option<DeltaBase>) -> Self {
+        self.delta = Some((base, Vec::new()));
+        self
+    }
+
+    /// Writes the collected contents, either as they are or as the difference to the base
+    fn write_delta(&mut self) -> std::io::Result<()> {
+        match self.delta.take() {
+            Some((base, contents)) => self.write_all(&delta::encode(base.as_ref(), &contents)),
+            None => Ok(()),
+        }
+    }
+
+    /// Encrypts the contents with `key` chunk by chunk, while they are written.
+    #[cfg(feature = "encryption")]
+    pub(crate) fn encrypt(mut self, key: &EncryptionKey) -> Self {
+        self.encryption = Some(key.encryptor());
+        self
+    }
+
+    /// Writes the last encrypted chunk, which is held back until the writer is finished
+    #[cfg(feature = "encryption")]
+    fn write_encrypted(&mut self) -> std::io::Result<()> {
+        match self.encryption.take() {
+            Some(encryptor) => self.write_all_payload(&encryptor.finish()?),
+            None => Ok(()),
+        }
+    }
+
+    /// Stores the length of the contents behind them, once the writer is finished.
+    pub(crate) fn length_footer(mut self) -> Self {
+        self.length_footer = true;
+        self
+    }
+
+    /// Pads the contents with zeros, so the file ends on a multiple of `sector_size`, once the writer is finished.
+    /// The `offset` is the number of bytes preceding the contents.
+    pub(crate) fn align_to(mut self, sector_size: u64, offset: u64) -> Self {
+        self.sector = Some((sector_size, offset));
+        self
+    }
+
+    /// Writes the zeros ending the padded contents, see `align_to`
+    fn write_padding(&mut self) -> std::io::Result<()> {
+        let (sector_size, offset) = match self.sector {
+            Some(sector) => sector,
+            None => return Ok(()),
+        };
+        let end = offset + self.written + LENGTH_FOOTER_LEN + TRAILER_LEN;
+        let padding = (sector_size - end % sector_size) % sector_size;
+        let zeros = [0u8; 512];
+        let mut remaining = padding;
+        while remaining > 0 {
+            let len = remaining.min(zeros.len() as u64) as usize;
+            self.write_all_with(&zeros[..len], Self::write_contents)?;
+            remaining -= len as u64;
+        }
+        Ok(())
+    }
+
+    /// The number of bytes added to `len` bytes of contents when the writer is finished, excluding the checksums
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+    fn appended_len(&self, len: u64) -> u64 {
+        [
+            (self.length_footer, LENGTH_FOOTER_LEN),
+            #[cfg(feature = "hmac")]
+            (self.mac.is_some(), MAC_LEN),
+            #[cfg(feature = "encryption")]
+            (self.encryption.is_some(), encryption::overhead(len)),
+        ]
+        .into_iter()
+        .filter(|(appended, _)| *appended)
+        .map(|(_, len)| len)
+        .sum()
+    }
+
+    /// Writes the encoded user metadata in front of the contents, it is neither encrypted nor authenticated
+    pub(crate) fn user_metadata(&mut self, encoded: &[u8]) -> std::io::Result<()> {
+        let len = u16::try_from(encoded.len()).map_err(std::io::Error::other)?;
+        let result = self
+            .write_all_with(&len.to_le_bytes(), Self::write_contents)
+            .and_then(|()| self.write_all_with(encoded, Self::write_contents));
+        self.failed |= result.is_err();
+        result
+    }
+
+    fn write_all_payload(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.write_all_with(buf, Self::write_payload)
+    }
+
+    fn write_all_with(
+        &mut self,
+        mut buf: &[u8],
+        write: fn(&mut Self, &[u8]) -> std::io::Result<usize>,
+    ) -> std::io::Result<()> {
+        while !buf.is_empty() {
+            match write(self, buf)? {
+                0 => return Err(std::io::Error::from(std::io::ErrorKind::WriteZero)),
+                count => buf = &buf[count..],
+            }
+        }
+        Ok(())
+    }
 }
 
-impl<T: Write> Drop for BufferedFileWriter<T> {
-    fn drop(&mut self) {
+impl<T: Write + Seek> BufferedFileWriter<T> {
+    /// Skips blocks of zeros instead of writing them, so the file system can leave holes in the target.
+    pub(crate) fn sparse(mut self) -> Self {
+        self.on_skip = Some(|inner, len| {
+            let len = i64::try_from(len).map_err(std::io::Error::other)?;
+            inner.seek(SeekFrom::Current(len)).map(drop)
+        });
+        self
+    }
+}
+
+impl<T: Write + Seek> BufferedFileWriter<T> {
+    ///
+    /// Writes `header` once the rest of the target has been written, instead of the placeholder
+    /// written in its place.
+    ///
+    /// The header is synchronized on its own, so it never reaches the disk before the contents.
+    pub(crate) fn deferred_header(mut self, header: SlotHeader) -> Self {
+        self.header = Some((header, |inner, header| {
+            inner.seek(SeekFrom::Start(0))?;
+            inner.write_all(header)?;
+            inner.seek(SeekFrom::End(0)).map(drop)
+        }));
+        self
+    }
+}
+
+impl<T: StorageFile> BufferedFileWriter<T> {
+    ///
+    /// Reserves the space for `len` bytes of content and the checksum before writing them.
+    ///
+    /// If the storage runs out of space, this fails while the older generations are still intact,
+    /// instead of leaving a partially written backing file behind.
+    pub fn preallocate(&mut self, len: u64) -> std::io::Result<()> {
+        let start = self.inner.stream_position()?;
+        let len = len.saturating_add(self.appended_len(len));
+        let checksums = match self.block_size {
+            Some(block_size) => (self.block_len + len) / block_size + 1,
+            None => 1,
+        };
+        let end = start
+            .checked_add(len)
+            .and_then(|end| end.checked_add(checksums * TRAILER_LEN))
+            .ok_or_else(|| std::io::Error::other("preallocated length too large"))?;
+        self.inner.allocate(end)
+    }
+}
+
+impl<T: Write> BufferedFileWriter<T> {
+    /// Provides access to the underlying file, e.g. for platform-specific calls
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    ///
+    /// Provides mutable access to the underlying file, e.g. for platform-specific calls.
+    ///
+    /// Bytes written or positions changed directly are not known to the writer, so the backing file
+    /// fails its verification afterwards.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    ///
+    /// Finishes the contents and commits them as the new generation.
+    ///
+    /// The trailer is written and synchronized according to the durability, before the generation becomes
+    /// visible to readers. Fails, if the contents could not be written completely or the generation could not
+    /// be committed. The generation is discarded then, like when the writer is dropped without being committed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::io::Write;
+    ///
+    /// use multibufferedfile::BufferedFile;
+    /// # let dir = std::env::temp_dir()
+    /// #     .join(format!("multibufferedfile-commit-doc-{}", std::process::id()));
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    ///
+    /// let file = BufferedFile::new(dir.join("state.bin")).unwrap();
+    /// let mut writer = file.write().unwrap();
+    /// writer.write_all(b"Hello World").unwrap();
+    /// let info = writer.commit().unwrap();
+    /// assert_eq!(info.bytes_written, 11);
+    /// assert_eq!(info.generation, Some(1));
+    ///
+    /// let mut writer = file.write().unwrap();
+    /// writer.write_all(b"discarded").unwrap();
+    /// drop(writer);
+    /// assert_eq!(file.read_to_vec().unwrap(), b"Hello World");
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn commit(mut self) -> std::io::Result<CommitInfo> {
+        let result = self.finish();
+        self.finished = true;
+        // SAFETY: the target is only taken here, by into_inner or by drop, which returns early for finished writers.
+        // It is closed before the commit, so the hook may e.g. rename it.
+        drop(unsafe { ManuallyDrop::take(&mut self.inner) });
+        let checksum = self.conclude(result)?;
+        Ok(CommitInfo {
+            bytes_written: self.bytes_written,
+            generation: self.generation,
+            checksum,
+        })
+    }
+
+    ///
+    /// Commits the generation like `commit` and returns the underlying file.
+    ///
+    /// The generation is committed while the returned file is still open, so committing a staged generation
+    /// (see `CommitStrategy::Rename`) fails on platforms, which can not rename open files.
+    pub fn into_inner(mut self) -> std::io::Result<T> {
+        let result = self.finish();
+        self.finished = true;
+        // SAFETY: the target is only taken here, by commit or by drop, which returns early for finished writers
+        let inner = unsafe { ManuallyDrop::take(&mut self.inner) };
+        self.conclude(result).map(|_| inner)
+    }
+
+    /// Writes the trailer, the deferred header and synchronizes the target according to the durability.
+    /// Returns the checksum stored in the trailer.
+    fn finish(&mut self) -> std::io::Result<u32> {
+        if !self.failed {
+            self.failed = self.write_delta().is_err();
+        }
+        #[cfg(feature = "encryption")]
+        if !self.failed {
+            self.failed = self.write_encrypted().is_err();
+        }
+        #[cfg(feature = "hmac")]
+        if !self.failed {
+            self.failed = self.write_mac().is_err();
+        }
+        if self.length_footer && !self.failed {
+            let len = self.written;
+            self.failed = self
+                .write_padding()
+                .and_then(|()| self.write_all_payload(&SlotTrailer::encode_length(len)))
+                .is_err();
+        }
         // SAFETY: this is the only instance where the digest is removed so it is still valid.
-        // this is drop so it can't be called more than once.
+        // finish is called at most once, either by commit, by into_inner or by drop.
         let digest = unsafe { ManuallyDrop::take(&mut self.digest) };
         let checksum = digest.finalize();
-        let _ = self.inner.write_all(&checksum.to_le_bytes());
+        let mut result = if self.failed {
+            Err(std::io::Error::other("the contents are incomplete"))
+        } else {
+            self.inner
+                .write_all(&SlotTrailer::encode_checksum(checksum))
+        };
+        if result.is_ok() && self.durability >= Durability::Flush {
+            result = self.inner.flush();
+        }
+        if let (Ok(()), true, Some(sync)) =
+            (&result, self.durability >= Durability::Fsync, self.on_sync)
+        {
+            result = sync(&mut self.inner);
+        }
+        if let (Ok(()), Some((header, write))) = (&result, self.header.take()) {
+            result = write(&mut self.inner, header.as_ref());
+            if result.is_ok() && self.durability >= Durability::Flush {
+                result = self.inner.flush();
+            }
+            if let (Ok(()), true, Some(sync)) =
+                (&result, self.durability >= Durability::Fsync, self.on_sync)
+            {
+                result = sync(&mut self.inner);
+            }
+        }
+        // the target is flushed regardless of the durability, so targets uploading their contents on flush,
+        // e.g. an `ObjectFile`, report failures here instead of losing them when they are dropped
+        result.and_then(|()| self.inner.flush()).map(|()| checksum)
+    }
+
+    /// Commits the finished generation or discards the unfinished one
+    fn conclude(&mut self, result: std::io::Result<u32>) -> std::io::Result<u32> {
+        match (result, self.on_commit.take(), self.on_abort.take()) {
+            (Ok(checksum), Some(hook), _) => hook().map(|()| checksum),
+            (Ok(checksum), None, _) => Ok(checksum),
+            (Err(err), _, Some(hook)) => {
+                if let Err(abort) = hook() {
+                    tracing::error!("Could not discard the unfinished file: {abort}");
+                }
+                Err(err)
+            }
+            (Err(err), _, None) => Err(err),
+        }
+    }
+}
+
+impl<T: Write> Drop for BufferedFileWriter<T> {
+    /// Discards the generation, which has not been committed
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.failed = true;
+        let result = self.finish();
+        // SAFETY: the target is only taken here and drop is not called more than once.
+        // It is closed before the unfinished file is discarded, so the hook may e.g. remove it.
+        drop(unsafe { ManuallyDrop::take(&mut self.inner) });
+        let _ = self.conclude(result);
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::{Cursor, Write};
+    use std::{
+        io::{Cursor, Write},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    use crate::{BufferedFileWriter, ChecksumAlgorithm, Durability};
+
+    /// Runs out of space after the given number of bytes
+    struct Full(Vec<u8>, usize);
+
+    impl Write for Full {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let count = buf.len().min(self.1 - self.0.len());
+            if count == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::StorageFull));
+            }
+            self.0.extend_from_slice(&buf[..count]);
+            Ok(count)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Keeps the contents until they are uploaded on flush, which always fails
+    struct Unreachable(Vec<u8>);
+
+    impl Write for Unreachable {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Err(std::io::Error::from(std::io::ErrorKind::ConnectionRefused))
+        }
+    }
 
-    use crate::BufferedFileWriter;
+    #[test]
+    fn failed_writes_are_not_committed() {
+        let committed = Arc::new(AtomicBool::new(false));
+        let aborted = Arc::new(AtomicBool::new(false));
+        let crc = ChecksumAlgorithm::default().crc();
+        let mut writer = BufferedFileWriter::new(Full(Vec::new(), 8), crc, Durability::None)
+            .on_commit({
+                let committed = Arc::clone(&committed);
+                Box::new(move || {
+                    committed.store(true, Ordering::SeqCst);
+                    Ok(())
+                })
+            })
+            .on_abort({
+                let aborted = Arc::clone(&aborted);
+                Box::new(move || {
+                    aborted.store(true, Ordering::SeqCst);
+                    Ok(())
+                })
+            });
+        assert!(writer.write_all(b"hello world").is_err());
+        drop(writer);
+
+        assert!(!committed.load(Ordering::SeqCst));
+        assert!(aborted.load(Ordering::SeqCst));
+    }
 
     #[test]
     fn simple() {
         const DATA: &[u8] = b"hello world";
         let mut buffer: Vec<u8> = Vec::new();
         let target = Cursor::new(&mut buffer);
-        let checksum = crate::CRC.checksum(DATA);
-        let mut writer = BufferedFileWriter::new(target);
+        let crc = ChecksumAlgorithm::default().crc();
+        let checksum = crc.checksum(DATA);
+        let mut writer = BufferedFileWriter::new(target, crc, Durability::None);
         writer.write_all(DATA).expect("Should be writeable");
-        drop(writer);
+        writer.commit().expect("Should be committed");
 
         let mut expected = Vec::new();
         expected.extend_from_slice(DATA);
         expected.extend_from_slice(&checksum.to_le_bytes());
         assert_eq!(buffer, expected);
     }
+
+    #[test]
+    fn into_inner_returns_the_finished_target() {
+        const DATA: &[u8] = b"hello world";
+        let crc = ChecksumAlgorithm::default().crc();
+        let mut writer = BufferedFileWriter::new(Vec::new(), crc, Durability::None);
+        writer.write_all(DATA).expect("Should be writeable");
+        assert!(writer.get_ref().starts_with(DATA));
+        let target = writer.into_inner().unwrap();
+        assert_eq!(&target[DATA.len()..], crc.checksum(DATA).to_le_bytes());
+
+        let mut writer = BufferedFileWriter::new(Full(Vec::new(), 8), crc, Durability::None);
+        assert!(writer.write_all(DATA).is_err());
+        assert!(writer.into_inner().is_err());
+    }
+
+    #[test]
+    fn failed_uploads_are_reported_by_commit() {
+        let committed = Arc::new(AtomicBool::new(false));
+        let crc = ChecksumAlgorithm::default().crc();
+        let mut writer = BufferedFileWriter::new(Unreachable(Vec::new()), crc, Durability::None)
+            .on_commit({
+                let committed = Arc::clone(&committed);
+                Box::new(move || {
+                    committed.store(true, Ordering::SeqCst);
+                    Ok(())
+                })
+            });
+        writer.write_all(b"hello world").unwrap();
+        let err = writer.commit().unwrap_err();
+
+        assert_eq!(err.kind(), std::io::ErrorKind::ConnectionRefused);
+        assert!(!committed.load(Ordering::SeqCst));
+    }
 }
@@ -0,0 +1,63 @@
+use bytes::Bytes;
+
+use crate::{BufferedFile, BufferedFileErrors, Storage};
+
+impl<S: Storage> BufferedFile<S> {
+    ///
+    /// Reads the whole content of the newest valid generation into `Bytes`, see `read_to_vec`.
+    ///
+    /// The contents are read into a single allocation, which is shared by all clones and slices of the returned
+    /// `Bytes`, e.g. to hand them to other threads or network responses without copying them.
+    /// With the `mmap` feature, the mapped contents of `read_mmap` can be converted into `Bytes` as well.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multibufferedfile::BufferedFileOptions;
+    /// # let dir = std::env::temp_dir().join("multibufferedfile-bytes-doc");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    ///
+    /// let file = BufferedFileOptions::new()
+    ///     .create_with(dir.join("greeting.txt"), b"Hello World")
+    ///     .unwrap();
+    /// let contents = file.read_bytes().unwrap();
+    /// assert_eq!(contents.slice(6..), "World");
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn read_bytes(&self) -> Result<Bytes, BufferedFileErrors> {
+        Ok(Bytes::from(self.read_to_vec()?))
+    }
+}
+
+/// Shares the mapping, which is unmapped once the last `Bytes` referring to it has been dropped
+#[cfg(feature = "mmap")]
+impl From<crate::MappedContents> for Bytes {
+    fn from(contents: crate::MappedContents) -> Self {
+        Bytes::from_owner(contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tests::utils::TempDir, BufferedFileOptions};
+
+    #[test]
+    fn bytes_share_the_contents() {
+        let dir = TempDir::new();
+        let file = dir.path().join("data-file.txt");
+        let managed_file = BufferedFileOptions::new()
+            .create_with(&file, b"Hello World")
+            .unwrap();
+        let contents = managed_file.read_bytes().unwrap();
+        let world = contents.slice(6..);
+        let handle = std::thread::spawn(move || world.to_vec());
+        assert_eq!(handle.join().unwrap(), b"World");
+        assert_eq!(contents, "Hello World");
+
+        #[cfg(feature = "mmap")]
+        {
+            let mapped = bytes::Bytes::from(managed_file.read_mmap().unwrap());
+            assert_eq!(mapped, contents);
+        }
+    }
+}